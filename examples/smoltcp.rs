@@ -0,0 +1,149 @@
+//! Call a method through a ubus TCP bridge, over smoltcp
+//!
+//! `call` is the superloop a microcontroller would run, using nothing from std; `main` runs it
+//! over a TUN device. On the router, expose ubusd over TCP with something like
+//! `socat TCP-LISTEN:8000,fork UNIX-CONNECT:/var/run/ubus/ubus.sock`, then:
+//!
+//! ```text
+//! ip tuntap add name tun0 mode tun user $USER
+//! ip link set tun0 up
+//! ip addr add 192.168.69.100/24 dev tun0
+//! cargo run --example smoltcp --features smoltcp -- tun0 192.168.69.1/24 192.168.69.100 192.168.1.1:8000 system board
+//! ```
+//!
+//! (with forwarding and NAT set up on the host to reach the router).
+use smoltcp::iface::{Config, Interface, SocketSet};
+use smoltcp::phy::{self, Device, Medium, TunTapInterface};
+use smoltcp::socket::tcp;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{HardwareAddress, IpCidr, IpEndpoint, Ipv4Address};
+use std::os::unix::io::AsRawFd;
+use ubus::*;
+
+type Bridge<'a> = NbConnection<SmoltcpIo<'a>, [u8; 4096], [u8; 512]>;
+
+/// Look `path` up, then call `method` on it, passing each reply's DATA table to `on_result`
+///
+/// `idle` is called with how long until the interface needs polling again whenever there's
+/// nothing to do.
+#[allow(clippy::too_many_arguments)]
+fn call<D: Device>(
+    iface: &mut Interface,
+    device: &mut D,
+    connection: &mut Bridge,
+    path: &str,
+    method: &str,
+    now: impl Fn() -> Instant,
+    mut idle: impl FnMut(Option<Duration>),
+    mut on_result: impl FnMut(BlobIter<BlobMsg>),
+) -> Result<(), Error<SmoltcpError>> {
+    let mut lookup = None;
+    let mut obj = None;
+    let mut invoke = None;
+    loop {
+        connection.io_mut().poll(iface, device, now());
+
+        // Requests only fail with WouldBlock while an earlier one is still being sent
+        if connection.peer().is_some() && lookup.is_none() {
+            match connection.request(LookupRequest::path(path)) {
+                Ok(sequence) => lookup = Some(sequence),
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+        if let (Some(obj), None) = (obj, invoke) {
+            match connection.invoke(obj, method, &[]) {
+                Ok(sequence) => invoke = Some(sequence),
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+        }
+
+        match connection.poll() {
+            Ok(EngineEvent::Data { sequence, attrs }) if Some(sequence) == lookup => {
+                for attr in attrs {
+                    if let MessageAttr::ObjId(id) = attr {
+                        obj = Some(id);
+                    }
+                }
+            }
+            Ok(EngineEvent::Data { sequence, attrs }) if Some(sequence) == invoke => {
+                for attr in attrs {
+                    if let MessageAttr::Data(data) = attr {
+                        on_result(BlobIter::new(data));
+                    }
+                }
+            }
+            Ok(EngineEvent::Done { sequence, status }) if Some(sequence) == lookup => {
+                if status != 0 {
+                    return Err(Error::Status(status));
+                }
+                if obj.is_none() {
+                    return Err(Error::InvalidData("No object in lookup reply"));
+                }
+            }
+            Ok(EngineEvent::Done { sequence, status }) if Some(sequence) == invoke => {
+                return match status {
+                    0 => Ok(()),
+                    status => Err(Error::Status(status)),
+                };
+            }
+            Ok(_) => {}
+            Err(nb::Error::WouldBlock) => {
+                idle(iface.poll_delay(now(), connection.io_mut().sockets()));
+            }
+            Err(nb::Error::Other(e)) => return Err(e),
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 7 {
+        eprintln!(
+            "Usage: {} <tun> <address/prefix> <gateway> <bridge:port> <path> <method>",
+            args[0]
+        );
+        std::process::exit(1);
+    }
+    let address: IpCidr = args[2].parse().expect("Invalid address");
+    let gateway: Ipv4Address = args[3].parse().expect("Invalid gateway");
+    let bridge: IpEndpoint = args[4].parse().expect("Invalid bridge");
+
+    let mut device = TunTapInterface::new(&args[1], Medium::Ip).expect("Failed to open TUN");
+    let fd = device.as_raw_fd();
+    let config = Config::new(HardwareAddress::Ip);
+    let mut iface = Interface::new(config, &mut device, Instant::now());
+    iface.update_ip_addrs(|addrs| addrs.push(address).unwrap());
+    iface.routes_mut().add_default_ipv4_route(gateway).unwrap();
+
+    let mut socket = tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 4096]),
+        tcp::SocketBuffer::new(vec![0; 4096]),
+    );
+    socket
+        .connect(iface.context(), bridge, 49152)
+        .expect("Failed to connect");
+    let mut sockets = SocketSet::new(vec![]);
+    let handle = sockets.add(socket);
+    let mut connection = NbConnection::new(SmoltcpIo::new(sockets, handle), [0; 4096], [0; 512]);
+
+    let result = call(
+        &mut iface,
+        &mut device,
+        &mut connection,
+        &args[5],
+        &args[6],
+        Instant::now,
+        |delay| phy::wait(fd, delay).expect("Failed to wait"),
+        |data| {
+            for msg in data {
+                println!("{:?}", msg);
+            }
+        },
+    );
+    if let Err(e) = result {
+        eprintln!("Failed: {}", e);
+        std::process::exit(1);
+    }
+}
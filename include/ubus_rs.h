@@ -0,0 +1,44 @@
+/*
+ * C interface to ubus-rs, built with `cargo rustc --release --features ffi --crate-type cdylib`
+ *
+ * Arguments and replies are blobmsg table payloads, as built and parsed by libubox
+ * (blob_data(b.head) and blob_len(b.head)). Functions returning int return 0 on success or a
+ * UBUS_STATUS_* code.
+ */
+#ifndef UBUS_RS_H
+#define UBUS_RS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+struct ubus_rs_connection;
+
+typedef void (*ubus_rs_data_cb)(void *context, const uint8_t *data, size_t len);
+typedef void (*ubus_rs_event_cb)(void *context, const char *id, const uint8_t *data, size_t len);
+
+/* Connect to the socket at path, or the default socket if path is NULL. NULL on failure. */
+struct ubus_rs_connection *ubus_rs_connect(const char *path);
+void ubus_rs_free(struct ubus_rs_connection *connection);
+int ubus_rs_fd(const struct ubus_rs_connection *connection);
+
+int ubus_rs_lookup_id(struct ubus_rs_connection *connection, const char *path, uint32_t *id);
+/* callback may be NULL to ignore replies */
+int ubus_rs_invoke(struct ubus_rs_connection *connection, uint32_t obj, const char *method,
+		   const uint8_t *data, size_t len, ubus_rs_data_cb callback, void *context);
+
+int ubus_rs_send_event(struct ubus_rs_connection *connection, const char *id,
+		       const uint8_t *data, size_t len);
+/* pattern may end with a '*' wildcard. Events arrive while handling messages. */
+int ubus_rs_register_event(struct ubus_rs_connection *connection, const char *pattern,
+			   ubus_rs_event_cb callback, void *context);
+int ubus_rs_handle_next(struct ubus_rs_connection *connection);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
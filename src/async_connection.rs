@@ -0,0 +1,246 @@
+use crate::maybe_async::{exchange, invoke_reply, lookup_reply, Link};
+use crate::*;
+use core::ops::ControlFlow;
+
+/// A ubus connection over an async transport
+///
+/// Requests go through the same code as `Connection`'s, so replies are matched, errors reported
+/// and late replies dropped in the same way. Notifications and events can be received as streams
+/// (see `subscriber_stream` and `event_stream`), but there are no objects with methods: other
+/// messages (such as calls) are logged and dropped.
+pub struct AsyncConnection<T: AsyncIO> {
+    pub(crate) io: T,
+    peer: u32,
+    pub(crate) sequences: Sequences<MAX_ABANDONED>,
+    pub(crate) buffer: Buffer,
+    pub(crate) max_message_size: usize,
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) streams: Streams,
+}
+
+impl<T: AsyncIO> AsyncConnection<T> {
+    /// Create a new ubus connection from an existing IO, waiting for ubusd's hello
+    pub async fn new(io: T) -> Result<Self, Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        let buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+        #[cfg(feature = "no_std")]
+        let buffer = [0u8; DEFAULT_BUFFER_SIZE];
+        let mut new = Self {
+            io,
+            peer: 0,
+            sequences: Sequences::new(),
+            buffer,
+            max_message_size: usize::MAX,
+            #[cfg(not(feature = "no_std"))]
+            streams: Streams::default(),
+        };
+
+        let message = new.next_message().await?;
+        if message.header.message != MessageType::HELLO {
+            return Err(Error::InvalidData("Expected hello"));
+        }
+        new.peer = message.header.peer.into();
+
+        Ok(new)
+    }
+
+    /// Our peer id, given by ubusd's hello
+    pub fn peer(&self) -> u32 {
+        self.peer
+    }
+
+    /// Receive the next message
+    pub async fn next_message(&mut self) -> Result<Message<'_>, Error<T::Error>> {
+        recv(&mut self.io, &mut self.buffer, self.max_message_size).await
+    }
+
+    /// Like `Connection::set_max_message_size`
+    pub fn set_max_message_size(&mut self, size: Option<usize>) {
+        self.max_message_size = size.unwrap_or(usize::MAX);
+    }
+
+    /// The limit set by `set_max_message_size`
+    pub fn max_message_size(&self) -> Option<usize> {
+        Some(self.max_message_size).filter(|&size| size != usize::MAX)
+    }
+
+    pub async fn send(&mut self, message: MessageBuilder<'_>) -> Result<(), Error<T::Error>> {
+        self.io.put(message.into()).await
+    }
+
+    /// Send `request`, passing each of its replies to `on_response` until the final STATUS
+    pub async fn call<'b>(
+        &mut self,
+        request: impl RequestMessage<'b>,
+        mut on_response: impl FnMut(&Response),
+    ) -> Result<(), Error<T::Error>> {
+        self.request(request, |message| {
+            on_response(&Response::from_message(message));
+            Ok(ControlFlow::Continue(()))
+        })
+        .await
+    }
+
+    /// Call `method` on `obj`, passing the DATA table of each reply to `on_result`
+    pub async fn invoke(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        mut on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<InvokeSummary, Error<T::Error>> {
+        self.invoke_until(obj, method, args, |data| {
+            on_result(data);
+            ControlFlow::Continue(())
+        })
+        .await
+    }
+
+    /// Like `Connection::invoke_until`
+    pub async fn invoke_until(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        mut on_result: impl FnMut(BlobIter<BlobMsg>) -> ControlFlow<()>,
+    ) -> Result<InvokeSummary, Error<T::Error>> {
+        let mut summary = InvokeSummary::default();
+        let mut stopped = false;
+        let request = InvokeRequest::new(obj, method).args(args);
+        self.request(request, |message| {
+            summary.add(message.blob.data);
+            let flow = invoke_reply(BlobIter::new(message.blob.data), &mut on_result)?;
+            stopped = flow.is_break();
+            Ok(flow)
+        })
+        .await?;
+        summary.status = if stopped { None } else { Some(0) };
+        Ok(summary)
+    }
+
+    pub async fn lookup(
+        &mut self,
+        on_object: impl FnMut(ObjectResult),
+        on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<T::Error>> {
+        self.lookup_inner(None, on_object, on_signature).await
+    }
+
+    /// Like `lookup`, but only for objects matching `path` (which may end with a `*` wildcard)
+    pub async fn lookup_path(
+        &mut self,
+        path: &str,
+        on_object: impl FnMut(ObjectResult),
+        on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<T::Error>> {
+        self.lookup_inner(Some(path), on_object, on_signature).await
+    }
+
+    /// Find the id of the object at `path`
+    pub async fn object_id(&mut self, path: &str) -> Result<u32, Error<T::Error>> {
+        let mut id = None;
+        self.lookup_path(path, |obj| id = Some(obj.id), |_| {})
+            .await?;
+        Ok(id.ok_or(Error::<NoIO>::InvalidData("No object in lookup reply"))?)
+    }
+
+    async fn lookup_inner(
+        &mut self,
+        path: Option<&str>,
+        mut on_object: impl FnMut(ObjectResult),
+        mut on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<T::Error>> {
+        let request = path.map_or_else(LookupRequest::all, LookupRequest::path);
+        self.request(request, |message| {
+            lookup_reply(
+                BlobIter::new(message.blob.data),
+                &mut on_object,
+                &mut on_signature,
+            )?;
+            Ok(ControlFlow::Continue(()))
+        })
+        .await
+    }
+
+    pub(crate) async fn request<'b>(
+        &mut self,
+        request: impl RequestMessage<'b>,
+        on_data: impl FnMut(&Message) -> Result<ControlFlow<()>, Error>,
+    ) -> Result<(), Error<T::Error>> {
+        let request = Request::new(self.sequences.allocate(), request);
+        let mut link = Parts {
+            io: &mut self.io,
+            max_message_size: self.max_message_size,
+            #[cfg(not(feature = "no_std"))]
+            streams: &mut self.streams,
+        };
+        exchange(
+            &mut link,
+            &mut self.buffer,
+            &mut self.sequences,
+            &request,
+            None,
+            on_data,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+async fn recv<'b, T: AsyncIO>(
+    io: &mut T,
+    buffer: &'b mut Buffer,
+    max_size: usize,
+) -> Result<Message<'b>, Error<T::Error>> {
+    #[cfg(not(feature = "no_std"))]
+    return Message::read_vec_limit(io, buffer, max_size).await;
+    #[cfg(feature = "no_std")]
+    Message::read_limit(io, buffer, max_size).await
+}
+
+/// The parts of an `AsyncConnection` which requests are exchanged over
+pub(crate) struct Parts<'a, T: AsyncIO> {
+    pub(crate) io: &'a mut T,
+    pub(crate) max_message_size: usize,
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) streams: &'a mut Streams,
+}
+
+impl<T: AsyncIO> Link for Parts<'_, T> {
+    type Error = T::Error;
+
+    async fn send(&mut self, data: &[u8], fd: Option<i32>) -> Result<(), Error<T::Error>> {
+        match fd {
+            Some(fd) => self.io.put_fd(data, fd).await,
+            None => self.io.put(data).await,
+        }
+    }
+
+    async fn recv<'b>(&mut self, buffer: &'b mut Buffer) -> Result<Message<'b>, Error<T::Error>> {
+        recv(self.io, buffer, self.max_message_size).await
+    }
+
+    async fn dispatch(&mut self, message: &Message<'_>) -> Result<(), Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        if let Some((id, reply)) = self.streams.dispatch(message) {
+            if reply {
+                let mut buffer = [0u8; 64];
+                let mut builder = MessageBuilder::new(
+                    &mut buffer,
+                    MessageHeader {
+                        version: MessageVersion::CURRENT,
+                        message: MessageType::STATUS,
+                        sequence: message.header.sequence,
+                        peer: message.header.peer,
+                    },
+                )?;
+                builder.put(MessageAttr::Status(0))?;
+                builder.put(MessageAttr::ObjId(id))?;
+                self.io.put(builder.into()).await?;
+            }
+            return Ok(());
+        }
+        warn!("Unexpected {:?}", message);
+        Ok(())
+    }
+}
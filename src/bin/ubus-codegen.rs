@@ -0,0 +1,58 @@
+use clap::Parser;
+use std::fs;
+use std::path::PathBuf;
+use std::process::exit;
+use std::time::Duration;
+use ubus::*;
+
+/// Timeout for reading signatures
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Parser)]
+#[command(
+    name = "ubus-codegen",
+    about = "Generate typed Rust clients for ubus objects, from their live signatures"
+)]
+struct Cli {
+    /// Set the unix domain socket to connect to
+    #[arg(short, long)]
+    socket: Option<PathBuf>,
+
+    /// Write the module to this file (default: stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Objects to generate clients for (e.g. network.interface)
+    #[arg(required = true)]
+    paths: Vec<String>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    if let Err(message) = run(&cli) {
+        eprintln!("{}", message);
+        exit(1);
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), String> {
+    let socket = cli.socket.clone().unwrap_or_else(default_socket);
+    let mut connection =
+        Connection::connect(&socket).map_err(|e| format!("Failed to connect to ubus: {}", e))?;
+    connection
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| format!("Failed to set timeout: {}", e))?;
+
+    let paths: Vec<&str> = cli.paths.iter().map(String::as_str).collect();
+    let module = generate_bindings(&mut connection, &paths).map_err(|e| match e {
+        Error::Status(4) => "Object not found".to_string(),
+        e => format!("Failed to read signatures: {}", e),
+    })?;
+    match &cli.output {
+        Some(path) => fs::write(path, module).map_err(|e| format!("Failed to write module: {}", e)),
+        None => {
+            print!("{}", module);
+            Ok(())
+        }
+    }
+}
@@ -1,27 +1,985 @@
-use std::path::Path;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::{ArgValueCompleter, CompletionCandidate};
+use clap_complete::env::{self as shells, EnvCompleter};
+use clap_complete::{CompleteEnv, Shell};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read};
+use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::{Duration, Instant};
+use ubus::*;
+
+/// Object ubusd uses for adding monitors
+const MONITOR_OBJECT: u32 = 3;
+
+/// Timeout used by commands which expect a reply, unless overridden
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// Colors (SGR parameters) of JSON object keys and each type of value, as jq uses
+const KEY_COLOR: &str = "1;34";
+const STRING_COLOR: &str = "32";
+const NUMBER_COLOR: &str = "36";
+const BOOL_COLOR: &str = "33";
+const NULL_COLOR: &str = "90";
+
+const STATUS_TIMEOUT: i32 = 7;
+const STATUS_UNKNOWN_ERROR: i32 = 9;
+const STATUS_CONNECTION_FAILED: i32 = 10;
+
+#[derive(Parser)]
+#[command(
+    name = "ubus",
+    about = "Command line client for the OpenWrt ubus daemon"
+)]
+struct Cli {
+    /// Set the unix domain socket to connect to
+    #[arg(short, long, global = true)]
+    socket: Option<PathBuf>,
+
+    /// Connect to a target defined in the configuration file
+    #[arg(short = 'T', long, global = true, value_name = "NAME")]
+    target: Option<String>,
+
+    /// Read settings from this file (default: ~/.config/ubus-rs/config.toml, if it exists)
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Set the timeout (in seconds) for a command to complete
+    #[arg(short, long, global = true)]
+    timeout: Option<u64>,
+
+    /// Use simplified output (for scripts)
+    #[arg(short = 'S', long, global = true)]
+    simple: bool,
+
+    /// More verbose output
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Color output (default: auto, when printing to a terminal unless NO_COLOR is set)
+    #[arg(long, global = true, value_name = "WHEN")]
+    color: Option<When>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List objects
+    List {
+        /// Only list objects matching this path (may end with a `*` wildcard)
+        #[arg(add = ArgValueCompleter::new(complete_path))]
+        path: Option<String>,
+    },
+    /// Call an object method
+    Call {
+        #[arg(add = ArgValueCompleter::new(complete_path))]
+        path: String,
+        method: String,
+        /// Arguments as a JSON object
+        message: Option<String>,
+        /// Repeat the call at this interval (e.g. 2s, 500ms, 1m), printing each result
+        #[arg(short, long, value_name = "INTERVAL", value_parser = parse_interval)]
+        watch: Option<Duration>,
+        /// Clear the screen before printing each result
+        #[arg(long, requires = "watch")]
+        clear: bool,
+        /// Only print what changed since the previous result (or since the JSON in FILE)
+        #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true)]
+        diff: Option<Option<PathBuf>>,
+    },
+    /// Listen for events
+    Listen {
+        /// Event patterns to listen for (default: all events)
+        patterns: Vec<String>,
+    },
+    /// Send an event
+    Send {
+        #[arg(value_name = "TYPE")]
+        ty: String,
+        /// Event data as a JSON object
+        message: Option<String>,
+    },
+    /// Subscribe to notifications from objects
+    Subscribe {
+        #[arg(required = true, add = ArgValueCompleter::new(complete_path))]
+        paths: Vec<String>,
+    },
+    /// Monitor ubus traffic
+    Monitor {
+        /// Only show messages of this type (e.g. invoke)
+        #[arg(short = 'm', long = "type")]
+        types: Vec<String>,
+        /// Only show messages received (r) or transmitted (t) by ubusd
+        #[arg(short = 'M', long, value_parser = ["r", "t"])]
+        direction: Option<String>,
+        /// Also capture the messages to a pcapng file (transmitted by ubusd as outbound)
+        #[arg(long, value_name = "FILE")]
+        pcap: Option<PathBuf>,
+    },
+    /// Wait for multiple objects to appear on ubus
+    #[command(name = "wait_for")]
+    WaitFor {
+        #[arg(required = true, add = ArgValueCompleter::new(complete_path))]
+        paths: Vec<String>,
+    },
+    /// Print a JSON Schema describing the arguments of an object's methods
+    Schema {
+        #[arg(add = ArgValueCompleter::new(complete_path))]
+        path: String,
+    },
+    /// Decode a raw dump of ubus messages (binary or hex) and print them
+    Decode {
+        /// File to read (default: stdin)
+        file: Option<PathBuf>,
+    },
+    /// Print a completion script for a shell
+    ///
+    /// The script runs ubus to complete each word, so object paths are completed from the bus
+    /// when it can be reached.
+    Completions {
+        shell: Shell,
+        /// Print a script which completes commands and options only, without running ubus
+        #[arg(long = "static")]
+        fixed: bool,
+    },
+}
+
+#[derive(Clone, Copy, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum When {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Defaults from the configuration file, which flags override
+///
+/// ```toml
+/// timeout = 10
+/// output = "simple"
+/// target = "office"
+///
+/// [targets.office]
+/// transport = "tcp"
+/// host = "192.168.1.1:8000"
+///
+/// [targets.lab]
+/// transport = "unix"
+/// socket = "/tmp/lab/ubus.sock"
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Socket to connect to when no target is used
+    socket: Option<PathBuf>,
+    /// Timeout (in seconds) for commands which wait for replies
+    timeout: Option<u64>,
+    output: Option<Format>,
+    color: Option<When>,
+    /// Target to connect to unless one is given with `--target`
+    target: Option<String>,
+    targets: BTreeMap<String, Target>,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    Pretty,
+    /// As `--simple`
+    Simple,
+}
+
+/// How to reach a ubusd, possibly on another router
+#[derive(Deserialize)]
+#[serde(tag = "transport", rename_all = "lowercase")]
+enum Target {
+    /// A local unix domain socket
+    Unix { socket: PathBuf },
+    /// A TCP bridge to ubusd, e.g. `socat TCP-LISTEN:8000,fork UNIX-CONNECT:/var/run/ubus/ubus.sock`
+    Tcp { host: String },
+}
+
+impl Config {
+    /// Read `file`, or the default file if it exists
+    fn load(file: Option<&Path>) -> Result<Self, Failure> {
+        let (path, required) = match (file, default_config()) {
+            (Some(file), _) => (file.to_path_buf(), true),
+            (None, Some(path)) => (path, false),
+            (None, None) => return Ok(Self::default()),
+        };
+        let failed =
+            |e: &dyn std::fmt::Display| Failure::Config(format!("{}: {}", path.display(), e));
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(e) => return Err(failed(&e)),
+        };
+        toml::from_str(&text).map_err(|e| failed(&e))
+    }
+
+    /// The target named by `--target` or the file, if any
+    fn target(&self, cli: &Cli) -> Result<Option<&Target>, Failure> {
+        match cli.target.as_ref().or(self.target.as_ref()) {
+            Some(name) => match self.targets.get(name) {
+                Some(target) => Ok(Some(target)),
+                None => Err(Failure::Config(format!("Unknown target '{}'", name))),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// `~/.config/ubus-rs/config.toml` (or under `$XDG_CONFIG_HOME`)
+fn default_config() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(base) => PathBuf::from(base),
+        None => Path::new(&std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("ubus-rs").join("config.toml"))
+}
+
+/// Read timeouts, for each transport the tool connects over
+trait ReadTimeout {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error<io::Error>>;
+}
+
+impl ReadTimeout for Connection<UnixStream> {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error<io::Error>> {
+        Connection::<UnixStream>::set_read_timeout(self, timeout)
+    }
+}
+
+impl ReadTimeout for Connection<StdIo<TcpStream>> {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), Error<io::Error>> {
+        Connection::<StdIo<TcpStream>>::set_read_timeout(self, timeout)
+    }
+}
+
+/// Reasons the tool can fail, along with the exit code reported for each
+enum Failure {
+    Config(String),
+    Connect(Error<io::Error>),
+    Command(Error<io::Error>),
+    Parse,
+    Input(io::Error),
+    Output(io::Error),
+}
+
+impl From<Error<io::Error>> for Failure {
+    fn from(e: Error<io::Error>) -> Self {
+        Failure::Command(e)
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        Failure::Command(e.into())
+    }
+}
 
 fn main() {
-    let socket = Path::new("/var/run/ubus/ubus.sock");
+    // Answers the completion scripts' requests (with COMPLETE set) and exits
+    CompleteEnv::with_factory(Cli::command).complete();
+    let cli = Cli::parse();
+    let result = Config::load(cli.config.as_deref()).and_then(|config| run(&cli, &config));
+    if let Err(failure) = result {
+        let (message, code) = match &failure {
+            Failure::Config(e) => (format!("Failed to read configuration: {}", e), -1),
+            Failure::Connect(e) => (format!("Failed to connect to ubus: {}", e), -1),
+            Failure::Parse => ("Failed to parse message data".into(), -1),
+            Failure::Input(e) => (format!("Failed to read input: {}", e), -1),
+            Failure::Output(e) => (format!("Failed to write output: {}", e), -1),
+            Failure::Command(e) => (format!("Command failed: {}", describe(e)), exit_code(e)),
+        };
+        if !cli.simple {
+            eprintln!("{}", message);
+        }
+        exit(code);
+    }
+}
+
+fn run(cli: &Cli, config: &Config) -> Result<(), Failure> {
+    let output = Output::new(cli, config);
+    match &cli.command {
+        Command::Decode { file } => return decode(file.as_deref(), output),
+        Command::Completions { shell, fixed } => return completions(*shell, *fixed),
+        _ => {}
+    }
+
+    // --socket overrides the target, which overrides the file's socket
+    let socket = match (&cli.socket, config.target(cli)?) {
+        (None, Some(Target::Tcp { host })) => {
+            let connection = Connection::connect_tcp(host.as_str()).map_err(Failure::Connect)?;
+            return command(cli, config, connection, output);
+        }
+        (Some(socket), _) | (None, Some(Target::Unix { socket })) => socket.clone(),
+        (None, None) => config.socket.clone().unwrap_or_else(default_socket),
+    };
+    let connection = Connection::connect(&socket).map_err(Failure::Connect)?;
+    command(cli, config, connection, output)
+}
+
+fn command<T: IO<Error = io::Error> + AsRawFd>(
+    cli: &Cli,
+    config: &Config,
+    mut connection: Connection<T>,
+    output: Output,
+) -> Result<(), Failure>
+where
+    Connection<T>: ReadTimeout,
+{
+    // Commands which just wait for replies time out by default, those which listen don't
+    let timeout = cli.timeout.or(config.timeout).unwrap_or(DEFAULT_TIMEOUT);
+    let request_timeout = Duration::from_secs(timeout);
+    let deadline = cli
+        .timeout
+        .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+    match &cli.command {
+        Command::List { path } => {
+            connection.set_read_timeout(Some(request_timeout))?;
+            list(&mut connection, path.as_deref(), cli.verbose, output)
+        }
+        Command::Call {
+            path,
+            method,
+            message,
+            watch,
+            clear,
+            diff,
+        } => {
+            connection.set_read_timeout(Some(request_timeout))?;
+            let message = parse_message(message.as_deref())?;
+            let mut buffer = vec![0u8; DEFAULT_BUFFER_SIZE];
+            let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+            args.push_json(&message)?;
+            let args = args.finish();
+            let mut previous = match diff {
+                Some(Some(file)) => Some(read_json(file)?),
+                _ => None,
+            };
+            let obj = connection.object_id(path).map_err(not_found)?;
+            loop {
+                let started = Instant::now();
+                // Replies are merged into one result, as the C tool does
+                let replies = connection.invoke_accumulate(obj, method, args)?;
+                let result = Value::Object(replies.table().to_json()?);
+                if *clear {
+                    // Clear the screen and move the cursor home
+                    print!("\x1b[2J\x1b[H");
+                }
+                if diff.is_none() {
+                    if !replies.is_empty() {
+                        print_json(&result, output);
+                    }
+                } else {
+                    match &previous {
+                        Some(previous) => print_diff("", Some(previous), Some(&result), output),
+                        None => print_json(&result, output),
+                    }
+                    previous = Some(result);
+                }
+                match watch {
+                    Some(interval) => {
+                        std::thread::sleep(interval.saturating_sub(started.elapsed()))
+                    }
+                    None => return Ok(()),
+                }
+            }
+        }
+        Command::Listen { patterns } => {
+            let handler = connection.event_handler(move |id, data| {
+                let mut event = Map::new();
+                event.insert(id.into(), table_to_json(data));
+                print_json(&Value::Object(event), output);
+            })?;
+            if patterns.is_empty() {
+                connection.register_event(&handler, "*")?;
+            }
+            for pattern in patterns {
+                connection.register_event(&handler, pattern)?;
+            }
+            run_until(&mut connection, deadline, || false).or_else(expected_timeout)
+        }
+        Command::Send { ty, message } => {
+            connection.set_read_timeout(Some(request_timeout))?;
+            let data = parse_message(message.as_deref())?;
+            let mut buffer = vec![0u8; DEFAULT_BUFFER_SIZE];
+            let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+            args.push_json(&data)?;
+            connection.send_event(ty, args.finish())?;
+            Ok(())
+        }
+        Command::Subscribe { paths } => {
+            let subscriber = connection.subscriber(move |ty, data| {
+                let mut notification = Map::new();
+                notification.insert(ty.into(), table_to_json(data));
+                print_json(&Value::Object(notification), output);
+            })?;
+            for path in paths {
+                let target = connection.object_id(path).map_err(not_found)?;
+                connection.subscribe(&subscriber, target)?;
+            }
+            run_until(&mut connection, deadline, || false).or_else(expected_timeout)
+        }
+        Command::Monitor {
+            types,
+            direction,
+            pcap,
+        } => {
+            let mut pcap = match pcap {
+                Some(path) => Some(create_pcap(path).map_err(Error::IO)?),
+                None => None,
+            };
+            connection.invoke(MONITOR_OBJECT, "add", &[], |_| {})?;
+            let result = monitor(
+                &mut connection,
+                deadline,
+                types,
+                direction.as_deref(),
+                pcap.as_mut(),
+            )
+            .or_else(expected_timeout);
+            if let Some(pcap) = pcap {
+                pcap.into_inner().map_err(Error::IO)?;
+            }
+            result
+        }
+        Command::WaitFor { paths } => {
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            connection.wait_for_objects(&paths, request_timeout)?;
+            Ok(())
+        }
+        Command::Schema { path } => {
+            connection.set_read_timeout(Some(request_timeout))?;
+            let schema = object_schema(&mut connection, path)?;
+            let schema: Value = serde_json::from_str(&schema).map_err(|_| Failure::Parse)?;
+            print_json(&schema, output);
+            Ok(())
+        }
+        Command::Decode { .. } | Command::Completions { .. } => {
+            unreachable!("Doesn't need a connection")
+        }
+    }
+}
 
-    let mut connection = match ubus::Connection::connect(socket) {
-        Ok(connection) => connection,
-        Err(err) => {
-            eprintln!("{}: Failed to open ubus socket. {}", socket.display(), err);
+fn list<T: IO<Error = io::Error>>(
+    connection: &mut Connection<T>,
+    path: Option<&str>,
+    verbose: bool,
+    output: Output,
+) -> Result<(), Failure> {
+    let on_object = |obj: ObjectResult| {
+        if verbose {
+            let id = format!("@{:08x}", obj.id);
+            println!(
+                "{} {}",
+                output.paint(KEY_COLOR, &format!("'{}'", obj.path)),
+                output.paint(NULL_COLOR, &id)
+            );
+        } else {
+            println!("{}", obj.path);
+        }
+    };
+    let on_signature = |sig: SignatureResult| {
+        if !verbose {
             return;
         }
+        let args: Vec<String> = sig
+            .args
+            .map(|(name, ty)| {
+                let name = output.paint(KEY_COLOR, &format!("\"{}\"", name));
+                let ty = output.paint(STRING_COLOR, &format!("\"{}\"", type_name(ty)));
+                format!("{}:{}", name, ty)
+            })
+            .collect();
+        let name = output.paint(KEY_COLOR, &format!("\"{}\"", sig.name));
+        println!("\t{}:{{{}}}", name, args.join(","));
     };
-    connection
-        .lookup(
-            |obj| {
-                println!("\n{:?}", obj);
-            },
-            |sig| {
-                print!("  {}(", sig.name);
-                for (name, ty) in sig.args {
-                    print!("{}: {:?}, ", name, ty);
+    match path {
+        Some(path) => connection.lookup_path(path, on_object, on_signature)?,
+        None => connection.lookup(on_object, on_signature)?,
+    }
+    Ok(())
+}
+
+fn monitor<T: IO<Error = io::Error>>(
+    connection: &mut Connection<T>,
+    deadline: Option<Instant>,
+    types: &[String],
+    direction: Option<&str>,
+    mut pcap: Option<&mut PcapWriter<io::BufWriter<File>>>,
+) -> Result<(), Error<io::Error>>
+where
+    Connection<T>: ReadTimeout,
+{
+    loop {
+        set_deadline(connection, deadline)?;
+        let message = connection.next_message()?;
+        if message.header.message != MessageType::MONITOR {
+            continue;
+        }
+
+        let monitored = match MonitorMessage::parse(message.blob.data) {
+            Ok(monitored) => monitored,
+            Err(_) => {
+                println!("Invalid monitor msg");
+                continue;
+            }
+        };
+
+        let name = MonitorType(monitored.message).to_string();
+        if !types.is_empty() && !types.iter().any(|t| t.eq_ignore_ascii_case(&name)) {
+            continue;
+        }
+        match direction {
+            Some("r") if monitored.send => continue,
+            Some("t") if !monitored.send => continue,
+            _ => {}
+        }
+
+        if let Some(pcap) = pcap.as_deref_mut() {
+            // Rebuild the message as it was on the wire
+            let header = MessageHeader {
+                version: MessageVersion::CURRENT,
+                message: monitored.message,
+                sequence: monitored.sequence.into(),
+                peer: monitored.peer.into(),
+            };
+            let tag = BlobTag::new(0, BlobTag::SIZE + monitored.attrs.len())?;
+            let mut packet = header.to_bytes().to_vec();
+            packet.extend_from_slice(&tag.to_bytes());
+            packet.extend_from_slice(monitored.attrs);
+            let direction = if monitored.send {
+                PcapDirection::Outbound
+            } else {
+                PcapDirection::Inbound
+            };
+            pcap.write(direction, &packet).map_err(Error::IO)?;
+        }
+
+        let mut line = String::new();
+        if write!(line, "{}", monitored).is_err() {
+            eprintln!("Skipping monitored message nested too deeply");
+            continue;
+        }
+        println!("{}", line);
+    }
+}
+
+fn create_pcap(path: &Path) -> Result<PcapWriter<io::BufWriter<File>>, io::Error> {
+    PcapWriter::new(io::BufWriter::new(File::create(path)?))
+}
+
+/// Reads messages from a raw dump
+struct SliceIo<'a>(&'a [u8]);
+impl IO for SliceIo<'_> {
+    type Error = io::Error;
+    fn put(&mut self, _data: &[u8]) -> Result<(), Error<io::Error>> {
+        Err(Error::InvalidData("Cannot write to a dump"))
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<io::Error>> {
+        if data.len() > self.0.len() {
+            return Err(Error::IO(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let (head, rest) = self.0.split_at(data.len());
+        data.copy_from_slice(head);
+        self.0 = rest;
+        Ok(())
+    }
+}
+
+fn completions(shell: Shell, fixed: bool) -> Result<(), Failure> {
+    let dynamic: Option<&dyn EnvCompleter> = match shell {
+        Shell::Bash => Some(&shells::Bash),
+        Shell::Elvish => Some(&shells::Elvish),
+        Shell::Fish => Some(&shells::Fish),
+        Shell::PowerShell => Some(&shells::Powershell),
+        Shell::Zsh => Some(&shells::Zsh),
+        _ => None,
+    };
+    let mut stdout = io::stdout();
+    match dynamic.filter(|_| !fixed) {
+        Some(dynamic) => {
+            // The script calls back into this binary, wherever it was installed
+            let completer = std::env::current_exe()
+                .map(|exe| exe.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| "ubus".into());
+            dynamic
+                .write_registration("COMPLETE", "ubus", "ubus", &completer, &mut stdout)
+                .map_err(Failure::Output)
+        }
+        None => {
+            clap_complete::generate(shell, &mut Cli::command(), "ubus", &mut stdout);
+            Ok(())
+        }
+    }
+}
+
+/// Complete an object path from the objects on the bus (none if it can't be reached quickly)
+fn complete_path(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(prefix) = current.to_str() else {
+        return Vec::new();
+    };
+    let Ok(mut connection) = Connection::connect(&default_socket()) else {
+        return Vec::new();
+    };
+    let mut paths = Vec::new();
+    let timeout = Some(Duration::from_secs(1));
+    if connection.set_read_timeout(timeout).is_ok() {
+        let pattern = format!("{}*", prefix);
+        let on_object = |obj: ObjectResult| paths.push(CompletionCandidate::new(obj.path));
+        let _ = connection.lookup_path(&pattern, on_object, |_| {});
+    }
+    paths
+}
+
+fn decode(file: Option<&Path>, output: Output) -> Result<(), Failure> {
+    let mut input = Vec::new();
+    match file {
+        Some(path) => File::open(path).and_then(|mut f| f.read_to_end(&mut input)),
+        None => io::stdin().read_to_end(&mut input),
+    }
+    .map_err(Failure::Input)?;
+
+    // Hex dumps are decoded first (ignoring whitespace)
+    let is_hex = |b: &u8| b.is_ascii_hexdigit() || b.is_ascii_whitespace();
+    if !input.is_empty() && input.iter().all(is_hex) {
+        let digits: Vec<u8> = input
+            .into_iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect();
+        if !digits.len().is_multiple_of(2) {
+            return Err(Failure::Parse);
+        }
+        input = digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect();
+    }
+
+    let mut io = SliceIo(&input);
+    let mut buffer = vec![0u8; DEFAULT_BUFFER_SIZE];
+    while !io.0.is_empty() {
+        let offset = input.len() - io.0.len();
+        let message = match Message::from_io(&mut io, &mut buffer) {
+            Ok(message) => message,
+            Err(Error::IO(_)) => {
+                println!("{:08x}: {} trailing bytes", offset, input.len() - offset);
+                return Err(Failure::Parse);
+            }
+            Err(e) => return Err(Failure::Command(e)),
+        };
+        println!(
+            "{:08x}: {:?} seq={} peer={:08x}",
+            offset, message.header.message, message.header.sequence, message.header.peer
+        );
+        let attrs = Value::Object(attrs_to_json(BlobIter::new(message.blob.data)));
+        print_json(&attrs, output);
+    }
+    Ok(())
+}
+
+/// Handle incoming messages until `done` returns true, or `deadline` passes
+fn run_until<T: IO<Error = io::Error>>(
+    connection: &mut Connection<T>,
+    deadline: Option<Instant>,
+    mut done: impl FnMut() -> bool,
+) -> Result<(), Error<io::Error>>
+where
+    Connection<T>: ReadTimeout,
+{
+    while !done() {
+        set_deadline(connection, deadline)?;
+        connection.handle_next_message()?;
+    }
+    Ok(())
+}
+
+fn set_deadline<T: IO<Error = io::Error>>(
+    connection: &mut Connection<T>,
+    deadline: Option<Instant>,
+) -> Result<(), Error<io::Error>>
+where
+    Connection<T>: ReadTimeout,
+{
+    if let Some(deadline) = deadline {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::Timeout);
+        }
+        connection.set_read_timeout(Some(remaining))?;
+    }
+    Ok(())
+}
+
+/// Running out of time is how listening commands finish
+fn expected_timeout(e: Error<io::Error>) -> Result<(), Failure> {
+    match e {
+        Error::Timeout => Ok(()),
+        e => Err(e.into()),
+    }
+}
+
+/// A failed lookup means the object doesn't exist
+fn not_found(e: Error<io::Error>) -> Error<io::Error> {
+    match e {
+        Error::InvalidData(_) => Error::Status(4),
+        e => e,
+    }
+}
+
+fn exit_code(e: &Error<io::Error>) -> i32 {
+    match e {
+        Error::Status(status) => *status,
+        Error::Timeout => STATUS_TIMEOUT,
+        Error::IO(_) => STATUS_CONNECTION_FAILED,
+        Error::InvalidData(_) => STATUS_UNKNOWN_ERROR,
+    }
+}
+
+fn describe(e: &Error<io::Error>) -> String {
+    let status = match e {
+        Error::Status(status) => *status,
+        e => return e.to_string(),
+    };
+    let message = match status {
+        0 => "Success",
+        1 => "Invalid command",
+        2 => "Invalid argument",
+        3 => "Method not found",
+        4 => "Not found",
+        5 => "No response",
+        6 => "Permission denied",
+        7 => "Request timed out",
+        8 => "Operation not supported",
+        10 => "Connection failed",
+        11 => "Out of memory",
+        12 => "Parsing message data failed",
+        13 => "System error",
+        _ => "Unknown error",
+    };
+    message.into()
+}
+
+fn type_name(ty: BlobMsgType) -> &'static str {
+    match ty {
+        BlobMsgType::ARRAY => "Array",
+        BlobMsgType::TABLE => "Table",
+        BlobMsgType::STRING => "String",
+        BlobMsgType::INT64 | BlobMsgType::INT32 | BlobMsgType::INT16 => "Integer",
+        BlobMsgType::INT8 => "Boolean",
+        BlobMsgType::DOUBLE => "Double",
+        _ => "(unknown)",
+    }
+}
+
+/// How results are printed
+#[derive(Clone, Copy)]
+struct Output {
+    /// Compact JSON on one line, for scripts
+    simple: bool,
+    /// Color JSON by type, and highlight `list -v`
+    color: bool,
+}
+
+impl Output {
+    fn new(cli: &Cli, config: &Config) -> Self {
+        let simple = cli.simple || config.output == Some(Format::Simple);
+        let color = match cli.color.or(config.color).unwrap_or(When::Auto) {
+            When::Always => true,
+            When::Never => false,
+            When::Auto => {
+                !simple && io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+        };
+        Self { simple, color }
+    }
+
+    /// `text` in the color `code`, if coloring
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+fn print_json(value: &Value, output: Output) {
+    match output {
+        Output { color: true, .. } => {
+            let mut json = String::new();
+            write_json(&mut json, value, output, (!output.simple).then_some(0));
+            println!("{}", json);
+        }
+        Output { simple: true, .. } => println!("{}", value),
+        Output { simple: false, .. } => println!("{:#}", value),
+    }
+}
+
+/// Write `value` as JSON colored by type, laid out as serde_json does
+///
+/// Indented for pretty output from `level`, or all on one line with `None`.
+fn write_json(out: &mut String, value: &Value, output: Output, level: Option<usize>) {
+    let newline = |out: &mut String, level: Option<usize>| {
+        if let Some(level) = level {
+            out.push('\n');
+            out.push_str(&"  ".repeat(level));
+        }
+    };
+    let inner = level.map(|level| level + 1);
+    let color = match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
                 }
-                std::println!(")");
-            },
-        )
-        .unwrap();
+                newline(out, inner);
+                write_json(out, item, output, inner);
+            }
+            newline(out, level);
+            out.push(']');
+            return;
+        }
+        Value::Object(fields) if !fields.is_empty() => {
+            out.push('{');
+            for (i, (key, item)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, inner);
+                let key = serde_json::to_string(key).unwrap();
+                out.push_str(&output.paint(KEY_COLOR, &key));
+                out.push_str(if level.is_some() { ": " } else { ":" });
+                write_json(out, item, output, inner);
+            }
+            newline(out, level);
+            out.push('}');
+            return;
+        }
+        Value::Array(_) | Value::Object(_) => {
+            out.push_str(&value.to_string());
+            return;
+        }
+        Value::String(_) => STRING_COLOR,
+        Value::Number(_) => NUMBER_COLOR,
+        Value::Bool(_) => BOOL_COLOR,
+        Value::Null => NULL_COLOR,
+    };
+    out.push_str(&output.paint(color, &value.to_string()));
+}
+
+/// Print the fields which differ between two results, by their dot separated paths
+///
+/// Added fields are shown with `+`, removed fields with `-`, and changed fields with `~`.
+fn print_diff(path: &str, old: Option<&Value>, new: Option<&Value>, output: Output) {
+    let child = |key: &dyn std::fmt::Display| match path {
+        "" => key.to_string(),
+        path => format!("{}.{}", path, key),
+    };
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            for (key, value) in old {
+                print_diff(&child(key), Some(value), new.get(key), output);
+            }
+            for (key, value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                print_diff(&child(key), None, Some(value), output);
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) => {
+            for i in 0..old.len().max(new.len()) {
+                print_diff(&child(&i), old.get(i), new.get(i), output);
+            }
+        }
+        (Some(old), Some(new)) if old == new => {}
+        (Some(old), Some(new)) => {
+            println!("{} {}: {} -> {}", output.paint("33", "~"), path, old, new)
+        }
+        (Some(old), None) => println!("{} {}: {}", output.paint("31", "-"), path, old),
+        (None, Some(new)) => println!("{} {}: {}", output.paint("32", "+"), path, new),
+        (None, None) => {}
+    }
+}
+
+/// Read a JSON result saved earlier (e.g. by `ubus call`)
+fn read_json(file: &Path) -> Result<Value, Failure> {
+    let mut input = Vec::new();
+    File::open(file)
+        .and_then(|mut f| f.read_to_end(&mut input))
+        .map_err(Failure::Input)?;
+    serde_json::from_slice(&input).map_err(|_| Failure::Parse)
+}
+
+/// Parse an interval such as `2s`, `500ms` or `1m` (plain numbers are seconds)
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(interval.len());
+    let (number, unit) = interval.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid interval '{}'", interval))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("Unknown interval unit '{}'", unit)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Parse a JSON object given on the command line
+fn parse_message(message: Option<&str>) -> Result<Value, Failure> {
+    match message.map(serde_json::from_str) {
+        None => Ok(Value::Object(Map::new())),
+        Some(Ok(object @ Value::Object(_))) => Ok(object),
+        Some(_) => Err(Failure::Parse),
+    }
+}
+
+/// A table as JSON to display, or why it couldn't be converted
+fn table_to_json(data: BlobIter<BlobMsg>) -> Value {
+    match data.to_json() {
+        Ok(object) => Value::Object(object),
+        Err(e) => Value::String(e.to_string()),
+    }
+}
+
+/// Describe the attributes of a ubus message (as seen by a monitor)
+fn attrs_to_json(attrs: BlobIter<MessageAttr>) -> Map<String, Value> {
+    let mut map = Map::new();
+    for attr in attrs {
+        let (name, value) = match attr {
+            MessageAttr::Status(v) => ("status", v.into()),
+            MessageAttr::ObjPath(v) => ("objpath", v.into()),
+            MessageAttr::ObjId(v) => ("objid", v.into()),
+            MessageAttr::Method(v) => ("method", v.into()),
+            MessageAttr::ObjType(v) => ("objtype", v.into()),
+            MessageAttr::Signature(v) => ("signature", table_to_json(v)),
+            MessageAttr::Data(v) => ("data", table_to_json(BlobIter::new(v))),
+            MessageAttr::Target(v) => ("target", v.into()),
+            MessageAttr::Active(v) => ("active", v.into()),
+            MessageAttr::NoReply(v) => ("no_reply", v.into()),
+            MessageAttr::User(v) => ("user", v.into()),
+            MessageAttr::Group(v) => ("group", v.into()),
+            _ => continue,
+        };
+        map.insert(name.into(), value);
+    }
+    map
 }
@@ -27,6 +27,12 @@ impl BlobTag {
         }
     }
 
+    /// Create a tag for an "extended" blob (one with a name header)
+    pub fn new_extended(id: u32, len: usize) -> Result<Self, Error> {
+        let tag = Self::new(id, len)?;
+        Ok(Self((u32::from(tag.0) | Self::EXTENDED_BIT).into()))
+    }
+
     /// Create BlobTag from a byte array
     pub fn from_bytes(bytes: [u8; Self::SIZE]) -> Self {
         unsafe { transmute(bytes) }
@@ -44,7 +50,7 @@ impl BlobTag {
         u32::from(self.0 & Self::LEN_MASK) as usize
     }
     /// Number of padding bytes between this blob and the next blob
-    fn padding(&self) -> usize {
+    pub(crate) fn padding(&self) -> usize {
         Self::ALIGNMENT.wrapping_sub(self.size()) & (Self::ALIGNMENT - 1)
     }
     /// Number of bytes to the next tag
@@ -73,6 +79,19 @@ impl core::fmt::Debug for BlobTag {
     }
 }
 
+/// Fill `payload` with `data` and a nul terminator
+fn str_with_nul(payload: &mut [u8], data: &str) {
+    payload[..data.len()].copy_from_slice(data.as_bytes());
+    payload[data.len()] = 0;
+}
+
+/// Fill `payload` from `iter` (any bytes it doesn't provide are left zeroed)
+fn copy_from_iter<'b>(payload: &mut [u8], iter: impl Iterator<Item = &'b u8>) {
+    for (dst, src) in payload.iter_mut().zip(iter) {
+        *dst = *src;
+    }
+}
+
 pub struct BlobBuilder<'a> {
     buffer: &'a mut [u8],
     offset: usize,
@@ -92,35 +111,99 @@ impl<'a> BlobBuilder<'a> {
     }
 
     pub fn push_str(&mut self, id: u32, data: &str) -> Result<(), Error> {
-        self.push_bytes(id, data.as_bytes().iter().chain([0u8].iter()))
+        self.push(id, None, data.len() + 1, |payload| {
+            str_with_nul(payload, data)
+        })
+    }
+
+    pub fn push_bytes<'b, I>(&mut self, id: u32, data: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'b u8>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = data.into_iter();
+        self.push(id, None, iter.len(), |payload| {
+            copy_from_iter(payload, iter)
+        })
+    }
+
+    pub fn push_named_u32(&mut self, id: u32, name: &str, data: u32) -> Result<(), Error> {
+        self.push_named_bytes(id, name, &data.to_be_bytes())
+    }
+
+    pub fn push_named_bool(&mut self, id: u32, name: &str, data: bool) -> Result<(), Error> {
+        self.push_named_bytes(id, name, if data { &[1] } else { &[0] })
+    }
+
+    pub fn push_named_str(&mut self, id: u32, name: &str, data: &str) -> Result<(), Error> {
+        self.push(id, Some(name.as_bytes()), data.len() + 1, |payload| {
+            str_with_nul(payload, data)
+        })
     }
 
-    pub fn push_bytes<'b>(
+    /// Push an "extended" blob, with `name` in its header (as blobmsg attributes have)
+    pub fn push_named_bytes<'b, I>(&mut self, id: u32, name: &str, data: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'b u8>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = data.into_iter();
+        self.push(id, Some(name.as_bytes()), iter.len(), |payload| {
+            copy_from_iter(payload, iter)
+        })
+    }
+
+    /// Push a blob with a `len` byte payload written by `fill`
+    ///
+    /// The whole blob's size is checked before anything is written, so a failed push leaves the
+    /// builder (and its buffer) untouched.
+    fn push(
         &mut self,
         id: u32,
-        data: impl IntoIterator<Item = &'b u8>,
+        name: Option<&[u8]>,
+        len: usize,
+        fill: impl FnOnce(&mut [u8]),
     ) -> Result<(), Error> {
-        let iter = data.into_iter();
-        let buffer = &mut self.buffer[self.offset..];
-
-        let mut len = BlobTag::SIZE;
-        for b in iter {
-            if len >= buffer.len() {
-                return Err(Error::InvalidData("BlobBuilder overflow!"));
-            }
-            buffer[len] = *b;
-            len += 1;
+        if name.is_some_and(|name| name.len() > u16::MAX as usize) {
+            return Err(Error::InvalidData("BlobBuilder overflow!"));
         }
+        let header = Self::header_len(name);
+        let tag = match name {
+            Some(_) => BlobTag::new_extended(id, header + len)?,
+            None => BlobTag::new(id, header + len)?,
+        };
+        let end = header + len;
+        let total = end + tag.padding();
+        let buffer = match self.buffer.get_mut(self.offset..) {
+            Some(buffer) if total <= buffer.len() => &mut buffer[..total],
+            _ => return Err(Error::InvalidData("BlobBuilder overflow!")),
+        };
 
-        let tag = BlobTag::new(id, len)?;
-        let pad = tag.padding();
-        buffer[..4].copy_from_slice(&tag.to_bytes());
-
-        self.offset += len + pad;
+        buffer.iter_mut().for_each(|b| *b = 0);
+        buffer[..BlobTag::SIZE].copy_from_slice(&tag.to_bytes());
+        if let Some(name) = name {
+            let name_start = BlobTag::SIZE + size_of::<u16>();
+            buffer[BlobTag::SIZE..name_start].copy_from_slice(&(name.len() as u16).to_be_bytes());
+            buffer[name_start..name_start + name.len()].copy_from_slice(name);
+        }
+        fill(&mut buffer[header..end]);
 
+        self.offset += total;
         Ok(())
     }
 
+    /// Size of a blob's tag plus (if it has a `name`) its name header
+    fn header_len(name: Option<&[u8]>) -> usize {
+        // Name header: u16 length, name, nul terminator, padding to alignment
+        let name_len = match name {
+            Some(name) => size_of::<u16>() + name.len() + 1,
+            None => return BlobTag::SIZE,
+        };
+        BlobTag::SIZE
+            + name_len
+            + (BlobTag::ALIGNMENT.wrapping_sub(name_len) & (BlobTag::ALIGNMENT - 1))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
@@ -130,11 +213,13 @@ impl<'a> BlobBuilder<'a> {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone)]
 pub struct Blob<'a> {
     pub tag: BlobTag,
     pub data: &'a [u8],
     pub name: Option<&'a str>,
+    /// The name as sent, kept even when it isn't valid UTF-8 (and so `name` is `None`)
+    pub raw_name: Option<&'a [u8]>,
 }
 
 impl<'a> Blob<'a> {
@@ -157,32 +242,75 @@ impl<'a> Blob<'a> {
         if tag.is_extended() {
             // Extended blobs have a name at the beginning
             // Get the string length
+            if data.len() < size_of::<u16>() {
+                return Err(Error::InvalidData("Extended header too short"));
+            }
             let (len_bytes, data) = data.split_at(size_of::<u16>());
             let ext_len = u16::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
-            // Get the string
+            // Get the string, followed by its nul terminator
+            if data.len() <= ext_len {
+                return Err(Error::InvalidData("Extended name longer than blob"));
+            }
             let (ext_bytes, data) = data.split_at(ext_len);
-            let name = str::from_utf8(ext_bytes).unwrap();
-            // Get the nul terminator (implicit)
+            // A badly encoded name only loses `name`, rather than the whole message
+            let name = str::from_utf8(ext_bytes).ok();
             let ext_len = ext_len + 1;
             let (terminator, data) = data.split_at(1);
-            valid_data!(terminator[0] == b'\0', "No extended name nul terminator");
+            if terminator[0] != b'\0' {
+                return Err(Error::InvalidData("No extended name nul terminator"));
+            }
             // Ensure the rest of the payload is aligned
             let ext_total = size_of::<u16>() + ext_len;
             let padding = BlobTag::ALIGNMENT.wrapping_sub(ext_total) & (BlobTag::ALIGNMENT - 1);
-            let data = &data[padding..];
+            let data = data.get(padding..).ok_or(Error::InvalidData(
+                "Extended header padding past end of blob",
+            ))?;
             Ok(Blob {
                 tag,
                 data,
-                name: Some(name),
+                name,
+                raw_name: Some(ext_bytes),
             })
         } else {
             Ok(Blob {
                 tag,
                 data,
                 name: None,
+                raw_name: None,
             })
         }
     }
+
+    /// The name, with any invalid UTF-8 replaced by U+FFFD
+    #[cfg(not(feature = "no_std"))]
+    pub fn name_lossy(&self) -> Option<std::borrow::Cow<'a, str>> {
+        self.raw_name.map(std::string::String::from_utf8_lossy)
+    }
+
+    /// Write this blob back out, as the same id, name and payload it was parsed from
+    ///
+    /// The result is identical to the wire form, apart from padding bytes (which are zeroed), so
+    /// parsed attributes can be forwarded or recorded without loss.
+    pub fn write_to(&self, builder: &mut BlobBuilder) -> Result<(), Error> {
+        let data = self.data;
+        builder.push(self.tag.id(), self.raw_name, data.len(), |payload| {
+            payload.copy_from_slice(data)
+        })
+    }
+
+    /// Number of bytes `write_to` writes (including padding to the next blob)
+    pub fn encoded_len(&self) -> usize {
+        let len = BlobBuilder::header_len(self.raw_name) + self.data.len();
+        len + (BlobTag::ALIGNMENT.wrapping_sub(len) & (BlobTag::ALIGNMENT - 1))
+    }
+
+    /// Like `write_to`, returning the bytes in a new buffer
+    #[cfg(not(feature = "no_std"))]
+    pub fn to_bytes(&self) -> Result<std::vec::Vec<u8>, Error> {
+        let mut buffer = std::vec![0u8; self.encoded_len()];
+        self.write_to(&mut BlobBuilder::from_bytes(&mut buffer))?;
+        Ok(buffer)
+    }
 }
 
 macro_rules! try_into_number {
@@ -192,7 +320,7 @@ macro_rules! try_into_number {
             type Error = Error;
             fn try_into(self) -> Result<$ty, Self::Error> {
                 let size = size_of::<$ty>();
-                if let Ok(bytes) = self.data[..size].try_into() {
+                if let Some(Ok(bytes)) = self.data.get(..size).map(TryInto::try_into) {
                     Ok(<$ty>::from_be_bytes(bytes))
                 } else {
                     Err(Error::InvalidData(stringify!("Blob wrong size for " $ty)))
@@ -242,6 +370,10 @@ impl<'a, T> BlobIter<'a, T> {
             _phantom: PhantomData,
         }
     }
+    /// Raw bytes of the blobs remaining in the iterator
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
 }
 impl<'a, T: TryFrom<Blob<'a>>> Iterator for BlobIter<'a, T> {
     type Item = T;
@@ -251,7 +383,7 @@ impl<'a, T: TryFrom<Blob<'a>>> Iterator for BlobIter<'a, T> {
         }
         if let Ok(blob) = Blob::from_bytes(self.data) {
             // Advance the internal pointer to the next tag
-            self.data = &self.data[blob.tag.next_tag()..];
+            self.data = self.data.get(blob.tag.next_tag()..).unwrap_or(&[]);
             if let Ok(blob) = blob.try_into() {
                 return Some(blob);
             }
@@ -259,6 +391,71 @@ impl<'a, T: TryFrom<Blob<'a>>> Iterator for BlobIter<'a, T> {
         None
     }
 }
+/// `{:#?}` is followed by a hexdump of the payload (see `HexDump`)
+impl core::fmt::Debug for Blob<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if !f.alternate() {
+            return f
+                .debug_struct("Blob")
+                .field("tag", &self.tag)
+                .field("data", &self.data)
+                .field("name", &self.name)
+                .field("raw_name", &self.raw_name)
+                .finish();
+        }
+        let mut s = f.debug_struct("Blob");
+        s.field("tag", &self.tag).field("name", &self.name);
+        if self.name.is_none() {
+            s.field("raw_name", &self.raw_name);
+        }
+        s.finish()?;
+        if !self.data.is_empty() {
+            write!(f, "\n{:?}", HexDump(self.data))?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats bytes as a hexdump, in lines of 16 bytes annotated with their offset and printable
+/// ASCII
+///
+/// ```text
+/// 0000  03 00 00 08 00 00 00 05  04 00 00 0b 73 74 61 74  |............stat|
+/// 0010  75 73 00 00                                       |us..|
+/// ```
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl core::fmt::Debug for HexDump<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        for (line, chunk) in self.0.chunks(16).enumerate() {
+            if line > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{:04x} ", line * 16)?;
+            for i in 0..16 {
+                if i == 8 {
+                    f.write_str(" ")?;
+                }
+                match chunk.get(i) {
+                    Some(byte) => write!(f, " {:02x}", byte)?,
+                    None => f.write_str("   ")?,
+                }
+            }
+            f.write_str("  |")?;
+            for &byte in chunk {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            f.write_str("|")?;
+        }
+        Ok(())
+    }
+}
+
 impl<T> core::fmt::Debug for BlobIter<'_, T> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(f, "BlobIter")
@@ -1,4 +1,4 @@
-use super::{Blob, BlobIter, Error};
+use super::{Blob, BlobIter, BlobTag, Error};
 use core::convert::{TryFrom, TryInto};
 use core::str;
 
@@ -14,6 +14,38 @@ values!(pub BlobMsgType(u32) {
     DOUBLE = 8,
 });
 
+/// How deeply tables and arrays may be nested inside a table before converting it fails
+///
+/// Used by the conversions which walk nested values (`to_owned`, `to_map`, and the CBOR and JSON
+/// ones), so a peer can't overflow the stack by sending deeply nested messages. Those taking a
+/// `max_depth` (such as `to_map_limit`) use that instead.
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// How deeply the value being converted is nested, and how deeply it may be
+#[derive(Clone, Copy)]
+pub(crate) struct Depth {
+    level: usize,
+    max: usize,
+}
+
+impl Depth {
+    /// The outermost table, which may have `max` levels of tables and arrays nested in it
+    pub(crate) fn new(max: usize) -> Self {
+        Self { level: 0, max }
+    }
+
+    /// Enter a table or array nested in this one, failing if that's deeper than allowed
+    pub(crate) fn nested(self) -> Result<Self, Error> {
+        if self.level >= self.max {
+            return Err(Error::InvalidData("Blobmsg nested too deeply"));
+        }
+        Ok(Self {
+            level: self.level + 1,
+            max: self.max,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum BlobMsgData<'a> {
     Array(BlobIter<'a, BlobMsg<'a>>),
@@ -29,6 +61,8 @@ pub enum BlobMsgData<'a> {
 
 pub struct BlobMsg<'a> {
     pub name: Option<&'a str>,
+    /// The name as sent, kept even when it isn't valid UTF-8 (and so `name` is `None`)
+    pub raw_name: Option<&'a [u8]>,
     pub data: BlobMsgData<'a>,
 }
 
@@ -43,20 +77,329 @@ impl<'a> TryFrom<Blob<'a>> for BlobMsg<'a> {
             BlobMsgType::INT32 => BlobMsgData::Int32(blob.try_into()?),
             BlobMsgType::INT16 => BlobMsgData::Int16(blob.try_into()?),
             BlobMsgType::INT8 => BlobMsgData::Int8(blob.try_into()?),
+            BlobMsgType::DOUBLE => BlobMsgData::Double(blob.try_into()?),
             id => BlobMsgData::Unknown(id, blob.data),
         };
         Ok(BlobMsg {
             name: blob.name,
+            raw_name: blob.raw_name,
             data,
         })
     }
 }
+impl<'a> BlobMsg<'a> {
+    /// The name, with any invalid UTF-8 replaced by U+FFFD
+    #[cfg(not(feature = "no_std"))]
+    pub fn name_lossy(&self) -> Option<std::borrow::Cow<'a, str>> {
+        self.raw_name.map(std::string::String::from_utf8_lossy)
+    }
+
+    /// Find a nested value by a dot separated path of table keys and array indexes
+    ///
+    /// For example `"ipv4-address.0.address"` finds the `address` of the first entry of the
+    /// `ipv4-address` array in this table. Returns `None` if any step isn't found.
+    pub fn path(&self, path: &str) -> Option<BlobMsg<'a>> {
+        let mut keys = path.split('.');
+        let first = self.child(keys.next()?)?;
+        keys.try_fold(first, |value, key| value.child(key))
+    }
+
+    /// The value named `key` in a table, or at index `key` in an array
+    fn child(&self, key: &str) -> Option<BlobMsg<'a>> {
+        match &self.data {
+            BlobMsgData::Table(items) => {
+                BlobIter::<BlobMsg>::new(items.as_bytes()).find(|item| item.name == Some(key))
+            }
+            BlobMsgData::Array(items) => {
+                let index = key.parse().ok()?;
+                BlobIter::<BlobMsg>::new(items.as_bytes()).nth(index)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a> BlobIter<'a, BlobMsg<'a>> {
+    /// Like `BlobMsg::path`, treating the remaining attributes (such as a reply) as a table
+    pub fn path(&self, path: &str) -> Option<BlobMsg<'a>> {
+        let table = BlobMsg {
+            name: None,
+            raw_name: None,
+            data: BlobMsgData::Table(BlobIter::new(self.as_bytes())),
+        };
+        table.path(path)
+    }
+}
+
 impl core::fmt::Debug for BlobMsg<'_> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         if let Some(name) = self.name {
             write!(f, "BlobMsg({}:{:?})", name, self.data)
+        } else if let Some(name) = self.raw_name {
+            write!(f, "BlobMsg({}:{:?})", name.escape_ascii(), self.data)
         } else {
             write!(f, "BlobMsg({:?})", self.data)
         }
     }
 }
+
+/// Builder for blobmsg payloads (named attributes, such as method arguments)
+pub struct BlobMsgBuilder<'a> {
+    buffer: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> BlobMsgBuilder<'a> {
+    pub fn from_bytes(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, offset: 0 }
+    }
+
+    pub fn push_string(&mut self, name: &str, data: &str) -> Result<(), Error> {
+        self.push(BlobMsgType::STRING, name, |b| {
+            b.put(data.as_bytes())?;
+            b.put(&[0])
+        })
+    }
+
+    pub fn push_int64(&mut self, name: &str, data: i64) -> Result<(), Error> {
+        self.push(BlobMsgType::INT64, name, |b| b.put(&data.to_be_bytes()))
+    }
+
+    pub fn push_int32(&mut self, name: &str, data: i32) -> Result<(), Error> {
+        self.push(BlobMsgType::INT32, name, |b| b.put(&data.to_be_bytes()))
+    }
+
+    pub fn push_int16(&mut self, name: &str, data: i16) -> Result<(), Error> {
+        self.push(BlobMsgType::INT16, name, |b| b.put(&data.to_be_bytes()))
+    }
+
+    pub fn push_int8(&mut self, name: &str, data: i8) -> Result<(), Error> {
+        self.push(BlobMsgType::INT8, name, |b| b.put(&data.to_be_bytes()))
+    }
+
+    /// Booleans are encoded as INT8, as libubox does
+    pub fn push_bool(&mut self, name: &str, data: bool) -> Result<(), Error> {
+        self.push_int8(name, data as i8)
+    }
+
+    pub fn push_double(&mut self, name: &str, data: f64) -> Result<(), Error> {
+        self.push(BlobMsgType::DOUBLE, name, |b| b.put(&data.to_be_bytes()))
+    }
+
+    /// Push a nested table, whose contents are built by `f`
+    pub fn push_table(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut BlobMsgBuilder) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.push(BlobMsgType::TABLE, name, f)
+    }
+
+    /// Push a nested array, whose contents are built by `f` (using empty names)
+    pub fn push_array(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut BlobMsgBuilder) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.push(BlobMsgType::ARRAY, name, f)
+    }
+
+    /// Push an UNSPEC attribute, with no value (JSON's null)
+    pub fn push_null(&mut self, name: &str) -> Result<(), Error> {
+        self.push(BlobMsgType::UNSPEC, name, |_| Ok(()))
+    }
+
+    /// Append already encoded blobmsg attributes
+    pub fn push_raw(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.put(data)
+    }
+
+    /// Push any value which knows its blobmsg type (see `PushBlobMsg`)
+    pub fn push_value<V: PushBlobMsg + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &V,
+    ) -> Result<(), Error> {
+        value.push_blobmsg(self, name)
+    }
+
+    fn push(
+        &mut self,
+        ty: BlobMsgType,
+        name: &str,
+        f: impl FnOnce(&mut BlobMsgBuilder) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let buffer = &mut self.buffer[self.offset..];
+
+        // Name header: u16 length, name, nul terminator, padding to alignment
+        let name_len = 2 + name.len() + 1;
+        let header = BlobTag::SIZE + name_len + (BlobTag::SIZE.wrapping_sub(name_len) & 3);
+        if header > buffer.len() || name.len() > u16::MAX as usize {
+            return Err(Error::InvalidData("BlobMsgBuilder overflow!"));
+        }
+        buffer[BlobTag::SIZE..header]
+            .iter_mut()
+            .for_each(|b| *b = 0);
+        buffer[BlobTag::SIZE..BlobTag::SIZE + 2]
+            .copy_from_slice(&(name.len() as u16).to_be_bytes());
+        buffer[BlobTag::SIZE + 2..BlobTag::SIZE + 2 + name.len()].copy_from_slice(name.as_bytes());
+
+        // Payload
+        let mut inner = BlobMsgBuilder::from_bytes(&mut buffer[header..]);
+        f(&mut inner)?;
+        let len = header + inner.offset;
+
+        let tag = BlobTag::new_extended(ty.value(), len)?;
+        let pad = tag.padding();
+        if len + pad > buffer.len() {
+            return Err(Error::InvalidData("BlobMsgBuilder overflow!"));
+        }
+        buffer[..BlobTag::SIZE].copy_from_slice(&tag.to_bytes());
+        buffer[len..len + pad].iter_mut().for_each(|b| *b = 0);
+
+        self.offset += len + pad;
+        Ok(())
+    }
+
+    fn put(&mut self, data: &[u8]) -> Result<(), Error> {
+        let end = self.offset + data.len();
+        if end > self.buffer.len() {
+            return Err(Error::InvalidData("BlobMsgBuilder overflow!"));
+        }
+        self.buffer[self.offset..end].copy_from_slice(data);
+        self.offset = end;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.offset
+    }
+
+    pub fn finish(self) -> &'a [u8] {
+        &self.buffer[..self.offset]
+    }
+}
+impl<'a> From<BlobMsgBuilder<'a>> for &'a [u8] {
+    fn from(val: BlobMsgBuilder<'a>) -> Self {
+        val.finish()
+    }
+}
+
+/// Values which can be pushed into a `BlobMsgBuilder` as a named attribute
+///
+/// Used by `BlobMsgBuilder::push_value` and the `blobmsg!` macro. Integers keep their width,
+/// apart from unsigned ones which widen to the next signed type.
+pub trait PushBlobMsg {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error>;
+}
+
+impl<T: PushBlobMsg + ?Sized> PushBlobMsg for &T {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+        (**self).push_blobmsg(builder, name)
+    }
+}
+
+impl PushBlobMsg for str {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+        builder.push_string(name, self)
+    }
+}
+
+impl PushBlobMsg for bool {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+        builder.push_bool(name, *self)
+    }
+}
+
+impl PushBlobMsg for f64 {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+        builder.push_double(name, *self)
+    }
+}
+
+macro_rules! push_blobmsg_int {
+    ( $( $ty:ty => $push:ident as $as:ty , )* ) => { $(
+        impl PushBlobMsg for $ty {
+            fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+                builder.$push(name, *self as $as)
+            }
+        }
+    )* };
+}
+push_blobmsg_int!(
+    i8 => push_int8 as i8,
+    i16 => push_int16 as i16,
+    i32 => push_int32 as i32,
+    i64 => push_int64 as i64,
+    u8 => push_int16 as i16,
+    u16 => push_int32 as i32,
+    u32 => push_int64 as i64,
+);
+
+/// Build a blobmsg table from a JSON-like literal
+///
+/// With a buffer, encodes the table into it, returning `Result<&[u8], Error>`:
+///
+/// ```
+/// # use ubus::*;
+/// let mut buffer = [0u8; 128];
+/// let channel = 11;
+/// let args = blobmsg!(&mut buffer => { "ssid": "foo", "channel": channel, "keys": ["a", "b"] });
+/// assert!(args.is_ok());
+/// ```
+///
+/// Without one, builds an owned `BlobMsgValue::Table` (not available with `no_std`):
+///
+/// ```
+/// # use ubus::*;
+/// let value = blobmsg!({ "ssid": "foo", "radio": { "channel": 11, "up": true } });
+/// ```
+///
+/// Keys are string literals, and values are nested `{...}` tables, `[...]` arrays, or any
+/// expression implementing `PushBlobMsg` (or `Into<BlobMsgValue>` for owned tables).
+#[macro_export]
+macro_rules! blobmsg {
+    ({ $($tt:tt)* }) => {
+        $crate::__blobmsg_value!({ $($tt)* })
+    };
+    ($buffer:expr => { $($tt:tt)* }) => {{
+        let mut builder = $crate::BlobMsgBuilder::from_bytes($buffer);
+        let result: Result<(), $crate::Error> = $crate::__blobmsg_push!(@table builder ($($tt)*));
+        result.map(|()| builder.finish())
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __blobmsg_push {
+    (@table $b:ident ()) => { Ok(()) };
+    (@table $b:ident ($key:literal : { $($inner:tt)* } $(, $($rest:tt)*)?)) => {
+        $b.push_table($key, |b| $crate::__blobmsg_push!(@table b ($($inner)*)))
+            .and_then(|()| $crate::__blobmsg_push!(@table $b ($($($rest)*)?)))
+    };
+    (@table $b:ident ($key:literal : [ $($inner:tt)* ] $(, $($rest:tt)*)?)) => {
+        $b.push_array($key, |b| $crate::__blobmsg_push!(@array b ($($inner)*)))
+            .and_then(|()| $crate::__blobmsg_push!(@table $b ($($($rest)*)?)))
+    };
+    (@table $b:ident ($key:literal : $value:expr $(, $($rest:tt)*)?)) => {
+        $b.push_value($key, &$value)
+            .and_then(|()| $crate::__blobmsg_push!(@table $b ($($($rest)*)?)))
+    };
+    (@array $b:ident ()) => { Ok(()) };
+    (@array $b:ident ({ $($inner:tt)* } $(, $($rest:tt)*)?)) => {
+        $b.push_table("", |b| $crate::__blobmsg_push!(@table b ($($inner)*)))
+            .and_then(|()| $crate::__blobmsg_push!(@array $b ($($($rest)*)?)))
+    };
+    (@array $b:ident ([ $($inner:tt)* ] $(, $($rest:tt)*)?)) => {
+        $b.push_array("", |b| $crate::__blobmsg_push!(@array b ($($inner)*)))
+            .and_then(|()| $crate::__blobmsg_push!(@array $b ($($($rest)*)?)))
+    };
+    (@array $b:ident ($value:expr $(, $($rest:tt)*)?)) => {
+        $b.push_value("", &$value)
+            .and_then(|()| $crate::__blobmsg_push!(@array $b ($($($rest)*)?)))
+    };
+}
@@ -0,0 +1,478 @@
+use crate::split::lock;
+use crate::*;
+use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::string::{String, ToString};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::vec::Vec;
+
+/// Ids below this are reserved for ubusd's built in objects
+const FIRST_ID: u32 = 0x100;
+
+const STATUS_INVALID_COMMAND: i32 = 1;
+const STATUS_INVALID_ARGUMENT: i32 = 2;
+const STATUS_METHOD_NOT_FOUND: i32 = 3;
+const STATUS_NOT_FOUND: i32 = 4;
+const STATUS_NO_RESPONSE: i32 = 5;
+const STATUS_PERMISSION_DENIED: i32 = 6;
+const STATUS_NOT_SUPPORTED: i32 = 8;
+
+struct BrokerObject {
+    owner: u32,
+    path: Option<String>,
+    ty: u32,
+    signature: Vec<u8>,
+}
+
+struct EventListener {
+    object: u32,
+    owner: u32,
+    pattern: String,
+}
+
+type Writer = Box<dyn IO<Error = std::io::Error> + Send>;
+
+/// Write halves of the connected clients' transports
+#[derive(Default)]
+struct Clients(BTreeMap<u32, Writer>);
+
+impl Clients {
+    /// Send a message to a client (ignored if the client has gone away)
+    fn send<'a>(
+        &mut self,
+        to: u32,
+        message: MessageType,
+        sequence: u16,
+        peer: u32,
+        attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+    ) -> Result<(), Error<std::io::Error>> {
+        match self.0.get_mut(&to) {
+            Some(io) => send_message(io.as_mut(), message, sequence, peer, attrs),
+            None => Ok(()),
+        }
+    }
+
+    /// Pass a message on unchanged, other than its peer
+    fn forward(
+        &mut self,
+        to: u32,
+        message: &Message,
+        peer: u32,
+    ) -> Result<(), Error<std::io::Error>> {
+        let io = match self.0.get_mut(&to) {
+            Some(io) => io,
+            None => return Ok(()),
+        };
+        let header = MessageHeader {
+            peer: peer.into(),
+            ..message.header
+        };
+        let mut data = Vec::with_capacity(MessageHeader::SIZE + message.blob.tag.size());
+        data.extend_from_slice(&header.to_bytes());
+        data.extend_from_slice(&message.blob.tag.to_bytes());
+        data.extend_from_slice(message.blob.data);
+        io.put(&data)
+    }
+}
+
+/// Attributes of a message from a client
+#[derive(Default)]
+struct Attrs<'a> {
+    path: Option<&'a str>,
+    id: Option<u32>,
+    ty: Option<u32>,
+    method: Option<&'a str>,
+    signature: &'a [u8],
+    data: &'a [u8],
+    no_reply: bool,
+}
+
+impl<'a> Attrs<'a> {
+    fn parse(data: &'a [u8]) -> Self {
+        let mut attrs = Self::default();
+        for attr in BlobIter::<MessageAttr>::new(data) {
+            match attr {
+                MessageAttr::ObjPath(val) => attrs.path = Some(val),
+                MessageAttr::ObjId(val) => attrs.id = Some(val),
+                MessageAttr::ObjType(val) => attrs.ty = Some(val),
+                MessageAttr::Method(val) => attrs.method = Some(val),
+                MessageAttr::Signature(val) => attrs.signature = val.as_bytes(),
+                MessageAttr::Data(val) => attrs.data = val,
+                MessageAttr::NoReply(val) => attrs.no_reply = val,
+                _ => continue,
+            }
+        }
+        attrs
+    }
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u32,
+    clients: Clients,
+    objects: BTreeMap<u32, BrokerObject>,
+    listeners: Vec<EventListener>,
+}
+
+impl State {
+    /// Allocate an id for a client or object
+    fn alloc_id(&mut self) -> u32 {
+        loop {
+            self.next_id = self.next_id.wrapping_add(1).max(FIRST_ID);
+            let id = self.next_id;
+            if !self.clients.0.contains_key(&id) && !self.objects.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+
+    fn handle(&mut self, client: u32, message: &Message) -> Result<(), Error<std::io::Error>> {
+        let sequence = message.header.sequence.into();
+        let peer = message.header.peer.into();
+        let attrs = Attrs::parse(message.blob.data);
+        let status = match message.header.message {
+            MessageType::HELLO => return Ok(()),
+            MessageType::PING => 0,
+            MessageType::ADD_OBJECT => self.add_object(client, sequence, &attrs)?,
+            MessageType::REMOVE_OBJECT => self.remove_object(client, sequence, &attrs)?,
+            MessageType::LOOKUP => self.lookup(client, sequence, &attrs)?,
+            MessageType::INVOKE => match self.invoke(client, sequence, &attrs)? {
+                Some(status) => status,
+                // The object's owner replies
+                None => return Ok(()),
+            },
+            // Replies from an object's owner go back to the caller
+            MessageType::STATUS | MessageType::DATA => {
+                let _ = self.clients.forward(peer, message, client);
+                return Ok(());
+            }
+            MessageType::SUBSCRIBE | MessageType::UNSUBSCRIBE | MessageType::NOTIFY => {
+                STATUS_NOT_SUPPORTED
+            }
+            MessageType::MONITOR => STATUS_NOT_SUPPORTED,
+            _ => STATUS_INVALID_COMMAND,
+        };
+        let attrs = [MessageAttr::Status(status)];
+        self.clients
+            .send(client, MessageType::STATUS, sequence, peer, attrs)
+    }
+
+    fn add_object(
+        &mut self,
+        client: u32,
+        sequence: u16,
+        attrs: &Attrs,
+    ) -> Result<i32, Error<std::io::Error>> {
+        if let Some(path) = attrs.path {
+            if self
+                .objects
+                .values()
+                .any(|o| o.path.as_deref() == Some(path))
+            {
+                return Ok(STATUS_INVALID_ARGUMENT);
+            }
+        }
+        let id = self.alloc_id();
+        let ty = attrs.ty.unwrap_or(0);
+        let object = BrokerObject {
+            owner: client,
+            path: attrs.path.map(ToString::to_string),
+            ty,
+            signature: attrs.signature.to_vec(),
+        };
+        self.objects.insert(id, object);
+
+        let reply = [MessageAttr::ObjId(id), MessageAttr::ObjType(ty)];
+        self.clients
+            .send(client, MessageType::DATA, sequence, client, reply)?;
+        if let Some(path) = attrs.path {
+            self.object_event(client, "ubus.object.add", id, path)?;
+        }
+        Ok(0)
+    }
+
+    fn remove_object(
+        &mut self,
+        client: u32,
+        sequence: u16,
+        attrs: &Attrs,
+    ) -> Result<i32, Error<std::io::Error>> {
+        let id = match attrs.id {
+            Some(id) => id,
+            None => return Ok(STATUS_INVALID_ARGUMENT),
+        };
+        match self.objects.get(&id) {
+            None => return Ok(STATUS_NOT_FOUND),
+            Some(object) if object.owner != client => return Ok(STATUS_PERMISSION_DENIED),
+            Some(_) => {}
+        }
+        let object = self.objects.remove(&id).unwrap();
+        self.listeners.retain(|l| l.object != id);
+
+        let reply = [MessageAttr::ObjId(id)];
+        self.clients
+            .send(client, MessageType::DATA, sequence, client, reply)?;
+        if let Some(path) = &object.path {
+            self.object_event(client, "ubus.object.remove", id, path)?;
+        }
+        Ok(0)
+    }
+
+    fn lookup(
+        &mut self,
+        client: u32,
+        sequence: u16,
+        attrs: &Attrs,
+    ) -> Result<i32, Error<std::io::Error>> {
+        let mut found = false;
+        for (id, object) in &self.objects {
+            // Only objects with a path can be looked up
+            let path = match (&object.path, attrs.path) {
+                (Some(path), Some(pattern)) if event_matches(pattern, path) => path,
+                (Some(path), None) => path,
+                _ => continue,
+            };
+            found = true;
+            let reply = [
+                MessageAttr::ObjPath(path),
+                MessageAttr::ObjId(*id),
+                MessageAttr::ObjType(object.ty),
+                MessageAttr::Signature(BlobIter::new(&object.signature)),
+            ];
+            self.clients
+                .send(client, MessageType::DATA, sequence, client, reply)?;
+        }
+        match attrs.path {
+            Some(path) if !found && !path.ends_with('*') => Ok(STATUS_NOT_FOUND),
+            _ => Ok(0),
+        }
+    }
+
+    fn invoke(
+        &mut self,
+        client: u32,
+        sequence: u16,
+        attrs: &Attrs,
+    ) -> Result<Option<i32>, Error<std::io::Error>> {
+        let (id, method) = match (attrs.id, attrs.method) {
+            (Some(id), Some(method)) => (id, method),
+            _ => return Ok(Some(STATUS_INVALID_ARGUMENT)),
+        };
+        if id == EVENT_OBJECT {
+            return self.event_method(client, method, attrs.data).map(Some);
+        }
+        let owner = match self.objects.get(&id) {
+            Some(object) => object.owner,
+            None => return Ok(Some(STATUS_NOT_FOUND)),
+        };
+
+        let forward = [
+            MessageAttr::ObjId(id),
+            MessageAttr::Method(method),
+            MessageAttr::Data(attrs.data),
+        ];
+        let no_reply = Some(MessageAttr::NoReply(true)).filter(|_| attrs.no_reply);
+        let forward = IntoIterator::into_iter(forward).chain(no_reply);
+        match self
+            .clients
+            .send(owner, MessageType::INVOKE, sequence, client, forward)
+        {
+            Ok(()) => Ok(None),
+            Err(_) => Ok(Some(STATUS_NO_RESPONSE)),
+        }
+    }
+
+    /// Methods of the built in event object
+    fn event_method(
+        &mut self,
+        client: u32,
+        method: &str,
+        args: &[u8],
+    ) -> Result<i32, Error<std::io::Error>> {
+        let mut object = None;
+        let mut pattern = None;
+        let mut id = None;
+        let mut data: &[u8] = &[];
+        for arg in BlobIter::<BlobMsg>::new(args) {
+            match (arg.name, arg.data) {
+                (Some("object"), BlobMsgData::Int32(val)) => object = Some(val as u32),
+                (Some("pattern"), BlobMsgData::String(val)) => pattern = Some(val),
+                (Some("id"), BlobMsgData::String(val)) => id = Some(val),
+                (Some("data"), BlobMsgData::Table(val)) => data = val.as_bytes(),
+                _ => continue,
+            }
+        }
+
+        match method {
+            "register" => {
+                let (object, pattern) = match (object, pattern) {
+                    (Some(object), Some(pattern)) => (object, pattern),
+                    _ => return Ok(STATUS_INVALID_ARGUMENT),
+                };
+                match self.objects.get(&object) {
+                    Some(o) if o.owner == client => {}
+                    Some(_) => return Ok(STATUS_PERMISSION_DENIED),
+                    None => return Ok(STATUS_NOT_FOUND),
+                }
+                self.listeners.push(EventListener {
+                    object,
+                    owner: client,
+                    pattern: pattern.to_string(),
+                });
+                Ok(0)
+            }
+            "send" => match id {
+                Some(id) => self.event(client, id, data).map(|_| 0),
+                None => Ok(STATUS_INVALID_ARGUMENT),
+            },
+            _ => Ok(STATUS_METHOD_NOT_FOUND),
+        }
+    }
+
+    /// Send an event about an object being added or removed
+    fn object_event(
+        &mut self,
+        client: u32,
+        event: &str,
+        id: u32,
+        path: &str,
+    ) -> Result<(), Error<std::io::Error>> {
+        // Room for the path, and the id with both attribute headers
+        let mut buffer = std::vec![0u8; path.len() + 64];
+        let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+        data.push_int32("id", id as i32)?;
+        data.push_string("path", path)?;
+        self.event(client, event, data.finish())
+    }
+
+    /// Deliver an event to every listener with a matching pattern
+    fn event(&mut self, client: u32, id: &str, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        let mut targets: Vec<(u32, u32)> = self
+            .listeners
+            .iter()
+            .filter(|l| event_matches(&l.pattern, id))
+            .map(|l| (l.owner, l.object))
+            .collect();
+        // Objects registered with several matching patterns get the event once
+        targets.sort_unstable();
+        targets.dedup();
+        for (owner, object) in targets {
+            let attrs = [
+                MessageAttr::ObjId(object),
+                MessageAttr::Method(id),
+                MessageAttr::Data(data),
+                MessageAttr::NoReply(true),
+            ];
+            // A listener which went away doesn't affect the sender
+            let _ = self
+                .clients
+                .send(owner, MessageType::INVOKE, 0, client, attrs);
+        }
+        Ok(())
+    }
+
+    /// Forget a client, along with its objects and event registrations
+    fn disconnect(&mut self, client: u32) {
+        self.clients.0.remove(&client);
+        self.listeners.retain(|l| l.owner != client);
+        let removed: Vec<u32> = self
+            .objects
+            .iter()
+            .filter(|(_, o)| o.owner == client)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in removed {
+            let object = self.objects.remove(&id).unwrap();
+            if let Some(path) = &object.path {
+                let _ = self.object_event(client, "ubus.object.remove", id, path);
+            }
+        }
+    }
+}
+
+/// A minimal ubusd, for tests and development environments without OpenWrt
+///
+/// Supports client ids, registering objects, LOOKUP, routing INVOKE between clients and events
+/// (through the `register` and `send` methods of object 1). Subscriptions and monitoring are not
+/// supported, and there is no access control.
+#[derive(Clone, Default)]
+pub struct Broker {
+    state: Arc<Mutex<State>>,
+}
+
+impl Broker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept clients from `listener`, serving each on its own thread
+    pub fn run(&self, listener: UnixListener) -> Result<(), Error<std::io::Error>> {
+        for stream in listener.incoming() {
+            let stream = stream.map_err(Error::IO)?;
+            let broker = self.clone();
+            thread::spawn(move || broker.serve(stream));
+        }
+        Ok(())
+    }
+
+    /// Connect a new client to the broker, served on its own thread
+    pub fn connect(&self) -> Result<Connection<UnixStream>, Error<std::io::Error>> {
+        let (client, server) = UnixStream::pair().map_err(Error::IO)?;
+        let broker = self.clone();
+        thread::spawn(move || broker.serve(server));
+        Connection::new(client)
+    }
+
+    /// Connect a new client over an in-memory `LoopbackIo`, served on its own thread
+    pub fn connect_loopback(&self) -> Result<Connection<LoopbackIo>, Error<std::io::Error>> {
+        let (client, server) = LoopbackIo::pair();
+        let broker = self.clone();
+        thread::spawn(move || broker.serve_io(server.clone(), server));
+        Connection::new(client)
+    }
+
+    /// Serve a single client until it disconnects (blocking!)
+    pub fn serve(&self, stream: UnixStream) -> Result<(), Error<std::io::Error>> {
+        let writer = stream.try_clone().map_err(Error::IO)?;
+        self.serve_io(stream, writer)
+    }
+
+    /// Like `serve`, for a client connected through any transport
+    ///
+    /// `reader` and `writer` are the two halves of the client's connection.
+    pub fn serve_io(
+        &self,
+        mut reader: impl IO<Error = std::io::Error>,
+        writer: impl IO<Error = std::io::Error> + Send + 'static,
+    ) -> Result<(), Error<std::io::Error>> {
+        let client = {
+            let mut state = lock(&self.state);
+            let client = state.alloc_id();
+            state.clients.0.insert(client, Box::new(writer));
+            state
+                .clients
+                .send(client, MessageType::HELLO, 0, client, None)?;
+            client
+        };
+
+        let mut buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+        let error = loop {
+            let message = match Message::from_io_vec(&mut reader, &mut buffer) {
+                Ok(message) => message,
+                Err(e) => break e,
+            };
+            if let Err(e) = lock(&self.state).handle(client, &message) {
+                break e;
+            }
+        };
+        lock(&self.state).disconnect(client);
+
+        match error {
+            Error::IO(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(()),
+            e => Err(e),
+        }
+    }
+}
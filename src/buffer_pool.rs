@@ -0,0 +1,86 @@
+use crate::split::lock;
+use crate::*;
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+/// Receive buffers shared between many connections
+///
+/// A connection given a pool (with `Connection::set_buffer_pool`) only holds a receive buffer
+/// while it's receiving and handling messages, taking one from the pool and giving it back once
+/// done. A gateway with dozens of mostly idle connections then needs about as many buffers as
+/// connections busy at once, rather than one per connection.
+///
+/// Buffers grow to fit the largest message they have held. Up to `max_idle` of them are kept for
+/// reuse, and any over that are freed as they're given back. Cloning gives another handle to the
+/// same pool.
+#[derive(Clone)]
+pub struct BufferPool {
+    idle: Arc<Mutex<Vec<Vec<u8>>>>,
+    max_idle: usize,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl BufferPool {
+    /// Create an empty pool keeping up to `max_idle` buffers for reuse
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            idle: Arc::new(Mutex::new(Vec::new())),
+            max_idle,
+        }
+    }
+
+    /// Number of buffers waiting to be reused
+    pub fn idle(&self) -> usize {
+        lock(&self.idle).len()
+    }
+
+    /// Take a buffer, or a new (empty) one if none are idle
+    pub fn take(&self) -> Vec<u8> {
+        lock(&self.idle).pop().unwrap_or_default()
+    }
+
+    /// Give a buffer back for reuse
+    pub fn give(&self, buffer: Vec<u8>) {
+        if buffer.capacity() == 0 {
+            return;
+        }
+        let mut idle = lock(&self.idle);
+        if idle.len() < self.max_idle {
+            idle.push(buffer);
+        }
+    }
+}
+
+impl core::fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "BufferPool(idle={}/{})", self.idle(), self.max_idle)
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Borrow receive buffers from `pool` while receiving, rather than keeping one (or `None` to
+    /// go back to keeping one)
+    ///
+    /// The connection's current buffer is given to the pool. A buffer taken by `next_message` is
+    /// kept until the next call which receives has finished, as the message borrows it.
+    pub fn set_buffer_pool(&mut self, pool: Option<BufferPool>) {
+        match &pool {
+            Some(pool) => pool.give(core::mem::take(&mut self.buffer)),
+            None if self.buffer.capacity() == 0 => {
+                self.buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+            }
+            None => {}
+        }
+        self.buffer_pool = pool;
+    }
+
+    /// The pool set with `set_buffer_pool`
+    pub fn buffer_pool(&self) -> Option<&BufferPool> {
+        self.buffer_pool.as_ref()
+    }
+}
@@ -0,0 +1,195 @@
+use super::*;
+use core::convert::TryFrom;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Builder collecting the options used to open a `Connection`
+#[derive(Clone, Debug)]
+pub struct ConnectionBuilder {
+    socket: PathBuf,
+    timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
+    recv_buffer: usize,
+    max_message_size: Option<usize>,
+    strict: bool,
+    socket_send_buffer: Option<usize>,
+    socket_recv_buffer: Option<usize>,
+    cloexec: bool,
+}
+
+impl Default for ConnectionBuilder {
+    fn default() -> Self {
+        Self {
+            socket: default_socket(),
+            timeout: None,
+            retry: None,
+            recv_buffer: DEFAULT_BUFFER_SIZE,
+            max_message_size: None,
+            strict: false,
+            socket_send_buffer: None,
+            socket_recv_buffer: None,
+            cloexec: true,
+        }
+    }
+}
+
+/// A non-blocking connection, as `ConnectionBuilder::connect_nonblocking` opens
+#[cfg(feature = "nb")]
+pub type NbUnixConnection = NbConnection<StdIo<UnixStream>, std::vec::Vec<u8>, std::vec::Vec<u8>>;
+
+impl ConnectionBuilder {
+    /// Path of the ubusd unix socket
+    pub fn socket(mut self, path: impl AsRef<Path>) -> Self {
+        self.socket = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Read and write timeout applied to the socket
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Retry with `policy` while the socket is missing or refusing connections (as
+    /// `Connection::connect_with_retry` does)
+    ///
+    /// Connecting with a clone of the builder as `Reconnecting`'s connect function retries
+    /// reconnecting too.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Initial size of the buffer messages are received into (it grows to fit larger messages)
+    ///
+    /// For `connect_nonblocking`, which can't grow it, the largest message received unless
+    /// `max_message_size` is set.
+    pub fn recv_buffer(mut self, size: usize) -> Self {
+        self.recv_buffer = size;
+        self
+    }
+
+    /// Reject received messages with more than `size` bytes of payload (see
+    /// `Connection::set_max_message_size`)
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = Some(size);
+        self
+    }
+
+    /// Treat unexpected messages as errors rather than ignoring them
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Kernel send buffer size of the socket (SO_SNDBUF)
+    pub fn socket_send_buffer(mut self, size: usize) -> Self {
+        self.socket_send_buffer = Some(size);
+        self
+    }
+
+    /// Kernel receive buffer size of the socket (SO_RCVBUF), not to be confused with
+    /// `recv_buffer`
+    pub fn socket_recv_buffer(mut self, size: usize) -> Self {
+        self.socket_recv_buffer = Some(size);
+        self
+    }
+
+    /// Close the socket when exec'ing another program (on by default, as for all std sockets)
+    pub fn cloexec(mut self, cloexec: bool) -> Self {
+        self.cloexec = cloexec;
+        self
+    }
+
+    pub fn connect(self) -> Result<Connection<UnixStream>, Error<std::io::Error>> {
+        match &self.retry {
+            Some(policy) => policy.retry(|| self.connect_once()),
+            None => self.connect_once(),
+        }
+    }
+
+    fn connect_once(&self) -> Result<Connection<UnixStream>, Error<std::io::Error>> {
+        let stream = UnixStream::connect(&self.socket).map_err(Error::IO)?;
+        stream.set_read_timeout(self.timeout).map_err(Error::IO)?;
+        stream.set_write_timeout(self.timeout).map_err(Error::IO)?;
+        self.configure(&stream).map_err(Error::IO)?;
+        let buffer = std::vec![0u8; self.recv_buffer];
+        let mut connection = Connection::with_buffer(stream, buffer, self.strict)?;
+        connection.set_max_message_size(self.max_message_size);
+        Ok(connection)
+    }
+
+    /// Connect with the socket in non-blocking mode, for driving from an event loop
+    ///
+    /// `Connection` reads and writes whole messages at a time, so this returns an `NbConnection`
+    /// instead, which keeps partial messages until the rest arrives. The bus's hello comes from
+    /// its first `poll`s. Messages received are limited to `max_message_size` (or `recv_buffer`
+    /// in all), and `timeout` and `strict` don't apply. The `retry` policy
+    /// applies to connecting to the socket, which blocks while it retries.
+    #[cfg(feature = "nb")]
+    pub fn connect_nonblocking(self) -> Result<NbUnixConnection, Error<std::io::Error>> {
+        let connect = || UnixStream::connect(&self.socket).map_err(Error::IO);
+        let stream = match &self.retry {
+            Some(policy) => policy.retry(connect)?,
+            None => connect()?,
+        };
+        self.configure(&stream).map_err(Error::IO)?;
+        stream.set_nonblocking(true).map_err(Error::IO)?;
+        let receive = self.max_message_size.map_or(self.recv_buffer, |size| {
+            MessageHeader::SIZE + BlobTag::SIZE + size
+        });
+        Ok(NbConnection::new(
+            StdIo::new(stream),
+            std::vec![0u8; receive],
+            std::vec![0u8; DEFAULT_BUFFER_SIZE],
+        ))
+    }
+
+    fn configure(&self, stream: &UnixStream) -> io::Result<()> {
+        let fd = stream.as_raw_fd();
+        if let Some(size) = self.socket_send_buffer {
+            set_buffer_size(fd, libc::SO_SNDBUF, size)?;
+        }
+        if let Some(size) = self.socket_recv_buffer {
+            set_buffer_size(fd, libc::SO_RCVBUF, size)?;
+        }
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let flags = match self.cloexec {
+            true => flags | libc::FD_CLOEXEC,
+            false => flags & !libc::FD_CLOEXEC,
+        };
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Connection<UnixStream> {
+    pub fn builder() -> ConnectionBuilder {
+        ConnectionBuilder::default()
+    }
+}
+
+fn set_buffer_size(fd: RawFd, option: libc::c_int, size: usize) -> io::Result<()> {
+    let size = libc::c_int::try_from(size).unwrap_or(libc::c_int::MAX);
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &size as *const libc::c_int as *const libc::c_void,
+            core::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use crate::*;
+
+/// High-level calls shared by the ways of reaching a bus: a socket `Connection`, or the
+/// JSON-RPC interface exposed over HTTP by uhttpd (`JsonRpc`, with the `jsonrpc` feature)
+pub trait Bus {
+    type Error;
+
+    /// Call `method` on the object at `path`, passing each reply table to `on_result`
+    fn call(
+        &mut self,
+        path: &str,
+        method: &str,
+        args: &[u8],
+        on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<(), Error<Self::Error>>;
+
+    /// List the objects matching `path` (which may end with a `*` wildcard), or all objects
+    fn list(
+        &mut self,
+        path: Option<&str>,
+        on_object: impl FnMut(ObjectResult),
+        on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<Self::Error>>;
+}
+
+impl<T: IO> Bus for Connection<T> {
+    type Error = T::Error;
+
+    fn call(
+        &mut self,
+        path: &str,
+        method: &str,
+        args: &[u8],
+        on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<(), Error<T::Error>> {
+        let mut id = None;
+        self.lookup_path(path, |obj| id = Some(obj.id), |_| {})?;
+        // UBUS_STATUS_NOT_FOUND, as ubusd would reply to a call on a missing object
+        let id = id.ok_or(Error::<NoIO>::Status(4))?;
+        self.invoke(id, method, args, on_result)?;
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        path: Option<&str>,
+        on_object: impl FnMut(ObjectResult),
+        on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<T::Error>> {
+        match path {
+            Some(path) => self.lookup_path(path, on_object, on_signature),
+            None => self.lookup(on_object, on_signature),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::*;
+use calloop::generic::Generic;
+use calloop::{EventSource, Interest, Mode, Poll, PostAction, Readiness, Token, TokenFactory};
+use std::os::unix::net::UnixStream;
+
+/// `calloop` event source handling a connection's messages as they arrive
+///
+/// Each incoming message is handled (dispatching calls and notifications to the connection's
+/// objects and subscribers), then the callback is passed the result along with the connection.
+/// The source removes itself from the loop once the connection fails.
+pub struct ConnectionSource {
+    connection: Connection<UnixStream>,
+    source: Generic<UnixStream>,
+}
+
+impl ConnectionSource {
+    pub fn new(connection: Connection<UnixStream>) -> Result<Self, Error<std::io::Error>> {
+        // Poll a duplicate of the socket, the connection keeps the original for reading
+        let stream = connection.io.try_clone().map_err(Error::IO)?;
+        Ok(Self {
+            connection,
+            source: Generic::new(stream, Interest::READ, Mode::Level),
+        })
+    }
+
+    /// The connection, for making calls or adding objects
+    pub fn connection(&mut self) -> &mut Connection<UnixStream> {
+        &mut self.connection
+    }
+
+    pub fn into_inner(self) -> Connection<UnixStream> {
+        self.connection
+    }
+}
+
+impl EventSource for ConnectionSource {
+    type Event = Result<(), Error<std::io::Error>>;
+    type Metadata = Connection<UnixStream>;
+    type Ret = ();
+    type Error = std::io::Error;
+
+    fn process_events<F>(
+        &mut self,
+        readiness: Readiness,
+        token: Token,
+        mut callback: F,
+    ) -> Result<PostAction, Self::Error>
+    where
+        F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
+    {
+        let connection = &mut self.connection;
+        self.source.process_events(readiness, token, |_, _| {
+            // Level triggered, so any further messages are handled on the next dispatch
+            let result = connection.handle_next_message();
+            let failed = result.is_err();
+            callback(result, connection);
+            Ok(if failed {
+                PostAction::Remove
+            } else {
+                PostAction::Continue
+            })
+        })
+    }
+
+    fn register(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.source.register(poll, token_factory)
+    }
+
+    fn reregister(
+        &mut self,
+        poll: &mut Poll,
+        token_factory: &mut TokenFactory,
+    ) -> calloop::Result<()> {
+        self.source.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> calloop::Result<()> {
+        self.source.unregister(poll)
+    }
+}
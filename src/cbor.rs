@@ -0,0 +1,186 @@
+use crate::*;
+use core::convert::TryFrom;
+use minicbor::data::Type;
+use minicbor::encode::write::{Cursor, Write};
+use minicbor::{Decoder, Encoder};
+
+fn encode_error<T>(_: minicbor::encode::Error<T>) -> Error {
+    Error::InvalidData("CBOR output buffer too small")
+}
+
+fn decode_error(_: minicbor::decode::Error) -> Error {
+    Error::InvalidData("Invalid CBOR")
+}
+
+/// Encode a blobmsg table as a CBOR map, returning the number of bytes written to `out`
+///
+/// Tables become maps, arrays become arrays, and INT8 values become booleans (as libubox's JSON
+/// conversion does). UNSPEC values become null, and other unknown types are an error, as is
+/// nesting deeper than `DEFAULT_MAX_DEPTH`.
+pub fn blobmsg_to_cbor(table: BlobIter<BlobMsg>, out: &mut [u8]) -> Result<usize, Error> {
+    blobmsg_to_cbor_limit(table, out, DEFAULT_MAX_DEPTH)
+}
+
+/// Like `blobmsg_to_cbor`, but failing if tables and arrays are nested deeper than `max_depth`
+pub fn blobmsg_to_cbor_limit(
+    table: BlobIter<BlobMsg>,
+    out: &mut [u8],
+    max_depth: usize,
+) -> Result<usize, Error> {
+    let mut encoder = Encoder::new(Cursor::new(out));
+    encode_container(&mut encoder, table, true, Depth::new(max_depth))?;
+    Ok(encoder.writer().position())
+}
+
+/// Encode a table or array at `depth`
+fn encode_container<W: Write>(
+    encoder: &mut Encoder<W>,
+    items: BlobIter<BlobMsg>,
+    table: bool,
+    depth: Depth,
+) -> Result<(), Error> {
+    if table {
+        encoder.begin_map().map_err(encode_error)?;
+    } else {
+        encoder.begin_array().map_err(encode_error)?;
+    }
+    for item in items {
+        if table {
+            encoder.str(item.name.unwrap_or("")).map_err(encode_error)?;
+        }
+        encode_value(encoder, item.data, depth)?;
+    }
+    encoder.end().map_err(encode_error)?;
+    Ok(())
+}
+
+fn encode_value<W: Write>(
+    encoder: &mut Encoder<W>,
+    data: BlobMsgData,
+    depth: Depth,
+) -> Result<(), Error> {
+    match data {
+        BlobMsgData::Array(items) => {
+            return encode_container(encoder, items, false, depth.nested()?);
+        }
+        BlobMsgData::Table(items) => {
+            return encode_container(encoder, items, true, depth.nested()?);
+        }
+        BlobMsgData::String(s) => encoder.str(s),
+        BlobMsgData::Int64(v) => encoder.i64(v),
+        BlobMsgData::Int32(v) => encoder.i32(v),
+        BlobMsgData::Int16(v) => encoder.i16(v),
+        BlobMsgData::Int8(v) => encoder.bool(v != 0),
+        BlobMsgData::Double(v) => encoder.f64(v),
+        BlobMsgData::Unknown(BlobMsgType::UNSPEC, _) => encoder.null(),
+        BlobMsgData::Unknown(..) => {
+            return Err(Error::InvalidData("Blobmsg type has no CBOR equivalent"))
+        }
+    }
+    .map_err(encode_error)?;
+    Ok(())
+}
+
+/// Decode a CBOR map, appending its entries to `builder` as blobmsg attributes
+///
+/// The reverse of `blobmsg_to_cbor`: integers become INT32 (or INT64 if they don't fit), and
+/// floats become DOUBLE. Map keys must be strings, byte strings and tags aren't supported, and
+/// nesting deeper than `DEFAULT_MAX_DEPTH` is an error.
+pub fn cbor_to_blobmsg(cbor: &[u8], builder: &mut BlobMsgBuilder) -> Result<(), Error> {
+    cbor_to_blobmsg_limit(cbor, builder, DEFAULT_MAX_DEPTH)
+}
+
+/// Like `cbor_to_blobmsg`, but failing if maps and arrays are nested deeper than `max_depth`
+pub fn cbor_to_blobmsg_limit(
+    cbor: &[u8],
+    builder: &mut BlobMsgBuilder,
+    max_depth: usize,
+) -> Result<(), Error> {
+    let mut decoder = Decoder::new(cbor);
+    decode_container(&mut decoder, builder, true, Depth::new(max_depth))?;
+    if decoder.position() != cbor.len() {
+        return Err(Error::InvalidData("Trailing data after CBOR"));
+    }
+    Ok(())
+}
+
+/// Decode a map or array at `depth`
+fn decode_container(
+    decoder: &mut Decoder,
+    builder: &mut BlobMsgBuilder,
+    table: bool,
+    depth: Depth,
+) -> Result<(), Error> {
+    let mut remaining = if table {
+        decoder.map()
+    } else {
+        decoder.array()
+    }
+    .map_err(decode_error)?;
+    loop {
+        match remaining {
+            Some(0) => break,
+            Some(n) => remaining = Some(n - 1),
+            // Indefinite length, ended by a break
+            None if decoder.datatype().map_err(decode_error)? == Type::Break => {
+                decoder.set_position(decoder.position() + 1);
+                break;
+            }
+            None => {}
+        }
+        let name = if table {
+            decoder.str().map_err(decode_error)?
+        } else {
+            ""
+        };
+        decode_value(decoder, builder, name, depth)?;
+    }
+    Ok(())
+}
+
+fn decode_value(
+    decoder: &mut Decoder,
+    builder: &mut BlobMsgBuilder,
+    name: &str,
+    depth: Depth,
+) -> Result<(), Error> {
+    match decoder.datatype().map_err(decode_error)? {
+        Type::Bool => builder.push_bool(name, decoder.bool().map_err(decode_error)?),
+        Type::Null | Type::Undefined => {
+            decoder.skip().map_err(decode_error)?;
+            builder.push_null(name)
+        }
+        Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::I8
+        | Type::I16
+        | Type::I32
+        | Type::I64
+        | Type::Int => {
+            let value = decoder.i64().map_err(decode_error)?;
+            match i32::try_from(value) {
+                Ok(value) => builder.push_int32(name, value),
+                Err(_) => builder.push_int64(name, value),
+            }
+        }
+        Type::F16 | Type::F32 | Type::F64 => {
+            builder.push_double(name, decoder.f64().map_err(decode_error)?)
+        }
+        Type::String => builder.push_string(name, decoder.str().map_err(decode_error)?),
+        Type::Map | Type::MapIndef => {
+            let depth = depth.nested()?;
+            builder.push_table(name, |builder| {
+                decode_container(decoder, builder, true, depth)
+            })
+        }
+        Type::Array | Type::ArrayIndef => {
+            let depth = depth.nested()?;
+            builder.push_array(name, |builder| {
+                decode_container(decoder, builder, false, depth)
+            })
+        }
+        _ => Err(Error::InvalidData("CBOR type has no blobmsg equivalent")),
+    }
+}
@@ -0,0 +1,200 @@
+use crate::*;
+use core::fmt::Write;
+use std::string::String;
+use std::vec::Vec;
+
+/// Generate a Rust module with typed clients for the objects at `paths`, from their signatures
+///
+/// Each object gets a client struct (e.g. `NetworkInterface` for `network.interface`) wrapping a
+/// `Connection`, with a method per signature method taking an arguments struct (e.g.
+/// `NetworkInterfaceStatusArgs`) whose fields are all optional, as ubus arguments are. Signatures
+/// don't describe replies, so these are returned as `invoke_collect` tables. TABLE and ARRAY
+/// arguments are passed as already encoded blobmsg, and arguments of unknown type are left out.
+pub fn generate_bindings<B: Bus>(bus: &mut B, paths: &[&str]) -> Result<String, Error<B::Error>> {
+    let mut objects = Vec::new();
+    for path in paths {
+        objects.push((*path, object_signature(bus, path)?));
+    }
+    let mut out = String::new();
+    write_module(&mut out, &objects).expect("Writing to a String can't fail");
+    Ok(out)
+}
+
+fn write_module(out: &mut String, objects: &[(&str, Vec<MethodSignature>)]) -> core::fmt::Result {
+    let paths: Vec<&str> = objects.iter().map(|(path, _)| *path).collect();
+    writeln!(
+        out,
+        "// Generated by ubus-codegen from the signatures of: {}",
+        paths.join(", ")
+    )?;
+    writeln!(out, "#![allow(dead_code)]")?;
+    writeln!(out, "use std::collections::BTreeMap;")?;
+    writeln!(out, "use ubus::*;")?;
+    for (path, methods) in objects {
+        write_object(out, path, methods)?;
+    }
+    Ok(())
+}
+
+fn write_object(out: &mut String, path: &str, methods: &[MethodSignature]) -> core::fmt::Result {
+    let client = camel_case(path);
+    for method in methods {
+        let args = std::format!("{}{}Args", client, camel_case(&method.name));
+        let fields: Vec<(String, &str, BlobMsgType)> = method
+            .args
+            .iter()
+            .filter(|(_, ty)| rust_type(*ty).is_some())
+            .map(|(name, ty)| (snake_case(name), name.as_str(), *ty))
+            .collect();
+
+        writeln!(out)?;
+        writeln!(out, "/// Arguments of `{}` `{}`", path, method.name)?;
+        writeln!(out, "#[derive(Clone, Debug, Default)]")?;
+        if fields.is_empty() {
+            writeln!(out, "pub struct {} {{}}", args)?;
+        } else {
+            writeln!(out, "pub struct {} {{", args)?;
+            for (field, _, ty) in &fields {
+                let ty = rust_type(*ty).unwrap_or_default();
+                writeln!(out, "    pub {}: Option<{}>,", field, ty)?;
+            }
+            writeln!(out, "}}")?;
+        }
+        writeln!(out)?;
+        writeln!(out, "impl {} {{", args)?;
+        let builder = if fields.is_empty() {
+            "_builder"
+        } else {
+            "builder"
+        };
+        writeln!(
+            out,
+            "    pub fn push_to(&self, {}: &mut BlobMsgBuilder) -> Result<(), Error> {{",
+            builder
+        )?;
+        for (field, name, ty) in &fields {
+            let push = match *ty {
+                BlobMsgType::STRING => "builder.push_string(NAME, value)",
+                BlobMsgType::INT64 => "builder.push_int64(NAME, *value)",
+                BlobMsgType::INT32 => "builder.push_int32(NAME, *value)",
+                BlobMsgType::INT16 => "builder.push_int16(NAME, *value)",
+                BlobMsgType::INT8 => "builder.push_bool(NAME, *value)",
+                BlobMsgType::DOUBLE => "builder.push_double(NAME, *value)",
+                BlobMsgType::ARRAY => "builder.push_array(NAME, |b| b.push_raw(value))",
+                _ => "builder.push_table(NAME, |b| b.push_raw(value))",
+            };
+            writeln!(out, "        if let Some(value) = &self.{} {{", field)?;
+            let name = std::format!("{:?}", name);
+            writeln!(out, "            {}?;", push.replace("NAME", &name))?;
+            writeln!(out, "        }}")?;
+        }
+        writeln!(out, "        Ok(())")?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "/// Client for the `{}` object", path)?;
+    writeln!(out, "pub struct {}<'c, T: IO> {{", client)?;
+    writeln!(out, "    connection: &'c mut Connection<T>,")?;
+    writeln!(out, "    id: u32,")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl<'c, T: IO> {}<'c, T> {{", client)?;
+    writeln!(
+        out,
+        "    pub fn new(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {{"
+    )?;
+    writeln!(out, "        let id = connection.object_id({:?})?;", path)?;
+    writeln!(out, "        Ok(Self {{ connection, id }})")?;
+    writeln!(out, "    }}")?;
+    for method in methods {
+        let args = std::format!("{}{}Args", client, camel_case(&method.name));
+        writeln!(out)?;
+        writeln!(out, "    pub fn {}(", snake_case(&method.name))?;
+        writeln!(out, "        &mut self,")?;
+        writeln!(out, "        args: &{},", args)?;
+        writeln!(
+            out,
+            "    ) -> Result<BTreeMap<String, BlobMsgValue>, Error<T::Error>> {{"
+        )?;
+        writeln!(
+            out,
+            "        let mut buffer = vec![0u8; DEFAULT_BUFFER_SIZE];"
+        )?;
+        writeln!(
+            out,
+            "        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);"
+        )?;
+        writeln!(out, "        args.push_to(&mut builder)?;")?;
+        writeln!(out, "        let args = builder.finish();")?;
+        writeln!(
+            out,
+            "        self.connection.invoke_collect(self.id, {:?}, args)",
+            method.name
+        )?;
+        writeln!(out, "    }}")?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Field type for an argument, or `None` for unknown types
+fn rust_type(ty: BlobMsgType) -> Option<&'static str> {
+    match ty {
+        BlobMsgType::STRING => Some("String"),
+        BlobMsgType::INT64 => Some("i64"),
+        BlobMsgType::INT32 => Some("i32"),
+        BlobMsgType::INT16 => Some("i16"),
+        // libubox treats INT8 as a boolean
+        BlobMsgType::INT8 => Some("bool"),
+        BlobMsgType::DOUBLE => Some("f64"),
+        BlobMsgType::ARRAY | BlobMsgType::TABLE => Some("Vec<u8>"),
+        _ => None,
+    }
+}
+
+/// Words of a ubus name, split on anything which can't be in an identifier
+fn words(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// Type name for a ubus name (`network.interface` becomes `NetworkInterface`)
+fn camel_case(name: &str) -> String {
+    let mut out = String::new();
+    for word in words(name) {
+        let mut chars = word.chars();
+        out.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+        out.extend(chars);
+    }
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.insert(0, 'X');
+    }
+    out
+}
+
+/// Field or method name for a ubus name (`ipv4-address` becomes `ipv4_address`)
+fn snake_case(name: &str) -> String {
+    let mut out = words(name)
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    if out.is_empty() {
+        out.push_str("unnamed");
+    }
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.insert(0, '_');
+    }
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else",
+        "enum", "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+        "move", "mut", "new", "pub", "ref", "return", "self", "static", "struct", "super", "trait",
+        "true", "try", "type", "unsafe", "use", "where", "while", "yield",
+    ];
+    // Keywords (and `new`, the client's constructor) get a trailing underscore, as some (like
+    // `self`) can't be raw identifiers
+    if KEYWORDS.contains(&out.as_str()) {
+        out.push('_');
+    }
+    out
+}
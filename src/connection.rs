@@ -1,4 +1,7 @@
+use crate::maybe_async::{block_on, close_fd, exchange, invoke_reply, lookup_reply, Link};
 use crate::*;
+use core::convert::TryFrom;
+use core::ops::ControlFlow;
 
 #[derive(Copy, Clone)]
 pub struct ObjectResult<'a> {
@@ -18,21 +21,56 @@ pub struct SignatureResult<'a> {
     pub args: &'a mut dyn Iterator<Item = (&'a str, BlobMsgType)>,
 }
 
+/// Default size of the receive buffer
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+#[cfg(not(feature = "no_std"))]
+pub(crate) type Buffer = std::vec::Vec<u8>;
+#[cfg(feature = "no_std")]
+pub(crate) type Buffer = [u8; DEFAULT_BUFFER_SIZE];
+
 pub struct Connection<T: IO> {
-    io: T,
-    peer: u32,
-    sequence: u16,
-    buffer: [u8; 64 * 1024],
+    pub(crate) io: T,
+    pub(crate) peer: u32,
+    pub(crate) sequences: Sequences<MAX_ABANDONED>,
+    pub(crate) strict: bool,
+    pub(crate) buffer: Buffer,
+    /// Largest message payload accepted (see `set_max_message_size`)
+    pub(crate) max_message_size: usize,
+    /// Session attached to every call (see `set_session`)
+    pub(crate) session: Option<Session>,
+    /// Where receive buffers are borrowed from (see `set_buffer_pool`)
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) buffer_pool: Option<BufferPool>,
+    pub(crate) handlers: Handlers,
 }
 
 impl<T: IO> Connection<T> {
     /// Create a new ubus connection from an existing IO
     pub fn new(io: T) -> Result<Self, Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        let buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+        #[cfg(feature = "no_std")]
+        let buffer = [0u8; DEFAULT_BUFFER_SIZE];
+        Self::with_buffer(io, buffer, false)
+    }
+
+    pub(crate) fn with_buffer(
+        io: T,
+        buffer: Buffer,
+        strict: bool,
+    ) -> Result<Self, Error<T::Error>> {
         let mut new = Self {
             io,
             peer: 0,
-            sequence: 0,
-            buffer: [0u8; 64 * 1024],
+            sequences: Sequences::new(),
+            strict,
+            buffer,
+            max_message_size: usize::MAX,
+            session: None,
+            #[cfg(not(feature = "no_std"))]
+            buffer_pool: None,
+            handlers: Handlers::default(),
         };
 
         // ubus server should say hello on connect
@@ -51,156 +89,627 @@ impl<T: IO> Connection<T> {
     }
 
     // Get next message from ubus channel (blocking!)
-    pub fn next_message(&mut self) -> Result<Message, Error<T::Error>> {
-        Message::from_io(&mut self.io, &mut self.buffer)
+    pub fn next_message(&mut self) -> Result<Message<'_>, Error<T::Error>> {
+        self.borrow_buffer();
+        Self::recv(
+            &mut self.io,
+            &mut self.buffer,
+            self.max_message_size,
+            &mut self.handlers,
+        )
+    }
+
+    /// Reject received messages with more than `size` bytes of payload (or `None` for no limit)
+    ///
+    /// Independent of the receive buffer, which (with std) grows to fit any message. Rejected
+    /// messages are read and discarded, failing whichever call was receiving with an error, and
+    /// the connection stays usable.
+    pub fn set_max_message_size(&mut self, size: Option<usize>) {
+        self.max_message_size = size.unwrap_or(usize::MAX);
+    }
+
+    /// The limit set by `set_max_message_size`
+    pub fn max_message_size(&self) -> Option<usize> {
+        Some(self.max_message_size).filter(|&size| size != usize::MAX)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "next_message",
+            level = "trace",
+            skip_all,
+            fields(message = tracing::field::Empty, sequence = tracing::field::Empty, bytes = tracing::field::Empty)
+        )
+    )]
+    pub(crate) fn recv<'b>(
+        io: &mut T,
+        buffer: &'b mut Buffer,
+        max_size: usize,
+        handlers: &mut Handlers,
+    ) -> Result<Message<'b>, Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        let message = handlers.hooks.receive(io, buffer, max_size)?;
+        #[cfg(feature = "no_std")]
+        let message = {
+            let _ = handlers;
+            Message::from_io_limit(io, buffer, max_size)?
+        };
+        span_record!("message", tracing::field::debug(message.header.message));
+        span_record!("sequence", u16::from(message.header.sequence));
+        span_record!("bytes", message.blob.data.len());
+        Ok(message)
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip_all, fields(bytes = tracing::field::Empty))
+    )]
     pub fn send(&mut self, message: MessageBuilder) -> Result<(), Error<T::Error>> {
-        self.io.put(message.into())
+        let data: &[u8] = message.into();
+        span_record!("bytes", data.len());
+        #[cfg(not(feature = "no_std"))]
+        return self.handlers.hooks.wrap(&mut self.io).put(data);
+        #[cfg(feature = "no_std")]
+        self.io.put(data)
+    }
+
+    /// Receive and handle a single message which isn't a reply to a request (blocking!)
+    ///
+    /// This is how notifications for subscribers get delivered when not in the middle of a call.
+    pub fn handle_next_message(&mut self) -> Result<(), Error<T::Error>> {
+        self.borrow_buffer();
+        let result = self.handle_message();
+        self.release_buffer();
+        #[cfg(not(feature = "no_std"))]
+        if result.is_err() {
+            self.handlers.hooks.stats.errors += 1;
+        }
+        result
+    }
+
+    /// Handle messages until the connection fails (such as when ubusd goes away)
+    ///
+    /// The loop for services: calls to this connection's objects are passed to their handlers,
+    /// notifications to its subscribers and events to its event handlers, with replies sent as
+    /// they return. Late replies to requests that were given up on are dropped.
+    pub fn run(&mut self) -> Result<(), Error<T::Error>> {
+        loop {
+            self.handle_next_message()?;
+        }
+    }
+
+    /// Like `run`, returning once `done` returns true
+    ///
+    /// `done` is checked before waiting for each message, so after the handler which finished.
+    pub fn run_until(&mut self, mut done: impl FnMut() -> bool) -> Result<(), Error<T::Error>> {
+        while !done() {
+            self.handle_next_message()?;
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self) -> Result<(), Error<T::Error>> {
+        self.release_dropped()?;
+        let message = Self::recv(
+            &mut self.io,
+            &mut self.buffer,
+            self.max_message_size,
+            &mut self.handlers,
+        )?;
+        let result = match message.header.message {
+            MessageType::STATUS | MessageType::DATA => {
+                trace!("Dropping unrelated {:?}", message);
+                Ok(())
+            }
+            _ => self.handlers.dispatch(&mut self.io, self.strict, &message),
+        };
+        close_fd(message.fd);
+        result
+    }
+
+    /// Stream a message straight to the transport (see `MessageWriter`)
+    pub fn writer(
+        &mut self,
+        header: MessageHeader,
+        len: usize,
+    ) -> Result<MessageWriter<'_, T>, Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        self.handlers.hooks.streamed(header, len)?;
+        MessageWriter::new(&mut self.io, header, len)
+    }
+
+    /// Like `send`, also passing the file descriptor `fd` to the peer
+    pub fn send_fd(&mut self, message: MessageBuilder, fd: i32) -> Result<(), Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        return self
+            .handlers
+            .hooks
+            .wrap(&mut self.io)
+            .put_fd(message.into(), fd);
+        #[cfg(feature = "no_std")]
+        self.io.put_fd(message.into(), fd)
+    }
+
+    /// Send a request, then wait for the final STATUS reply
+    ///
+    /// The attributes of every DATA reply along the way are passed to `on_data`.
+    pub(crate) fn request<'b>(
+        &mut self,
+        request: impl RequestMessage<'b>,
+        mut on_data: impl FnMut(BlobIter<MessageAttr>) -> Result<(), Error>,
+    ) -> Result<(), Error<T::Error>> {
+        self.request_fd(request, None, |attrs| {
+            on_data(attrs).map(ControlFlow::Continue)
+        })?;
+        Ok(())
     }
 
+    /// Like `request`, optionally passing a file descriptor with the request
+    ///
+    /// Returns the file descriptor passed along with any of the replies. `on_data` may break to
+    /// stop waiting without the final STATUS (the rest of the replies are then dropped as they
+    /// arrive, being for an old sequence number).
+    pub(crate) fn request_fd<'b>(
+        &mut self,
+        request: impl RequestMessage<'b>,
+        fd: Option<i32>,
+        mut on_data: impl FnMut(BlobIter<MessageAttr>) -> Result<ControlFlow<()>, Error>,
+    ) -> Result<Option<i32>, Error<T::Error>> {
+        self.request_message(request, fd, |message| {
+            on_data(BlobIter::new(message.blob.data))
+        })
+    }
+
+    /// Like `request_fd`, passing the whole of each DATA reply to `on_data`
+    fn request_message<'b>(
+        &mut self,
+        request: impl RequestMessage<'b>,
+        fd: Option<i32>,
+        on_data: impl FnMut(&Message) -> Result<ControlFlow<()>, Error>,
+    ) -> Result<Option<i32>, Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        {
+            self.handlers.hooks.stats.outstanding += 1;
+        }
+        let result = self.exchange(request, fd, on_data);
+        #[cfg(not(feature = "no_std"))]
+        {
+            let stats = &mut self.handlers.hooks.stats;
+            stats.outstanding -= 1;
+            if result.is_err() {
+                stats.errors += 1;
+            }
+        }
+        result
+    }
+
+    /// Send a request and wait for its replies, see `request_fd`
+    fn exchange<'b>(
+        &mut self,
+        request: impl RequestMessage<'b>,
+        fd: Option<i32>,
+        on_data: impl FnMut(&Message) -> Result<ControlFlow<()>, Error>,
+    ) -> Result<Option<i32>, Error<T::Error>> {
+        self.release_dropped()?;
+
+        let request = Request::new(self.sequences.allocate(), request);
+        span_record!("sequence", request.sequence);
+
+        self.borrow_buffer();
+        let mut link = Parts {
+            io: &mut self.io,
+            handlers: &mut self.handlers,
+            strict: self.strict,
+            max_message_size: self.max_message_size,
+        };
+        let result = block_on(exchange(
+            &mut link,
+            &mut self.buffer,
+            &mut self.sequences,
+            &request,
+            fd,
+            on_data,
+        ));
+        self.release_buffer();
+        result
+    }
+
+    /// Remove this connection's objects (and their subscriptions) from the bus, then close it
+    ///
+    /// ubusd cleans up straight away, rather than whenever it notices the connection has gone.
+    /// Everything is closed even if a step fails, returning the first error.
+    pub fn close(mut self) -> Result<(), Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        let removed = self.remove_all_objects();
+        #[cfg(feature = "no_std")]
+        let removed = Ok(());
+        let closed = self.io.close();
+        removed.and(closed)
+    }
+
+    /// Clean up after any handles that have been dropped since the last request
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) fn release_dropped(&mut self) -> Result<(), Error<T::Error>> {
+        while let Some(id) = self.handlers.objects.next_dropped() {
+            self.remove_object(id)?;
+        }
+        Ok(())
+    }
+    #[cfg(feature = "no_std")]
+    pub(crate) fn release_dropped(&mut self) -> Result<(), Error<T::Error>> {
+        Ok(())
+    }
+
+    /// Take a receive buffer from the pool, if there is one and we don't already hold one
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) fn borrow_buffer(&mut self) {
+        if let Some(pool) = &self.buffer_pool {
+            if self.buffer.capacity() == 0 {
+                self.buffer = pool.take();
+            }
+        }
+    }
+    #[cfg(feature = "no_std")]
+    pub(crate) fn borrow_buffer(&mut self) {}
+
+    /// Give the receive buffer back to the pool, if there is one
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) fn release_buffer(&mut self) {
+        if let Some(pool) = &self.buffer_pool {
+            pool.give(core::mem::take(&mut self.buffer));
+        }
+    }
+    #[cfg(feature = "no_std")]
+    pub(crate) fn release_buffer(&mut self) {}
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, args, on_result), fields(obj = obj, sequence = tracing::field::Empty))
+    )]
     pub fn invoke(
         &mut self,
         obj: u32,
         method: &str,
-        _args: &[BlobMsgData],
+        args: &[u8],
         mut on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<InvokeSummary, Error<T::Error>> {
+        let (_, summary) = self.invoke_inner(obj, method, args, None, |data| {
+            on_result(data);
+            ControlFlow::Continue(())
+        })?;
+        Ok(summary)
+    }
+
+    /// Call `method` on `obj` with NO_REPLY set, returning as soon as the request is sent
+    ///
+    /// For event-like calls which shouldn't block on slow objects. Any reply sent anyway is dropped.
+    pub fn invoke_noreply(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
     ) -> Result<(), Error<T::Error>> {
-        self.sequence += 1;
-        let sequence = self.sequence.into();
-
-        let mut buffer = [0u8; 1024];
-        let mut message = MessageBuilder::new(
-            &mut buffer,
-            MessageHeader {
-                version: MessageVersion::CURRENT,
-                message: MessageType::INVOKE,
-                sequence,
-                peer: obj.into(),
-            },
-        )
-        .unwrap();
+        self.release_dropped()?;
 
-        message.put(MessageAttr::ObjId(obj))?;
-        message.put(MessageAttr::Method(method))?;
-        message.put(MessageAttr::Data(&[]))?;
+        let sequence = self.sequences.allocate();
+        with_session(self.session, args, |args| {
+            let request = InvokeRequest::new(obj, method).args(args).no_reply();
+            #[cfg(not(feature = "no_std"))]
+            let io = &mut self.handlers.hooks.wrap(&mut self.io);
+            #[cfg(feature = "no_std")]
+            let io = &mut self.io;
+            send_message(io, InvokeRequest::TYPE, sequence, obj, request)
+        })
+    }
 
-        self.send(message)?;
-        'message: loop {
-            let message = self.next_message()?;
-            if message.header.sequence != sequence {
-                continue;
+    /// Like `invoke`, converting the reply's DATA table into `R`
+    pub fn invoke_as<R>(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+    ) -> Result<R, Error<T::Error>>
+    where
+        R: for<'a> TryFrom<BlobMsg<'a>>,
+    {
+        let mut result = None;
+        self.invoke(obj, method, args, |data| {
+            if result.is_none() {
+                let table = BlobMsg {
+                    name: None,
+                    raw_name: None,
+                    data: BlobMsgData::Table(data),
+                };
+                result = Some(R::try_from(table).ok());
             }
+        })?;
+        match result {
+            Some(Some(value)) => Ok(value),
+            Some(None) => Err(Error::InvalidData("Could not convert reply")),
+            None => Err(Error::InvalidData("No data in reply")),
+        }
+    }
 
-            let attrs = BlobIter::<MessageAttr>::new(message.blob.data);
-
-            match message.header.message {
-                MessageType::STATUS => {
-                    for attr in attrs {
-                        if let MessageAttr::Status(0) = attr {
-                            return Ok(());
-                        } else if let MessageAttr::Status(status) = attr {
-                            return Err(Error::Status(status));
-                        }
-                    }
-                    return Err(Error::InvalidData("Invalid status message"));
-                }
-                MessageType::DATA => {
-                    for attr in attrs {
-                        if let MessageAttr::Data(data) = attr {
-                            on_result(BlobIter::<BlobMsg>::new(data));
-                            continue 'message;
-                        }
-                    }
-                    return Err(Error::InvalidData("Invalid data message"));
-                }
-                unknown => {
-                    std::dbg!(unknown);
+    /// Like `invoke`, optionally passing a file descriptor along with the request
+    ///
+    /// Returns the file descriptor passed back by the object (if any), which the caller then owns.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, args, on_result), fields(obj = obj, sequence = tracing::field::Empty))
+    )]
+    pub fn invoke_fd(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        fd: Option<i32>,
+        mut on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<Option<i32>, Error<T::Error>> {
+        let (fd, _) = self.invoke_inner(obj, method, args, fd, |data| {
+            on_result(data);
+            ControlFlow::Continue(())
+        })?;
+        Ok(fd)
+    }
+
+    /// Like `invoke`, but `on_result` can break to stop waiting for the rest of the replies
+    ///
+    /// Returns straight away once `on_result` breaks, without waiting for the call to finish.
+    /// Replies still to come for it are dropped as they arrive, so the connection stays usable
+    /// (rather than having to be dropped to escape an object which keeps sending data). Objects
+    /// which go quiet instead can be given up on with a read timeout.
+    pub fn invoke_until(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        on_result: impl FnMut(BlobIter<BlobMsg>) -> ControlFlow<()>,
+    ) -> Result<InvokeSummary, Error<T::Error>> {
+        let (_, summary) = self.invoke_inner(obj, method, args, None, on_result)?;
+        Ok(summary)
+    }
+
+    /// Like `invoke`, but passing the whole of each DATA reply to `on_message`
+    ///
+    /// For monitors and proxies which need the header (e.g. the replying peer) or attributes
+    /// other than the DATA table.
+    pub fn invoke_raw(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        mut on_message: impl FnMut(&Message),
+    ) -> Result<(), Error<T::Error>> {
+        with_session(self.session, args, |args| {
+            let request = InvokeRequest::new(obj, method).args(args);
+            self.request_message(request, None, |message| {
+                on_message(message);
+                Ok(ControlFlow::Continue(()))
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Send `request`, passing each of its replies to `on_response` until the final STATUS
+    ///
+    /// Sends the request as it is, without adding the session set with `set_session`.
+    pub fn call<'b>(
+        &mut self,
+        request: impl RequestMessage<'b>,
+        mut on_response: impl FnMut(&Response),
+    ) -> Result<(), Error<T::Error>> {
+        self.request_message(request, None, |message| {
+            on_response(&Response::from_message(message));
+            Ok(ControlFlow::Continue(()))
+        })?;
+        Ok(())
+    }
+
+    fn invoke_inner(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        fd: Option<i32>,
+        mut on_result: impl FnMut(BlobIter<BlobMsg>) -> ControlFlow<()>,
+    ) -> Result<(Option<i32>, InvokeSummary), Error<T::Error>> {
+        let mut summary = InvokeSummary::default();
+        let mut stopped = false;
+        let fd = with_session(self.session, args, |args| {
+            let request = InvokeRequest::new(obj, method).args(args);
+            self.request_message(request, fd, |message| {
+                summary.add(message.blob.data);
+                let flow = invoke_reply(BlobIter::new(message.blob.data), &mut on_result)?;
+                stopped = flow.is_break();
+                Ok(flow)
+            })
+        })?;
+        summary.status = if stopped { None } else { Some(0) };
+        Ok((fd, summary))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(sequence = tracing::field::Empty))
+    )]
+    pub fn lookup(
+        &mut self,
+        on_object: impl FnMut(ObjectResult),
+        on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<T::Error>> {
+        self.lookup_inner(None, on_object, on_signature)
+    }
+
+    /// Like `lookup`, but only for objects matching `path` (which may end with a `*` wildcard)
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, on_object, on_signature), fields(sequence = tracing::field::Empty))
+    )]
+    pub fn lookup_path(
+        &mut self,
+        path: &str,
+        on_object: impl FnMut(ObjectResult),
+        on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<T::Error>> {
+        self.lookup_inner(Some(path), on_object, on_signature)
+    }
+
+    /// Find the object with the id `id`, passing it to `on_object`
+    ///
+    /// ubusd can only look objects up by path, so this goes through the full object list. Fails
+    /// with the NOT_FOUND status if there is no such object.
+    pub fn lookup_id(
+        &mut self,
+        id: u32,
+        mut on_object: impl FnMut(ObjectResult),
+    ) -> Result<(), Error<T::Error>> {
+        let mut found = false;
+        self.lookup(
+            |object| {
+                if object.id == id && !found {
+                    found = true;
+                    on_object(object);
                 }
-            }
+            },
+            |_| {},
+        )?;
+        if !found {
+            // UBUS_STATUS_NOT_FOUND
+            return Err(Error::Status(4));
         }
+        Ok(())
     }
 
-    pub fn lookup(
+    /// Like `lookup_path` (or `lookup` for `None`), but passing the whole of each DATA reply
+    /// (one per object) to `on_message`
+    pub fn lookup_raw(
+        &mut self,
+        path: Option<&str>,
+        mut on_message: impl FnMut(&Message),
+    ) -> Result<(), Error<T::Error>> {
+        let request = path.map_or_else(LookupRequest::all, LookupRequest::path);
+        self.request_message(request, None, |message| {
+            on_message(message);
+            Ok(ControlFlow::Continue(()))
+        })?;
+        Ok(())
+    }
+
+    /// Find the id of the object at `path`
+    pub fn object_id(&mut self, path: &str) -> Result<u32, Error<T::Error>> {
+        let mut id = None;
+        self.lookup_path(path, |obj| id = Some(obj.id), |_| {})?;
+        Ok(id.ok_or(Error::<NoIO>::InvalidData("No object in lookup reply"))?)
+    }
+
+    fn lookup_inner(
         &mut self,
+        path: Option<&str>,
         mut on_object: impl FnMut(ObjectResult),
         mut on_signature: impl FnMut(SignatureResult),
     ) -> Result<(), Error<T::Error>> {
-        self.sequence += 1;
-        let sequence = self.sequence.into();
-
-        let mut buffer = [0u8; 1024];
-        let message = MessageBuilder::new(
-            &mut buffer,
-            MessageHeader {
-                version: MessageVersion::CURRENT,
-                message: MessageType::LOOKUP,
-                sequence,
-                peer: 0.into(),
-            },
-        )
-        .unwrap();
+        let request = path.map_or_else(LookupRequest::all, LookupRequest::path);
+        self.request(request, |attrs| {
+            lookup_reply(attrs, &mut on_object, &mut on_signature)
+        })
+    }
+}
 
-        self.send(message)?;
+/// The parts of a `Connection` which requests are exchanged over, apart from its receive buffer
+struct Parts<'a, T: IO> {
+    io: &'a mut T,
+    handlers: &'a mut Handlers,
+    strict: bool,
+    max_message_size: usize,
+}
 
-        loop {
-            let message = self.next_message()?;
-            if message.header.sequence != sequence {
-                continue;
-            }
+impl<T: IO> Link for Parts<'_, T> {
+    type Error = T::Error;
 
-            let attrs = BlobIter::<MessageAttr>::new(message.blob.data);
+    async fn send(&mut self, data: &[u8], fd: Option<i32>) -> Result<(), Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        let io = &mut self.handlers.hooks.wrap(self.io);
+        #[cfg(feature = "no_std")]
+        let io = &mut *self.io;
+        match fd {
+            Some(fd) => io.put_fd(data, fd),
+            None => io.put(data),
+        }
+    }
 
-            if message.header.message == MessageType::STATUS {
-                for attr in attrs {
-                    if let MessageAttr::Status(0) = attr {
-                        return Ok(());
-                    } else if let MessageAttr::Status(status) = attr {
-                        return Err(Error::Status(status));
-                    }
-                }
-                return Err(Error::InvalidData("Invalid status message"));
-            }
+    async fn recv<'b>(&mut self, buffer: &'b mut Buffer) -> Result<Message<'b>, Error<T::Error>> {
+        Connection::recv(self.io, buffer, self.max_message_size, self.handlers)
+    }
 
-            if message.header.message != MessageType::DATA {
-                continue;
-            }
+    async fn dispatch(&mut self, message: &Message<'_>) -> Result<(), Error<T::Error>> {
+        self.handlers.dispatch(self.io, self.strict, message)
+    }
+}
 
-            let mut obj_path: Option<&str> = None;
-            let mut obj_id: Option<u32> = None;
-            let mut obj_type: Option<u32> = None;
-            for attr in attrs {
-                match attr {
-                    MessageAttr::ObjPath(path) => obj_path = Some(path),
-                    MessageAttr::ObjId(id) => obj_id = Some(id),
-                    MessageAttr::ObjType(ty) => obj_type = Some(ty),
-                    MessageAttr::Signature(nested) => {
-                        let object = ObjectResult {
-                            path: obj_path.unwrap(),
-                            id: obj_id.unwrap(),
-                            ty: obj_type.unwrap(),
-                        };
-                        on_object(object);
-
-                        for signature in nested {
-                            if let BlobMsgData::Table(table) = signature.data {
-                                on_signature(SignatureResult {
-                                    object,
-                                    name: signature.name.unwrap(),
-                                    args: &mut table.map(|arg| {
-                                        if let BlobMsgData::Int32(typeid) = arg.data {
-                                            (arg.name.unwrap(), BlobMsgType::from(typeid as u32))
-                                        } else {
-                                            panic!()
-                                        }
-                                    }),
-                                });
-                            }
-                        }
-                    }
-                    _ => continue,
-                }
-            }
+/// Build and send a single message (used for replies to requests from other peers)
+pub(crate) fn send_message<'a, T: IO + ?Sized>(
+    io: &mut T,
+    message: MessageType,
+    sequence: u16,
+    peer: u32,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) -> Result<(), Error<T::Error>> {
+    // Replies may carry a whole DATA table, so size the buffer to fit when we can
+    #[cfg(not(feature = "no_std"))]
+    let attrs: std::vec::Vec<_> = attrs.into_iter().collect();
+    #[cfg(not(feature = "no_std"))]
+    let mut buffer = message_buffer(message_size(attrs.iter().map(MessageAttr::size)))?;
+    #[cfg(feature = "no_std")]
+    let mut buffer = message_buffer(0)?;
+    let buffer_len = buffer.len();
+    let mut size = message_size(core::iter::empty());
+    let mut builder = MessageBuilder::new(
+        &mut buffer,
+        MessageHeader {
+            version: MessageVersion::CURRENT,
+            message,
+            sequence: sequence.into(),
+            peer: peer.into(),
+        },
+    )?;
+    for attr in attrs {
+        size += attr.size();
+        if size > buffer_len {
+            return Err(Error::InvalidData("Request too large"));
+        }
+        builder.put(attr)?;
+    }
+    io.put(builder.into())
+}
+
+/// Handlers for messages which aren't replies to our own requests
+#[derive(Default)]
+pub(crate) struct Handlers {
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) objects: Objects,
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) hooks: Hooks,
+}
+
+impl Handlers {
+    pub(crate) fn dispatch<T: IO>(
+        &mut self,
+        io: &mut T,
+        strict: bool,
+        message: &Message,
+    ) -> Result<(), Error<T::Error>> {
+        #[cfg(not(feature = "no_std"))]
+        if self.objects.dispatch(&mut self.hooks.wrap(io), message)? {
+            return Ok(());
+        }
+        #[cfg(feature = "no_std")]
+        let _ = io;
+
+        warn!("Unexpected {:?}", message);
+        if strict {
+            return Err(Error::InvalidData("Unexpected message"));
         }
+        Ok(())
     }
 }
@@ -0,0 +1,253 @@
+use crate::*;
+
+/// Most requests an `Engine` keeps track of at once
+pub const MAX_PENDING: usize = 16;
+
+/// Something received, from `Engine::poll`
+pub enum EngineEvent<'a> {
+    /// The bus said hello, giving our peer id. Requests can be made from now on.
+    Hello { peer: u32 },
+    /// A DATA reply to the request `sequence`, such as an object found by a lookup, or the
+    /// `MessageAttr::Data` table returned by a call
+    Data {
+        sequence: u16,
+        attrs: BlobIter<'a, MessageAttr<'a>>,
+    },
+    /// The request `sequence` finished, with `status` 0 for success
+    Done { sequence: u16, status: i32 },
+    /// Any other message, such as a call to one of our objects or a notification
+    Message(Message<'a>),
+}
+
+impl core::fmt::Debug for EngineEvent<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            EngineEvent::Hello { peer } => write!(f, "Hello({:08x})", peer),
+            EngineEvent::Data { sequence, attrs } => {
+                write!(f, "Data({}, {} bytes)", sequence, attrs.as_bytes().len())
+            }
+            EngineEvent::Done { sequence, status } => write!(f, "Done({}, {})", sequence, status),
+            EngineEvent::Message(message) => write!(f, "{:?}", message),
+        }
+    }
+}
+
+/// The ubus protocol without any IO, as the core of `NbConnection`: framing, sequencing and
+/// matching replies to requests
+///
+/// Bytes received from the socket go into `receive_buffer` (or `feed`), and `poll` turns them
+/// into events. Requests are built into caller supplied buffers for the transport to send. This
+/// is only for transports which can't block or await a whole message, such as nonblocking sockets
+/// and embedded network stacks, and is built with the `nb` feature. `Connection` and
+/// `AsyncConnection` don't use it: they read whole messages and share `maybe_async::exchange`.
+///
+/// Replies to requests the engine isn't tracking are dropped, as `Connection` does.
+pub struct Engine<B> {
+    buffer: B,
+    /// Bytes of `buffer` holding received data
+    filled: usize,
+    /// Bytes at the start of `buffer` holding the message last returned by `poll`
+    consumed: usize,
+    /// Bytes still to throw away of a message too large for `buffer`
+    skip: usize,
+    peer: Option<u32>,
+    sequences: Sequences<MAX_PENDING>,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Engine<B> {
+    /// Create an engine receiving into `buffer`, which limits the size of messages
+    pub fn new(buffer: B) -> Self {
+        Self {
+            buffer,
+            filled: 0,
+            consumed: 0,
+            skip: 0,
+            peer: None,
+            sequences: Sequences::new(),
+        }
+    }
+
+    /// Our peer id, once the bus has said hello
+    pub fn peer(&self) -> Option<u32> {
+        self.peer
+    }
+
+    /// Is the request `sequence` still waiting for its status
+    pub fn is_pending(&self, sequence: u16) -> bool {
+        self.sequences.is_pending(sequence)
+    }
+
+    /// Space to receive into, after which `received` must be told how many bytes arrived
+    ///
+    /// Empty if the buffer is full, until `poll` takes a message out of it.
+    pub fn receive_buffer(&mut self) -> &mut [u8] {
+        self.compact();
+        &mut self.buffer.as_mut()[self.filled..]
+    }
+
+    /// Record that `len` bytes were received into `receive_buffer`
+    pub fn received(&mut self, len: usize) {
+        self.filled = (self.filled + len).min(self.buffer.as_ref().len());
+    }
+
+    /// Copy received bytes in, returning how many fit
+    pub fn feed(&mut self, data: &[u8]) -> usize {
+        let space = self.receive_buffer();
+        let len = data.len().min(space.len());
+        space[..len].copy_from_slice(&data[..len]);
+        self.received(len);
+        len
+    }
+
+    /// The next event, or `None` until more bytes are received
+    ///
+    /// Malformed messages fail with an error, but are skipped over, so polling can continue. A
+    /// header which can't be parsed (such as one with the wrong version) loses track of where
+    /// messages start, so everything received so far is dropped with it.
+    pub fn poll(&mut self) -> Result<Option<EngineEvent<'_>>, Error> {
+        loop {
+            self.compact();
+            let pre_size = MessageHeader::SIZE + BlobTag::SIZE;
+            if self.filled < pre_size {
+                return Ok(None);
+            }
+            let (header, tag) = match Message::parse_pre(&self.buffer.as_ref()[..pre_size]) {
+                Ok(pre) => pre,
+                Err(e) => {
+                    // There's no telling where the next message starts
+                    self.consumed = self.filled;
+                    return Err(e);
+                }
+            };
+            let len = pre_size + tag.inner_len();
+            if len > self.buffer.as_ref().len() {
+                self.skip = len;
+                return Err(Error::InvalidData("Message too large for receive buffer"));
+            }
+            if self.filled < len {
+                return Ok(None);
+            }
+            self.consumed = len;
+
+            let sequence = u16::from(header.sequence);
+            match header.message {
+                _ if self.peer.is_none() => {
+                    if header.message != MessageType::HELLO {
+                        return Err(Error::InvalidData("Expected hello"));
+                    }
+                    let peer = header.peer.into();
+                    self.peer = Some(peer);
+                    return Ok(Some(EngineEvent::Hello { peer }));
+                }
+                MessageType::STATUS | MessageType::DATA if !self.is_pending(sequence) => {
+                    trace!("Dropping unrelated {:?}", header.message);
+                    continue;
+                }
+                _ => break,
+            }
+        }
+
+        let buffer = &self.buffer.as_ref()[..self.consumed];
+        let message = Message::from_buffer(buffer, None)?;
+        message.validate()?;
+        let sequence = u16::from(message.header.sequence);
+        let attrs = || BlobIter::<MessageAttr>::new(message.blob.data);
+        Ok(Some(match message.header.message {
+            MessageType::DATA => EngineEvent::Data {
+                sequence,
+                attrs: attrs(),
+            },
+            MessageType::STATUS => {
+                self.sequences.finish(sequence);
+                let status = attrs()
+                    .find_map(|attr| match attr {
+                        MessageAttr::Status(status) => Some(status),
+                        _ => None,
+                    })
+                    .ok_or(Error::InvalidData("Invalid status message"))?;
+                EngineEvent::Done { sequence, status }
+            }
+            _ => EngineEvent::Message(message),
+        }))
+    }
+
+    /// Build the request `message` to `peer` into `out`, returning its sequence number and bytes
+    ///
+    /// Its replies come back from `poll` as `Data` events, ending with `Done`.
+    pub fn request<'o, 'a>(
+        &mut self,
+        out: &'o mut [u8],
+        message: MessageType,
+        peer: u32,
+        attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+    ) -> Result<(u16, &'o [u8]), Error> {
+        if self.peer.is_none() {
+            return Err(Error::InvalidData("Request before hello"));
+        }
+        let sequence = self.sequences.start()?;
+        match build(out, message, sequence, peer, attrs) {
+            Ok(bytes) => Ok((sequence, bytes)),
+            Err(e) => {
+                self.sequences.finish(sequence);
+                Err(e)
+            }
+        }
+    }
+
+    /// Build a call of `method` on `obj` into `out`, as `request` does
+    pub fn invoke<'o>(
+        &mut self,
+        out: &'o mut [u8],
+        obj: u32,
+        method: &str,
+        args: &[u8],
+    ) -> Result<(u16, &'o [u8]), Error> {
+        let request = InvokeRequest::new(obj, method).args(args);
+        self.request(out, InvokeRequest::TYPE, obj, request)
+    }
+
+    /// Build a reply (or any message which doesn't expect one) into `out`
+    ///
+    /// For answering calls to our objects, with the `sequence` and `peer` of the call.
+    pub fn reply<'o, 'a>(
+        &self,
+        out: &'o mut [u8],
+        message: MessageType,
+        sequence: u16,
+        peer: u32,
+        attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+    ) -> Result<&'o [u8], Error> {
+        build(out, message, sequence, peer, attrs)
+    }
+
+    /// Drop the message last returned by `poll`, and anything left of an oversized one
+    fn compact(&mut self) {
+        let drop = self.consumed + self.skip.min(self.filled - self.consumed);
+        if drop > 0 {
+            self.skip -= drop - self.consumed;
+            self.buffer.as_mut().copy_within(drop..self.filled, 0);
+            self.filled -= drop;
+            self.consumed = 0;
+        }
+    }
+}
+
+fn build<'o, 'a>(
+    out: &'o mut [u8],
+    message: MessageType,
+    sequence: u16,
+    peer: u32,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) -> Result<&'o [u8], Error> {
+    let header = MessageHeader {
+        version: MessageVersion::CURRENT,
+        message,
+        sequence: sequence.into(),
+        peer: peer.into(),
+    };
+    let mut builder = MessageBuilder::new(out, header)?;
+    for attr in attrs {
+        builder.put(attr)?;
+    }
+    Ok(builder.finish())
+}
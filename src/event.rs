@@ -0,0 +1,131 @@
+use crate::events::Event;
+use crate::split::lock;
+use crate::*;
+use std::boxed::Box;
+use std::collections::BTreeSet;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::string::{String, ToString};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+/// Id of ubusd's built in object for registering and sending events
+pub const EVENT_OBJECT: u32 = 1;
+
+/// Does `id` match `pattern` (which may end with a `*` wildcard, as ubusd supports)
+pub fn event_matches(pattern: &str, id: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => id.starts_with(prefix),
+        None => pattern == id,
+    }
+}
+
+/// Handle to a (hidden) event handler object registered with `Connection::event_handler`
+///
+/// Dropping the handle removes the object, and so all of its registered patterns.
+#[derive(Debug)]
+pub struct EventHandler {
+    object: Object,
+    pub(crate) patterns: Arc<Mutex<Vec<String>>>,
+}
+
+impl EventHandler {
+    /// Object id of the event handler object
+    pub fn id(&self) -> u32 {
+        self.object.id()
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Register an event handler object, passing events it receives to `callback`
+    ///
+    /// No events are received until patterns are added with `register_event`.
+    pub fn event_handler(
+        &mut self,
+        mut callback: impl FnMut(&str, BlobIter<BlobMsg>) + Send + 'static,
+    ) -> Result<EventHandler, Error<T::Error>> {
+        let patterns: Arc<Mutex<Vec<String>>> = Arc::default();
+        let registered = patterns.clone();
+        let handler = ObjectHandler::Notify(Box::new(move |id, data| {
+            // Only pass on events we asked for
+            if lock(&registered).iter().any(|p| event_matches(p, id)) {
+                callback(id, data);
+            }
+            0
+        }));
+        let id = self.register_object(None)?;
+        let object = self.handlers.objects.insert(id, handler);
+        Ok(EventHandler { object, patterns })
+    }
+
+    /// Deliver events matching `pattern` (which may end with a `*` wildcard) to `handler`
+    ///
+    /// A handler may be registered for several patterns, but receives each event only once.
+    pub fn register_event(
+        &mut self,
+        handler: &EventHandler,
+        pattern: &str,
+    ) -> Result<(), Error<T::Error>> {
+        let mut buffer = [0u8; 512];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_int32("object", handler.id() as i32)?;
+        args.push_string("pattern", pattern)?;
+        self.invoke(EVENT_OBJECT, "register", args.finish(), |_| {})?;
+        lock(&handler.patterns).push(pattern.to_string());
+        Ok(())
+    }
+
+    /// Send the event `id`, with the blobmsg table `data`
+    pub fn send_event(&mut self, id: &str, data: &[u8]) -> Result<(), Error<T::Error>> {
+        let mut buffer = std::vec![0u8; data.len() + id.len() + 64];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("id", id)?;
+        args.push_table("data", |b| b.push_raw(data))?;
+        self.invoke(EVENT_OBJECT, "send", args.finish(), |_| {})?;
+        Ok(())
+    }
+}
+
+impl<T: IO<Error = io::Error> + AsRawFd> Connection<T> {
+    /// Wait until there are objects at all of `paths`, failing with `Error::Timeout` if some are
+    /// still missing after `timeout`
+    ///
+    /// For ordering a daemon's startup after the services it uses. Watches "ubus.object.add"
+    /// events, registering for them before looking up the objects already there so none are
+    /// missed. Other messages (such as calls to our objects) are handled while waiting.
+    pub fn wait_for_objects(
+        &mut self,
+        paths: &[&str],
+        timeout: Duration,
+    ) -> Result<(), Error<io::Error>> {
+        let deadline = Instant::now() + timeout;
+        let pending: Arc<Mutex<BTreeSet<String>>> =
+            Arc::new(Mutex::new(paths.iter().map(|p| p.to_string()).collect()));
+        let added = pending.clone();
+        let handler = self.event_handler(move |id, data| {
+            if let Ok(Event::ObjectAdded { path, .. }) = Event::parse(id, data) {
+                lock(&added).remove(path);
+            }
+        })?;
+        self.register_event(&handler, "ubus.object.add")?;
+        for path in paths {
+            match self.object_id(path) {
+                Ok(_) => {
+                    lock(&pending).remove(*path);
+                }
+                // Not there yet
+                Err(Error::Status(4)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        while !lock(&pending).is_empty() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if select(&[&*self], Some(left))?.is_empty() {
+                return Err(Error::Timeout);
+            }
+            self.handle_next_message()?;
+        }
+        Ok(())
+    }
+}
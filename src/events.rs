@@ -0,0 +1,126 @@
+//! Typed forms of the events OpenWrt's daemons broadcast
+//!
+//! `Event::parse` turns an event's id and data (as passed to an `event_handler` callback) into an
+//! enum, so handlers can match on what happened rather than comparing ids and pulling fields out of
+//! the blobmsg table by hand. Events without a variant of their own are passed through as
+//! `Event::Other`.
+
+use crate::*;
+
+/// A well-known event
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// netifd brought `interface` (e.g. "wan") up ("network.interface", action "ifup")
+    InterfaceUp { interface: &'a str },
+    /// netifd took `interface` down ("network.interface", action "ifdown")
+    InterfaceDown { interface: &'a str },
+    /// An object was added to the bus ("ubus.object.add")
+    ObjectAdded { id: u32, path: &'a str },
+    /// An object was removed from the bus ("ubus.object.remove")
+    ObjectRemoved { id: u32, path: &'a str },
+    /// A kernel hotplug event, from procd's "hotplug.<subsystem>"
+    Hotplug(Hotplug<'a>),
+    /// Any other event, including "network.interface" actions other than up and down
+    Other {
+        id: &'a str,
+        data: BlobIter<'a, BlobMsg<'a>>,
+    },
+}
+
+/// A kernel hotplug (uevent) event
+#[derive(Debug)]
+pub struct Hotplug<'a> {
+    /// e.g. "net", "block" or "usb"
+    pub subsystem: &'a str,
+    pub action: HotplugAction<'a>,
+    /// The device's name (DEVICENAME, DEVNAME or INTERFACE), if the event has one
+    pub device: Option<&'a str>,
+    /// All of the event's variables (ACTION, DEVPATH, SEQNUM...)
+    pub vars: BlobIter<'a, BlobMsg<'a>>,
+}
+
+/// What happened to a device, from a hotplug event's ACTION
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HotplugAction<'a> {
+    Add,
+    Remove,
+    Change,
+    Other(&'a str),
+}
+
+/// Error for a well-known event without a field its variant needs
+fn missing() -> Error {
+    Error::InvalidData("Event is missing a field")
+}
+
+impl<'a> Event<'a> {
+    /// Parse the event `id`, with the blobmsg table `data`
+    ///
+    /// Fails if a well-known event is missing fields, rather than passing it through as `Other`.
+    pub fn parse(id: &'a str, data: BlobIter<'a, BlobMsg<'a>>) -> Result<Self, Error> {
+        Ok(match id {
+            "network.interface" => {
+                let interface = string(&data, "interface").ok_or_else(missing)?;
+                match string(&data, "action") {
+                    Some("ifup") => Event::InterfaceUp { interface },
+                    Some("ifdown") => Event::InterfaceDown { interface },
+                    _ => Event::Other { id, data },
+                }
+            }
+            "ubus.object.add" | "ubus.object.remove" => {
+                let path = string(&data, "path").ok_or_else(missing)?;
+                let object = int(&data, "id").ok_or_else(missing)?;
+                if id == "ubus.object.add" {
+                    Event::ObjectAdded { id: object, path }
+                } else {
+                    Event::ObjectRemoved { id: object, path }
+                }
+            }
+            _ => match id.strip_prefix("hotplug.") {
+                Some(subsystem) => Event::Hotplug(Hotplug::parse(subsystem, data)?),
+                None => Event::Other { id, data },
+            },
+        })
+    }
+}
+
+impl<'a> Hotplug<'a> {
+    fn parse(subsystem: &'a str, vars: BlobIter<'a, BlobMsg<'a>>) -> Result<Self, Error> {
+        let action = match string(&vars, "ACTION").ok_or_else(missing)? {
+            "add" => HotplugAction::Add,
+            "remove" => HotplugAction::Remove,
+            "change" => HotplugAction::Change,
+            action => HotplugAction::Other(action),
+        };
+        let device = ["DEVICENAME", "DEVNAME", "INTERFACE"]
+            .iter()
+            .find_map(|name| string(&vars, name));
+        Ok(Self {
+            subsystem,
+            action,
+            device,
+            vars,
+        })
+    }
+
+    /// The variable `name` (e.g. "DEVPATH"), if the event has it
+    pub fn var(&self, name: &str) -> Option<&'a str> {
+        string(&self.vars, name)
+    }
+}
+
+/// The string field `name` of `data`
+fn string<'a>(data: &BlobIter<'a, BlobMsg<'a>>, name: &str) -> Option<&'a str> {
+    BlobIter::<BlobMsg>::new(data.as_bytes()).find_map(|msg| match msg.data {
+        BlobMsgData::String(value) if msg.name == Some(name) => Some(value),
+        _ => None,
+    })
+}
+
+/// The integer field `name` of `data`, as sent by `blobmsg_add_u32`
+fn int(data: &BlobIter<'_, BlobMsg<'_>>, name: &str) -> Option<u32> {
+    BlobIter::<BlobMsg>::new(data.as_bytes()).find_map(|msg| match msg.data {
+        BlobMsgData::Int32(value) if msg.name == Some(name) => Some(value as u32),
+        _ => None,
+    })
+}
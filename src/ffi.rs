@@ -0,0 +1,243 @@
+//! C interface to the client, for linking C and C++ components against this crate rather than
+//! libubus
+//!
+//! Build the shared library with `cargo rustc --release --features ffi --crate-type cdylib`, and
+//! include `include/ubus_rs.h`. Arguments and replies are blobmsg table payloads, as built and
+//! parsed by libubox's `blobmsg_*` functions (`blob_data(b.head)` and `blob_len(b.head)`).
+//!
+//! Functions returning `int` return 0 on success, or a `UBUS_STATUS_*` code: the object's own
+//! status for failed calls, `UBUS_STATUS_INVALID_ARGUMENT` for bad arguments,
+//! `UBUS_STATUS_TIMEOUT` for timeouts, `UBUS_STATUS_CONNECTION_FAILED` for socket errors and
+//! `UBUS_STATUS_UNKNOWN_ERROR` for malformed messages. Callbacks run on the calling thread, before
+//! the function which received the message returns.
+
+use crate::*;
+use core::ffi::{c_char, c_int, c_void, CStr};
+use core::{ptr, slice};
+use std::boxed::Box;
+use std::ffi::CString;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::vec::Vec;
+
+const STATUS_INVALID_ARGUMENT: c_int = 2;
+const STATUS_TIMEOUT: c_int = 7;
+const STATUS_UNKNOWN_ERROR: c_int = 9;
+const STATUS_CONNECTION_FAILED: c_int = 10;
+
+/// Called with each reply to `ubus_rs_invoke`
+pub type DataCallback = extern "C" fn(context: *mut c_void, data: *const u8, len: usize);
+
+/// Called with each event matching a pattern registered with `ubus_rs_register_event`
+pub type EventCallback =
+    extern "C" fn(context: *mut c_void, id: *const c_char, data: *const u8, len: usize);
+
+/// A connection, opaque to C
+pub struct UbusRsConnection {
+    connection: Connection<UnixStream>,
+    handlers: Vec<EventHandler>,
+}
+
+/// The caller's context pointer, which is only used on the thread handling messages
+struct Context(*mut c_void);
+unsafe impl Send for Context {}
+
+fn status(e: Error<std::io::Error>) -> c_int {
+    match e {
+        Error::Status(status) => status,
+        Error::Timeout => STATUS_TIMEOUT,
+        Error::IO(_) => STATUS_CONNECTION_FAILED,
+        Error::InvalidData(_) => STATUS_UNKNOWN_ERROR,
+    }
+}
+
+fn result<R>(result: Result<R, Error<std::io::Error>>) -> c_int {
+    result.err().map_or(0, status)
+}
+
+/// A C string argument, or `None` if it is null or not UTF-8
+unsafe fn string<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// A blobmsg argument, which may be null if empty
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+/// Connect to the socket at `path`, or the default socket if `path` is null
+///
+/// Returns null if the connection fails.
+///
+/// # Safety
+///
+/// `path` must be null or a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_connect(path: *const c_char) -> *mut UbusRsConnection {
+    let connection = if path.is_null() {
+        Connection::connect_default()
+    } else {
+        match string(path) {
+            Some(path) => Connection::connect(Path::new(path)),
+            None => return ptr::null_mut(),
+        }
+    };
+    match connection {
+        Ok(connection) => Box::into_raw(Box::new(UbusRsConnection {
+            connection,
+            handlers: Vec::new(),
+        })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Close `connection`, removing its event registrations
+///
+/// # Safety
+///
+/// `connection` must be null or returned by `ubus_rs_connect`, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_free(connection: *mut UbusRsConnection) {
+    if !connection.is_null() {
+        drop(Box::from_raw(connection));
+    }
+}
+
+/// The connection's socket, for adding to a poll loop
+///
+/// # Safety
+///
+/// `connection` must be returned by `ubus_rs_connect`.
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_fd(connection: *const UbusRsConnection) -> c_int {
+    (*connection).connection.as_raw_fd()
+}
+
+/// Find the id of the object at `path`, storing it in `id`
+///
+/// # Safety
+///
+/// `connection` must be returned by `ubus_rs_connect`, `path` a NUL terminated string and `id`
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_lookup_id(
+    connection: *mut UbusRsConnection,
+    path: *const c_char,
+    id: *mut u32,
+) -> c_int {
+    let path = match string(path) {
+        Some(path) if !id.is_null() => path,
+        _ => return STATUS_INVALID_ARGUMENT,
+    };
+    match (*connection).connection.object_id(path) {
+        Ok(found) => {
+            *id = found;
+            0
+        }
+        Err(e) => status(e),
+    }
+}
+
+/// Call `method` on the object `obj` with the blobmsg table `data`, passing each reply to
+/// `callback` (which may be null to ignore them)
+///
+/// # Safety
+///
+/// `connection` must be returned by `ubus_rs_connect`, `method` a NUL terminated string and
+/// `data` `len` readable bytes (or null if `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_invoke(
+    connection: *mut UbusRsConnection,
+    obj: u32,
+    method: *const c_char,
+    data: *const u8,
+    len: usize,
+    callback: Option<DataCallback>,
+    context: *mut c_void,
+) -> c_int {
+    let method = match string(method) {
+        Some(method) => method,
+        None => return STATUS_INVALID_ARGUMENT,
+    };
+    let args = bytes(data, len);
+    result((*connection).connection.invoke(obj, method, args, |reply| {
+        if let Some(callback) = callback {
+            let reply = reply.as_bytes();
+            callback(context, reply.as_ptr(), reply.len());
+        }
+    }))
+}
+
+/// Send the event `id` with the blobmsg table `data`
+///
+/// # Safety
+///
+/// `connection` must be returned by `ubus_rs_connect`, `id` a NUL terminated string and `data`
+/// `len` readable bytes (or null if `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_send_event(
+    connection: *mut UbusRsConnection,
+    id: *const c_char,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let id = match string(id) {
+        Some(id) => id,
+        None => return STATUS_INVALID_ARGUMENT,
+    };
+    result((*connection).connection.send_event(id, bytes(data, len)))
+}
+
+/// Pass events matching `pattern` (which may end with a `*` wildcard) to `callback`
+///
+/// Events arrive as the connection handles messages, in `ubus_rs_handle_next` or while waiting
+/// for replies to calls.
+///
+/// # Safety
+///
+/// `connection` must be returned by `ubus_rs_connect` and `pattern` a NUL terminated string.
+/// `context` must stay valid until the connection is freed.
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_register_event(
+    connection: *mut UbusRsConnection,
+    pattern: *const c_char,
+    callback: EventCallback,
+    context: *mut c_void,
+) -> c_int {
+    let pattern = match string(pattern) {
+        Some(pattern) => pattern,
+        None => return STATUS_INVALID_ARGUMENT,
+    };
+    let connection = &mut *connection;
+    let context = Context(context);
+    let handler = connection.connection.event_handler(move |id, data| {
+        // Event ids come from the bus, so can't contain a NUL
+        let id = CString::new(id).unwrap_or_default();
+        let data = data.as_bytes();
+        callback(context.0, id.as_ptr(), data.as_ptr(), data.len());
+    });
+    let registered = handler.and_then(|handler| {
+        connection.connection.register_event(&handler, pattern)?;
+        connection.handlers.push(handler);
+        Ok(())
+    });
+    result(registered)
+}
+
+/// Wait for and handle the next message, such as an event
+///
+/// # Safety
+///
+/// `connection` must be returned by `ubus_rs_connect`.
+#[no_mangle]
+pub unsafe extern "C" fn ubus_rs_handle_next(connection: *mut UbusRsConnection) -> c_int {
+    result((*connection).connection.handle_next_message())
+}
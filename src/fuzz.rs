@@ -0,0 +1,107 @@
+use crate::*;
+use arbitrary::{Arbitrary, Unstructured};
+use std::io;
+use std::vec::Vec;
+
+impl<'a> Arbitrary<'a> for MessageHeader {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(MessageHeader {
+            version: u.arbitrary()?,
+            message: u.arbitrary()?,
+            sequence: u.arbitrary::<u16>()?.into(),
+            peer: u.arbitrary::<u32>()?.into(),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for BlobTag {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(BlobTag::from_bytes(u.arbitrary()?))
+    }
+}
+
+/// Only attributes `MessageBuilder` can write are generated (not `Signature` or `Subscribers`)
+impl<'a> Arbitrary<'a> for MessageAttr<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=11)? {
+            0 => MessageAttr::Status(u.arbitrary()?),
+            1 => MessageAttr::ObjPath(u.arbitrary()?),
+            2 => MessageAttr::ObjId(u.arbitrary()?),
+            3 => MessageAttr::Method(u.arbitrary()?),
+            4 => MessageAttr::ObjType(u.arbitrary()?),
+            5 => MessageAttr::Data(u.arbitrary()?),
+            6 => MessageAttr::Target(u.arbitrary()?),
+            7 => MessageAttr::Active(u.arbitrary()?),
+            8 => MessageAttr::NoReply(u.arbitrary()?),
+            9 => MessageAttr::User(u.arbitrary()?),
+            10 => MessageAttr::Group(u.arbitrary()?),
+            _ => MessageAttr::Unknown(u.arbitrary()?, u.arbitrary()?),
+        })
+    }
+}
+
+impl<'a, T> Arbitrary<'a> for BlobIter<'a, T> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(BlobIter::new(u.arbitrary()?))
+    }
+}
+
+/// Reads from the fuzzer's input as if it arrived on a socket
+struct SliceIo<'a>(&'a [u8]);
+impl IO for SliceIo<'_> {
+    type Error = io::Error;
+    fn put(&mut self, _data: &[u8]) -> Result<(), Error<io::Error>> {
+        Err(Error::InvalidData("Cannot write to fuzzer input"))
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<io::Error>> {
+        if data.len() > self.0.len() {
+            return Err(Error::IO(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let (head, rest) = self.0.split_at(data.len());
+        data.copy_from_slice(head);
+        self.0 = rest;
+        Ok(())
+    }
+}
+
+/// Fuzzing entry point: receive messages from `data` as a connection would, walking every
+/// attribute (and any blobmsg they carry) until the input runs out or is invalid
+///
+/// Any panic is a bug, invalid input must only ever produce errors.
+pub fn fuzz_parse_message(data: &[u8]) {
+    let mut io = SliceIo(data);
+    let mut buffer = Vec::new();
+    while let Ok(message) = Message::from_io_vec(&mut io, &mut buffer) {
+        for attr in BlobIter::<MessageAttr>::from(message.blob) {
+            match attr {
+                MessageAttr::Signature(signature) => walk_blobmsg(signature),
+                MessageAttr::Data(data) => fuzz_parse_blobmsg(data),
+                MessageAttr::Subscribers(subscribers) => subscribers.for_each(|_| {}),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Fuzzing entry point: parse `data` as a blobmsg table, walking into every nested value
+pub fn fuzz_parse_blobmsg(data: &[u8]) {
+    walk_blobmsg(BlobIter::new(data));
+}
+
+/// Walk a table, and tables or arrays nested in it up to `DEFAULT_MAX_DEPTH`, then convert it as each
+/// of the nesting-limited conversions do
+fn walk_blobmsg(items: BlobIter<BlobMsg>) {
+    walk_nested(BlobIter::new(items.as_bytes()), 0);
+    let _ = items.to_map();
+}
+
+fn walk_nested(items: BlobIter<BlobMsg>, depth: usize) {
+    for item in items {
+        match item.data {
+            BlobMsgData::Array(items) | BlobMsgData::Table(items) if depth < DEFAULT_MAX_DEPTH => {
+                walk_nested(items, depth + 1)
+            }
+            _ => {}
+        }
+    }
+}
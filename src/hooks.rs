@@ -0,0 +1,175 @@
+use crate::maybe_async::close_fd;
+use crate::*;
+use core::convert::TryInto;
+use std::boxed::Box;
+use std::vec::Vec;
+
+/// What a send or receive hook wants done with a message
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Intercept {
+    /// Carry on as normal
+    Pass,
+    /// Silently discard the message, as if it was lost
+    Drop,
+}
+
+/// Hook called with the header and encoded bytes of each message before it is sent
+pub type SendHook = Box<dyn FnMut(&MessageHeader, &[u8]) -> Result<Intercept, Error> + Send>;
+
+/// Hook called with each message received, before it is handled
+pub type ReceiveHook = Box<dyn FnMut(&Message) -> Result<Intercept, Error> + Send>;
+
+/// Hooks set on a connection (see `Connection::on_send` and `Connection::on_receive`), and
+/// counters for the messages passing through it
+#[derive(Default)]
+pub(crate) struct Hooks {
+    on_send: Option<SendHook>,
+    on_receive: Option<ReceiveHook>,
+    pub(crate) stats: Stats,
+}
+
+impl Hooks {
+    /// Should the encoded message `data` be sent
+    fn pass(&mut self, data: &[u8]) -> Result<bool, Error> {
+        let hook = match &mut self.on_send {
+            Some(hook) => hook,
+            None => return Ok(true),
+        };
+        let header = data
+            .get(..MessageHeader::SIZE)
+            .ok_or(Error::InvalidData("Message shorter than its header"))?;
+        let header = MessageHeader::from_bytes(header.try_into().unwrap());
+        Ok(hook(&header, data)? == Intercept::Pass)
+    }
+
+    /// Count the encoded message `data` once it has been sent
+    fn sent(&mut self, data: &[u8]) {
+        if let Some(&ty) = data.get(1) {
+            self.stats.count_sent(MessageType::from(ty), data.len());
+        }
+    }
+
+    /// Check a message streamed by `MessageWriter`, which hooks only see the header of
+    pub(crate) fn streamed(&mut self, header: MessageHeader, len: usize) -> Result<(), Error> {
+        if !self.pass(&header.to_bytes())? {
+            return Err(Error::InvalidData("Send hook dropped a streamed message"));
+        }
+        let size = MessageHeader::SIZE + BlobTag::SIZE + len;
+        self.stats.count_sent(header.message, size);
+        Ok(())
+    }
+
+    /// Receive the next message (of at most `max_size` bytes) which the receive hook doesn't drop
+    pub(crate) fn receive<'b, T: IO>(
+        &mut self,
+        io: &mut T,
+        buffer: &'b mut Vec<u8>,
+        max_size: usize,
+    ) -> Result<Message<'b>, Error<T::Error>> {
+        receive_filtered(io, buffer, max_size, |message| self.received(message))
+    }
+
+    /// Count a received message, and ask the receive hook whether it should be handled
+    pub(crate) fn received(&mut self, message: &Message) -> Result<bool, Error> {
+        let size = MessageHeader::SIZE + BlobTag::SIZE + message.blob.data.len();
+        self.stats.count_received(message.header.message, size);
+        let pass = match &mut self.on_receive {
+            Some(hook) => hook(message),
+            None => Ok(Intercept::Pass),
+        };
+        match pass {
+            Ok(Intercept::Pass) => Ok(true),
+            // Nothing else will see the message, so nothing else will close its descriptor
+            Ok(Intercept::Drop) => {
+                close_fd(message.fd);
+                Ok(false)
+            }
+            Err(e) => {
+                close_fd(message.fd);
+                Err(e)
+            }
+        }
+    }
+
+    /// Wrap `io` so messages written to it go through the send hook
+    pub(crate) fn wrap<'a, T: IO>(&'a mut self, io: &'a mut T) -> Hooked<'a, T> {
+        Hooked { io, hooks: self }
+    }
+}
+
+/// Receive the next message (of at most `max_size` bytes) which `filter` passes
+pub(crate) fn receive_filtered<'b, T: IO>(
+    io: &mut T,
+    buffer: &'b mut Vec<u8>,
+    max_size: usize,
+    mut filter: impl FnMut(&Message) -> Result<bool, Error>,
+) -> Result<Message<'b>, Error<T::Error>> {
+    let fd = loop {
+        let message = Message::from_io_vec_limit(io, buffer, max_size)?;
+        if filter(&message)? {
+            break message.fd;
+        }
+    };
+    // Parsed again so the message can borrow the buffer for longer than the loop
+    Ok(Message::from_buffer(buffer, fd)?)
+}
+
+/// An IO which passes each message written through a connection's send hook
+///
+/// Each `put` must be a whole message, as written by `Connection::send` and `send_message`.
+pub(crate) struct Hooked<'a, T> {
+    io: &'a mut T,
+    hooks: &'a mut Hooks,
+}
+
+impl<T: IO> IO for Hooked<'_, T> {
+    type Error = T::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<T::Error>> {
+        if self.hooks.pass(data)? {
+            self.io.put(data)?;
+            self.hooks.sent(data);
+        }
+        Ok(())
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<T::Error>> {
+        self.io.get(data)
+    }
+    fn put_fd(&mut self, data: &[u8], fd: i32) -> Result<(), Error<T::Error>> {
+        if self.hooks.pass(data)? {
+            self.io.put_fd(data, fd)?;
+            self.hooks.sent(data);
+        }
+        Ok(())
+    }
+    fn get_fd(&mut self, data: &mut [u8]) -> Result<Option<i32>, Error<T::Error>> {
+        self.io.get_fd(data)
+    }
+    fn close(&mut self) -> Result<(), Error<T::Error>> {
+        self.io.close()
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Call `hook` with the header and encoded bytes of each message before it is sent
+    ///
+    /// For logging, tracing, filtering, or fault injection. The hook may drop the message, or
+    /// fail the send with an error. Messages streamed with `writer` are passed with only their
+    /// header, and fail if dropped. Replaces any previous send hook.
+    pub fn on_send(
+        &mut self,
+        hook: impl FnMut(&MessageHeader, &[u8]) -> Result<Intercept, Error> + Send + 'static,
+    ) {
+        self.handlers.hooks.on_send = Some(Box::new(hook));
+    }
+
+    /// Call `hook` with each message received, before it is handled
+    ///
+    /// Dropped messages are skipped as if never received, and an error from the hook is returned
+    /// from whichever call was receiving. Replaces any previous receive hook.
+    pub fn on_receive(
+        &mut self,
+        hook: impl FnMut(&Message) -> Result<Intercept, Error> + Send + 'static,
+    ) {
+        self.handlers.hooks.on_receive = Some(Box::new(hook));
+    }
+}
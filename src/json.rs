@@ -0,0 +1,237 @@
+use crate::*;
+use core::convert::TryFrom;
+use core::fmt;
+use serde::de::{Deserializer as _, Visitor};
+use serde_json_core::de::{Deserializer, Error as JsonError};
+
+fn json_error(e: JsonError) -> Error {
+    match e {
+        JsonError::EscapedStringIsTooLong => Error::InvalidData("JSON string too long to unescape"),
+        _ => Error::InvalidData("Invalid JSON"),
+    }
+}
+
+/// Parse a JSON object, appending its members to `builder` as blobmsg attributes
+///
+/// Doesn't allocate: strings containing escapes are unescaped into `scratch`, which needs to be
+/// large enough for the object keys leading to (and including) the longest such string.
+/// Integers become INT32 (or INT64 if they don't fit), and other numbers become DOUBLE. Nesting
+/// deeper than `DEFAULT_MAX_DEPTH` is an error.
+pub fn json_to_blobmsg(
+    json: &[u8],
+    scratch: &mut [u8],
+    builder: &mut BlobMsgBuilder,
+) -> Result<(), Error> {
+    json_to_blobmsg_limit(json, scratch, builder, DEFAULT_MAX_DEPTH)
+}
+
+/// Like `json_to_blobmsg`, but failing if objects and arrays are nested deeper than `max_depth`
+pub fn json_to_blobmsg_limit(
+    json: &[u8],
+    scratch: &mut [u8],
+    builder: &mut BlobMsgBuilder,
+    max_depth: usize,
+) -> Result<(), Error> {
+    let mut parser = Parser {
+        json,
+        pos: 0,
+        depth: Depth::new(max_depth),
+    };
+    parser.expect(b'{')?;
+    parser.container(scratch, builder, true)?;
+    if parser.peek().is_some() {
+        return Err(Error::InvalidData("Trailing data after JSON"));
+    }
+    Ok(())
+}
+
+/// Splits the document into tokens, leaving strings and numbers to `serde-json-core`
+struct Parser<'a> {
+    json: &'a [u8],
+    pos: usize,
+    /// Objects and arrays the parser is inside, not counting the outermost object
+    depth: Depth,
+}
+
+/// A parsed string, either borrowed from the document or unescaped into the start of `scratch`
+enum Str<'a> {
+    Borrowed(&'a str),
+    Unescaped(usize),
+}
+
+struct StrVisitor;
+
+impl<'de> Visitor<'de> for StrVisitor {
+    type Value = Str<'de>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E> {
+        Ok(Str::Borrowed(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Str::Unescaped(v.len()))
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// Next non-whitespace byte, without consuming it
+    fn peek(&mut self) -> Option<u8> {
+        while let Some(&b) = self.json.get(self.pos) {
+            if !matches!(b, b' ' | b'\t' | b'\n' | b'\r') {
+                return Some(b);
+            }
+            self.pos += 1;
+        }
+        None
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), Error> {
+        if self.peek() != Some(byte) {
+            return Err(Error::InvalidData("Invalid JSON"));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Consume bytes while `f` matches, returning them
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'a [u8] {
+        let start = self.pos;
+        while self.json.get(self.pos).is_some_and(|&b| f(b)) {
+            self.pos += 1;
+        }
+        &self.json[start..self.pos]
+    }
+
+    /// Parse a string, unescaping it into `scratch` if needed
+    fn string<'s>(&mut self, scratch: &'s mut [u8]) -> Result<(&'s str, &'s mut [u8]), Error>
+    where
+        'a: 's,
+    {
+        if self.peek() != Some(b'"') {
+            return Err(Error::InvalidData("Invalid JSON"));
+        }
+        let start = self.pos;
+        let mut escaped = false;
+        self.pos += 1;
+        loop {
+            match self.json.get(self.pos) {
+                None => return Err(Error::InvalidData("Invalid JSON")),
+                Some(b'"') if !escaped => break,
+                Some(&b) => escaped = !escaped && b == b'\\',
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        let token = &self.json[start..self.pos];
+
+        let parsed = Deserializer::new(token, Some(&mut *scratch))
+            .deserialize_str(StrVisitor)
+            .map_err(json_error)?;
+        Ok(match parsed {
+            Str::Borrowed(s) => (s, scratch),
+            Str::Unescaped(len) => {
+                let (s, rest) = scratch.split_at_mut(len);
+                // Already checked by serde-json-core
+                let s = core::str::from_utf8(s).map_err(|_| Error::InvalidData("Invalid JSON"))?;
+                (s, rest)
+            }
+        })
+    }
+
+    /// Parse the members of a nested object or array, its opening bracket already consumed
+    fn nested(
+        &mut self,
+        scratch: &mut [u8],
+        builder: &mut BlobMsgBuilder,
+        object: bool,
+    ) -> Result<(), Error> {
+        let outer = self.depth;
+        self.depth = outer.nested()?;
+        let result = self.container(scratch, builder, object);
+        self.depth = outer;
+        result
+    }
+
+    /// Parse the members of an object or array, its opening bracket already consumed
+    fn container(
+        &mut self,
+        scratch: &mut [u8],
+        builder: &mut BlobMsgBuilder,
+        object: bool,
+    ) -> Result<(), Error> {
+        let end = if object { b'}' } else { b']' };
+        if self.peek() == Some(end) {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            let (name, scratch) = if object {
+                let (name, scratch) = self.string(&mut *scratch)?;
+                self.expect(b':')?;
+                (name, scratch)
+            } else {
+                ("", &mut *scratch)
+            };
+            self.value(name, scratch, builder)?;
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b) if b == end => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => return Err(Error::InvalidData("Invalid JSON")),
+            }
+        }
+    }
+
+    fn value(
+        &mut self,
+        name: &str,
+        scratch: &mut [u8],
+        builder: &mut BlobMsgBuilder,
+    ) -> Result<(), Error> {
+        match self.peek() {
+            Some(b'{') => {
+                self.pos += 1;
+                builder.push_table(name, |b| self.nested(scratch, b, true))
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                builder.push_array(name, |b| self.nested(scratch, b, false))
+            }
+            Some(b'"') => {
+                let (value, _) = self.string(scratch)?;
+                builder.push_string(name, value)
+            }
+            Some(b'-') | Some(b'0'..=b'9') => {
+                let token = self.take_while(|b| b.is_ascii_digit() || b"+-.eE".contains(&b));
+                if token.iter().any(|b| b".eE".contains(b)) {
+                    let (value, _) = serde_json_core::from_slice(token).map_err(json_error)?;
+                    builder.push_double(name, value)
+                } else {
+                    let (value, _): (i64, _) =
+                        serde_json_core::from_slice(token).map_err(json_error)?;
+                    match i32::try_from(value) {
+                        Ok(value) => builder.push_int32(name, value),
+                        Err(_) => builder.push_int64(name, value),
+                    }
+                }
+            }
+            Some(b'n') => {
+                let token = self.take_while(|b| b.is_ascii_alphabetic());
+                let ((), _) = serde_json_core::from_slice(token).map_err(json_error)?;
+                builder.push_null(name)
+            }
+            Some(_) => {
+                let token = self.take_while(|b| b.is_ascii_alphabetic());
+                let (value, _) = serde_json_core::from_slice(token).map_err(json_error)?;
+                builder.push_bool(name, value)
+            }
+            None => Err(Error::InvalidData("Invalid JSON")),
+        }
+    }
+}
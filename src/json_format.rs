@@ -0,0 +1,162 @@
+use crate::*;
+use core::fmt::{self, Write};
+
+/// Write the attributes of a table (as a JSON object) or an array, converting values as libubox's
+/// `blobmsg_format_json` does
+///
+/// Writes straight to `out`, without building a JSON value first. Fails if tables and arrays are
+/// nested deeper than `DEFAULT_MAX_DEPTH` (part of the output will have been written).
+pub(crate) fn format_items(
+    out: &mut dyn Write,
+    items: BlobIter<BlobMsg>,
+    table: bool,
+    depth: Depth,
+) -> fmt::Result {
+    out.write_char(if table { '{' } else { '[' })?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.write_char(',')?;
+        }
+        if table {
+            format_name(out, item.raw_name.unwrap_or(b""))?;
+            out.write_char(':')?;
+        }
+        format_value(out, &item.data, depth)?;
+    }
+    out.write_char(if table { '}' } else { ']' })
+}
+
+fn format_value(out: &mut dyn Write, data: &BlobMsgData, depth: Depth) -> fmt::Result {
+    match data {
+        BlobMsgData::Table(items) => {
+            format_items(out, BlobIter::new(items.as_bytes()), true, nested(depth)?)
+        }
+        BlobMsgData::Array(items) => {
+            format_items(out, BlobIter::new(items.as_bytes()), false, nested(depth)?)
+        }
+        BlobMsgData::String(v) => format_string(out, v),
+        BlobMsgData::Int64(v) => write!(out, "{}", v),
+        BlobMsgData::Int32(v) => write!(out, "{}", v),
+        BlobMsgData::Int16(v) => write!(out, "{}", v),
+        BlobMsgData::Int8(v) => out.write_str(if *v != 0 { "true" } else { "false" }),
+        BlobMsgData::Double(v) if v.is_finite() => write!(out, "{}", v),
+        BlobMsgData::Double(_) | BlobMsgData::Unknown(..) => out.write_str("null"),
+    }
+}
+
+/// `Depth::nested`, for formatters (which can't say why they failed)
+pub(crate) fn nested(depth: Depth) -> Result<Depth, fmt::Error> {
+    depth.nested().map_err(|_| fmt::Error)
+}
+
+pub(crate) fn format_string(out: &mut dyn Write, s: &str) -> fmt::Result {
+    out.write_char('"')?;
+    format_chars(out, s)?;
+    out.write_char('"')
+}
+
+/// Like `format_string`, replacing any invalid UTF-8 with U+FFFD
+fn format_name(out: &mut dyn Write, name: &[u8]) -> fmt::Result {
+    out.write_char('"')?;
+    for chunk in name.utf8_chunks() {
+        format_chars(out, chunk.valid())?;
+        if !chunk.invalid().is_empty() {
+            out.write_char(char::REPLACEMENT_CHARACTER)?;
+        }
+    }
+    out.write_char('"')
+}
+
+fn format_chars(out: &mut dyn Write, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Formats the value as JSON (without its name), converting values as `format_items` does
+///
+/// Fails if tables and arrays are nested deeper than `DEFAULT_MAX_DEPTH`.
+impl fmt::Display for BlobMsgData<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format_value(f, self, Depth::new(DEFAULT_MAX_DEPTH))
+    }
+}
+
+/// Formats the value as JSON, as `BlobMsgData` does (a name can only be kept by the enclosing
+/// table)
+impl fmt::Display for BlobMsg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.data.fmt(f)
+    }
+}
+
+/// Formats the remaining attributes (such as a reply) as a JSON object
+impl fmt::Display for BlobIter<'_, BlobMsg<'_>> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let depth = Depth::new(DEFAULT_MAX_DEPTH);
+        format_items(f, BlobIter::new(self.as_bytes()), true, depth)
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+mod writer {
+    use super::*;
+    use std::io;
+
+    /// Adapts an `io::Write` for the formatter, keeping the error `fmt::Error` can't carry
+    struct Adapter<'w, W> {
+        out: &'w mut W,
+        error: Option<io::Error>,
+    }
+
+    impl<W: io::Write> Write for Adapter<'_, W> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.out.write_all(s.as_bytes()).map_err(|e| {
+                self.error = Some(e);
+                fmt::Error
+            })
+        }
+    }
+
+    impl<T: IO<Error = io::Error>> Connection<T> {
+        /// Like `invoke`, writing each reply's DATA table to `out` as a line of JSON
+        ///
+        /// Replies are written as they arrive, straight from the receive buffer, so gateways
+        /// needn't hold (or convert) a whole large reply at once. Values are converted as
+        /// `BlobIter::to_json` does.
+        pub fn invoke_to_writer(
+            &mut self,
+            obj: u32,
+            method: &str,
+            args: &[u8],
+            out: &mut impl io::Write,
+        ) -> Result<(), Error<io::Error>> {
+            let mut result = Ok(());
+            self.invoke(obj, method, args, |data| {
+                if result.is_err() {
+                    return;
+                }
+                let mut adapter = Adapter { out, error: None };
+                let depth = Depth::new(DEFAULT_MAX_DEPTH);
+                let written = format_items(&mut adapter, data, true, depth)
+                    .and_then(|()| adapter.write_char('\n'));
+                if written.is_err() {
+                    result = Err(match adapter.error {
+                        Some(e) => Error::IO(e),
+                        None => Error::InvalidData("Blobmsg nested too deeply"),
+                    });
+                }
+            })?;
+            result
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::*;
+use serde_json::{Map, Value};
+use std::string::{String, ToString};
+
+impl BlobMsgBuilder<'_> {
+    /// Push the members of a JSON object, as libubox's `blobmsg_add_object` does
+    ///
+    /// Anything other than an object is rejected, as it has no names to push values under.
+    pub fn push_json(&mut self, value: &Value) -> Result<(), Error> {
+        match value {
+            Value::Object(object) => self.push_json_object(object, Depth::new(DEFAULT_MAX_DEPTH)),
+            _ => Err(Error::InvalidData("JSON value is not an object")),
+        }
+    }
+
+    /// Push the members of an object at `depth`
+    fn push_json_object(&mut self, object: &Map<String, Value>, depth: Depth) -> Result<(), Error> {
+        object
+            .iter()
+            .try_for_each(|(name, value)| push_json_value(self, name, value, depth))
+    }
+}
+
+/// Converted as libubox's `blobmsg_add_json_element` does: booleans become INT8, integers INT32
+/// (or INT64 if they don't fit), other numbers DOUBLE, and null UNSPEC
+///
+/// Fails if objects and arrays are nested deeper than `DEFAULT_MAX_DEPTH`.
+impl PushBlobMsg for Value {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+        push_json_value(builder, name, self, Depth::new(DEFAULT_MAX_DEPTH))
+    }
+}
+
+/// `push_blobmsg` for a value at `depth`
+fn push_json_value(
+    builder: &mut BlobMsgBuilder,
+    name: &str,
+    value: &Value,
+    depth: Depth,
+) -> Result<(), Error> {
+    match value {
+        Value::Null => builder.push_null(name),
+        Value::Bool(v) => builder.push_bool(name, *v),
+        Value::Number(n) => match n.as_i64() {
+            Some(v) if v >= i32::MIN as i64 && v <= i32::MAX as i64 => {
+                builder.push_int32(name, v as i32)
+            }
+            Some(v) => builder.push_int64(name, v),
+            None => builder.push_double(name, n.as_f64().unwrap_or_default()),
+        },
+        Value::String(v) => builder.push_string(name, v),
+        Value::Array(items) => {
+            let depth = depth.nested()?;
+            builder.push_array(name, |b| {
+                items
+                    .iter()
+                    .try_for_each(|item| push_json_value(b, "", item, depth))
+            })
+        }
+        Value::Object(object) => {
+            let depth = depth.nested()?;
+            builder.push_table(name, |b| b.push_json_object(object, depth))
+        }
+    }
+}
+
+impl BlobIter<'_, BlobMsg<'_>> {
+    /// Convert the remaining attributes to a JSON object, as libubox's `blobmsg_format_json` does
+    ///
+    /// INT8 values become booleans and UNSPEC (or unknown types) null. Fails if tables and arrays
+    /// are nested deeper than `DEFAULT_MAX_DEPTH`.
+    pub fn to_json(&self) -> Result<Map<String, Value>, Error> {
+        self.to_json_limit(DEFAULT_MAX_DEPTH)
+    }
+
+    /// Like `to_json`, but failing if tables and arrays are nested deeper than `max_depth`
+    pub fn to_json_limit(&self, max_depth: usize) -> Result<Map<String, Value>, Error> {
+        json_object(BlobIter::new(self.as_bytes()), Depth::new(max_depth))
+    }
+}
+
+impl BlobMsgData<'_> {
+    /// Convert the value to JSON (see `BlobIter::to_json`)
+    pub fn to_json(&self) -> Result<Value, Error> {
+        json_value(self, Depth::new(DEFAULT_MAX_DEPTH))
+    }
+}
+
+/// `to_json` for a table at `depth`
+fn json_object(items: BlobIter<BlobMsg>, depth: Depth) -> Result<Map<String, Value>, Error> {
+    items
+        .map(|item| {
+            let name = item.name.unwrap_or("").to_string();
+            Ok((name, json_value(&item.data, depth)?))
+        })
+        .collect()
+}
+
+/// `to_json` for a value at `depth`
+fn json_value(data: &BlobMsgData, depth: Depth) -> Result<Value, Error> {
+    Ok(match data {
+        BlobMsgData::Array(items) => {
+            let depth = depth.nested()?;
+            BlobIter::<BlobMsg>::new(items.as_bytes())
+                .map(|item| json_value(&item.data, depth))
+                .collect::<Result<_, _>>()?
+        }
+        BlobMsgData::Table(items) => Value::Object(json_object(
+            BlobIter::new(items.as_bytes()),
+            depth.nested()?,
+        )?),
+        BlobMsgData::String(v) => (*v).into(),
+        BlobMsgData::Int64(v) => (*v).into(),
+        BlobMsgData::Int32(v) => (*v).into(),
+        BlobMsgData::Int16(v) => (*v).into(),
+        // libubox treats INT8 as a boolean
+        BlobMsgData::Int8(v) => (*v != 0).into(),
+        BlobMsgData::Double(v) => (*v).into(),
+        BlobMsgData::Unknown(..) => Value::Null,
+    })
+}
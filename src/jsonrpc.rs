@@ -0,0 +1,192 @@
+use crate::*;
+use serde_json::{json, Map, Value};
+use std::io;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// Session id used before logging in, which rpcd grants only the anonymous ACLs
+pub const ANONYMOUS_SESSION: &str = "00000000000000000000000000000000";
+
+/// Client for the `/ubus` JSON-RPC interface exposed over HTTP(S) by uhttpd (uhttpd-mod-ubus)
+///
+/// Offers the same `Bus` calls as a socket connection, for controlling routers which can only be
+/// reached over the network. Arguments and results are converted between blobmsg and JSON, so
+/// INT8 values become booleans and integers come back as INT32 (or INT64 if they don't fit).
+pub struct JsonRpc {
+    agent: ureq::Agent,
+    url: String,
+    session: String,
+    id: u64,
+}
+
+impl JsonRpc {
+    /// Talk to the JSON-RPC endpoint at `url` (e.g. `https://192.168.1.1/ubus`), anonymously
+    pub fn new(url: &str) -> Self {
+        Self::with_agent(ureq::Agent::new(), url)
+    }
+
+    /// Like `new`, making requests with `agent` (e.g. to set timeouts or TLS options)
+    pub fn with_agent(agent: ureq::Agent, url: &str) -> Self {
+        Self {
+            agent,
+            url: url.to_string(),
+            session: ANONYMOUS_SESSION.to_string(),
+            id: 0,
+        }
+    }
+
+    /// Log in through rpcd's `session` object, using the new session for later calls
+    pub fn login(&mut self, username: &str, password: &str) -> Result<(), Error<io::Error>> {
+        let args = json!({ "username": username, "password": password });
+        let data = self.call_json("session", "login", args)?;
+        let session = data
+            .as_ref()
+            .and_then(|data| data.get("ubus_rpc_session")?.as_str())
+            .ok_or(Error::<NoIO>::InvalidData("No session in login reply"))?;
+        self.session = session.to_string();
+        Ok(())
+    }
+
+    /// Session id passed with each call
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Use an existing session (e.g. one saved from an earlier `login`)
+    pub fn set_session(&mut self, session: &str) {
+        self.session = session.to_string();
+    }
+
+    /// Make a JSON-RPC request, returning its result
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, Error<io::Error>> {
+        self.id += 1;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": self.id,
+            "method": method,
+            "params": params,
+        });
+        let response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(request)
+            .map_err(http_error)?
+            .into_json()
+            .map_err(Error::IO)?;
+
+        if let Some(error) = response.get("error") {
+            return Err(match error.get("code").and_then(Value::as_i64) {
+                // Mapped back to the ubus status codes uhttpd translated them from
+                Some(-32000) => Error::Status(4),
+                Some(-32601) => Error::Status(3),
+                Some(-32602) => Error::Status(2),
+                Some(-32001) | Some(-32002) => Error::Status(6),
+                Some(-32003) => Error::Timeout,
+                _ => Error::InvalidData("JSON-RPC error"),
+            });
+        }
+        let result = response.get("result");
+        Ok(result
+            .cloned()
+            .ok_or(Error::<NoIO>::InvalidData("No result in JSON-RPC reply"))?)
+    }
+
+    /// Call `method` on `path` with JSON arguments, returning the reply table (if any)
+    fn call_json(
+        &mut self,
+        path: &str,
+        method: &str,
+        args: Value,
+    ) -> Result<Option<Map<String, Value>>, Error<io::Error>> {
+        let params = json!([self.session, path, method, args]);
+        let mut result = match self.request("call", params)? {
+            Value::Array(result) => result.into_iter(),
+            _ => return Err(Error::InvalidData("Invalid call result")),
+        };
+        let status = result
+            .next()
+            .and_then(|status| status.as_i64())
+            .ok_or(Error::<NoIO>::InvalidData("No status in call result"))?;
+        if status != 0 {
+            return Err(Error::Status(status as i32));
+        }
+        match result.next() {
+            Some(Value::Object(data)) => Ok(Some(data)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Bus for JsonRpc {
+    type Error = io::Error;
+
+    fn call(
+        &mut self,
+        path: &str,
+        method: &str,
+        args: &[u8],
+        mut on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<(), Error<io::Error>> {
+        let args = Value::Object(BlobIter::<BlobMsg>::new(args).to_json()?);
+        if let Some(data) = self.call_json(path, method, args)? {
+            let mut buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+            let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+            builder.push_json(&Value::Object(data))?;
+            on_result(BlobIter::new(builder.finish()));
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        path: Option<&str>,
+        mut on_object: impl FnMut(ObjectResult),
+        mut on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<io::Error>> {
+        let objects = match self.request("list", json!([path.unwrap_or("*")]))? {
+            Value::Object(objects) => objects,
+            _ => return Err(Error::InvalidData("Invalid list result")),
+        };
+        for (path, methods) in &objects {
+            // The JSON-RPC interface doesn't expose object ids or types
+            let object = ObjectResult { path, id: 0, ty: 0 };
+            on_object(object);
+            let methods = methods.as_object().into_iter().flatten();
+            for (name, args) in methods {
+                let args: Vec<(&str, BlobMsgType)> = args
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(arg, ty)| (arg.as_str(), type_from_name(ty.as_str())))
+                    .collect();
+                on_signature(SignatureResult {
+                    object,
+                    name,
+                    args: &mut args.into_iter(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn http_error(e: ureq::Error) -> Error<io::Error> {
+    let message = match e {
+        ureq::Error::Status(status, _) => std::format!("HTTP status {}", status),
+        ureq::Error::Transport(transport) => transport.to_string(),
+    };
+    Error::IO(io::Error::other(message))
+}
+
+/// Blobmsg type for the argument type names uhttpd lists in signatures
+fn type_from_name(name: Option<&str>) -> BlobMsgType {
+    match name {
+        Some("boolean") => BlobMsgType::INT8,
+        Some("number") => BlobMsgType::INT32,
+        Some("string") => BlobMsgType::STRING,
+        Some("array") => BlobMsgType::ARRAY,
+        Some("object") => BlobMsgType::TABLE,
+        Some("double") => BlobMsgType::DOUBLE,
+        _ => BlobMsgType::UNSPEC,
+    }
+}
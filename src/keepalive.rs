@@ -0,0 +1,81 @@
+use crate::split::{Reply, Waiter};
+use crate::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Path looked up to ping ubusd, which answers (normally with NOT_FOUND) while it's there
+const PING_PATH: &str = "ubus-rs.keepalive";
+
+/// Handle to a keepalive started with `Requester::keepalive`
+///
+/// Dropping the handle stops the pings.
+#[derive(Debug)]
+pub struct Keepalive {
+    broken: Arc<AtomicBool>,
+    _stop: Sender<()>,
+}
+
+impl Keepalive {
+    /// Has a ping gone unanswered, so the connection was shut down
+    pub fn is_broken(&self) -> bool {
+        self.broken.load(Ordering::Relaxed)
+    }
+}
+
+impl Requester {
+    /// Ping ubusd every `interval` from a background thread, shutting the connection down if a
+    /// ping isn't answered within `timeout`
+    ///
+    /// A socket which has silently died (e.g. ubusd hung, or the peer's end vanished without a
+    /// close) would otherwise leave requests and the `EventReader` waiting forever. Once shut down
+    /// the `EventReader` fails with an IO error and waiting requests are woken, so it's noticed
+    /// (and reconnected, e.g. with `connect_with_retry`) within `interval + timeout`.
+    pub fn keepalive(&self, interval: Duration, timeout: Duration) -> Keepalive {
+        let broken = Arc::new(AtomicBool::new(false));
+        let (stop, stopped) = channel();
+        let requester = self.clone();
+        let flag = broken.clone();
+        thread::spawn(move || {
+            // Stops once the handle is dropped
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if !requester.ping(timeout) {
+                    warn!("Keepalive ping unanswered, shutting the connection down");
+                    flag.store(true, Ordering::Relaxed);
+                    requester.shutdown();
+                    return;
+                }
+            }
+        });
+        Keepalive {
+            broken,
+            _stop: stop,
+        }
+    }
+
+    /// Send a ping, returning whether ubusd answered within `timeout`
+    fn ping(&self, timeout: Duration) -> bool {
+        let (tx, rx) = channel();
+        let request = LookupRequest::path(PING_PATH);
+        if self
+            .send_request(LookupRequest::TYPE, 0, request, Waiter::Blocking(tx))
+            .is_err()
+        {
+            return false;
+        }
+        wait_for_status(&rx, Instant::now() + timeout)
+    }
+}
+
+fn wait_for_status(replies: &Receiver<Reply>, deadline: Instant) -> bool {
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match replies.recv_timeout(left) {
+            Ok(Reply::Status(_)) => return true,
+            Ok(Reply::Data(_)) => continue,
+            Err(_) => return false,
+        }
+    }
+}
@@ -1,9 +1,12 @@
 #![no_std]
 #![allow(dead_code)]
 
-#[cfg(not(no_std))]
+#[cfg(not(feature = "no_std"))]
 extern crate std;
 
+#[cfg(all(feature = "cli", feature = "no_std"))]
+compile_error!("The `cli` feature builds the command line tools, which need std (not `no_std`)");
+
 /// Macro for defining helpful enum-like opaque structs
 macro_rules! values {
     (
@@ -54,11 +57,49 @@ macro_rules! values {
                 }
             }
         }
+        #[cfg(all(feature = "fuzzing", not(feature = "no_std")))]
+        impl<'a> arbitrary::Arbitrary<'a> for $name {
+            fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+                // Mostly known values, which get further into the parsers
+                if u.ratio(3, 4)? {
+                    Ok(*u.choose(&[ $( Self::$variant ),* ])?)
+                } else {
+                    Ok(Self(u.arbitrary()?))
+                }
+            }
+        }
+    };
+}
+
+/// Log macros which forward to the `log` crate when the feature is enabled
+#[cfg(feature = "log")]
+macro_rules! warn {
+    ($($arg:tt)*) => (log::warn!($($arg)*))
+}
+#[cfg(feature = "log")]
+macro_rules! trace {
+    ($($arg:tt)*) => (log::trace!($($arg)*))
+}
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)*) => {{ if false { let _ = format_args!($($arg)*); } }}
+}
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {{ if false { let _ = format_args!($($arg)*); } }}
+}
+
+/// Record a value into the current tracing span (when the feature is enabled)
+macro_rules! span_record {
+    ($field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
     };
 }
 
+/// Debug builds panic on invalid data, except when fuzzing (which feeds it in on purpose)
 macro_rules! invalid_data_panic {
-    ($($arg:tt)*) => (if cfg!(debug_assertions) { panic!($($arg)*); })
+    ($($arg:tt)*) => (if cfg!(all(debug_assertions, not(feature = "fuzzing"))) { panic!($($arg)*); })
 }
 
 macro_rules! valid_data {
@@ -95,6 +136,8 @@ pub enum Error<T = NoIO> {
     IO(T),
     InvalidData(&'static str),
     Status(i32),
+    /// A read or write deadline expired
+    Timeout,
 }
 
 impl<T: core::fmt::Display> core::fmt::Display for Error<T> {
@@ -104,6 +147,21 @@ impl<T: core::fmt::Display> core::fmt::Display for Error<T> {
             IO(e) => write!(f, "IO Error: {}", e),
             InvalidData(e) => write!(f, "Invalid Data: {}", e),
             Status(e) => write!(f, "Ubus Status: {}", e),
+            Timeout => write!(f, "Timed out"),
+        }
+    }
+}
+
+impl core::error::Error for NoIO {}
+
+/// Available without std too, so no_std code (and error handling crates) can wrap it
+///
+/// The `source` of an IO error is the transport's own error.
+impl<T: core::error::Error + 'static> core::error::Error for Error<T> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -115,6 +173,7 @@ impl<T: IOError> From<Error<NoIO>> for Error<T> {
             IO(_) => unreachable!(),
             InvalidData(v) => InvalidData(v),
             Status(v) => Status(v),
+            Timeout => Timeout,
         }
     }
 }
@@ -131,17 +190,214 @@ pub trait IO {
     type Error: IOError;
     fn put(&mut self, data: &[u8]) -> Result<(), Error<Self::Error>>;
     fn get(&mut self, data: &mut [u8]) -> Result<(), Error<Self::Error>>;
+
+    /// Like `put`, also passing the file descriptor `fd` to the peer (if supported)
+    fn put_fd(&mut self, _data: &[u8], _fd: i32) -> Result<(), Error<Self::Error>> {
+        Err(Error::InvalidData("File descriptor passing not supported"))
+    }
+    /// Like `get`, also returning any file descriptor passed by the peer
+    fn get_fd(&mut self, data: &mut [u8]) -> Result<Option<i32>, Error<Self::Error>> {
+        self.get(data).map(|_| None)
+    }
+    /// Flush anything buffered and shut the transport down (if supported)
+    fn close(&mut self) -> Result<(), Error<Self::Error>> {
+        Ok(())
+    }
+}
+
+/// Like `IO`, for transports driven by an async runtime (used by `AsyncConnection`)
+pub trait AsyncIO {
+    type Error: IOError;
+    fn put(
+        &mut self,
+        data: &[u8],
+    ) -> impl core::future::Future<Output = Result<(), Error<Self::Error>>>;
+    fn get(
+        &mut self,
+        data: &mut [u8],
+    ) -> impl core::future::Future<Output = Result<(), Error<Self::Error>>>;
+
+    /// Like `put`, also passing the file descriptor `fd` to the peer (if supported)
+    fn put_fd(
+        &mut self,
+        _data: &[u8],
+        _fd: i32,
+    ) -> impl core::future::Future<Output = Result<(), Error<Self::Error>>> {
+        async { Err(Error::InvalidData("File descriptor passing not supported")) }
+    }
+    /// Like `get`, also returning any file descriptor passed by the peer
+    fn get_fd(
+        &mut self,
+        data: &mut [u8],
+    ) -> impl core::future::Future<Output = Result<Option<i32>, Error<Self::Error>>> {
+        async move { self.get(data).await.map(|_| None) }
+    }
 }
 
-#[cfg(not(no_std))]
+#[cfg(feature = "async")]
+mod async_connection;
+#[cfg(not(feature = "no_std"))]
+mod buffer_pool;
+#[cfg(not(feature = "no_std"))]
+mod builder;
+#[cfg(not(feature = "no_std"))]
 mod stdio;
+#[cfg(not(feature = "no_std"))]
+pub use stdio::{default_socket, StdIo, DEFAULT_SOCKET_PATHS};
 
 mod blob;
 mod blobmsg;
+#[cfg(not(feature = "no_std"))]
+mod broker;
+mod bus;
+#[cfg(all(feature = "calloop", not(feature = "no_std")))]
+mod calloop_source;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(not(feature = "no_std"))]
+mod codegen;
 mod connection;
+#[cfg(feature = "nb")]
+mod engine;
+#[cfg(not(feature = "no_std"))]
+mod event;
+pub mod events;
+#[cfg(all(feature = "ffi", not(feature = "no_std")))]
+pub mod ffi;
+#[cfg(all(feature = "fuzzing", not(feature = "no_std")))]
+mod fuzz;
+#[cfg(not(feature = "no_std"))]
+mod hooks;
+#[cfg(feature = "json")]
+mod json;
+mod json_format;
+#[cfg(all(feature = "serde_json", not(feature = "no_std")))]
+mod json_value;
+#[cfg(all(feature = "jsonrpc", not(feature = "no_std")))]
+mod jsonrpc;
+#[cfg(not(feature = "no_std"))]
+mod keepalive;
+mod lookup;
+#[cfg(not(feature = "no_std"))]
+mod loopback;
+mod maybe_async;
 mod message;
+mod monitor;
+#[cfg(feature = "nb")]
+mod nb_connection;
+#[cfg(not(feature = "no_std"))]
+mod object;
+#[cfg(not(feature = "no_std"))]
+mod pcap;
+#[cfg(not(feature = "no_std"))]
+mod pool;
+#[cfg(not(feature = "no_std"))]
+mod proxy;
+#[cfg(not(feature = "no_std"))]
+mod reconnect;
+#[cfg(not(feature = "no_std"))]
+mod record;
+mod request;
+#[cfg(not(feature = "no_std"))]
+mod retry;
+#[cfg(not(feature = "no_std"))]
+mod schema;
+#[cfg(not(feature = "no_std"))]
+mod select;
+mod sequence;
+#[cfg(all(feature = "services", not(feature = "no_std")))]
+pub mod services;
+mod session;
+#[cfg(all(feature = "smol", not(feature = "no_std")))]
+mod smol_io;
+#[cfg(feature = "smoltcp")]
+mod smoltcp_io;
+#[cfg(not(feature = "no_std"))]
+mod split;
+#[cfg(not(feature = "no_std"))]
+mod stats;
+#[cfg(all(feature = "async", not(feature = "no_std")))]
+mod stream;
+#[cfg(not(feature = "no_std"))]
+mod subscriber;
+#[cfg(not(feature = "no_std"))]
+mod threaded;
+#[cfg(all(feature = "tokio", not(feature = "no_std")))]
+mod tokio_io;
+#[cfg(not(feature = "no_std"))]
+mod value;
 
+#[cfg(feature = "async")]
+pub use async_connection::*;
 pub use blob::*;
 pub use blobmsg::*;
+#[cfg(not(feature = "no_std"))]
+pub use broker::*;
+#[cfg(not(feature = "no_std"))]
+pub use buffer_pool::*;
+#[cfg(not(feature = "no_std"))]
+pub use builder::*;
+pub use bus::*;
+#[cfg(all(feature = "calloop", not(feature = "no_std")))]
+pub use calloop_source::*;
+#[cfg(feature = "cbor")]
+pub use cbor::*;
+#[cfg(not(feature = "no_std"))]
+pub use codegen::*;
 pub use connection::*;
+#[cfg(feature = "nb")]
+pub use engine::*;
+#[cfg(not(feature = "no_std"))]
+pub use event::*;
+#[cfg(all(feature = "fuzzing", not(feature = "no_std")))]
+pub use fuzz::*;
+#[cfg(not(feature = "no_std"))]
+pub use hooks::*;
+#[cfg(feature = "json")]
+pub use json::*;
+#[cfg(all(feature = "jsonrpc", not(feature = "no_std")))]
+pub use jsonrpc::*;
+#[cfg(not(feature = "no_std"))]
+pub use keepalive::*;
+pub use lookup::*;
+#[cfg(not(feature = "no_std"))]
+pub use loopback::*;
 pub use message::*;
+pub use monitor::*;
+#[cfg(feature = "nb")]
+pub use nb_connection::*;
+#[cfg(not(feature = "no_std"))]
+pub use object::*;
+#[cfg(not(feature = "no_std"))]
+pub use pcap::*;
+#[cfg(not(feature = "no_std"))]
+pub use pool::*;
+#[cfg(not(feature = "no_std"))]
+pub use proxy::*;
+#[cfg(not(feature = "no_std"))]
+pub use reconnect::*;
+#[cfg(not(feature = "no_std"))]
+pub use record::*;
+pub use request::*;
+#[cfg(not(feature = "no_std"))]
+pub use retry::*;
+#[cfg(not(feature = "no_std"))]
+pub use schema::*;
+#[cfg(not(feature = "no_std"))]
+pub use select::*;
+pub use sequence::*;
+pub use session::*;
+#[cfg(feature = "smoltcp")]
+pub use smoltcp_io::*;
+#[cfg(not(feature = "no_std"))]
+pub use split::*;
+#[cfg(not(feature = "no_std"))]
+pub use stats::*;
+#[cfg(all(feature = "async", not(feature = "no_std")))]
+pub use stream::*;
+#[cfg(not(feature = "no_std"))]
+pub use subscriber::*;
+#[cfg(not(feature = "no_std"))]
+pub use threaded::*;
+#[cfg(not(feature = "no_std"))]
+pub use value::*;
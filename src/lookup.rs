@@ -0,0 +1,204 @@
+use crate::maybe_async::close_fd;
+use crate::*;
+
+/// An object found by a lookup, with its methods
+#[derive(Clone, Copy)]
+pub struct LookupEntry<'a> {
+    pub object: ObjectResult<'a>,
+    signature: &'a [u8],
+}
+
+impl<'a> LookupEntry<'a> {
+    /// Parse the attributes of a DATA reply to a lookup
+    pub fn parse(attrs: BlobIter<'a, MessageAttr<'a>>) -> Result<Self, Error> {
+        let (mut path, mut id, mut ty) = (None, None, None);
+        let mut signature: &[u8] = &[];
+        for attr in attrs {
+            match attr {
+                MessageAttr::ObjPath(val) => path = Some(val),
+                MessageAttr::ObjId(val) => id = Some(val),
+                MessageAttr::ObjType(val) => ty = Some(val),
+                MessageAttr::Signature(val) => signature = val.as_bytes(),
+                _ => {}
+            }
+        }
+        let (Some(path), Some(id), Some(ty)) = (path, id, ty) else {
+            return Err(Error::InvalidData("Lookup reply is missing a field"));
+        };
+        Ok(Self {
+            object: ObjectResult { path, id, ty },
+            signature,
+        })
+    }
+
+    /// The object's methods, as listed in its signature
+    pub fn methods(&self) -> impl Iterator<Item = LookupMethod<'a>> + 'a {
+        BlobIter::<BlobMsg>::new(self.signature).filter_map(|method| match method.data {
+            BlobMsgData::Table(args) => Some(LookupMethod {
+                name: method.name.unwrap_or(""),
+                args: args.as_bytes(),
+            }),
+            _ => None,
+        })
+    }
+}
+
+impl core::fmt::Debug for LookupEntry<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list()
+            .entry(&self.object)
+            .entries(self.methods())
+            .finish()
+    }
+}
+
+/// A method of an object found by a lookup
+#[derive(Clone, Copy)]
+pub struct LookupMethod<'a> {
+    pub name: &'a str,
+    args: &'a [u8],
+}
+
+impl<'a> LookupMethod<'a> {
+    /// The method's arguments, with the types it expects
+    pub fn args(&self) -> LookupArgs<'a> {
+        LookupArgs(BlobIter::new(self.args))
+    }
+}
+
+/// Iterator over the arguments of a `LookupMethod`, as names and types
+pub struct LookupArgs<'a>(BlobIter<'a, BlobMsg<'a>>);
+
+impl<'a> Iterator for LookupArgs<'a> {
+    type Item = (&'a str, BlobMsgType);
+    fn next(&mut self) -> Option<Self::Item> {
+        for arg in &mut self.0 {
+            if let BlobMsgData::Int32(ty) = arg.data {
+                return Some((arg.name.unwrap_or(""), BlobMsgType::from(ty as u32)));
+            }
+        }
+        None
+    }
+}
+
+impl core::fmt::Debug for LookupMethod<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (i, (name, ty)) in self.args().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{}{}: {:?}", sep, name, ty)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A lookup in progress, returned by `Connection::lookup_iter`
+///
+/// Each call to `next` receives the next object, so errors come back with `?` and the caller can
+/// stop at any point. Dropping the lookup before it has finished leaves the rest of its replies
+/// to be dropped as they arrive (and a receive buffer borrowed from a `BufferPool` to be given
+/// back by the next call which receives). Other messages arriving meanwhile (such as calls to our
+/// objects) are handled as `handle_next_message` would.
+pub struct Lookup<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    sequence: u16,
+    done: bool,
+}
+
+impl<T: IO> Lookup<'_, T> {
+    /// The next object found, or `None` once they all have been
+    ///
+    /// Not an `Iterator`, as each entry borrows the connection's receive buffer until the next
+    /// call.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<LookupEntry<'_>>, Error<T::Error>> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.receive() {
+            Ok(Some(fd)) => {
+                let message = Message::from_buffer(&self.connection.buffer[..], fd)?;
+                Ok(Some(LookupEntry::parse(BlobIter::new(message.blob.data))?))
+            }
+            result => {
+                self.done = true;
+                self.connection.release_buffer();
+                result.map(|_| None)
+            }
+        }
+    }
+
+    /// Receive up to the next reply to the lookup, returning the file descriptor passed with it if
+    /// it's DATA, or `None` for the final STATUS
+    fn receive(&mut self) -> Result<Option<Option<i32>>, Error<T::Error>> {
+        let c = &mut *self.connection;
+        c.borrow_buffer();
+        loop {
+            let message = Connection::recv(
+                &mut c.io,
+                &mut c.buffer,
+                c.max_message_size,
+                &mut c.handlers,
+            )?;
+            let sequence = u16::from(message.header.sequence);
+            match message.header.message {
+                MessageType::STATUS | MessageType::DATA if sequence != self.sequence => {
+                    trace!("Dropping unrelated {:?}", message);
+                    close_fd(message.fd);
+                    if message.header.message == MessageType::STATUS {
+                        c.sequences.finish(sequence);
+                    }
+                }
+                MessageType::STATUS => {
+                    c.sequences.finish(sequence);
+                    Response::from_message(&message).status()?;
+                    return Ok(None);
+                }
+                // Parsed again by `next`, to borrow the buffer for longer than the loop
+                MessageType::DATA => return Ok(Some(message.fd)),
+                _ => {
+                    let dispatched = c.handlers.dispatch(&mut c.io, c.strict, &message);
+                    close_fd(message.fd);
+                    dispatched?
+                }
+            }
+        }
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Look up the objects matching `path` (or all of them for `None`), returning a cursor to
+    /// receive them from one at a time
+    ///
+    /// ```no_run
+    /// # use ubus::*;
+    /// # fn f(connection: &mut Connection<std::os::unix::net::UnixStream>) -> Result<(), Error<std::io::Error>> {
+    /// let mut lookup = connection.lookup_iter(Some("network.*"))?;
+    /// while let Some(entry) = lookup.next()? {
+    ///     for method in entry.methods() {
+    ///         println!("{} {}", entry.object.path, method.name);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lookup_iter(&mut self, path: Option<&str>) -> Result<Lookup<'_, T>, Error<T::Error>> {
+        self.release_dropped()?;
+        let sequence = self.sequences.allocate();
+        let request = path.map_or_else(LookupRequest::all, LookupRequest::path);
+        let request = Request::new(sequence, request);
+        let mut buffer = message_buffer(request.size())?;
+        let builder = request.build(&mut buffer)?;
+        #[cfg(not(feature = "no_std"))]
+        self.handlers.hooks.wrap(&mut self.io).put(builder.into())?;
+        #[cfg(feature = "no_std")]
+        self.io.put(builder.into())?;
+        // Kept back until the final STATUS, in case the lookup is dropped before then
+        self.sequences.insert(sequence);
+        Ok(Lookup {
+            connection: self,
+            sequence,
+            done: false,
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use crate::split::lock;
+use crate::*;
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+/// Default number of bytes buffered in each direction of a loopback pair
+pub const LOOPBACK_CAPACITY: usize = 4 * DEFAULT_BUFFER_SIZE;
+
+#[derive(Default)]
+struct PipeState {
+    buffer: VecDeque<u8>,
+    closed: bool,
+}
+
+/// One direction of a loopback pair
+struct Pipe {
+    state: Mutex<PipeState>,
+    changed: Condvar,
+    capacity: usize,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::default(),
+            changed: Condvar::new(),
+            capacity,
+        })
+    }
+
+    fn close(&self) {
+        lock(&self.state).closed = true;
+        self.changed.notify_all();
+    }
+}
+
+struct Endpoint {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.tx.close();
+    }
+}
+
+/// One end of an in-memory connection, for wiring up clients and servers inside one process
+///
+/// Writes block while the other end's buffer is full. Once either end (and all its clones) is
+/// dropped, writes fail with `BrokenPipe` and reads fail with `UnexpectedEof` after draining.
+#[derive(Clone)]
+pub struct LoopbackIo {
+    endpoint: Arc<Endpoint>,
+}
+
+impl LoopbackIo {
+    /// Create two connected endpoints
+    pub fn pair() -> (Self, Self) {
+        Self::pair_with_capacity(LOOPBACK_CAPACITY)
+    }
+
+    /// Like `pair`, buffering up to `capacity` bytes in each direction
+    pub fn pair_with_capacity(capacity: usize) -> (Self, Self) {
+        let (a, b) = (Pipe::new(capacity), Pipe::new(capacity));
+        let first = Endpoint {
+            rx: a.clone(),
+            tx: b.clone(),
+        };
+        let second = Endpoint { rx: b, tx: a };
+        (
+            Self {
+                endpoint: Arc::new(first),
+            },
+            Self {
+                endpoint: Arc::new(second),
+            },
+        )
+    }
+}
+
+impl IO for LoopbackIo {
+    type Error = std::io::Error;
+    fn put(&mut self, mut data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        let pipe = &self.endpoint.tx;
+        let mut state = lock(&pipe.state);
+        while !data.is_empty() {
+            if state.closed {
+                return Err(Error::IO(ErrorKind::BrokenPipe.into()));
+            }
+            let space = pipe.capacity - state.buffer.len();
+            if space == 0 {
+                state = pipe
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+                continue;
+            }
+            let (chunk, rest) = data.split_at(space.min(data.len()));
+            state.buffer.extend(chunk);
+            data = rest;
+            pipe.changed.notify_all();
+        }
+        Ok(())
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        let pipe = &self.endpoint.rx;
+        let mut state = lock(&pipe.state);
+        let mut offset = 0;
+        while offset < data.len() {
+            if state.buffer.is_empty() {
+                if state.closed {
+                    return Err(Error::IO(ErrorKind::UnexpectedEof.into()));
+                }
+                state = pipe
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+                continue;
+            }
+            let len = state.buffer.len().min(data.len() - offset);
+            for (dst, src) in data[offset..offset + len]
+                .iter_mut()
+                .zip(state.buffer.drain(..len))
+            {
+                *dst = src;
+            }
+            offset += len;
+            pipe.changed.notify_all();
+        }
+        Ok(())
+    }
+    fn close(&mut self) -> Result<(), Error<std::io::Error>> {
+        self.endpoint.rx.close();
+        self.endpoint.tx.close();
+        Ok(())
+    }
+}
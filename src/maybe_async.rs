@@ -0,0 +1,177 @@
+use crate::*;
+use core::future::Future;
+use core::ops::ControlFlow;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+/// Run `future`, which must not wait, to completion
+///
+/// The protocol logic (receiving messages, exchanging requests for their replies) is written once
+/// as async functions. `AsyncConnection` awaits them, and `Connection` runs them over `Blocking`
+/// IO, which never waits, so they finish in a single poll.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let mut cx = Context::from_waker(Waker::noop());
+    match pin!(future).poll(&mut cx) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("Blocking IO never waits"),
+    }
+}
+
+/// A blocking `IO` as an `AsyncIO`, whose futures are ready as soon as they're polled
+pub(crate) struct Blocking<'a, T: ?Sized>(pub(crate) &'a mut T);
+
+impl<T: IO + ?Sized> AsyncIO for Blocking<'_, T> {
+    type Error = T::Error;
+    fn put(&mut self, data: &[u8]) -> impl Future<Output = Result<(), Error<T::Error>>> {
+        core::future::ready(self.0.put(data))
+    }
+    fn get(&mut self, data: &mut [u8]) -> impl Future<Output = Result<(), Error<T::Error>>> {
+        core::future::ready(self.0.get(data))
+    }
+    fn put_fd(
+        &mut self,
+        data: &[u8],
+        fd: i32,
+    ) -> impl Future<Output = Result<(), Error<T::Error>>> {
+        core::future::ready(self.0.put_fd(data, fd))
+    }
+    fn get_fd(
+        &mut self,
+        data: &mut [u8],
+    ) -> impl Future<Output = Result<Option<i32>, Error<T::Error>>> {
+        core::future::ready(self.0.get_fd(data))
+    }
+}
+
+/// What `exchange` needs of a connection, apart from its receive buffer
+pub(crate) trait Link {
+    type Error: IOError;
+    /// Send a whole message, passing `fd` along with it
+    async fn send(&mut self, data: &[u8], fd: Option<i32>) -> Result<(), Error<Self::Error>>;
+    /// Receive the next message into `buffer`
+    async fn recv<'b>(&mut self, buffer: &'b mut Buffer)
+        -> Result<Message<'b>, Error<Self::Error>>;
+    /// Handle a message which isn't a reply, such as a call to one of our objects
+    async fn dispatch(&mut self, message: &Message<'_>) -> Result<(), Error<Self::Error>>;
+}
+
+/// Send `request`, then wait for the final STATUS reply
+///
+/// Each DATA reply along the way is passed to `on_data`, which may break to stop waiting without
+/// the final STATUS (the rest of the replies are then dropped as they arrive, being for an old
+/// sequence number). Returns the file descriptor passed along with the last of the replies which
+/// had one. Any other received file descriptors (on earlier replies, unrelated messages, or
+/// if the request fails) are closed.
+///
+/// The request is in `sequences` until its final STATUS arrives, so if it's given up on (by
+/// breaking, failing or being cancelled) its sequence number isn't reused while replies may
+/// still come.
+pub(crate) async fn exchange<L: Link>(
+    link: &mut L,
+    buffer: &mut Buffer,
+    sequences: &mut Sequences<MAX_ABANDONED>,
+    request: &Request<'_>,
+    fd: Option<i32>,
+    mut on_data: impl FnMut(&Message) -> Result<ControlFlow<()>, Error>,
+) -> Result<Option<i32>, Error<L::Error>> {
+    let mut request_buffer = message_buffer(request.size())?;
+    let builder = request.build(&mut request_buffer)?;
+    sequences.insert(request.sequence);
+    link.send(builder.into(), fd).await?;
+
+    let mut reply_fd = None;
+    let result = loop {
+        let message = match link.recv(buffer).await {
+            Ok(message) => message,
+            Err(e) => break Err(e),
+        };
+        let response = Response::from_message(&message);
+        match message.header.message {
+            MessageType::STATUS | MessageType::DATA if !response.answers(request) => {
+                trace!("Dropping unrelated {:?}", message);
+                close_fd(message.fd);
+                if response.is_final() {
+                    // An earlier request given up on has finished
+                    sequences.finish(response.sequence);
+                }
+                continue;
+            }
+            MessageType::STATUS | MessageType::DATA if message.fd.is_some() => {
+                close_fd(core::mem::replace(&mut reply_fd, message.fd));
+            }
+            _ => {}
+        }
+        match message.header.message {
+            MessageType::STATUS => {
+                sequences.finish(request.sequence);
+                break response.status().map_err(Error::from);
+            }
+            MessageType::DATA => match on_data(&message) {
+                Ok(ControlFlow::Break(())) => {
+                    trace!("Stopped waiting for replies to {}", request.sequence);
+                    break Ok(());
+                }
+                Ok(ControlFlow::Continue(())) => {}
+                Err(e) => break Err(e.into()),
+            },
+            _ => {
+                // Our objects don't take file descriptors
+                let dispatched = link.dispatch(&message).await;
+                close_fd(message.fd);
+                if let Err(e) = dispatched {
+                    break Err(e);
+                }
+            }
+        }
+    };
+    match result {
+        Ok(()) => Ok(reply_fd),
+        Err(e) => {
+            close_fd(reply_fd);
+            Err(e)
+        }
+    }
+}
+
+/// Close a received file descriptor which nothing takes ownership of
+pub(crate) fn close_fd(fd: Option<i32>) {
+    #[cfg(unix)]
+    if let Some(fd) = fd {
+        // Received over the socket, so no one else has it
+        unsafe { libc::close(fd) };
+    }
+    #[cfg(not(unix))]
+    let _ = fd;
+}
+
+/// Pass the DATA table of a reply to an invoke to `on_result`
+pub(crate) fn invoke_reply(
+    attrs: BlobIter<MessageAttr>,
+    on_result: &mut impl FnMut(BlobIter<BlobMsg>) -> ControlFlow<()>,
+) -> Result<ControlFlow<()>, Error> {
+    for attr in attrs {
+        if let MessageAttr::Data(data) = attr {
+            return Ok(on_result(BlobIter::<BlobMsg>::new(data)));
+        }
+    }
+    Err(Error::InvalidData("Invalid data message"))
+}
+
+/// Pass the object in a reply to a lookup to `on_object`, and its methods to `on_signature`
+pub(crate) fn lookup_reply(
+    attrs: BlobIter<MessageAttr>,
+    on_object: &mut impl FnMut(ObjectResult),
+    on_signature: &mut impl FnMut(SignatureResult),
+) -> Result<(), Error> {
+    let entry = LookupEntry::parse(attrs)?;
+    on_object(entry.object);
+    for method in entry.methods() {
+        let mut args = method.args();
+        on_signature(SignatureResult {
+            object: entry.object,
+            name: method.name,
+            args: &mut args,
+        });
+    }
+    Ok(())
+}
@@ -1,4 +1,5 @@
-use crate::{Blob, BlobBuilder, BlobIter, BlobMsg, BlobTag, Error, IO};
+use crate::maybe_async::{block_on, close_fd, Blocking};
+use crate::{AsyncIO, Blob, BlobBuilder, BlobIter, BlobMsg, BlobTag, Error, HexDump, IO};
 use core::convert::TryInto;
 use core::mem::{size_of, transmute};
 use storage_endian::{BEu16, BEu32};
@@ -39,6 +40,12 @@ values!(pub MessageAttrId(u32) {
     GROUP       = 0x0d,
 });
 
+/// Longest object path sent to ubusd, which rejects longer ones with an opaque status
+pub const MAX_PATH_LEN: usize = 255;
+
+/// Longest method name sent to ubusd, which rejects longer ones with an opaque status
+pub const MAX_METHOD_LEN: usize = 255;
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct MessageHeader {
@@ -65,15 +72,207 @@ impl MessageHeader {
 pub struct Message<'a> {
     pub header: MessageHeader,
     pub blob: Blob<'a>,
+    /// File descriptor passed along with the message
+    pub fd: Option<i32>,
 }
 
 impl<'a> Message<'a> {
+    /// Bytes read before the payload: the message header and the blob tag
+    const PRE_SIZE: usize = MessageHeader::SIZE + BlobTag::SIZE;
+
     pub fn from_io<T: IO>(io: &mut T, buffer: &'a mut [u8]) -> Result<Self, Error<T::Error>> {
-        let (pre_buffer, buffer) = buffer.split_at_mut(MessageHeader::SIZE + BlobTag::SIZE);
+        Self::from_io_limit(io, buffer, usize::MAX)
+    }
+
+    /// Like `from_io`, but rejecting messages with more than `max_size` bytes of payload
+    ///
+    /// Messages too large for the limit (or for `buffer`) are read and discarded before the error
+    /// is returned, so the stream stays in sync and the next message can still be received.
+    pub fn from_io_limit<T: IO>(
+        io: &mut T,
+        buffer: &'a mut [u8],
+        max_size: usize,
+    ) -> Result<Self, Error<T::Error>> {
+        block_on(Self::read_limit(&mut Blocking(io), buffer, max_size))
+    }
+
+    /// `from_io_limit` for any transport, blocking or async
+    pub(crate) async fn read_limit<T: AsyncIO>(
+        io: &mut T,
+        buffer: &'a mut [u8],
+        max_size: usize,
+    ) -> Result<Self, Error<T::Error>> {
+        if buffer.len() < Self::PRE_SIZE {
+            return Err(Error::InvalidData("Receive buffer too small"));
+        }
 
         // Read in the message header and the following blob tag
-        io.get(pre_buffer)?;
+        let fd = ReceivedFd(io.get_fd(&mut buffer[..Self::PRE_SIZE]).await?);
+        let (header, tag) = Self::parse_pre(&buffer[..Self::PRE_SIZE])?;
+
+        // Get a slice the size of the blob's data bytes (do we need to worry about padding here?)
+        let len = tag.inner_len();
+        if len > max_size {
+            discard(io, len, buffer).await?;
+            return Err(Error::InvalidData("Message larger than the maximum size"));
+        }
+        if len > buffer.len() - Self::PRE_SIZE {
+            discard(io, len, buffer).await?;
+            return Err(Error::InvalidData("Message too large for receive buffer"));
+        }
+        let data = &mut buffer[Self::PRE_SIZE..Self::PRE_SIZE + len];
 
+        // Receive data into slice
+        io.get(data).await?;
+
+        // Create the blob from our parts
+        let blob = Blob::from_tag_and_data(tag, data)?;
+
+        let message = Message {
+            header,
+            blob,
+            fd: None,
+        };
+        message.validate()?;
+        Ok(Message {
+            fd: fd.take(),
+            ..message
+        })
+    }
+
+    /// Like `from_io`, but resizes `buffer` to exactly fit each message (so there is no size limit)
+    #[cfg(not(feature = "no_std"))]
+    pub fn from_io_vec<T: IO>(
+        io: &mut T,
+        buffer: &'a mut std::vec::Vec<u8>,
+    ) -> Result<Self, Error<T::Error>> {
+        Self::from_io_vec_limit(io, buffer, usize::MAX)
+    }
+
+    /// Like `from_io_vec`, but rejecting (and discarding) messages with more than `max_size` bytes
+    /// of payload, as `from_io_limit` does
+    #[cfg(not(feature = "no_std"))]
+    pub fn from_io_vec_limit<T: IO>(
+        io: &mut T,
+        buffer: &'a mut std::vec::Vec<u8>,
+        max_size: usize,
+    ) -> Result<Self, Error<T::Error>> {
+        block_on(Self::read_vec_limit(&mut Blocking(io), buffer, max_size))
+    }
+
+    /// `from_io_vec_limit` for any transport, blocking or async
+    #[cfg(not(feature = "no_std"))]
+    pub(crate) async fn read_vec_limit<T: AsyncIO>(
+        io: &mut T,
+        buffer: &'a mut std::vec::Vec<u8>,
+        max_size: usize,
+    ) -> Result<Self, Error<T::Error>> {
+        buffer.resize(Self::PRE_SIZE, 0);
+        let fd = ReceivedFd(io.get_fd(buffer).await?);
+        let (header, tag) = Self::parse_pre(buffer)?;
+
+        let len = tag.inner_len();
+        if len > max_size {
+            buffer.resize(crate::DEFAULT_BUFFER_SIZE, 0);
+            discard(io, len, buffer).await?;
+            return Err(Error::InvalidData("Message larger than the maximum size"));
+        }
+        buffer.resize(Self::PRE_SIZE + len, 0);
+        let data = &mut buffer[Self::PRE_SIZE..];
+        io.get(data).await?;
+        let blob = Blob::from_tag_and_data(tag, data)?;
+
+        let message = Message {
+            header,
+            blob,
+            fd: None,
+        };
+        message.validate()?;
+        Ok(Message {
+            fd: fd.take(),
+            ..message
+        })
+    }
+
+    /// Parse a message which `from_io_vec` already read (and checked) into `buffer`
+    pub(crate) fn from_buffer(buffer: &'a [u8], fd: Option<i32>) -> Result<Self, Error> {
+        let (header, tag) = Self::parse_pre(buffer.get(..Self::PRE_SIZE).unwrap_or(&[]))?;
+        let data = buffer
+            .get(Self::PRE_SIZE..Self::PRE_SIZE + tag.inner_len())
+            .ok_or(Error::InvalidData("Truncated message"))?;
+        let blob = Blob::from_tag_and_data(tag, data)?;
+        Ok(Message { header, blob, fd })
+    }
+
+    /// Check the attributes are well formed, and include those required by the message type
+    ///
+    /// For example an INVOKE must carry OBJID and METHOD, and a STATUS must carry STATUS. Messages
+    /// are checked as they are received, so handlers can rely on this.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut seen = 0u64;
+        let mut data = self.blob.data;
+        while !data.is_empty() {
+            let tag = match data.get(..BlobTag::SIZE) {
+                Some(tag) => BlobTag::from_bytes(tag.try_into().unwrap()),
+                None => return Err(Error::InvalidData("Truncated attribute")),
+            };
+            if tag.size() < BlobTag::SIZE || tag.size() > data.len() {
+                return Err(Error::InvalidData("Truncated attribute"));
+            }
+            let blob = Blob::from_tag_and_data(tag, &data[BlobTag::SIZE..])?;
+            let id = MessageAttrId::from(blob.tag.id());
+            let min_len = match id {
+                MessageAttrId::STATUS
+                | MessageAttrId::OBJID
+                | MessageAttrId::OBJTYPE
+                | MessageAttrId::TARGET => size_of::<u32>(),
+                MessageAttrId::ACTIVE | MessageAttrId::NO_REPLY => 1,
+                _ => 0,
+            };
+            if blob.data.len() < min_len {
+                return Err(Error::InvalidData("Attribute too short for its type"));
+            }
+            if let MessageAttrId::OBJPATH
+            | MessageAttrId::METHOD
+            | MessageAttrId::USER
+            | MessageAttrId::GROUP = id
+            {
+                let _: &str = blob.try_into()?;
+            }
+            seen |= 1u64.checked_shl(id.value()).unwrap_or(0);
+            let next = blob.tag.size() + blob.tag.padding();
+            data = data.get(next..).unwrap_or(&[]);
+        }
+
+        use MessageAttrId as Attr;
+        let required: &[(MessageAttrId, &'static str)] = match self.header.message {
+            MessageType::STATUS => &[(Attr::STATUS, "STATUS without STATUS attribute")],
+            MessageType::INVOKE => &[
+                (Attr::OBJID, "INVOKE without OBJID attribute"),
+                (Attr::METHOD, "INVOKE without METHOD attribute"),
+            ],
+            MessageType::REMOVE_OBJECT => &[(Attr::OBJID, "REMOVE_OBJECT without OBJID attribute")],
+            MessageType::SUBSCRIBE => &[
+                (Attr::OBJID, "SUBSCRIBE without OBJID attribute"),
+                (Attr::TARGET, "SUBSCRIBE without TARGET attribute"),
+            ],
+            MessageType::UNSUBSCRIBE => &[
+                (Attr::OBJID, "UNSUBSCRIBE without OBJID attribute"),
+                (Attr::TARGET, "UNSUBSCRIBE without TARGET attribute"),
+            ],
+            MessageType::NOTIFY => &[(Attr::OBJID, "NOTIFY without OBJID attribute")],
+            _ => &[],
+        };
+        for (id, msg) in required {
+            if seen & (1 << id.value()) == 0 {
+                return Err(Error::InvalidData(msg));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the message header and blob tag which start every message
+    pub(crate) fn parse_pre(pre_buffer: &[u8]) -> Result<(MessageHeader, BlobTag), Error> {
         let (header, tag) = pre_buffer.split_at(MessageHeader::SIZE);
 
         let header = MessageHeader::from_bytes(header.try_into().unwrap());
@@ -82,21 +281,43 @@ impl<'a> Message<'a> {
         let tag = BlobTag::from_bytes(tag.try_into().unwrap());
         tag.is_valid()?;
 
-        // Get a slice the size of the blob's data bytes (do we need to worry about padding here?)
-        let data = &mut buffer[..tag.inner_len()];
-
-        // Receive data into slice
-        io.get(data)?;
-
-        // Create the blob from our parts
-        let blob = Blob::from_tag_and_data(tag, data).unwrap();
+        Ok((header, tag))
+    }
+}
 
-        Ok(Message { header, blob })
+/// Read and throw away the `len` payload bytes of a message which won't be received, a chunk at
+/// a time through `scratch`
+async fn discard<T: AsyncIO>(
+    io: &mut T,
+    mut len: usize,
+    scratch: &mut [u8],
+) -> Result<(), Error<T::Error>> {
+    while len > 0 {
+        let chunk = len.min(scratch.len());
+        io.get(&mut scratch[..chunk]).await?;
+        len -= chunk;
     }
+    Ok(())
 }
 
+/// `{:#?}` shows the header fields and attributes, followed by a hexdump of the payload (see
+/// `HexDump`)
 impl core::fmt::Debug for Message<'_> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if f.alternate() {
+            f.debug_struct("Message")
+                .field("message", &self.header.message)
+                .field("sequence", &self.header.sequence)
+                .field("peer", &format_args!("{:08x}", self.header.peer))
+                .field("size", &self.blob.data.len())
+                .field("fd", &self.fd)
+                .field("attrs", &Attrs(self.blob.data))
+                .finish()?;
+            if !self.blob.data.is_empty() {
+                write!(f, "\n{:?}", HexDump(self.blob.data))?;
+            }
+            return Ok(());
+        }
         write!(
             f,
             "Message({:?} seq={} peer={:08x}, size={})",
@@ -108,6 +329,36 @@ impl core::fmt::Debug for Message<'_> {
     }
 }
 
+/// A message's attributes, one per line even in `{:#?}`, as a hexdump of them follows
+struct Attrs<'a>(&'a [u8]);
+
+impl core::fmt::Debug for Attrs<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let mut list = f.debug_list();
+        for attr in BlobIter::<MessageAttr>::new(self.0) {
+            list.entry(&format_args!("{:?}", attr));
+        }
+        list.finish()
+    }
+}
+
+/// A file descriptor received with a message, closed unless it's taken
+///
+/// Keeps the descriptor from leaking when the rest of the message can't be read or is invalid.
+struct ReceivedFd(Option<i32>);
+
+impl ReceivedFd {
+    fn take(mut self) -> Option<i32> {
+        self.0.take()
+    }
+}
+
+impl Drop for ReceivedFd {
+    fn drop(&mut self) {
+        close_fd(self.0.take());
+    }
+}
+
 pub struct MessageBuilder<'a> {
     buffer: &'a mut [u8],
     offset: usize,
@@ -130,6 +381,7 @@ impl<'a> MessageBuilder<'a> {
     }
 
     pub fn put(&mut self, attr: MessageAttr) -> Result<(), Error> {
+        attr.check()?;
         let mut blob = BlobBuilder::from_bytes(&mut self.buffer[self.offset..]);
 
         match attr {
@@ -137,16 +389,21 @@ impl<'a> MessageBuilder<'a> {
             MessageAttr::ObjPath(val) => blob.push_str(MessageAttrId::OBJPATH.value(), val)?,
             MessageAttr::ObjId(val) => blob.push_u32(MessageAttrId::OBJID.value(), val)?,
             MessageAttr::Method(val) => blob.push_str(MessageAttrId::METHOD.value(), val)?,
-            MessageAttr::ObjType(val) => blob.push_u32(MessageAttrId::STATUS.value(), val)?,
-            MessageAttr::Signature(_) => unimplemented!(),
+            MessageAttr::ObjType(val) => blob.push_u32(MessageAttrId::OBJTYPE.value(), val)?,
+            MessageAttr::Signature(val) => {
+                blob.push_bytes(MessageAttrId::SIGNATURE.value(), val.as_bytes())?
+            }
             MessageAttr::Data(val) => blob.push_bytes(MessageAttrId::DATA.value(), val)?,
             MessageAttr::Target(val) => blob.push_u32(MessageAttrId::TARGET.value(), val)?,
-            MessageAttr::Active(val) => blob.push_bool(MessageAttrId::USER.value(), val)?,
-            MessageAttr::NoReply(val) => blob.push_bool(MessageAttrId::USER.value(), val)?,
-            MessageAttr::Subscribers(_) => unimplemented!(),
+            MessageAttr::Active(val) => blob.push_bool(MessageAttrId::ACTIVE.value(), val)?,
+            MessageAttr::NoReply(val) => blob.push_bool(MessageAttrId::NO_REPLY.value(), val)?,
+            MessageAttr::Subscribers(val) => {
+                blob.push_bytes(MessageAttrId::SUBSCRIBERS.value(), val.as_bytes())?
+            }
             MessageAttr::User(val) => blob.push_str(MessageAttrId::USER.value(), val)?,
             MessageAttr::Group(val) => blob.push_str(MessageAttrId::GROUP.value(), val)?,
             MessageAttr::Unknown(id, val) => blob.push_bytes(id.value(), val)?,
+            MessageAttr::Raw(val) => val.write_to(&mut blob)?,
         };
 
         self.offset += blob.len();
@@ -170,7 +427,81 @@ impl<'a> From<MessageBuilder<'a>> for &'a [u8] {
     }
 }
 
+/// Writes a message straight through `IO::put`, without building it in a buffer first
+///
+/// The total size of the attributes must be known up front (the sum of `MessageAttr::size`).
+/// The header is sent immediately, so an error part way through leaves a partial message behind.
+pub struct MessageWriter<'a, T: IO> {
+    io: &'a mut T,
+    remaining: usize,
+}
+
+impl<'a, T: IO> MessageWriter<'a, T> {
+    pub fn new(io: &'a mut T, header: MessageHeader, len: usize) -> Result<Self, Error<T::Error>> {
+        let tag = BlobTag::new(0, BlobTag::SIZE + len)?;
+        io.put(&header.to_bytes())?;
+        io.put(&tag.to_bytes())?;
+        Ok(Self { io, remaining: len })
+    }
+
+    pub fn put(&mut self, attr: &MessageAttr) -> Result<(), Error<T::Error>> {
+        attr.check()?;
+        let size = attr.size();
+        if size > self.remaining {
+            return Err(Error::InvalidData("MessageWriter overflow!"));
+        }
+        let tag = attr.tag()?;
+        self.io.put(&tag.to_bytes())?;
+
+        match attr {
+            MessageAttr::Status(val) => self.io.put(&val.to_be_bytes())?,
+            MessageAttr::ObjId(val) | MessageAttr::ObjType(val) | MessageAttr::Target(val) => {
+                self.io.put(&val.to_be_bytes())?
+            }
+            MessageAttr::ObjPath(val)
+            | MessageAttr::Method(val)
+            | MessageAttr::User(val)
+            | MessageAttr::Group(val) => {
+                self.io.put(val.as_bytes())?;
+                self.io.put(&[0])?;
+            }
+            MessageAttr::Active(val) | MessageAttr::NoReply(val) => self.io.put(&[*val as u8])?,
+            MessageAttr::Signature(val) => self.io.put(val.as_bytes())?,
+            MessageAttr::Subscribers(val) => self.io.put(val.as_bytes())?,
+            MessageAttr::Data(val) | MessageAttr::Unknown(_, val) => self.io.put(val)?,
+            MessageAttr::Raw(val) => {
+                if let Some(name) = val.raw_name {
+                    // Name header: length, name, then a nul terminator and padding
+                    self.io.put(&(name.len() as u16).to_be_bytes())?;
+                    self.io.put(name)?;
+                    self.io.put(&[0; BlobTag::SIZE][..1 + name_padding(name)])?;
+                }
+                self.io.put(val.data)?;
+            }
+        }
+
+        let pad = tag.padding();
+        if pad > 0 {
+            self.io.put(&[0; BlobTag::SIZE][..pad])?;
+        }
+        self.remaining -= size;
+        Ok(())
+    }
+
+    /// Check the message was completed
+    pub fn finish(self) -> Result<(), Error<T::Error>> {
+        if self.remaining != 0 {
+            return Err(Error::InvalidData("MessageWriter incomplete"));
+        }
+        Ok(())
+    }
+}
+
+/// An attribute of a message
+///
+/// More variants may be added as ubusd gains attributes, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum MessageAttr<'a> {
     Status(i32),
     ObjPath(&'a str),
@@ -186,6 +517,97 @@ pub enum MessageAttr<'a> {
     User(&'a str),
     Group(&'a str),
     Unknown(MessageAttrId, &'a [u8]),
+    /// An attribute of an unknown id with an extended (named) header, kept whole so it's written
+    /// back out with its name
+    Raw(Blob<'a>),
+}
+
+impl MessageAttr<'_> {
+    pub fn id(&self) -> MessageAttrId {
+        match self {
+            MessageAttr::Status(_) => MessageAttrId::STATUS,
+            MessageAttr::ObjPath(_) => MessageAttrId::OBJPATH,
+            MessageAttr::ObjId(_) => MessageAttrId::OBJID,
+            MessageAttr::Method(_) => MessageAttrId::METHOD,
+            MessageAttr::ObjType(_) => MessageAttrId::OBJTYPE,
+            MessageAttr::Signature(_) => MessageAttrId::SIGNATURE,
+            MessageAttr::Data(_) => MessageAttrId::DATA,
+            MessageAttr::Target(_) => MessageAttrId::TARGET,
+            MessageAttr::Active(_) => MessageAttrId::ACTIVE,
+            MessageAttr::NoReply(_) => MessageAttrId::NO_REPLY,
+            MessageAttr::Subscribers(_) => MessageAttrId::SUBSCRIBERS,
+            MessageAttr::User(_) => MessageAttrId::USER,
+            MessageAttr::Group(_) => MessageAttrId::GROUP,
+            MessageAttr::Unknown(id, _) => *id,
+            MessageAttr::Raw(val) => val.tag.id().into(),
+        }
+    }
+
+    /// Check the attribute can be sent: strings without embedded NULs, and object paths and
+    /// method names within `MAX_PATH_LEN` and `MAX_METHOD_LEN`
+    ///
+    /// Done by the builders, so mistakes are caught before anything is sent.
+    pub fn check(&self) -> Result<(), Error> {
+        let (val, max, too_long) = match self {
+            MessageAttr::ObjPath(val) => (val, MAX_PATH_LEN, "Object path too long"),
+            MessageAttr::Method(val) => (val, MAX_METHOD_LEN, "Method name too long"),
+            MessageAttr::User(val) | MessageAttr::Group(val) => (val, usize::MAX, ""),
+            _ => return Ok(()),
+        };
+        if val.len() > max {
+            return Err(Error::InvalidData(too_long));
+        }
+        if val.as_bytes().contains(&0) {
+            return Err(Error::InvalidData("String attribute contains NUL"));
+        }
+        Ok(())
+    }
+
+    /// Number of bytes following the attribute's tag
+    fn payload_len(&self) -> usize {
+        match self {
+            MessageAttr::Status(_)
+            | MessageAttr::ObjId(_)
+            | MessageAttr::ObjType(_)
+            | MessageAttr::Target(_) => size_of::<u32>(),
+            MessageAttr::ObjPath(val)
+            | MessageAttr::Method(val)
+            | MessageAttr::User(val)
+            | MessageAttr::Group(val) => val.len() + 1,
+            MessageAttr::Active(_) | MessageAttr::NoReply(_) => 1,
+            MessageAttr::Signature(val) => val.as_bytes().len(),
+            MessageAttr::Subscribers(val) => val.as_bytes().len(),
+            MessageAttr::Data(val) | MessageAttr::Unknown(_, val) => val.len(),
+            MessageAttr::Raw(val) => match val.raw_name {
+                Some(name) => {
+                    size_of::<u16>() + name.len() + 1 + name_padding(name) + val.data.len()
+                }
+                None => val.data.len(),
+            },
+        }
+    }
+
+    /// The attribute's tag, as `MessageWriter` sends it
+    fn tag(&self) -> Result<BlobTag, Error> {
+        match self {
+            MessageAttr::Raw(val) if val.raw_name.is_some() => {
+                BlobTag::new_extended(val.tag.id(), BlobTag::SIZE + self.payload_len())
+            }
+            _ => BlobTag::new(self.id().value(), BlobTag::SIZE + self.payload_len()),
+        }
+    }
+
+    /// Total number of bytes this attribute takes up in a message (tag + data + padding)
+    pub fn size(&self) -> usize {
+        let len = BlobTag::SIZE + self.payload_len();
+        len + (BlobTag::SIZE.wrapping_sub(len) & (BlobTag::SIZE - 1))
+    }
+}
+
+/// Padding after an extended header's name and its nul terminator
+fn name_padding(name: &[u8]) -> usize {
+    let len = size_of::<u16>() + name.len() + 1;
+    BlobTag::SIZE.wrapping_sub(len) & (BlobTag::SIZE - 1)
 }
 
 impl<'a> From<Blob<'a>> for MessageAttr<'a> {
@@ -204,6 +626,8 @@ impl<'a> From<Blob<'a>> for MessageAttr<'a> {
             MessageAttrId::SUBSCRIBERS => MessageAttr::Subscribers(blob.into()),
             MessageAttrId::USER => MessageAttr::User(blob.try_into().unwrap()),
             MessageAttrId::GROUP => MessageAttr::Group(blob.try_into().unwrap()),
+            // Unknown attributes keep any name, to be forwarded unchanged
+            _ if blob.raw_name.is_some() => MessageAttr::Raw(blob),
             id => MessageAttr::Unknown(id, blob.data),
         }
     }
@@ -0,0 +1,203 @@
+use crate::json_format::{format_items, format_string, nested};
+use crate::*;
+use core::convert::TryInto;
+use core::fmt::{self, Write};
+
+values!(pub MonitorAttrId(u32) {
+    CLIENT  = 0x00,
+    PEER    = 0x01,
+    SEND    = 0x02,
+    SEQ     = 0x03,
+    TYPE    = 0x04,
+    DATA    = 0x05,
+});
+
+/// A message seen by a monitor, as ubusd describes it in a MONITOR message
+///
+/// Formats (with `{}`) as the line `ubus monitor` prints for it (without the newline), such as
+/// `-> 1b0d2f9e #00000000         invoke: {"objid":-123,"method":"status","data":{}}`.
+#[derive(Debug, Clone, Copy)]
+pub struct MonitorMessage<'a> {
+    /// The client ubusd received the message from, or sent it to
+    pub client: u32,
+    /// The peer in the message's header
+    pub peer: u32,
+    /// Sent by ubusd to `client` (rather than received from it)
+    pub send: bool,
+    pub sequence: u16,
+    pub message: MessageType,
+    /// The message's attributes
+    pub attrs: &'a [u8],
+}
+
+impl<'a> MonitorMessage<'a> {
+    /// Parse the attributes of a MONITOR message
+    ///
+    /// Fails if any of those `ubus monitor` needs is missing (it prints "Invalid monitor msg").
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let (mut client, mut peer, mut send, mut ty) = (None, None, None, None);
+        let mut sequence = 0;
+        let mut attrs = None;
+        for blob in BlobIter::<Blob>::new(data) {
+            let int = || {
+                blob.data
+                    .get(..4)
+                    .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+            };
+            match MonitorAttrId::from(blob.tag.id()) {
+                MonitorAttrId::CLIENT => client = int(),
+                MonitorAttrId::PEER => peer = int(),
+                MonitorAttrId::SEND => send = blob.data.first().map(|b| *b != 0),
+                MonitorAttrId::SEQ => sequence = int().unwrap_or(0) as u16,
+                MonitorAttrId::TYPE => ty = int(),
+                MonitorAttrId::DATA => attrs = Some(blob.data),
+                _ => {}
+            }
+        }
+        let (Some(client), Some(peer), Some(send), Some(ty), Some(attrs)) =
+            (client, peer, send, ty, attrs)
+        else {
+            return Err(Error::InvalidData("Invalid monitor message"));
+        };
+        Ok(Self {
+            client,
+            peer,
+            send,
+            sequence,
+            message: MessageType::from(ty as u8),
+            attrs,
+        })
+    }
+
+    /// Describe `message` as a monitor would see it, e.g. to show a connection's own traffic
+    ///
+    /// `client` is the connection's peer id, and `send` is true for messages it received.
+    pub fn new(client: u32, send: bool, message: &Message<'a>) -> Self {
+        Self {
+            client,
+            peer: message.header.peer.into(),
+            send,
+            sequence: message.header.sequence.into(),
+            message: message.header.message,
+            attrs: message.blob.data,
+        }
+    }
+}
+
+impl fmt::Display for MonitorMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {:08x} #{:08x} {:>14}: {}",
+            if self.send { "->" } else { "<-" },
+            self.client,
+            self.peer,
+            MonitorType(self.message),
+            MonitorAttrs(self.attrs)
+        )
+    }
+}
+
+/// A message type, named as `ubus monitor` names it (e.g. `add_object`, or the number if unknown)
+///
+/// The same names are accepted by `ubus monitor -m`.
+#[derive(Debug, Clone, Copy)]
+pub struct MonitorType(pub MessageType);
+
+impl fmt::Display for MonitorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.0 {
+            MessageType::HELLO => "hello",
+            MessageType::STATUS => "status",
+            MessageType::DATA => "data",
+            MessageType::PING => "ping",
+            MessageType::LOOKUP => "lookup",
+            MessageType::INVOKE => "invoke",
+            MessageType::ADD_OBJECT => "add_object",
+            MessageType::REMOVE_OBJECT => "remove_object",
+            MessageType::SUBSCRIBE => "subscribe",
+            MessageType::UNSUBSCRIBE => "unsubscribe",
+            MessageType::NOTIFY => "notify",
+            other => return fmt::Display::fmt(&other.value(), f),
+        };
+        f.pad(name)
+    }
+}
+
+/// The attributes of a message as `ubus monitor` shows them, as a JSON object
+///
+/// Only the attributes it knows the names of are shown, in order of id, with the last of any
+/// given more than once. Attributes too short for their type are left out. Fails if DATA or
+/// SIGNATURE is nested deeper than `DEFAULT_MAX_DEPTH` (part of the output will have been written).
+#[derive(Debug, Clone, Copy)]
+pub struct MonitorAttrs<'a>(pub &'a [u8]);
+
+impl fmt::Display for MonitorAttrs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MessageAttrId as Attr;
+        const NAMES: [(MessageAttrId, &str); 11] = [
+            (Attr::STATUS, "status"),
+            (Attr::OBJPATH, "objpath"),
+            (Attr::OBJID, "objid"),
+            (Attr::METHOD, "method"),
+            (Attr::OBJTYPE, "objtype"),
+            (Attr::SIGNATURE, "signature"),
+            (Attr::DATA, "data"),
+            (Attr::ACTIVE, "active"),
+            (Attr::NO_REPLY, "no_reply"),
+            (Attr::USER, "user"),
+            (Attr::GROUP, "group"),
+        ];
+        // DATA and SIGNATURE are tables inside the object shown
+        let depth = Depth::new(DEFAULT_MAX_DEPTH);
+        f.write_char('{')?;
+        let mut first = true;
+        for (id, name) in NAMES {
+            let attr = BlobIter::<Blob>::new(self.0)
+                .filter(|blob| blob.tag.id() == id.value())
+                .filter_map(parse_attr)
+                .last();
+            let Some(attr) = attr else {
+                continue;
+            };
+            if !first {
+                f.write_char(',')?;
+            }
+            first = false;
+            format_string(f, name)?;
+            f.write_char(':')?;
+            match attr {
+                MessageAttr::Status(v) => write!(f, "{}", v)?,
+                // Shown signed, as libubox formats int32
+                MessageAttr::ObjId(v) | MessageAttr::ObjType(v) => write!(f, "{}", v as i32)?,
+                MessageAttr::ObjPath(v)
+                | MessageAttr::Method(v)
+                | MessageAttr::User(v)
+                | MessageAttr::Group(v) => format_string(f, v)?,
+                MessageAttr::Signature(v) => format_items(f, v, true, nested(depth)?)?,
+                MessageAttr::Data(v) => format_items(f, BlobIter::new(v), true, nested(depth)?)?,
+                MessageAttr::Active(v) | MessageAttr::NoReply(v) => {
+                    f.write_str(if v { "true" } else { "false" })?
+                }
+                _ => f.write_str("null")?,
+            }
+        }
+        f.write_char('}')
+    }
+}
+
+/// `blob` as a known attribute, if it's long enough for its type
+fn parse_attr(blob: Blob) -> Option<MessageAttr> {
+    let valid = match MessageAttrId::from(blob.tag.id()) {
+        MessageAttrId::STATUS | MessageAttrId::OBJID | MessageAttrId::OBJTYPE => {
+            blob.data.len() >= 4
+        }
+        MessageAttrId::ACTIVE | MessageAttrId::NO_REPLY => !blob.data.is_empty(),
+        MessageAttrId::OBJPATH
+        | MessageAttrId::METHOD
+        | MessageAttrId::USER
+        | MessageAttrId::GROUP => TryInto::<&str>::try_into(blob).is_ok(),
+        _ => true,
+    };
+    valid.then(|| blob.into())
+}
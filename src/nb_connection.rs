@@ -0,0 +1,169 @@
+use crate::*;
+
+/// Like `IO`, for transports which return `WouldBlock` rather than waiting (the `nb` convention)
+///
+/// Reads and writes may be partial, returning how many bytes were moved.
+pub trait NbIO {
+    type Error: IOError;
+    /// Write some of `data`, returning how many bytes were written
+    fn write(&mut self, data: &[u8]) -> nb::Result<usize, Error<Self::Error>>;
+    /// Read into `data`, returning how many bytes were read (0 once the peer has closed)
+    fn read(&mut self, data: &mut [u8]) -> nb::Result<usize, Error<Self::Error>>;
+}
+
+/// A connection for superloops on targets without an async executor
+///
+/// Nothing waits: each method does what it can straight away and returns `WouldBlock` if it has
+/// to be called again later. Partially received messages are kept (in an `Engine`, receiving into
+/// `B`) until the rest arrives, and requests are built into `O` and sent as the transport accepts
+/// them.
+///
+/// ```
+/// # use ubus::*;
+/// fn superloop<T: NbIO>(connection: &mut NbConnection<T, [u8; 1024], [u8; 256]>) {
+///     loop {
+///         match connection.poll() {
+///             Ok(EngineEvent::Hello { .. }) => { /* requests can be made from now on */ }
+///             Ok(event) => { /* handle event */ }
+///             Err(nb::Error::WouldBlock) => { /* do other work */ }
+///             Err(nb::Error::Other(_)) => return, // reconnect
+///         }
+///     }
+/// }
+/// ```
+pub struct NbConnection<T: NbIO, B, O> {
+    io: T,
+    engine: Engine<B>,
+    out: O,
+    /// Bytes of `out` sent so far
+    sent: usize,
+    /// Bytes of `out` holding a message to send
+    unsent: usize,
+}
+
+impl<T, B, O> NbConnection<T, B, O>
+where
+    T: NbIO,
+    B: AsRef<[u8]> + AsMut<[u8]>,
+    O: AsRef<[u8]> + AsMut<[u8]>,
+{
+    /// Create a connection receiving into `receive` and building requests in `send`, which limit
+    /// the size of messages each way
+    pub fn new(io: T, receive: B, send: O) -> Self {
+        Self {
+            io,
+            engine: Engine::new(receive),
+            out: send,
+            sent: 0,
+            unsent: 0,
+        }
+    }
+
+    /// Our peer id, once the bus has said hello
+    pub fn peer(&self) -> Option<u32> {
+        self.engine.peer()
+    }
+
+    /// The protocol state, such as which requests are still waiting for replies
+    pub fn engine(&self) -> &Engine<B> {
+        &self.engine
+    }
+
+    /// The transport, e.g. to service its network stack between polls
+    pub fn io_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// Receive what has arrived, returning the next event once a whole message is in
+    ///
+    /// Also carries on sending any request not yet sent. The bus's hello comes first (as
+    /// `EngineEvent::Hello`), then replies to requests and other messages.
+    pub fn poll(&mut self) -> nb::Result<EngineEvent<'_>, Error<T::Error>> {
+        match self.flush() {
+            Ok(()) | Err(nb::Error::WouldBlock) => {}
+            Err(e) => return Err(e),
+        }
+        loop {
+            let space = self.engine.receive_buffer();
+            if space.is_empty() {
+                break;
+            }
+            match self.io.read(space) {
+                Ok(0) => return Err(nb::Error::Other(Error::InvalidData("Connection closed"))),
+                Ok(len) => self.engine.received(len),
+                Err(nb::Error::WouldBlock) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        match self.engine.poll() {
+            Ok(Some(event)) => Ok(event),
+            Ok(None) => Err(nb::Error::WouldBlock),
+            Err(e) => Err(nb::Error::Other(e.into())),
+        }
+    }
+
+    /// Send `request`, returning its sequence number
+    ///
+    /// Returns `WouldBlock` while an earlier request is still being sent. Once built, the request
+    /// is sent as far as the transport allows, and the rest by later calls to `poll` or `flush`.
+    /// Its replies come from `poll` as `Data` events, ending with `Done`.
+    pub fn request<'a, R: RequestMessage<'a>>(
+        &mut self,
+        request: R,
+    ) -> nb::Result<u16, Error<T::Error>> {
+        self.flush()?;
+        let peer = request.peer();
+        let (sequence, bytes) = self
+            .engine
+            .request(self.out.as_mut(), R::TYPE, peer, request)
+            .map_err(|e| nb::Error::Other(e.into()))?;
+        self.unsent = bytes.len();
+        match self.flush() {
+            Ok(()) | Err(nb::Error::WouldBlock) => Ok(sequence),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Call `method` on `obj`, as `request` does
+    pub fn invoke(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+    ) -> nb::Result<u16, Error<T::Error>> {
+        self.request(InvokeRequest::new(obj, method).args(args))
+    }
+
+    /// Send a reply (or any message which doesn't expect one), as `Engine::reply` builds it
+    pub fn reply<'a>(
+        &mut self,
+        message: MessageType,
+        sequence: u16,
+        peer: u32,
+        attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+    ) -> nb::Result<(), Error<T::Error>> {
+        self.flush()?;
+        let bytes = self
+            .engine
+            .reply(self.out.as_mut(), message, sequence, peer, attrs)
+            .map_err(|e| nb::Error::Other(e.into()))?;
+        self.unsent = bytes.len();
+        match self.flush() {
+            Ok(()) | Err(nb::Error::WouldBlock) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send what's left of the last request or reply
+    pub fn flush(&mut self) -> nb::Result<(), Error<T::Error>> {
+        while self.sent < self.unsent {
+            match self.io.write(&self.out.as_ref()[self.sent..self.unsent])? {
+                0 => return Err(nb::Error::Other(Error::InvalidData("Connection closed"))),
+                len => self.sent += len,
+            }
+        }
+        self.sent = 0;
+        self.unsent = 0;
+        Ok(())
+    }
+}
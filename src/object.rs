@@ -0,0 +1,279 @@
+use crate::*;
+use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::vec::Vec;
+
+/// Callback receiving the type and payload of each notification
+///
+/// The returned status code is sent back if the publisher asked for a reply.
+pub type NotifyCallback = Box<dyn FnMut(&str, BlobIter<BlobMsg>) -> i32 + Send>;
+
+/// Handler for method calls on a served object
+///
+/// Receives the method name and arguments, may write a reply table into the builder, and
+/// returns the status code for the call.
+pub type MethodHandler = Box<dyn FnMut(&str, BlobIter<BlobMsg>, &mut BlobBuilder) -> i32 + Send>;
+
+/// Handler for method calls which may be answered later, see `Connection::add_object_deferrable`
+///
+/// Like a `MethodHandler`, but returns `None` to defer the reply, keeping the `DeferredRequest`
+/// to complete it with later.
+pub type DeferrableHandler = Box<
+    dyn FnMut(&str, BlobIter<BlobMsg>, &mut BlobBuilder, DeferredRequest) -> Option<i32> + Send,
+>;
+
+pub(crate) enum ObjectHandler {
+    Notify(NotifyCallback),
+    Method(MethodHandler),
+    Deferrable(DeferrableHandler),
+}
+
+/// A call whose reply was deferred by its handler, to be completed with
+/// `Connection::complete_deferred`
+///
+/// The caller waits (until it times out) if the request is dropped without being completed.
+#[derive(Debug)]
+#[must_use]
+pub struct DeferredRequest {
+    object: u32,
+    sequence: u16,
+    peer: u32,
+    no_reply: bool,
+}
+
+pub(crate) struct ObjectEntry {
+    pub(crate) handler: ObjectHandler,
+    /// Objects this object is subscribed to (subscribers only)
+    pub(crate) targets: Vec<u32>,
+}
+
+/// Objects registered on the bus by a connection
+pub(crate) struct Objects {
+    pub(crate) entries: BTreeMap<u32, ObjectEntry>,
+    dropped: (Sender<u32>, Receiver<u32>),
+}
+
+impl Default for Objects {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            dropped: channel(),
+        }
+    }
+}
+
+impl Objects {
+    pub(crate) fn insert(&mut self, id: u32, handler: ObjectHandler) -> Object {
+        let entry = ObjectEntry {
+            handler,
+            targets: Vec::new(),
+        };
+        self.entries.insert(id, entry);
+        Object {
+            id,
+            dropped: self.dropped.0.clone(),
+        }
+    }
+
+    /// Next object whose handle was dropped, and which hasn't already been removed
+    pub(crate) fn next_dropped(&mut self) -> Option<u32> {
+        while let Ok(id) = self.dropped.1.try_recv() {
+            if self.entries.contains_key(&id) {
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Handle a message if it is addressed to one of our objects
+    pub(crate) fn dispatch<T: IO>(
+        &mut self,
+        io: &mut T,
+        message: &Message,
+    ) -> Result<bool, Error<T::Error>> {
+        let Request {
+            message: ty,
+            sequence,
+            peer,
+            attrs,
+        } = Request::from_message(message);
+        let (id, entry) = match attrs
+            .obj_id
+            .and_then(|id| Some((id, self.entries.get_mut(&id)?)))
+        {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+        let (method, target, no_reply) = (attrs.method, attrs.target, attrs.no_reply);
+        let data = attrs.data.unwrap_or_default();
+
+        match ty {
+            // Method calls, and notifications (which ubusd forwards to subscribers as invokes)
+            MessageType::INVOKE => {
+                // Checked by `Message::validate` as it was received
+                let method = method.unwrap_or_default();
+                let request = DeferredRequest {
+                    object: id,
+                    sequence,
+                    peer,
+                    no_reply,
+                };
+                let mut reply = std::vec::Vec::new();
+                let status = match &mut entry.handler {
+                    ObjectHandler::Notify(callback) => Some(callback(method, BlobIter::new(data))),
+                    ObjectHandler::Method(handler) => {
+                        reply.resize(DEFAULT_BUFFER_SIZE, 0);
+                        let mut builder = BlobBuilder::from_bytes(&mut reply);
+                        let status = handler(method, BlobIter::new(data), &mut builder);
+                        let len = builder.len();
+                        reply.truncate(len);
+                        Some(status)
+                    }
+                    ObjectHandler::Deferrable(handler) => {
+                        reply.resize(DEFAULT_BUFFER_SIZE, 0);
+                        let mut builder = BlobBuilder::from_bytes(&mut reply);
+                        let status = handler(method, BlobIter::new(data), &mut builder, request);
+                        let len = builder.len();
+                        reply.truncate(len);
+                        status
+                    }
+                };
+                if let Some(status) = status {
+                    send_reply(io, id, sequence, peer, no_reply, status, &reply)?;
+                }
+            }
+            // ubusd tells subscribers when a target object went away
+            MessageType::UNSUBSCRIBE => {
+                entry.targets.retain(|t| Some(*t) != target);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Send the DATA (if any) and STATUS replies to a call, unless the caller asked for no reply
+fn send_reply<T: IO + ?Sized>(
+    io: &mut T,
+    id: u32,
+    sequence: u16,
+    peer: u32,
+    no_reply: bool,
+    status: i32,
+    data: &[u8],
+) -> Result<(), Error<T::Error>> {
+    if no_reply {
+        return Ok(());
+    }
+    if !data.is_empty() {
+        let attrs = [MessageAttr::ObjId(id), MessageAttr::Data(data)];
+        send_message(io, MessageType::DATA, sequence, peer, attrs)?;
+    }
+    let attrs = [MessageAttr::Status(status), MessageAttr::ObjId(id)];
+    send_message(io, MessageType::STATUS, sequence, peer, attrs)
+}
+
+/// Handle to an object registered on the bus by this connection
+///
+/// Dropping the handle removes the object from the bus (on the connection's next request).
+#[derive(Debug)]
+pub struct Object {
+    id: u32,
+    dropped: Sender<u32>,
+}
+
+impl Object {
+    /// Object id assigned by ubusd
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        let _ = self.dropped.send(self.id);
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Register an object on the bus, returning its id
+    pub(crate) fn register_object(&mut self, path: Option<&str>) -> Result<u32, Error<T::Error>> {
+        let mut id = None;
+        let on_data = |attrs: BlobIter<MessageAttr>| {
+            for attr in attrs {
+                if let MessageAttr::ObjId(val) = attr {
+                    id = Some(val);
+                }
+            }
+            Ok(())
+        };
+        self.request(AddObjectRequest::new(path), on_data)?;
+        Ok(id.ok_or(Error::<NoIO>::InvalidData("No object id in reply"))?)
+    }
+
+    /// Serve an object at `path`, passing calls to its methods to `handler`
+    pub fn add_object(
+        &mut self,
+        path: &str,
+        handler: impl FnMut(&str, BlobIter<BlobMsg>, &mut BlobBuilder) -> i32 + Send + 'static,
+    ) -> Result<Object, Error<T::Error>> {
+        let id = self.register_object(Some(path))?;
+        let handler = ObjectHandler::Method(Box::new(handler));
+        Ok(self.handlers.objects.insert(id, handler))
+    }
+
+    /// Like `add_object`, but `handler` may defer its reply (see `DeferrableHandler`)
+    ///
+    /// For long running operations, which shouldn't hold up handling other messages.
+    pub fn add_object_deferrable(
+        &mut self,
+        path: &str,
+        handler: impl FnMut(&str, BlobIter<BlobMsg>, &mut BlobBuilder, DeferredRequest) -> Option<i32>
+            + Send
+            + 'static,
+    ) -> Result<Object, Error<T::Error>> {
+        let id = self.register_object(Some(path))?;
+        let handler = ObjectHandler::Deferrable(Box::new(handler));
+        Ok(self.handlers.objects.insert(id, handler))
+    }
+
+    /// Reply to a deferred call, with `status` and the blobmsg table `data` (if not empty)
+    pub fn complete_deferred(
+        &mut self,
+        request: DeferredRequest,
+        status: i32,
+        data: &[u8],
+    ) -> Result<(), Error<T::Error>> {
+        let DeferredRequest {
+            object,
+            sequence,
+            peer,
+            no_reply,
+        } = request;
+        let mut io = self.handlers.hooks.wrap(&mut self.io);
+        send_reply(&mut io, object, sequence, peer, no_reply, status, data)
+    }
+
+    /// Remove an object registered by this connection from the bus
+    pub fn remove_object(&mut self, id: u32) -> Result<(), Error<T::Error>> {
+        self.handlers.objects.entries.remove(&id);
+        self.request(RemoveObjectRequest::new(id), |_| Ok(()))
+    }
+
+    /// Unsubscribe and remove every object, carrying on after failures and returning the first
+    pub(crate) fn remove_all_objects(&mut self) -> Result<(), Error<T::Error>> {
+        let mut result = Ok(());
+        let ids: Vec<u32> = self.handlers.objects.entries.keys().copied().collect();
+        for id in ids {
+            let targets = self.handlers.objects.entries[&id].targets.clone();
+            for target in targets {
+                let request = UnsubscribeRequest::new(id, target);
+                let unsubscribed = self.request(request, |_| Ok(()));
+                result = result.and(unsubscribed);
+            }
+            result = result.and(self.remove_object(id));
+        }
+        result
+    }
+}
@@ -0,0 +1,194 @@
+use crate::*;
+use core::convert::TryInto;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::vec::Vec;
+
+/// Direction of a captured message, relative to the capturing side
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PcapDirection {
+    Inbound,
+    Outbound,
+}
+
+/// Writes whole ubus messages as packets of a pcapng file, for inspecting in Wireshark
+///
+/// There is no link type assigned to ubus, so packets use one of the user-defined ones
+/// (`LINKTYPE_USER0` unless another is given), which Wireshark can map to a dissector. Each packet
+/// is a message as sent on the wire, header included, flagged with its direction.
+pub struct PcapWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// `LINKTYPE_USER0`
+    pub const LINK_TYPE: u16 = 147;
+
+    /// Start a capture, writing the file's section and interface headers
+    pub fn new(out: W) -> Result<Self, std::io::Error> {
+        Self::with_link_type(out, Self::LINK_TYPE)
+    }
+
+    pub fn with_link_type(mut out: W, link_type: u16) -> Result<Self, std::io::Error> {
+        // Section header block: byte order magic, version 1.0, unknown section length
+        let mut block = Vec::new();
+        block.extend_from_slice(&0x1a2b_3c4du32.to_le_bytes());
+        block.extend_from_slice(&1u16.to_le_bytes());
+        block.extend_from_slice(&0u16.to_le_bytes());
+        block.extend_from_slice(&(-1i64).to_le_bytes());
+        write_block(&mut out, 0x0a0d_0d0a, &block)?;
+        // Interface description block: link type, reserved, no snapshot length limit
+        let mut block = Vec::new();
+        block.extend_from_slice(&link_type.to_le_bytes());
+        block.extend_from_slice(&0u16.to_le_bytes());
+        block.extend_from_slice(&0u32.to_le_bytes());
+        write_block(&mut out, 1, &block)?;
+        Ok(Self { out })
+    }
+
+    /// Write a message, captured now
+    pub fn write(
+        &mut self,
+        direction: PcapDirection,
+        message: &[u8],
+    ) -> Result<(), std::io::Error> {
+        self.write_at(direction, SystemTime::now(), message)
+    }
+
+    /// Write a message, captured at `time`
+    pub fn write_at(
+        &mut self,
+        direction: PcapDirection,
+        time: SystemTime,
+        message: &[u8],
+    ) -> Result<(), std::io::Error> {
+        // Microseconds, the default timestamp resolution
+        let micros = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let len = message.len() as u32;
+        let mut block = Vec::with_capacity(32 + message.len());
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        block.extend_from_slice(&(micros as u32).to_le_bytes());
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(message);
+        block.resize(block.len() + (4 - message.len() % 4) % 4, 0);
+        // epb_flags option, with the direction in the lowest bits, then the end of options
+        let flags: u32 = match direction {
+            PcapDirection::Inbound => 1,
+            PcapDirection::Outbound => 2,
+        };
+        block.extend_from_slice(&2u16.to_le_bytes());
+        block.extend_from_slice(&4u16.to_le_bytes());
+        block.extend_from_slice(&flags.to_le_bytes());
+        block.extend_from_slice(&[0; 4]);
+        write_block(&mut self.out, 6, &block)
+    }
+
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(mut self) -> Result<W, std::io::Error> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Write a pcapng block: type, total length, body (already padded), total length again
+fn write_block(out: &mut impl Write, ty: u32, body: &[u8]) -> Result<(), std::io::Error> {
+    let len = (body.len() + 12) as u32;
+    out.write_all(&ty.to_le_bytes())?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&len.to_le_bytes())
+}
+
+/// Wraps a transport, capturing all messages sent and received to a pcapng file
+///
+/// Like `RecordingIo`, but reassembles the reads and writes into whole messages, one per packet.
+/// Sent messages are outbound and received ones inbound. File descriptors passed along are not
+/// captured.
+pub struct PcapIo<T: IO, W: Write> {
+    io: T,
+    pcap: PcapWriter<W>,
+    sent: Vec<u8>,
+    received: Vec<u8>,
+    error: Option<std::io::Error>,
+}
+
+impl<T: IO, W: Write> PcapIo<T, W> {
+    pub fn new(io: T, pcap: PcapWriter<W>) -> Self {
+        Self {
+            io,
+            pcap,
+            sent: Vec::new(),
+            received: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Stop capturing, returning the transport and capture (or the first error writing it)
+    ///
+    /// Any partial message is dropped.
+    pub fn into_inner(mut self) -> Result<(T, PcapWriter<W>), std::io::Error> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.pcap.flush()?;
+        Ok((self.io, self.pcap))
+    }
+
+    fn capture(&mut self, direction: PcapDirection, data: &[u8]) {
+        let pending = match direction {
+            PcapDirection::Inbound => &mut self.received,
+            PcapDirection::Outbound => &mut self.sent,
+        };
+        pending.extend_from_slice(data);
+        // The header is followed by the message's blob tag, giving the length of the rest
+        const PREFIX: usize = MessageHeader::SIZE + BlobTag::SIZE;
+        while pending.len() >= PREFIX {
+            let tag = BlobTag::from_bytes(pending[MessageHeader::SIZE..PREFIX].try_into().unwrap());
+            // Something invalid is captured as it is, rather than stalling the capture
+            let len = (MessageHeader::SIZE + tag.size()).max(PREFIX);
+            if pending.len() < len {
+                break;
+            }
+            if self.error.is_none() {
+                self.error = self.pcap.write(direction, &pending[..len]).err();
+            }
+            pending.drain(..len);
+        }
+    }
+}
+
+impl<T: IO, W: Write> IO for PcapIo<T, W> {
+    type Error = T::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<T::Error>> {
+        self.io.put(data)?;
+        self.capture(PcapDirection::Outbound, data);
+        Ok(())
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<T::Error>> {
+        self.io.get(data)?;
+        self.capture(PcapDirection::Inbound, data);
+        Ok(())
+    }
+    fn put_fd(&mut self, data: &[u8], fd: i32) -> Result<(), Error<T::Error>> {
+        self.io.put_fd(data, fd)?;
+        self.capture(PcapDirection::Outbound, data);
+        Ok(())
+    }
+    fn get_fd(&mut self, data: &mut [u8]) -> Result<Option<i32>, Error<T::Error>> {
+        let fd = self.io.get_fd(data)?;
+        self.capture(PcapDirection::Inbound, data);
+        Ok(fd)
+    }
+    fn close(&mut self) -> Result<(), Error<T::Error>> {
+        let _ = self.pcap.flush();
+        self.io.close()
+    }
+}
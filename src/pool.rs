@@ -0,0 +1,255 @@
+use crate::split::lock;
+use crate::*;
+use core::convert::TryFrom;
+use core::ops::{Deref, DerefMut};
+use std::boxed::Box;
+use std::os::unix::net::UnixStream;
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::vec::Vec;
+
+type Connect<T> = Box<dyn Fn() -> Result<Connection<T>, Error<<T as IO>::Error>> + Send + Sync>;
+
+struct PoolState<T: IO> {
+    idle: Vec<Connection<T>>,
+    open: usize,
+}
+
+/// A set of up to `size` connections to ubusd, shared between threads
+///
+/// Connections are opened as needed and checked out for each call, so concurrent callers don't
+/// serialize behind a single socket. A connection which fails with an IO error is discarded rather
+/// than returned to the pool, along with any idle ones (which a restart of ubusd will have broken
+/// too). One which times out is discarded on its own.
+pub struct Pool<T: IO> {
+    connect: Connect<T>,
+    size: usize,
+    state: Mutex<PoolState<T>>,
+    released: Condvar,
+    session: Mutex<Option<Session>>,
+}
+
+impl<T: IO> Pool<T> {
+    /// Create a pool of up to `size` connections, each opened with `connect`
+    pub fn new(
+        size: usize,
+        connect: impl Fn() -> Result<Connection<T>, Error<T::Error>> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            connect: Box::new(connect),
+            size: size.max(1),
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            released: Condvar::new(),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Maximum number of connections the pool opens
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Like `Connection::set_session`, for every connection as it's checked out
+    pub fn set_session(&self, session: Option<Session>) {
+        *lock(&self.session) = session;
+    }
+
+    /// The session set by `set_session`
+    pub fn session(&self) -> Option<Session> {
+        *lock(&self.session)
+    }
+
+    /// Number of connections currently open (idle or checked out)
+    pub fn open(&self) -> usize {
+        lock(&self.state).open
+    }
+
+    /// Check out a connection, blocking while all `size` connections are in use
+    pub fn get(&self) -> Result<PoolConnection<'_, T>, Error<T::Error>> {
+        let mut state = lock(&self.state);
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(PoolConnection::new(self, connection));
+            }
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match (self.connect)() {
+                    Ok(connection) => Ok(PoolConnection::new(self, connection)),
+                    Err(e) => {
+                        self.release(None);
+                        Err(e)
+                    }
+                };
+            }
+            state = self
+                .released
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Return a connection to the pool, or close its slot if `None`
+    fn release(&self, connection: Option<Connection<T>>) {
+        let mut state = lock(&self.state);
+        match connection {
+            Some(connection) => state.idle.push(connection),
+            None => state.open -= 1,
+        }
+        self.released.notify_one();
+    }
+
+    /// Close the idle connections, which are likely broken too once one of them has failed (as
+    /// when ubusd restarts)
+    fn drop_idle(&self) {
+        let idle = {
+            let mut state = lock(&self.state);
+            let idle = core::mem::take(&mut state.idle);
+            state.open -= idle.len();
+            idle
+        };
+        self.released.notify_all();
+        drop(idle);
+    }
+
+    /// Run `f` on a pooled connection, retrying once on a fresh connection if it fails with an IO
+    /// error (which is usually a connection ubusd has closed) or times out
+    ///
+    /// Unless `idempotent`, only retried if an IO error came before `f` sent an INVOKE, so a call
+    /// ubusd may already have delivered isn't made twice. A call which timed out may have been
+    /// made too, so is never retried unless `idempotent`.
+    fn with_retry<R>(
+        &self,
+        idempotent: bool,
+        mut f: impl FnMut(&mut Connection<T>) -> Result<R, Error<T::Error>>,
+    ) -> Result<R, Error<T::Error>> {
+        let mut connection = self.get()?;
+        let invokes = connection.stats().sent(MessageType::INVOKE);
+        let result = f(&mut connection);
+        let retry = match &result {
+            Err(Error::IO(_)) => {
+                idempotent || connection.stats().sent(MessageType::INVOKE) == invokes
+            }
+            Err(Error::Timeout) => idempotent,
+            _ => return result,
+        };
+        self.failed(connection, &result);
+        if !retry {
+            return result;
+        }
+        let mut connection = self.get()?;
+        let result = f(&mut connection);
+        self.failed(connection, &result);
+        result
+    }
+
+    /// Close `connection` if `result` shows it can't be used again
+    ///
+    /// After an IO error the idle connections go too. After a timeout only this one does, as its
+    /// reply may still arrive and be mistaken for the answer to a later request.
+    fn failed<R>(&self, connection: PoolConnection<'_, T>, result: &Result<R, Error<T::Error>>) {
+        match result {
+            Err(Error::IO(_)) => {
+                connection.discard();
+                self.drop_idle();
+            }
+            Err(Error::Timeout) => connection.discard(),
+            _ => {}
+        }
+    }
+
+    /// Like `Connection::invoke`, on a pooled connection
+    ///
+    /// Retried on another connection if the one used turns out to be broken before the call is
+    /// sent, but not once it has been (when the call may have been made).
+    pub fn invoke(
+        &self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        mut on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<InvokeSummary, Error<T::Error>> {
+        self.with_retry(false, |c| c.invoke(obj, method, args, &mut on_result))
+    }
+
+    /// Like `Connection::invoke_as`, on a pooled connection
+    pub fn invoke_as<R>(&self, obj: u32, method: &str, args: &[u8]) -> Result<R, Error<T::Error>>
+    where
+        R: for<'a> TryFrom<BlobMsg<'a>>,
+    {
+        self.with_retry(false, |c| c.invoke_as(obj, method, args))
+    }
+
+    /// Like `Connection::invoke_noreply`, on a pooled connection
+    pub fn invoke_noreply(
+        &self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+    ) -> Result<(), Error<T::Error>> {
+        self.with_retry(false, |c| c.invoke_noreply(obj, method, args))
+    }
+
+    /// Like `Connection::object_id`, on a pooled connection
+    pub fn object_id(&self, path: &str) -> Result<u32, Error<T::Error>> {
+        self.with_retry(true, |c| c.object_id(path))
+    }
+}
+
+impl<T: IO> core::fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Pool")
+            .field("size", &self.size)
+            .field("open", &self.open())
+            .finish()
+    }
+}
+
+/// A connection checked out of a `Pool`, returned to it when dropped
+pub struct PoolConnection<'a, T: IO> {
+    pool: &'a Pool<T>,
+    connection: Option<Connection<T>>,
+}
+
+impl<'a, T: IO> PoolConnection<'a, T> {
+    fn new(pool: &'a Pool<T>, mut connection: Connection<T>) -> Self {
+        connection.set_session(pool.session());
+        Self {
+            pool,
+            connection: Some(connection),
+        }
+    }
+
+    /// Close the connection instead of returning it to the pool (e.g. after an error)
+    pub fn discard(mut self) {
+        self.connection = None;
+    }
+}
+
+impl<T: IO> Deref for PoolConnection<'_, T> {
+    type Target = Connection<T>;
+    fn deref(&self) -> &Connection<T> {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl<T: IO> DerefMut for PoolConnection<'_, T> {
+    fn deref_mut(&mut self) -> &mut Connection<T> {
+        self.connection.as_mut().unwrap()
+    }
+}
+
+impl<T: IO> Drop for PoolConnection<'_, T> {
+    fn drop(&mut self) {
+        self.pool.release(self.connection.take());
+    }
+}
+
+impl ConnectionBuilder {
+    /// Create a pool of up to `size` connections, each opened with these options
+    pub fn pool(self, size: usize) -> Pool<UnixStream> {
+        Pool::new(size, move || self.clone().connect())
+    }
+}
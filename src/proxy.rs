@@ -0,0 +1,230 @@
+use crate::*;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::string::{String, ToString};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::vec::Vec;
+
+/// One of the two buses joined by a `Proxy`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Side {
+    A,
+    B,
+}
+
+impl Side {
+    pub fn other(self) -> Self {
+        match self {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        }
+    }
+}
+
+/// An object served on one bus which forwards calls to the object at the same path on the other
+struct Route {
+    from: Side,
+    path: String,
+    /// Id of the real object, looked up again if it goes away
+    id: u32,
+    _object: Object,
+}
+
+/// Work queued by the proxy's objects and event handlers as messages arrive
+enum Forward {
+    Call {
+        route: usize,
+        method: String,
+        args: Vec<u8>,
+        request: DeferredRequest,
+    },
+    Event {
+        to: Side,
+        id: String,
+        data: Vec<u8>,
+    },
+}
+
+/// Joins two buses (e.g. a container's ubusd and the host's), forwarding selected objects and
+/// events between them
+///
+/// Forwarded calls are answered from the other bus once it replies, using that bus's own object
+/// ids and sequence numbers. Messages are handled by `run` (or by driving each connection's
+/// `handle_next_message` and then calling `process`).
+pub struct Proxy<A: IO, B: IO> {
+    a: Connection<A>,
+    b: Connection<B>,
+    routes: Vec<Route>,
+    event_handlers: Vec<EventHandler>,
+    sender: Sender<Forward>,
+    queue: Receiver<Forward>,
+}
+
+impl<E: IOError, A: IO<Error = E>, B: IO<Error = E>> Proxy<A, B> {
+    pub fn new(a: Connection<A>, b: Connection<B>) -> Self {
+        let (sender, queue) = channel();
+        Self {
+            a,
+            b,
+            routes: Vec::new(),
+            event_handlers: Vec::new(),
+            sender,
+            queue,
+        }
+    }
+
+    pub fn a(&mut self) -> &mut Connection<A> {
+        &mut self.a
+    }
+
+    pub fn b(&mut self) -> &mut Connection<B> {
+        &mut self.b
+    }
+
+    /// Serve the object at `path` on side `from` at the same path on the other side
+    pub fn forward_object(&mut self, from: Side, path: &str) -> Result<(), Error<E>> {
+        let id = match from {
+            Side::A => self.a.object_id(path)?,
+            Side::B => self.b.object_id(path)?,
+        };
+        let route = self.routes.len();
+        let sender = self.sender.clone();
+        let handler = move |method: &str, args: BlobIter<BlobMsg>, _: &mut BlobBuilder, request| {
+            let call = Forward::Call {
+                route,
+                method: method.to_string(),
+                args: args.as_bytes().to_vec(),
+                request,
+            };
+            // Only fails once the proxy is gone, leaving the caller to time out
+            let _ = sender.send(call);
+            None
+        };
+        let object = match from.other() {
+            Side::A => self.a.add_object_deferrable(path, handler)?,
+            Side::B => self.b.add_object_deferrable(path, handler)?,
+        };
+        self.routes.push(Route {
+            from,
+            path: path.to_string(),
+            id,
+            _object: object,
+        });
+        Ok(())
+    }
+
+    /// Send events matching `pattern` on side `from` to the other side too
+    ///
+    /// ubusd doesn't deliver events back to the connection sending them, so patterns may be
+    /// forwarded in both directions without events looping.
+    pub fn forward_events(&mut self, from: Side, pattern: &str) -> Result<(), Error<E>> {
+        let sender = self.sender.clone();
+        let to = from.other();
+        let callback = move |id: &str, data: BlobIter<BlobMsg>| {
+            let event = Forward::Event {
+                to,
+                id: id.to_string(),
+                data: data.as_bytes().to_vec(),
+            };
+            let _ = sender.send(event);
+        };
+        let handler = match from {
+            Side::A => {
+                let handler = self.a.event_handler(callback)?;
+                self.a.register_event(&handler, pattern)?;
+                handler
+            }
+            Side::B => {
+                let handler = self.b.event_handler(callback)?;
+                self.b.register_event(&handler, pattern)?;
+                handler
+            }
+        };
+        self.event_handlers.push(handler);
+        Ok(())
+    }
+
+    /// Forward the calls and events queued while handling messages
+    pub fn process(&mut self) -> Result<(), Error<E>> {
+        while let Ok(forward) = self.queue.try_recv() {
+            match forward {
+                Forward::Call {
+                    route,
+                    method,
+                    args,
+                    request,
+                } => {
+                    let (status, data) = self.forward_call(route, &method, &args)?;
+                    match self.routes[route].from.other() {
+                        Side::A => self.a.complete_deferred(request, status, &data)?,
+                        Side::B => self.b.complete_deferred(request, status, &data)?,
+                    }
+                }
+                Forward::Event { to, id, data } => match to {
+                    Side::A => self.a.send_event(&id, &data)?,
+                    Side::B => self.b.send_event(&id, &data)?,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Make a call on the route's real object, returning the status and reply data to pass back
+    fn forward_call(
+        &mut self,
+        route: usize,
+        method: &str,
+        args: &[u8],
+    ) -> Result<(i32, Vec<u8>), Error<E>> {
+        let route = &mut self.routes[route];
+        let mut data = Vec::new();
+        for attempt in 0..2 {
+            let on_result = |reply: BlobIter<BlobMsg>| data.extend_from_slice(reply.as_bytes());
+            let result = match route.from {
+                Side::A => self.a.invoke(route.id, method, args, on_result),
+                Side::B => self.b.invoke(route.id, method, args, on_result),
+            };
+            match result {
+                Ok(_) => return Ok((0, data)),
+                // The object may have been re-registered with a new id, but NOT_FOUND is also a
+                // normal result of some methods, so only call again if the id has changed
+                Err(Error::Status(4)) if attempt == 0 => {
+                    let id = match route.from {
+                        Side::A => self.a.object_id(&route.path),
+                        Side::B => self.b.object_id(&route.path),
+                    };
+                    match id {
+                        Ok(id) if id != route.id => {
+                            route.id = id;
+                            data.clear();
+                        }
+                        Err(Error::IO(e)) => return Err(Error::IO(e)),
+                        _ => break,
+                    }
+                }
+                Err(Error::Status(status)) => return Ok((status, data)),
+                Err(Error::IO(e)) => return Err(Error::IO(e)),
+                // UBUS_STATUS_UNKNOWN_ERROR
+                Err(_) => return Ok((9, data)),
+            }
+        }
+        // UBUS_STATUS_NOT_FOUND
+        Ok((4, data))
+    }
+}
+
+impl<A: IO<Error = io::Error> + AsRawFd, B: IO<Error = io::Error> + AsRawFd> Proxy<A, B> {
+    /// Handle messages from both buses, forwarding calls and events, until either fails
+    pub fn run(&mut self) -> Result<(), Error<io::Error>> {
+        loop {
+            self.process()?;
+            let ready = select(&[&self.a, &self.b], None)?;
+            if ready.contains(&0) {
+                self.a.handle_next_message()?;
+            }
+            if ready.contains(&1) {
+                self.b.handle_next_message()?;
+            }
+        }
+    }
+}
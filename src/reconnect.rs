@@ -0,0 +1,294 @@
+use crate::split::lock;
+use crate::*;
+use std::boxed::Box;
+use std::string::{String, ToString};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+type Connect<T> = Box<dyn FnMut() -> Result<Connection<T>, Error<<T as IO>::Error>> + Send>;
+type Callback = Arc<Mutex<Box<dyn FnMut(&str, BlobIter<BlobMsg>) + Send>>>;
+type RefreshCallback = Box<dyn FnMut(&Refreshed) + Send>;
+
+/// Object id used while a path isn't registered
+const MISSING: u32 = 0;
+const STATUS_NOT_FOUND: i32 = 4;
+
+/// An object path resolved by a `Reconnecting` connection
+///
+/// The id is looked up again whenever the connection is re-established (ubusd hands out new ids
+/// after a restart), so clones of the handle always see the current id.
+#[derive(Clone, Debug)]
+pub struct PathHandle {
+    path: Arc<str>,
+    id: Arc<AtomicU32>,
+}
+
+impl PathHandle {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Current object id, or `None` if the object was missing when last looked up
+    pub fn id(&self) -> Option<u32> {
+        match self.id.load(Ordering::Relaxed) {
+            MISSING => None,
+            id => Some(id),
+        }
+    }
+}
+
+/// A path handle whose id was looked up again after reconnecting
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Refreshed {
+    pub path: String,
+    pub old: Option<u32>,
+    pub new: Option<u32>,
+}
+
+struct Subscription {
+    target: PathHandle,
+    callback: Callback,
+    subscriber: Option<Subscriber>,
+}
+
+/// A connection which re-establishes itself after failing, e.g. when ubusd restarts
+///
+/// An IO error is taken to mean the connection was lost. The next use reconnects, looks up every
+/// `PathHandle` again, re-subscribes, and reports the refreshed handles to the `on_refresh`
+/// callback. Calls made through `invoke` are retried once after reconnecting, but only if the
+/// connection failed before the call was sent: once sent, ubusd may already have delivered it.
+pub struct Reconnecting<T: IO> {
+    connect: Connect<T>,
+    connection: Option<Connection<T>>,
+    handles: Vec<PathHandle>,
+    subscriptions: Vec<Subscription>,
+    on_refresh: Option<RefreshCallback>,
+    /// Counters from connections which have since been lost
+    stats: Stats,
+}
+
+impl<T: IO> Reconnecting<T> {
+    /// Connect with `connect`, which is called again to reconnect
+    pub fn new(
+        mut connect: impl FnMut() -> Result<Connection<T>, Error<T::Error>> + Send + 'static,
+    ) -> Result<Self, Error<T::Error>> {
+        let connection = connect()?;
+        Ok(Self {
+            connect: Box::new(connect),
+            connection: Some(connection),
+            handles: Vec::new(),
+            subscriptions: Vec::new(),
+            on_refresh: None,
+            stats: Stats::default(),
+        })
+    }
+
+    /// Call `callback` with each path handle looked up again after reconnecting
+    pub fn on_refresh(&mut self, callback: impl FnMut(&Refreshed) + Send + 'static) {
+        self.on_refresh = Some(Box::new(callback));
+    }
+
+    /// The current connection, reconnecting first if it was lost
+    pub fn connection(&mut self) -> Result<&mut Connection<T>, Error<T::Error>> {
+        if self.connection.is_none() {
+            self.reconnect()?;
+        }
+        Ok(self.connection.as_mut().unwrap())
+    }
+
+    /// Is there a connection (which hasn't yet failed)
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Counters for the messages sent and received across all connections, and reconnects
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        if let Some(connection) = &self.connection {
+            stats.add(connection.stats());
+        }
+        stats
+    }
+
+    /// Look up `path`, returning a handle which is kept up to date across reconnects
+    pub fn resolve(&mut self, path: &str) -> Result<PathHandle, Error<T::Error>> {
+        let id = self.with_retry(true, |c| c.object_id(path))?;
+        let handle = PathHandle {
+            path: path.into(),
+            id: Arc::new(AtomicU32::new(id)),
+        };
+        self.handles.push(handle.clone());
+        Ok(handle)
+    }
+
+    /// Pass notifications from `target` to `callback`, subscribing again after reconnecting
+    pub fn subscribe(
+        &mut self,
+        target: &PathHandle,
+        callback: impl FnMut(&str, BlobIter<BlobMsg>) + Send + 'static,
+    ) -> Result<(), Error<T::Error>> {
+        let callback: Callback = Arc::new(Mutex::new(Box::new(callback)));
+        let subscriber = Self::subscribe_on(self.connection()?, target.id(), &callback)?;
+        self.subscriptions.push(Subscription {
+            target: target.clone(),
+            callback,
+            subscriber: Some(subscriber),
+        });
+        Ok(())
+    }
+
+    /// Call `method` on the object at `target`, see `Connection::invoke`
+    pub fn invoke(
+        &mut self,
+        target: &PathHandle,
+        method: &str,
+        args: &[u8],
+        mut on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<InvokeSummary, Error<T::Error>> {
+        self.with_retry(false, |c| {
+            let id = target.id().ok_or(Error::<NoIO>::Status(STATUS_NOT_FOUND))?;
+            c.invoke(id, method, args, &mut on_result)
+        })
+    }
+
+    /// Receive and handle a single message, see `Connection::handle_next_message`
+    ///
+    /// After the connection is lost or times out this returns the error, and reconnects on the
+    /// next call.
+    pub fn handle_next_message(&mut self) -> Result<(), Error<T::Error>> {
+        let result = self.connection()?.handle_next_message();
+        self.check(result)
+    }
+
+    /// Drop the current connection (if any) and connect again, refreshing handles and subscriptions
+    ///
+    /// Nothing is updated unless every lookup and subscription either succeeds or finds its
+    /// object missing, so a failure part way through leaves the handles as they were.
+    pub fn reconnect(&mut self) -> Result<(), Error<T::Error>> {
+        self.disconnect();
+        let mut connection = (self.connect)()?;
+        self.stats.reconnects += 1;
+        trace!("Reconnected, looking up {} paths again", self.handles.len());
+
+        let mut ids = Vec::new();
+        for handle in &self.handles {
+            ids.push(match connection.object_id(&handle.path) {
+                Ok(id) => Some(id),
+                Err(Error::Status(_)) => None,
+                Err(e) => return Err(e),
+            });
+        }
+        let mut subscribers = Vec::new();
+        for subscription in &self.subscriptions {
+            // The old subscriber object went away with the old connection, and targets which have
+            // gone missing can't be subscribed to until the next reconnect
+            let target = &subscription.target;
+            let id = self
+                .handles
+                .iter()
+                .position(|handle| Arc::ptr_eq(&handle.id, &target.id))
+                .and_then(|i| ids[i]);
+            subscribers.push(
+                match Self::subscribe_on(&mut connection, id, &subscription.callback) {
+                    Ok(subscriber) => Some(subscriber),
+                    Err(Error::Status(status)) => {
+                        trace!("Can't subscribe to {} again ({})", target.path, status);
+                        None
+                    }
+                    Err(e) => return Err(e),
+                },
+            );
+        }
+
+        let mut refreshed = Vec::new();
+        for (handle, new) in self.handles.iter().zip(ids) {
+            let old = handle.id();
+            if old != new {
+                trace!("{} changed from {:?} to {:?}", handle.path, old, new);
+            }
+            handle.id.store(new.unwrap_or(MISSING), Ordering::Relaxed);
+            refreshed.push(Refreshed {
+                path: handle.path.to_string(),
+                old,
+                new,
+            });
+        }
+        for (subscription, subscriber) in self.subscriptions.iter_mut().zip(subscribers) {
+            subscription.subscriber = subscriber;
+        }
+        self.connection = Some(connection);
+
+        if let Some(on_refresh) = &mut self.on_refresh {
+            for refreshed in &refreshed {
+                on_refresh(refreshed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribe to the object `id` (`None` if it's missing), passing notifications to `callback`
+    fn subscribe_on(
+        connection: &mut Connection<T>,
+        id: Option<u32>,
+        callback: &Callback,
+    ) -> Result<Subscriber, Error<T::Error>> {
+        let id = id.ok_or(Error::<NoIO>::Status(STATUS_NOT_FOUND))?;
+        let callback = callback.clone();
+        let subscriber = connection.subscriber(move |ty, data| (lock(&callback))(ty, data))?;
+        connection.subscribe(&subscriber, id)?;
+        Ok(subscriber)
+    }
+
+    /// Forget the connection if `result` shows it was lost, or timed out (after which a late
+    /// reply, or the rest of a message, could still arrive on it)
+    fn check<R>(&mut self, result: Result<R, Error<T::Error>>) -> Result<R, Error<T::Error>> {
+        match result {
+            Err(Error::IO(_)) => {
+                warn!("Connection lost, reconnecting on next use");
+                self.disconnect();
+            }
+            Err(Error::Timeout) => {
+                warn!("Connection timed out, reconnecting on next use");
+                self.disconnect();
+            }
+            _ => {}
+        }
+        result
+    }
+
+    /// Drop the connection, keeping its counters
+    fn disconnect(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.stats.add(connection.stats());
+        }
+    }
+
+    /// Run `f` on the connection, reconnecting and retrying once if the connection was lost or
+    /// timed out
+    ///
+    /// Unless `idempotent`, only retried if the connection was lost before `f` sent an INVOKE, and
+    /// never after a timeout (when the call may have been made).
+    fn with_retry<R>(
+        &mut self,
+        idempotent: bool,
+        mut f: impl FnMut(&mut Connection<T>) -> Result<R, Error<T::Error>>,
+    ) -> Result<R, Error<T::Error>> {
+        let reconnected = self.connection.is_none();
+        let connection = self.connection()?;
+        let invokes = connection.stats().sent(MessageType::INVOKE);
+        let result = f(connection);
+        let sent = connection.stats().sent(MessageType::INVOKE) != invokes;
+        match self.check(result) {
+            Err(Error::IO(_)) if !reconnected && (idempotent || !sent) => {
+                let result = f(self.connection()?);
+                self.check(result)
+            }
+            Err(Error::Timeout) if !reconnected && idempotent => {
+                let result = f(self.connection()?);
+                self.check(result)
+            }
+            result => result,
+        }
+    }
+}
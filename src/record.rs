@@ -0,0 +1,165 @@
+use crate::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::Path;
+use std::time::Instant;
+use std::vec::Vec;
+
+/// Wraps a transport, logging all bytes sent and received to a writer
+///
+/// Each `put`/`get` is written as a line: microseconds since the recording started, `>` for sent or
+/// `<` for received, then the bytes in hex. File descriptors passed along are not recorded.
+pub struct RecordingIo<T: IO, W: Write> {
+    io: T,
+    log: W,
+    start: Instant,
+    error: Option<std::io::Error>,
+}
+
+impl<T: IO, W: Write> RecordingIo<T, W> {
+    pub fn new(io: T, log: W) -> Self {
+        Self {
+            io,
+            log,
+            start: Instant::now(),
+            error: None,
+        }
+    }
+
+    /// Stop recording, returning the transport and log (or the first error writing the log)
+    pub fn into_inner(mut self) -> Result<(T, W), std::io::Error> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.log.flush()?;
+        Ok((self.io, self.log))
+    }
+
+    fn record(&mut self, direction: char, data: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        let elapsed = self.start.elapsed().as_micros();
+        let mut line = std::format!("{} {} ", elapsed, direction);
+        for b in data {
+            line += &std::format!("{:02x}", b);
+        }
+        line.push('\n');
+        self.error = self.log.write_all(line.as_bytes()).err();
+    }
+}
+
+impl<T: IO, W: Write> IO for RecordingIo<T, W> {
+    type Error = T::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<T::Error>> {
+        self.io.put(data)?;
+        self.record('>', data);
+        Ok(())
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<T::Error>> {
+        self.io.get(data)?;
+        self.record('<', data);
+        Ok(())
+    }
+    fn put_fd(&mut self, data: &[u8], fd: i32) -> Result<(), Error<T::Error>> {
+        self.io.put_fd(data, fd)?;
+        self.record('>', data);
+        Ok(())
+    }
+    fn get_fd(&mut self, data: &mut [u8]) -> Result<Option<i32>, Error<T::Error>> {
+        let fd = self.io.get_fd(data)?;
+        self.record('<', data);
+        Ok(fd)
+    }
+    fn close(&mut self) -> Result<(), Error<T::Error>> {
+        let _ = self.log.flush();
+        self.io.close()
+    }
+}
+
+/// Plays back a log written by `RecordingIo`, for deterministic tests against a captured session
+///
+/// Reads return the recorded received bytes, in order. Writes are checked against the recorded sent
+/// bytes (failing with `InvalidData` on a mismatch), unless disabled with `check_writes`.
+pub struct ReplayIo {
+    received: Vec<u8>,
+    sent: Vec<u8>,
+    read_offset: usize,
+    write_offset: usize,
+    check_writes: bool,
+}
+
+impl ReplayIo {
+    /// Parse a recording
+    pub fn new(log: impl BufRead) -> Result<Self, std::io::Error> {
+        let mut replay = Self {
+            received: Vec::new(),
+            sent: Vec::new(),
+            read_offset: 0,
+            write_offset: 0,
+            check_writes: true,
+        };
+        let invalid = || std::io::Error::new(ErrorKind::InvalidData, "Invalid recording");
+        for line in log.lines() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+            let (_elapsed, direction, hex) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(elapsed), Some(direction), hex) => (elapsed, direction, hex.unwrap_or("")),
+                (None, ..) => continue,
+                _ => return Err(invalid()),
+            };
+            let stream = match direction {
+                ">" => &mut replay.sent,
+                "<" => &mut replay.received,
+                _ => return Err(invalid()),
+            };
+            if hex.len() % 2 != 0 {
+                return Err(invalid());
+            }
+            for i in (0..hex.len()).step_by(2) {
+                let byte = hex.get(i..i + 2).ok_or_else(invalid)?;
+                stream.push(u8::from_str_radix(byte, 16).map_err(|_| invalid())?);
+            }
+        }
+        Ok(replay)
+    }
+
+    /// Parse a recording from a file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+
+    /// Whether writes must match the recording (the default)
+    pub fn check_writes(mut self, check: bool) -> Self {
+        self.check_writes = check;
+        self
+    }
+
+    /// Have all the recorded received bytes been read
+    pub fn is_finished(&self) -> bool {
+        self.read_offset == self.received.len()
+    }
+}
+
+impl IO for ReplayIo {
+    type Error = std::io::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        if self.check_writes {
+            let end = self.write_offset + data.len();
+            if self.sent.get(self.write_offset..end) != Some(data) {
+                return Err(Error::InvalidData("Write does not match recording"));
+            }
+            self.write_offset = end;
+        }
+        Ok(())
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        let end = self.read_offset + data.len();
+        match self.received.get(self.read_offset..end) {
+            Some(recorded) => data.copy_from_slice(recorded),
+            None => return Err(Error::IO(ErrorKind::UnexpectedEof.into())),
+        }
+        self.read_offset = end;
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+use crate::*;
+
+/// A request to ubusd or an object, with its required attributes in the order ubusd expects
+///
+/// Built with the constructors of `LookupRequest`, `InvokeRequest`, `SubscribeRequest` and
+/// `UnsubscribeRequest`, which take every required attribute, so a request can't be missing one.
+/// The attributes are produced by iterating the request.
+pub trait RequestMessage<'a>: IntoIterator<Item = MessageAttr<'a>> + Copy {
+    /// The type of message the request is sent as
+    const TYPE: MessageType;
+
+    /// The peer the request is addressed to (0 for ubusd itself)
+    fn peer(&self) -> u32 {
+        0
+    }
+}
+
+impl<'b> MessageBuilder<'b> {
+    /// Build `request` with the sequence number `sequence` into `buffer`
+    pub fn from_request<'a, R: RequestMessage<'a>>(
+        buffer: &'b mut [u8],
+        sequence: u16,
+        request: R,
+    ) -> Result<Self, Error> {
+        let header = MessageHeader {
+            version: MessageVersion::CURRENT,
+            message: R::TYPE,
+            sequence: sequence.into(),
+            peer: request.peer().into(),
+        };
+        let mut builder = Self::new(buffer, header)?;
+        for attr in request {
+            builder.put(attr)?;
+        }
+        Ok(builder)
+    }
+}
+
+/// The attributes of a request, in order
+pub struct RequestAttrs<'a> {
+    attrs: [Option<MessageAttr<'a>>; 4],
+    next: usize,
+}
+
+impl<'a> RequestAttrs<'a> {
+    fn new(attrs: [Option<MessageAttr<'a>>; 4]) -> Self {
+        Self { attrs, next: 0 }
+    }
+}
+
+impl<'a> Iterator for RequestAttrs<'a> {
+    type Item = MessageAttr<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(attr) = self.attrs.get_mut(self.next) {
+            self.next += 1;
+            if let Some(attr) = attr.take() {
+                return Some(attr);
+            }
+        }
+        None
+    }
+}
+
+/// Look up objects, by path or all of them
+#[derive(Copy, Clone, Debug)]
+pub struct LookupRequest<'a> {
+    path: Option<&'a str>,
+}
+
+impl<'a> LookupRequest<'a> {
+    /// Every object on the bus
+    pub fn all() -> Self {
+        Self { path: None }
+    }
+
+    /// Objects matching `path` (which may end with a `*` wildcard)
+    pub fn path(path: &'a str) -> Self {
+        Self { path: Some(path) }
+    }
+}
+
+impl<'a> IntoIterator for LookupRequest<'a> {
+    type Item = MessageAttr<'a>;
+    type IntoIter = RequestAttrs<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        RequestAttrs::new([self.path.map(MessageAttr::ObjPath), None, None, None])
+    }
+}
+
+impl<'a> RequestMessage<'a> for LookupRequest<'a> {
+    const TYPE: MessageType = MessageType::LOOKUP;
+}
+
+/// Call a method of an object
+#[derive(Copy, Clone, Debug)]
+pub struct InvokeRequest<'a> {
+    obj: u32,
+    method: &'a str,
+    args: &'a [u8],
+    no_reply: bool,
+}
+
+impl<'a> InvokeRequest<'a> {
+    /// Call `method` on `obj`, without arguments
+    pub fn new(obj: u32, method: &'a str) -> Self {
+        Self {
+            obj,
+            method,
+            args: &[],
+            no_reply: false,
+        }
+    }
+
+    /// Pass the blobmsg table `args` to the method
+    pub fn args(mut self, args: &'a [u8]) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Ask the object not to reply
+    pub fn no_reply(mut self) -> Self {
+        self.no_reply = true;
+        self
+    }
+}
+
+impl<'a> IntoIterator for InvokeRequest<'a> {
+    type Item = MessageAttr<'a>;
+    type IntoIter = RequestAttrs<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        RequestAttrs::new([
+            Some(MessageAttr::ObjId(self.obj)),
+            Some(MessageAttr::Method(self.method)),
+            Some(MessageAttr::Data(self.args)),
+            Some(MessageAttr::NoReply(true)).filter(|_| self.no_reply),
+        ])
+    }
+}
+
+impl<'a> RequestMessage<'a> for InvokeRequest<'a> {
+    const TYPE: MessageType = MessageType::INVOKE;
+    fn peer(&self) -> u32 {
+        self.obj
+    }
+}
+
+/// Have the object `subscriber` receive notifications from the object `target`
+#[derive(Copy, Clone, Debug)]
+pub struct SubscribeRequest {
+    subscriber: u32,
+    target: u32,
+}
+
+impl SubscribeRequest {
+    pub fn new(subscriber: u32, target: u32) -> Self {
+        Self { subscriber, target }
+    }
+}
+
+impl IntoIterator for SubscribeRequest {
+    type Item = MessageAttr<'static>;
+    type IntoIter = RequestAttrs<'static>;
+    fn into_iter(self) -> Self::IntoIter {
+        RequestAttrs::new([
+            Some(MessageAttr::ObjId(self.subscriber)),
+            Some(MessageAttr::Target(self.target)),
+            None,
+            None,
+        ])
+    }
+}
+
+impl RequestMessage<'static> for SubscribeRequest {
+    const TYPE: MessageType = MessageType::SUBSCRIBE;
+}
+
+/// Stop the object `subscriber` receiving notifications from the object `target`
+#[derive(Copy, Clone, Debug)]
+pub struct UnsubscribeRequest {
+    subscriber: u32,
+    target: u32,
+}
+
+impl UnsubscribeRequest {
+    pub fn new(subscriber: u32, target: u32) -> Self {
+        Self { subscriber, target }
+    }
+}
+
+impl IntoIterator for UnsubscribeRequest {
+    type Item = MessageAttr<'static>;
+    type IntoIter = RequestAttrs<'static>;
+    fn into_iter(self) -> Self::IntoIter {
+        SubscribeRequest::new(self.subscriber, self.target).into_iter()
+    }
+}
+
+impl RequestMessage<'static> for UnsubscribeRequest {
+    const TYPE: MessageType = MessageType::UNSUBSCRIBE;
+}
+
+/// Register an object, at `path` or (for subscribers) without one
+#[derive(Copy, Clone, Debug)]
+pub struct AddObjectRequest<'a> {
+    path: Option<&'a str>,
+}
+
+impl<'a> AddObjectRequest<'a> {
+    pub fn new(path: Option<&'a str>) -> Self {
+        Self { path }
+    }
+}
+
+impl<'a> IntoIterator for AddObjectRequest<'a> {
+    type Item = MessageAttr<'a>;
+    type IntoIter = RequestAttrs<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        RequestAttrs::new([self.path.map(MessageAttr::ObjPath), None, None, None])
+    }
+}
+
+impl<'a> RequestMessage<'a> for AddObjectRequest<'a> {
+    const TYPE: MessageType = MessageType::ADD_OBJECT;
+}
+
+/// Remove the object `id` registered by this connection
+#[derive(Copy, Clone, Debug)]
+pub struct RemoveObjectRequest {
+    id: u32,
+}
+
+impl RemoveObjectRequest {
+    pub fn new(id: u32) -> Self {
+        Self { id }
+    }
+}
+
+impl IntoIterator for RemoveObjectRequest {
+    type Item = MessageAttr<'static>;
+    type IntoIter = RequestAttrs<'static>;
+    fn into_iter(self) -> Self::IntoIter {
+        RequestAttrs::new([Some(MessageAttr::ObjId(self.id)), None, None, None])
+    }
+}
+
+impl RequestMessage<'static> for RemoveObjectRequest {
+    const TYPE: MessageType = MessageType::REMOVE_OBJECT;
+}
+
+/// The attributes of a message, by id (the last wins if one is repeated)
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MessageAttrs<'a> {
+    pub status: Option<i32>,
+    pub obj_path: Option<&'a str>,
+    pub obj_id: Option<u32>,
+    pub method: Option<&'a str>,
+    pub obj_type: Option<u32>,
+    /// The blobmsg table of method signatures
+    pub signature: Option<&'a [u8]>,
+    /// The blobmsg table of arguments or results
+    pub data: Option<&'a [u8]>,
+    pub target: Option<u32>,
+    pub active: Option<bool>,
+    pub no_reply: bool,
+    pub user: Option<&'a str>,
+    pub group: Option<&'a str>,
+}
+
+impl<'a> MessageAttrs<'a> {
+    /// Parse `attrs`, skipping unknown ones
+    pub fn parse(attrs: BlobIter<'a, MessageAttr<'a>>) -> Self {
+        Self::parse_iter(attrs)
+    }
+
+    fn parse_iter(attrs: impl IntoIterator<Item = MessageAttr<'a>>) -> Self {
+        let mut parsed = Self::default();
+        for attr in attrs {
+            match attr {
+                MessageAttr::Status(val) => parsed.status = Some(val),
+                MessageAttr::ObjPath(val) => parsed.obj_path = Some(val),
+                MessageAttr::ObjId(val) => parsed.obj_id = Some(val),
+                MessageAttr::Method(val) => parsed.method = Some(val),
+                MessageAttr::ObjType(val) => parsed.obj_type = Some(val),
+                MessageAttr::Signature(val) => parsed.signature = Some(val.as_bytes()),
+                MessageAttr::Data(val) => parsed.data = Some(val),
+                MessageAttr::Target(val) => parsed.target = Some(val),
+                MessageAttr::Active(val) => parsed.active = Some(val),
+                MessageAttr::NoReply(val) => parsed.no_reply = val,
+                MessageAttr::User(val) => parsed.user = Some(val),
+                MessageAttr::Group(val) => parsed.group = Some(val),
+                MessageAttr::Subscribers(_) | MessageAttr::Unknown(..) | MessageAttr::Raw(_) => {}
+            }
+        }
+        parsed
+    }
+
+    /// The attributes which are present, in order of id (as ubusd and libubus send them)
+    pub fn iter(&self) -> impl Iterator<Item = MessageAttr<'a>> {
+        let signature = self
+            .signature
+            .map(|val| MessageAttr::Signature(BlobIter::new(val)));
+        IntoIterator::into_iter([
+            self.status.map(MessageAttr::Status),
+            self.obj_path.map(MessageAttr::ObjPath),
+            self.obj_id.map(MessageAttr::ObjId),
+            self.method.map(MessageAttr::Method),
+            self.obj_type.map(MessageAttr::ObjType),
+            signature,
+            self.data.map(MessageAttr::Data),
+            self.target.map(MessageAttr::Target),
+            self.active.map(MessageAttr::Active),
+            Some(MessageAttr::NoReply(true)).filter(|_| self.no_reply),
+            self.user.map(MessageAttr::User),
+            self.group.map(MessageAttr::Group),
+        ])
+        .flatten()
+    }
+}
+
+/// A request, sent or received: its type, sequence number, peer and attributes
+#[derive(Copy, Clone, Debug)]
+pub struct Request<'a> {
+    pub message: MessageType,
+    pub sequence: u16,
+    pub peer: u32,
+    pub attrs: MessageAttrs<'a>,
+}
+
+impl<'a> Request<'a> {
+    /// `request`, to be sent with the sequence number `sequence`
+    pub fn new<R: RequestMessage<'a>>(sequence: u16, request: R) -> Self {
+        Self {
+            message: R::TYPE,
+            sequence,
+            peer: request.peer(),
+            attrs: MessageAttrs::parse_iter(request),
+        }
+    }
+
+    /// A request received from another peer
+    pub fn from_message(message: &Message<'a>) -> Self {
+        Self {
+            message: message.header.message,
+            sequence: message.header.sequence.into(),
+            peer: message.header.peer.into(),
+            attrs: MessageAttrs::parse(BlobIter::new(message.blob.data)),
+        }
+    }
+
+    /// Build the request into `buffer`
+    pub fn build<'b>(&self, buffer: &'b mut [u8]) -> Result<MessageBuilder<'b>, Error> {
+        let header = MessageHeader {
+            version: MessageVersion::CURRENT,
+            message: self.message,
+            sequence: self.sequence.into(),
+            peer: self.peer.into(),
+        };
+        let mut builder = MessageBuilder::new(buffer, header)?;
+        for attr in self.attrs.iter() {
+            builder.put(attr)?;
+        }
+        Ok(builder)
+    }
+
+    /// Number of bytes the request takes up once built
+    pub fn size(&self) -> usize {
+        message_size(self.attrs.iter().map(|attr| attr.size()))
+    }
+}
+
+/// Size of the buffer messages are built in without std (with std, it's sized to fit each one)
+#[cfg(feature = "no_std")]
+pub(crate) const MESSAGE_BUFFER_SIZE: usize = 1024;
+
+/// Number of bytes a message takes up, given the size of each of its attributes
+pub(crate) fn message_size(attrs: impl Iterator<Item = usize>) -> usize {
+    MessageHeader::SIZE + BlobTag::SIZE + attrs.sum::<usize>()
+}
+
+/// A buffer to build a message of `size` bytes in
+#[cfg(not(feature = "no_std"))]
+pub(crate) fn message_buffer(size: usize) -> Result<std::vec::Vec<u8>, Error> {
+    Ok(std::vec![0u8; size])
+}
+
+/// A buffer to build a message of `size` bytes in
+#[cfg(feature = "no_std")]
+pub(crate) fn message_buffer(size: usize) -> Result<[u8; MESSAGE_BUFFER_SIZE], Error> {
+    if size > MESSAGE_BUFFER_SIZE {
+        return Err(Error::InvalidData("Request too large"));
+    }
+    Ok([0u8; MESSAGE_BUFFER_SIZE])
+}
+
+/// A reply to a request (DATA, or the final STATUS)
+#[derive(Copy, Clone, Debug)]
+pub struct Response<'a> {
+    pub message: MessageType,
+    pub sequence: u16,
+    pub peer: u32,
+    pub attrs: MessageAttrs<'a>,
+    /// File descriptor passed along with the reply
+    pub fd: Option<i32>,
+}
+
+impl<'a> Response<'a> {
+    pub fn from_message(message: &Message<'a>) -> Self {
+        Self {
+            message: message.header.message,
+            sequence: message.header.sequence.into(),
+            peer: message.header.peer.into(),
+            attrs: MessageAttrs::parse(BlobIter::new(message.blob.data)),
+            fd: message.fd,
+        }
+    }
+
+    /// Is this a reply to `request` (rather than an unrelated message, or a late reply to an
+    /// earlier request)
+    pub fn answers(&self, request: &Request) -> bool {
+        matches!(self.message, MessageType::DATA | MessageType::STATUS)
+            && self.sequence == request.sequence
+    }
+
+    /// Is this the final reply, ending the request
+    pub fn is_final(&self) -> bool {
+        self.message == MessageType::STATUS
+    }
+
+    /// The request's result: `Ok` for the 0 status, otherwise `Error::Status`
+    ///
+    /// Only for the final reply, others (and malformed ones) being `InvalidData`.
+    pub fn status(&self) -> Result<(), Error> {
+        match self.attrs.status {
+            Some(0) if self.is_final() => Ok(()),
+            Some(status) if self.is_final() => Err(Error::Status(status)),
+            _ => Err(Error::InvalidData("Invalid status message")),
+        }
+    }
+
+    /// The reply's DATA table
+    pub fn data(&self) -> Option<BlobIter<'a, BlobMsg<'a>>> {
+        self.attrs.data.map(BlobIter::new)
+    }
+}
+
+/// What came back from an invoke
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InvokeSummary {
+    /// Number of DATA replies
+    pub messages: usize,
+    /// Total size of the tables returned in the DATA replies
+    pub bytes: usize,
+    /// The final status, which is always 0 (others being returned as `Error::Status`), or `None`
+    /// if the invoke stopped before it arrived
+    pub status: Option<i32>,
+}
+
+impl InvokeSummary {
+    /// Whether any data came back
+    pub fn has_data(&self) -> bool {
+        self.messages > 0
+    }
+
+    /// Count a DATA reply with the attributes `attrs`
+    pub(crate) fn add(&mut self, attrs: &[u8]) {
+        self.messages += 1;
+        for attr in BlobIter::<MessageAttr>::new(attrs) {
+            if let MessageAttr::Data(data) = attr {
+                self.bytes += data.len();
+            }
+        }
+    }
+}
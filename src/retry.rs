@@ -0,0 +1,136 @@
+use crate::*;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+/// How `Connection::connect_with_retry` (and `RetryPolicy::retry`) wait between attempts
+///
+/// The delay starts at `initial_delay` and doubles after each failed attempt, up to `max_delay`.
+/// Up to `jitter` of each delay is taken off at random, so services started together (e.g. at
+/// boot, before ubusd) don't all retry at once. By default it retries forever.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    attempts: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+            attempts: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay after the first failed attempt
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Longest delay between attempts
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Fraction (0 to 1) of each delay which may be taken off at random
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Give up after `attempts` attempts (at least one is always made)
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = Some(attempts);
+        self
+    }
+
+    /// Keep trying until an attempt succeeds (the default)
+    pub fn forever(mut self) -> Self {
+        self.attempts = None;
+        self
+    }
+
+    /// Delay after failed attempt number `attempt` (counting from 0), before jitter
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// Call `f` until it succeeds, waiting between attempts
+    ///
+    /// Only the errors of a socket which is missing (`NotFound`) or not yet listening
+    /// (`ConnectionRefused`), and timeouts, are retried. Others (such as `PermissionDenied`) won't
+    /// go away by waiting, so are returned straight away. After the last attempt its error is
+    /// returned.
+    pub fn retry<R>(
+        &self,
+        mut f: impl FnMut() -> Result<R, Error<io::Error>>,
+    ) -> Result<R, Error<io::Error>> {
+        let mut attempt = 0;
+        loop {
+            let error = match f() {
+                Ok(result) => return Ok(result),
+                Err(e) if retryable(&e) => e,
+                Err(e) => return Err(e),
+            };
+            if self
+                .attempts
+                .is_some_and(|attempts| attempt + 1 >= attempts)
+            {
+                return Err(error);
+            }
+            let delay = self.delay(attempt);
+            std::thread::sleep(delay.mul_f64(1.0 - self.jitter * random()));
+            attempt = attempt.saturating_add(1);
+        }
+    }
+}
+
+/// Whether `error` may go away by waiting, as while ubusd is starting
+fn retryable(error: &Error<io::Error>) -> bool {
+    match error {
+        Error::IO(e) => matches!(
+            e.kind(),
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+        ),
+        Error::Timeout => true,
+        _ => false,
+    }
+}
+
+/// A random number from 0 to 1, from the random keys std gives each `RandomState`
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Connection<UnixStream> {
+    /// Like `connect`, retrying with `policy` while the socket is missing or refusing connections
+    ///
+    /// For services started before ubusd. To keep a connection up afterwards, use this as the
+    /// connect function of `Reconnecting`.
+    pub fn connect_with_retry(
+        path: &Path,
+        policy: &RetryPolicy,
+    ) -> Result<Self, Error<std::io::Error>> {
+        policy.retry(|| Self::connect(path))
+    }
+}
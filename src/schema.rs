@@ -0,0 +1,110 @@
+use crate::*;
+use core::fmt::Write;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// JSON Schema dialect of the documents generated
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// A method in an object's signature, and the types of its arguments
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodSignature {
+    pub name: String,
+    pub args: Vec<(String, BlobMsgType)>,
+}
+
+/// Read the signature of the object at `path`
+///
+/// Returns `Status(4)` (UBUS_STATUS_NOT_FOUND) if there is no such object.
+pub fn object_signature<B: Bus>(
+    bus: &mut B,
+    path: &str,
+) -> Result<Vec<MethodSignature>, Error<B::Error>> {
+    let mut found = false;
+    let mut methods = Vec::new();
+    bus.list(
+        Some(path),
+        |object| found |= object.path == path,
+        |signature| {
+            if signature.object.path == path {
+                let args = signature
+                    .args
+                    .map(|(name, ty)| (name.to_string(), ty))
+                    .collect();
+                let name = signature.name.to_string();
+                methods.push(MethodSignature { name, args });
+            }
+        },
+    )?;
+    if !found {
+        return Err(Error::Status(4));
+    }
+    Ok(methods)
+}
+
+/// Describe the methods of the object at `path`, from its signature, as a JSON Schema document
+///
+/// Each method's arguments are a schema under `$defs` (e.g. `#/$defs/status`). ubus doesn't mark
+/// arguments as required, and objects ignore ones they don't know, so neither is enforced.
+/// Returns `Status(4)` (UBUS_STATUS_NOT_FOUND) if there is no such object.
+pub fn object_schema<B: Bus>(bus: &mut B, path: &str) -> Result<String, Error<B::Error>> {
+    let methods = object_signature(bus, path)?;
+    let mut schema = String::new();
+    write_schema(&mut schema, path, &methods).expect("Writing to a String can't fail");
+    Ok(schema)
+}
+
+fn write_schema(out: &mut String, path: &str, methods: &[MethodSignature]) -> core::fmt::Result {
+    write!(out, "{{\"$schema\":")?;
+    write_string(out, SCHEMA_DIALECT)?;
+    write!(out, ",\"title\":")?;
+    write_string(out, path)?;
+    write!(out, ",\"$defs\":{{")?;
+    for (i, method) in methods.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(out, &method.name)?;
+        write!(out, ":{{\"type\":\"object\",\"properties\":{{")?;
+        for (j, (name, ty)) in method.args.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            write_string(out, name)?;
+            match type_name(*ty) {
+                Some(ty) => write!(out, ":{{\"type\":\"{}\"}}", ty)?,
+                None => out.push_str(":{}"),
+            }
+        }
+        out.push_str("}}");
+    }
+    out.push_str("}}");
+    Ok(())
+}
+
+/// JSON Schema type of a blobmsg type, as libubox converts it to JSON
+fn type_name(ty: BlobMsgType) -> Option<&'static str> {
+    match ty {
+        BlobMsgType::ARRAY => Some("array"),
+        BlobMsgType::TABLE => Some("object"),
+        BlobMsgType::STRING => Some("string"),
+        BlobMsgType::INT64 | BlobMsgType::INT32 | BlobMsgType::INT16 => Some("integer"),
+        BlobMsgType::INT8 => Some("boolean"),
+        BlobMsgType::DOUBLE => Some("number"),
+        _ => None,
+    }
+}
+
+fn write_string(out: &mut String, s: &str) -> core::fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::*;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+/// Wait until any of `connections` has a message to receive, returning the indexes of those
+/// which do (in order), or none if `timeout` expires first
+///
+/// Lets one thread serve several buses (e.g. a host's and its containers'), handling a message
+/// from each ready connection with `handle_next_message`. Connections closed by the peer count
+/// as ready, so the next receive reports it. Only select between requests, as a connection
+/// waiting for replies reads them itself.
+pub fn select(
+    connections: &[&dyn AsRawFd],
+    timeout: Option<Duration>,
+) -> Result<Vec<usize>, Error<io::Error>> {
+    let mut fds: Vec<libc::pollfd> = connections
+        .iter()
+        .map(|connection| libc::pollfd {
+            fd: connection.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let wait = match deadline {
+            // Rounded up, so a short timeout doesn't become a busy loop
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                left.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        };
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, wait) };
+        if ready >= 0 {
+            break;
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(Error::IO(e));
+        }
+    }
+    Ok(fds
+        .iter()
+        .enumerate()
+        .filter(|(_, fd)| fd.revents != 0)
+        .map(|(index, _)| index)
+        .collect())
+}
@@ -0,0 +1,102 @@
+use crate::*;
+
+/// Number of requests given up on (timed out, stopped early or not waited for) whose sequence
+/// numbers connections keep back until their STATUS arrives, or until this many newer ones have
+/// been given up on
+pub(crate) const MAX_ABANDONED: usize = 16;
+
+/// Request sequence numbers, and which of up to `N` requests are still waiting for replies
+///
+/// Numbers count up, wrapping around after 65535, skipping any still pending so late replies to
+/// an old request can't be taken for replies to a new one. Requests are pending from `start` (or
+/// `insert`) until `finish`, normally once their final STATUS has arrived.
+#[derive(Debug, Clone)]
+pub struct Sequences<const N: usize> {
+    last: u16,
+    /// Pending sequence numbers, oldest first
+    pending: [u16; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for Sequences<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Sequences<N> {
+    pub const fn new() -> Self {
+        Self {
+            last: 0,
+            pending: [0; N],
+            len: 0,
+        }
+    }
+
+    /// The next sequence number not pending, without making it pending
+    pub fn allocate(&mut self) -> u16 {
+        loop {
+            self.last = self.last.wrapping_add(1);
+            if !self.is_pending(self.last) {
+                return self.last;
+            }
+        }
+    }
+
+    /// Allocate a sequence number and make it pending, failing if `N` requests already are
+    pub fn start(&mut self) -> Result<u16, Error> {
+        if self.len == N {
+            return Err(Error::InvalidData("Too many requests in progress"));
+        }
+        let sequence = self.allocate();
+        self.insert(sequence);
+        Ok(sequence)
+    }
+
+    /// Make `sequence` pending, forgetting the oldest pending request if `N` already are
+    ///
+    /// For requests which may be abandoned (timing out or stopping early), whose replies can still
+    /// arrive however long after.
+    pub fn insert(&mut self, sequence: u16) {
+        if N == 0 || self.is_pending(sequence) {
+            return;
+        }
+        if self.len == N {
+            self.pending.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.pending[self.len] = sequence;
+        self.len += 1;
+    }
+
+    /// Is the request `sequence` still waiting for replies
+    pub fn is_pending(&self, sequence: u16) -> bool {
+        self.pending[..self.len].contains(&sequence)
+    }
+
+    /// The request `sequence` has finished, returning whether it was pending
+    pub fn finish(&mut self, sequence: u16) -> bool {
+        match self.pending[..self.len].iter().position(|&s| s == sequence) {
+            Some(index) => {
+                self.pending.copy_within(index + 1..self.len, index);
+                self.len -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget all pending requests, e.g. after reconnecting
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Number of pending requests
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
@@ -0,0 +1,157 @@
+//! DHCP leases, from odhcpd's `dhcp` object or LuCI's `luci-rpc`
+
+use super::{array, int, string, strings, table, tables, Table};
+use crate::*;
+use std::string::String;
+use std::vec::Vec;
+
+/// A lease handed out to a client
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lease {
+    /// Client hardware address, as "00:11:22:aa:bb:cc"
+    pub mac: Option<String>,
+    /// DHCPv6 client id
+    pub duid: Option<String>,
+    pub hostname: Option<String>,
+    /// Leased addresses: one for IPv4, possibly several for IPv6
+    pub addresses: Vec<String>,
+    /// Device the lease was handed out on (odhcpd only), e.g. "br-lan"
+    pub device: Option<String>,
+    /// Seconds until the lease expires, or `None` if it doesn't
+    pub expires: Option<i64>,
+}
+
+/// Which object to ask for leases
+enum Source {
+    /// odhcpd's `dhcp`, which also knows the device of each lease
+    Odhcpd,
+    /// LuCI's `luci-rpc`, which also covers leases handed out by dnsmasq
+    Luci,
+}
+
+/// Client for lease queries
+pub struct Dhcp<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    id: u32,
+    source: Source,
+}
+
+impl<'c, T: IO> Dhcp<'c, T> {
+    /// Query odhcpd's `dhcp` object
+    pub fn new(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id("dhcp")?;
+        Ok(Self {
+            connection,
+            id,
+            source: Source::Odhcpd,
+        })
+    }
+
+    /// Query LuCI's `luci-rpc` object, for systems running dnsmasq rather than odhcpd
+    pub fn luci(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id("luci-rpc")?;
+        Ok(Self {
+            connection,
+            id,
+            source: Source::Luci,
+        })
+    }
+
+    /// The current DHCPv4 leases
+    pub fn ipv4_leases(&mut self) -> Result<Vec<Lease>, Error<T::Error>> {
+        self.leases(4)
+    }
+
+    /// The current DHCPv6 leases
+    pub fn ipv6_leases(&mut self) -> Result<Vec<Lease>, Error<T::Error>> {
+        self.leases(6)
+    }
+
+    fn leases(&mut self, family: i32) -> Result<Vec<Lease>, Error<T::Error>> {
+        Ok(match self.source {
+            Source::Odhcpd => {
+                let method = if family == 4 {
+                    "ipv4leases"
+                } else {
+                    "ipv6leases"
+                };
+                let reply = self.connection.invoke_collect(self.id, method, &[])?;
+                // Leases are grouped by device
+                table(&reply, "device")
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|(device, leases)| match leases {
+                        BlobMsgValue::Table(leases) => tables(array(leases, "leases"))
+                            .map(|lease| Lease::from_odhcpd(device, lease))
+                            .collect(),
+                        _ => Vec::new(),
+                    })
+                    .collect()
+            }
+            Source::Luci => {
+                let mut buffer = [0u8; 64];
+                let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+                args.push_int32("family", family)?;
+                let reply =
+                    self.connection
+                        .invoke_collect(self.id, "getDHCPLeases", args.finish())?;
+                let name = if family == 4 {
+                    "dhcp_leases"
+                } else {
+                    "dhcp6_leases"
+                };
+                tables(array(&reply, name)).map(Lease::from_luci).collect()
+            }
+        })
+    }
+}
+
+impl Lease {
+    fn from_odhcpd(device: &str, lease: &Table) -> Self {
+        // IPv4 leases have one address, IPv6 ones a list of tables
+        let mut addresses: Vec<String> = string(lease, "address").into_iter().collect();
+        addresses.extend(tables(array(lease, "ipv6-addr")).filter_map(|a| string(a, "address")));
+        Self {
+            mac: string(lease, "mac").map(|mac| format_mac(&mac)),
+            duid: string(lease, "duid"),
+            hostname: string(lease, "hostname").filter(|name| !name.is_empty()),
+            addresses,
+            device: Some(device.into()),
+            // odhcpd reports static leases as expiring in INT32_MAX seconds
+            expires: int(lease, "valid").filter(|&valid| valid < i32::MAX as i64),
+        }
+    }
+
+    fn from_luci(lease: &Table) -> Self {
+        let mut addresses = strings(array(lease, "ip6addrs"));
+        if addresses.is_empty() {
+            addresses.extend(string(lease, "ipaddr").or_else(|| string(lease, "ip6addr")));
+        }
+        Self {
+            mac: string(lease, "macaddr").map(|mac| format_mac(&mac)),
+            duid: string(lease, "duid"),
+            hostname: string(lease, "hostname"),
+            addresses,
+            device: None,
+            // Leases which never expire have `false` or -1
+            expires: int(lease, "expires").filter(|&expires| expires >= 0),
+        }
+    }
+}
+
+/// Normalise a MAC address to lowercase with colons, as odhcpd reports it without any
+fn format_mac(mac: &str) -> String {
+    let digits: Vec<char> = mac
+        .chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let mut out = String::new();
+    for (i, pair) in digits.chunks(2).enumerate() {
+        if i > 0 {
+            out.push(':');
+        }
+        out.extend(pair);
+    }
+    out
+}
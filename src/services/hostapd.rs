@@ -0,0 +1,180 @@
+//! hostapd's `hostapd.<interface>` objects, for managing an access point's clients
+
+use super::{boolean, int, string, table, Table};
+use crate::*;
+use std::string::String;
+use std::vec::Vec;
+
+/// A station known to the access point, from `get_clients`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Client {
+    /// Hardware address, as "00:11:22:aa:bb:cc"
+    pub mac: String,
+    pub auth: bool,
+    pub assoc: bool,
+    pub authorized: bool,
+    pub preauth: bool,
+    pub wds: bool,
+    pub wmm: bool,
+    pub ht: bool,
+    pub vht: bool,
+    pub he: bool,
+    pub wps: bool,
+    /// Management frame protection
+    pub mfp: bool,
+    /// Association id
+    pub aid: Option<i64>,
+    /// In dBm
+    pub signal: Option<i64>,
+    pub rx_bytes: Option<i64>,
+    pub tx_bytes: Option<i64>,
+    pub rx_packets: Option<i64>,
+    pub tx_packets: Option<i64>,
+    /// Bitrates of the last frames, in kbit/s
+    pub rx_rate: Option<i64>,
+    pub tx_rate: Option<i64>,
+}
+
+/// A change of a client's state, notified by hostapd
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientEvent {
+    /// A station associated
+    Assoc {
+        address: String,
+        /// In dBm
+        signal: Option<i64>,
+        /// In MHz
+        freq: Option<i64>,
+    },
+    /// A station left
+    Disassoc { address: String },
+}
+
+/// Client for the `hostapd.<interface>` object of one access point
+pub struct Hostapd<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    id: u32,
+}
+
+impl<'c, T: IO> Hostapd<'c, T> {
+    /// Manage the access point on `interface`, e.g. "phy0-ap0"
+    pub fn new(
+        connection: &'c mut Connection<T>,
+        interface: &str,
+    ) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id(&std::format!("hostapd.{}", interface))?;
+        Ok(Self { connection, id })
+    }
+
+    /// The stations known to the access point
+    pub fn get_clients(&mut self) -> Result<Vec<Client>, Error<T::Error>> {
+        let reply = self
+            .connection
+            .invoke_collect(self.id, "get_clients", &[])?;
+        Ok(table(&reply, "clients")
+            .into_iter()
+            .flatten()
+            .filter_map(|(mac, client)| match client {
+                BlobMsgValue::Table(client) => Some(Client::from_table(mac, client)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Disconnect the station `mac` with the 802.11 reason code `reason`
+    ///
+    /// Deauthenticates rather than just disassociating if `deauth` is set, and refuses the
+    /// station's attempts to come back for `ban_time` milliseconds.
+    pub fn del_client(
+        &mut self,
+        mac: &str,
+        reason: u16,
+        deauth: bool,
+        ban_time: Option<u32>,
+    ) -> Result<(), Error<T::Error>> {
+        let mut buffer = [0u8; 128];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("addr", mac)?;
+        args.push_int32("reason", reason.into())?;
+        args.push_bool("deauth", deauth)?;
+        if let Some(ban_time) = ban_time {
+            args.push_int32("ban_time", ban_time as i32)?;
+        }
+        self.connection
+            .invoke(self.id, "del_client", args.finish(), |_| {})?;
+        Ok(())
+    }
+
+    /// Start WPS push button configuration
+    pub fn wps_start(&mut self) -> Result<(), Error<T::Error>> {
+        self.connection.invoke(self.id, "wps_start", &[], |_| {})?;
+        Ok(())
+    }
+
+    /// Pass stations associating and leaving to `callback`
+    ///
+    /// The callback runs as the connection handles messages (e.g. in `handle_next_message`).
+    /// Dropping the returned handle unsubscribes.
+    pub fn subscribe(
+        &mut self,
+        mut callback: impl FnMut(ClientEvent) + Send + 'static,
+    ) -> Result<Subscriber, Error<T::Error>> {
+        let subscriber = self.connection.subscriber(move |ty, data| {
+            if let Some(event) = data
+                .to_map()
+                .ok()
+                .and_then(|data| ClientEvent::from_table(ty, &data))
+            {
+                callback(event)
+            }
+        })?;
+        self.connection.subscribe(&subscriber, self.id)?;
+        Ok(subscriber)
+    }
+}
+
+impl Client {
+    fn from_table(mac: &str, client: &Table) -> Self {
+        let flag = |name| boolean(client, name).unwrap_or(false);
+        let pair = |name, direction| table(client, name).and_then(|t| int(t, direction));
+        Self {
+            mac: mac.into(),
+            auth: flag("auth"),
+            assoc: flag("assoc"),
+            authorized: flag("authorized"),
+            preauth: flag("preauth"),
+            wds: flag("wds"),
+            wmm: flag("wmm"),
+            ht: flag("ht"),
+            vht: flag("vht"),
+            he: flag("he"),
+            wps: flag("wps"),
+            mfp: flag("mfp"),
+            aid: int(client, "aid"),
+            signal: int(client, "signal"),
+            rx_bytes: pair("bytes", "rx"),
+            tx_bytes: pair("bytes", "tx"),
+            rx_packets: pair("packets", "rx"),
+            tx_packets: pair("packets", "tx"),
+            rx_rate: pair("rate", "rx"),
+            tx_rate: pair("rate", "tx"),
+        }
+    }
+}
+
+impl ClientEvent {
+    /// Parse a notification, skipping those (like "probe") which aren't about clients coming and
+    /// going
+    fn from_table(ty: &str, data: &Table) -> Option<Self> {
+        let address = string(data, "address")?;
+        match ty {
+            "assoc" => Some(ClientEvent::Assoc {
+                address,
+                signal: int(data, "signal"),
+                freq: int(data, "freq"),
+            }),
+            "disassoc" => Some(ClientEvent::Disassoc { address }),
+            _ => None,
+        }
+    }
+}
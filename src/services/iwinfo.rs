@@ -0,0 +1,221 @@
+//! rpcd's `iwinfo` object, for the state of wireless devices
+
+use super::{array, boolean, int, missing, string, strings, table, tables, Table};
+use crate::*;
+use std::string::String;
+use std::vec::Vec;
+
+/// A wireless device's state, from `info`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Info {
+    /// Radio the device belongs to, e.g. "phy0"
+    pub phy: Option<String>,
+    pub ssid: Option<String>,
+    pub bssid: Option<String>,
+    pub country: Option<String>,
+    /// Operating mode, e.g. "Master" or "Client"
+    pub mode: Option<String>,
+    pub channel: Option<i64>,
+    /// In MHz
+    pub frequency: Option<i64>,
+    /// In dBm
+    pub txpower: Option<i64>,
+    pub quality: Option<i64>,
+    pub quality_max: Option<i64>,
+    /// In dBm
+    pub signal: Option<i64>,
+    /// In dBm
+    pub noise: Option<i64>,
+    /// In kbit/s
+    pub bitrate: Option<i64>,
+    pub encryption: Option<Encryption>,
+}
+
+/// The security of a network
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Encryption {
+    pub enabled: bool,
+    /// WPA versions, e.g. [2, 3] for WPA2/WPA3 mixed mode
+    pub wpa: Vec<i64>,
+    /// Key management, e.g. "psk" or "sae"
+    pub authentication: Vec<String>,
+    /// e.g. "ccmp"
+    pub ciphers: Vec<String>,
+}
+
+/// A station associated with an access point, from `assoclist`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Station {
+    pub mac: String,
+    /// In dBm
+    pub signal: Option<i64>,
+    /// In dBm
+    pub noise: Option<i64>,
+    /// Milliseconds since the station was last heard from
+    pub inactive: Option<i64>,
+    /// Seconds since the station associated
+    pub connected_time: Option<i64>,
+    pub authorized: Option<bool>,
+    pub rx: Rate,
+    pub tx: Rate,
+}
+
+/// One direction of a station's traffic
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rate {
+    /// Bitrate of the last frame, in kbit/s
+    pub rate: Option<i64>,
+    /// Channel width, in MHz
+    pub mhz: Option<i64>,
+    pub packets: Option<i64>,
+    pub bytes: Option<i64>,
+}
+
+/// A network found by `scan`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanResult {
+    /// Missing for hidden networks
+    pub ssid: Option<String>,
+    pub bssid: String,
+    pub mode: Option<String>,
+    pub channel: Option<i64>,
+    /// In dBm
+    pub signal: Option<i64>,
+    pub quality: Option<i64>,
+    pub quality_max: Option<i64>,
+    pub encryption: Option<Encryption>,
+}
+
+/// Client for rpcd's `iwinfo` object
+pub struct Iwinfo<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    id: u32,
+}
+
+impl<'c, T: IO> Iwinfo<'c, T> {
+    pub fn new(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id("iwinfo")?;
+        Ok(Self { connection, id })
+    }
+
+    /// The names of the wireless devices, e.g. "wlan0"
+    pub fn devices(&mut self) -> Result<Vec<String>, Error<T::Error>> {
+        let reply = self.connection.invoke_collect(self.id, "devices", &[])?;
+        Ok(strings(array(&reply, "devices")))
+    }
+
+    /// The state of `device`
+    pub fn info(&mut self, device: &str) -> Result<Info, Error<T::Error>> {
+        let reply = self.call("info", device)?;
+        Ok(Info::from_table(&reply))
+    }
+
+    /// The stations associated with `device`
+    pub fn assoclist(&mut self, device: &str) -> Result<Vec<Station>, Error<T::Error>> {
+        let reply = self.call("assoclist", device)?;
+        let stations = tables(array(&reply, "results"))
+            .map(Station::from_table)
+            .collect::<Result<_, _>>()?;
+        Ok(stations)
+    }
+
+    /// Scan for networks with `device`, which takes a few seconds
+    pub fn scan(&mut self, device: &str) -> Result<Vec<ScanResult>, Error<T::Error>> {
+        let reply = self.call("scan", device)?;
+        let results = tables(array(&reply, "results"))
+            .map(ScanResult::from_table)
+            .collect::<Result<_, _>>()?;
+        Ok(results)
+    }
+
+    fn call(&mut self, method: &str, device: &str) -> Result<Table, Error<T::Error>> {
+        let mut buffer = [0u8; 128];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("device", device)?;
+        self.connection
+            .invoke_collect(self.id, method, args.finish())
+    }
+}
+
+impl Info {
+    fn from_table(info: &Table) -> Self {
+        Self {
+            phy: string(info, "phy"),
+            ssid: string(info, "ssid"),
+            bssid: string(info, "bssid"),
+            country: string(info, "country"),
+            mode: string(info, "mode"),
+            channel: int(info, "channel"),
+            frequency: int(info, "frequency"),
+            txpower: int(info, "txpower"),
+            quality: int(info, "quality"),
+            quality_max: int(info, "quality_max"),
+            signal: int(info, "signal"),
+            noise: int(info, "noise"),
+            bitrate: int(info, "bitrate"),
+            encryption: table(info, "encryption").map(Encryption::from_table),
+        }
+    }
+}
+
+impl Encryption {
+    fn from_table(encryption: &Table) -> Self {
+        Self {
+            enabled: boolean(encryption, "enabled").unwrap_or(false),
+            wpa: array(encryption, "wpa")
+                .iter()
+                .filter_map(|version| match version {
+                    BlobMsgValue::Int64(v) => Some(*v),
+                    _ => None,
+                })
+                .collect(),
+            authentication: strings(array(encryption, "authentication")),
+            ciphers: strings(array(encryption, "ciphers")),
+        }
+    }
+}
+
+impl Station {
+    fn from_table(station: &Table) -> Result<Self, Error> {
+        Ok(Self {
+            mac: string(station, "mac").ok_or_else(missing)?,
+            signal: int(station, "signal"),
+            noise: int(station, "noise"),
+            inactive: int(station, "inactive"),
+            connected_time: int(station, "connected_time"),
+            authorized: boolean(station, "authorized"),
+            rx: table(station, "rx")
+                .map(Rate::from_table)
+                .unwrap_or_default(),
+            tx: table(station, "tx")
+                .map(Rate::from_table)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl Rate {
+    fn from_table(rate: &Table) -> Self {
+        Self {
+            rate: int(rate, "rate"),
+            mhz: int(rate, "mhz"),
+            packets: int(rate, "packets"),
+            bytes: int(rate, "bytes"),
+        }
+    }
+}
+
+impl ScanResult {
+    fn from_table(result: &Table) -> Result<Self, Error> {
+        Ok(Self {
+            ssid: string(result, "ssid"),
+            bssid: string(result, "bssid").ok_or_else(missing)?,
+            mode: string(result, "mode"),
+            channel: int(result, "channel"),
+            signal: int(result, "signal"),
+            quality: int(result, "quality"),
+            quality_max: int(result, "quality_max"),
+            encryption: table(result, "encryption").map(Encryption::from_table),
+        })
+    }
+}
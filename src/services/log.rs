@@ -0,0 +1,132 @@
+//! logd's `log` object, for the system log
+
+use super::{array, int, missing, string, tables, Table};
+use crate::*;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::FromRawFd;
+use std::string::String;
+use std::vec::Vec;
+
+/// A line of the system log
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub message: String,
+    /// Sequence number, counting up from logd's start
+    pub id: i64,
+    /// Facility and severity, as in syslog: `facility << 3 | severity`
+    pub priority: i64,
+    /// Where the line came from: 0 for the kernel, 1 for syslog, 2 for logd itself
+    pub source: i64,
+    /// Milliseconds since the epoch
+    pub time: i64,
+}
+
+impl Entry {
+    /// Facility, e.g. 3 for daemon
+    pub fn facility(&self) -> i64 {
+        self.priority >> 3
+    }
+
+    /// Severity, from 0 (emergency) to 7 (debug)
+    pub fn severity(&self) -> i64 {
+        self.priority & 7
+    }
+
+    fn from_table(entry: &Table) -> Result<Self, Error> {
+        Ok(Self {
+            message: string(entry, "msg").ok_or_else(missing)?,
+            id: int(entry, "id").ok_or_else(missing)?,
+            priority: int(entry, "priority").ok_or_else(missing)?,
+            source: int(entry, "source").ok_or_else(missing)?,
+            time: int(entry, "time").ok_or_else(missing)?,
+        })
+    }
+}
+
+/// Client for logd's `log` object
+pub struct Log<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    id: u32,
+}
+
+impl<'c, T: IO> Log<'c, T> {
+    pub fn new(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id("log")?;
+        Ok(Self { connection, id })
+    }
+
+    /// The last `lines` lines of the log buffer, or all of it
+    pub fn read(&mut self, lines: Option<u32>) -> Result<Vec<Entry>, Error<T::Error>> {
+        let args = read_args(&mut [0u8; 64], lines, false)?;
+        let reply = self.connection.invoke_collect(self.id, "read", &args)?;
+        let entries = tables(array(&reply, "log"))
+            .map(Entry::from_table)
+            .collect::<Result<_, _>>()?;
+        Ok(entries)
+    }
+
+    /// Follow the log, starting with the last `lines` lines (or all of it)
+    ///
+    /// logd streams entries through a pipe passed back with the reply, so the stream doesn't tie
+    /// up the connection.
+    pub fn follow(&mut self, lines: Option<u32>) -> Result<LogStream, Error<T::Error>> {
+        let args = read_args(&mut [0u8; 64], lines, true)?;
+        let fd = self
+            .connection
+            .invoke_fd(self.id, "read", &args, None, |_| {})?
+            .ok_or(Error::<NoIO>::InvalidData("No log stream in reply"))?;
+        Ok(LogStream {
+            file: unsafe { File::from_raw_fd(fd) },
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Add `message` to the log, as logd's own source
+    pub fn write(&mut self, message: &str) -> Result<(), Error<T::Error>> {
+        let mut buffer = std::vec![0u8; message.len() + 64];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("event", message)?;
+        self.connection
+            .invoke(self.id, "write", args.finish(), |_| {})?;
+        Ok(())
+    }
+}
+
+fn read_args(buffer: &mut [u8], lines: Option<u32>, stream: bool) -> Result<Vec<u8>, Error> {
+    let mut args = BlobMsgBuilder::from_bytes(buffer);
+    args.push_int32("lines", lines.unwrap_or(0) as i32)?;
+    args.push_bool("stream", stream)?;
+    Ok(args.finish().to_vec())
+}
+
+/// Log entries as logd streams them, from `Log::follow`
+///
+/// Iterating blocks until the next entry arrives, ending when logd closes the stream.
+pub struct LogStream {
+    file: File,
+    buffer: Vec<u8>,
+}
+
+impl Iterator for LogStream {
+    type Item = Result<Entry, Error<io::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Each entry is a blob holding the entry's table
+        let mut tag = [0u8; BlobTag::SIZE];
+        match self.file.read_exact(&mut tag) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(Error::IO(e))),
+        }
+        let tag = BlobTag::from_bytes(tag);
+        self.buffer.resize(tag.inner_len(), 0);
+        if let Err(e) = self.file.read_exact(&mut self.buffer) {
+            return Some(Err(Error::IO(e)));
+        }
+        let entry = BlobIter::<BlobMsg>::new(&self.buffer)
+            .to_map()
+            .and_then(|entry| Entry::from_table(&entry));
+        Some(entry.map_err(Error::from))
+    }
+}
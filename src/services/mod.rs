@@ -0,0 +1,80 @@
+//! Typed clients for the objects found on every OpenWrt system
+//!
+//! Each wraps a `Connection`, converting replies into structs so tools don't each walk the
+//! same blobmsg tables. Fields OpenWrt doesn't always report are optional.
+
+pub mod dhcp;
+pub mod hostapd;
+pub mod iwinfo;
+pub mod log;
+pub mod network;
+pub mod procd;
+pub mod system;
+pub mod uci;
+
+use crate::*;
+use std::collections::BTreeMap;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// A reply (or a table nested in it), as returned by `invoke_collect`
+type Table = BTreeMap<String, BlobMsgValue>;
+
+/// Error for a reply without a field the typed result needs
+fn missing() -> Error {
+    Error::InvalidData("Reply is missing a field")
+}
+
+fn string(table: &Table, name: &str) -> Option<String> {
+    match table.get(name) {
+        Some(BlobMsgValue::String(v)) => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+fn int(table: &Table, name: &str) -> Option<i64> {
+    match table.get(name) {
+        Some(BlobMsgValue::Int64(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+fn boolean(table: &Table, name: &str) -> Option<bool> {
+    match table.get(name) {
+        Some(BlobMsgValue::Bool(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+fn table<'t>(table: &'t Table, name: &str) -> Option<&'t Table> {
+    match table.get(name) {
+        Some(BlobMsgValue::Table(v)) => Some(v),
+        _ => None,
+    }
+}
+
+fn array<'t>(table: &'t Table, name: &str) -> &'t [BlobMsgValue] {
+    match table.get(name) {
+        Some(BlobMsgValue::Array(v)) => v,
+        _ => &[],
+    }
+}
+
+/// The tables in an array, skipping anything else
+fn tables(values: &[BlobMsgValue]) -> impl Iterator<Item = &Table> {
+    values.iter().filter_map(|value| match value {
+        BlobMsgValue::Table(v) => Some(v),
+        _ => None,
+    })
+}
+
+/// The strings in an array, skipping anything else
+fn strings(values: &[BlobMsgValue]) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(|value| match value {
+            BlobMsgValue::String(v) => Some(v.to_string()),
+            _ => None,
+        })
+        .collect()
+}
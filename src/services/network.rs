@@ -0,0 +1,149 @@
+//! netifd's `network.interface` objects
+
+use super::{array, boolean, int, missing, string, strings, tables, Table};
+use crate::*;
+use std::string::String;
+use std::vec::Vec;
+
+/// An interface's state, from `network.interface.<name> status` or `network.interface dump`
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterfaceStatus {
+    /// Logical interface name, e.g. "lan"
+    pub interface: String,
+    pub up: bool,
+    pub pending: bool,
+    pub available: bool,
+    pub autostart: bool,
+    pub dynamic: bool,
+    /// Seconds since the interface came up
+    pub uptime: Option<i64>,
+    /// Protocol handler, e.g. "static" or "dhcp"
+    pub proto: String,
+    /// Device configured for the interface, e.g. "br-lan"
+    pub device: Option<String>,
+    /// Device the interface's addresses are on, which may differ for tunnels
+    pub l3_device: Option<String>,
+    pub metric: Option<i64>,
+    pub ipv4_address: Vec<Address>,
+    pub ipv6_address: Vec<Address>,
+    pub route: Vec<Route>,
+    pub dns_server: Vec<String>,
+    pub dns_search: Vec<String>,
+}
+
+/// An address assigned to an interface
+#[derive(Clone, Debug, PartialEq)]
+pub struct Address {
+    pub address: String,
+    /// Prefix length
+    pub mask: u8,
+}
+
+/// A route set up by an interface
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    pub target: String,
+    /// Prefix length
+    pub mask: u8,
+    pub nexthop: String,
+    pub source: Option<String>,
+}
+
+/// Client for netifd's interfaces
+pub struct Network<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+}
+
+impl<'c, T: IO> Network<'c, T> {
+    pub fn new(connection: &'c mut Connection<T>) -> Self {
+        Self { connection }
+    }
+
+    /// The state of every interface
+    pub fn dump(&mut self) -> Result<Vec<InterfaceStatus>, Error<T::Error>> {
+        let id = self.connection.object_id("network.interface")?;
+        let reply = self.connection.invoke_collect(id, "dump", &[])?;
+        let statuses = tables(array(&reply, "interface"))
+            .map(|status| {
+                let name = string(status, "interface").ok_or_else(missing)?;
+                InterfaceStatus::from_table(name, status)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(statuses)
+    }
+
+    /// The state of the interface `interface` (e.g. "lan")
+    pub fn status(&mut self, interface: &str) -> Result<InterfaceStatus, Error<T::Error>> {
+        let reply = self.call(interface, "status")?;
+        Ok(InterfaceStatus::from_table(interface.into(), &reply)?)
+    }
+
+    /// Bring the interface up
+    pub fn up(&mut self, interface: &str) -> Result<(), Error<T::Error>> {
+        self.call(interface, "up")?;
+        Ok(())
+    }
+
+    /// Take the interface down
+    pub fn down(&mut self, interface: &str) -> Result<(), Error<T::Error>> {
+        self.call(interface, "down")?;
+        Ok(())
+    }
+
+    /// Renew the interface's lease (for protocols such as DHCP)
+    pub fn renew(&mut self, interface: &str) -> Result<(), Error<T::Error>> {
+        self.call(interface, "renew")?;
+        Ok(())
+    }
+
+    /// Call `method` on the interface's own object
+    fn call(&mut self, interface: &str, method: &str) -> Result<Table, Error<T::Error>> {
+        let path = std::format!("network.interface.{}", interface);
+        let id = self.connection.object_id(&path)?;
+        self.connection.invoke_collect(id, method, &[])
+    }
+}
+
+impl InterfaceStatus {
+    fn from_table(interface: String, status: &Table) -> Result<Self, Error> {
+        let flag = |name| boolean(status, name).unwrap_or(false);
+        Ok(Self {
+            interface,
+            up: boolean(status, "up").ok_or_else(missing)?,
+            pending: flag("pending"),
+            available: flag("available"),
+            autostart: flag("autostart"),
+            dynamic: flag("dynamic"),
+            uptime: int(status, "uptime"),
+            proto: string(status, "proto").ok_or_else(missing)?,
+            device: string(status, "device"),
+            l3_device: string(status, "l3_device"),
+            metric: int(status, "metric"),
+            ipv4_address: addresses(status, "ipv4-address"),
+            ipv6_address: addresses(status, "ipv6-address"),
+            route: tables(array(status, "route"))
+                .filter_map(|route| {
+                    Some(Route {
+                        target: string(route, "target")?,
+                        mask: int(route, "mask")? as u8,
+                        nexthop: string(route, "nexthop")?,
+                        source: string(route, "source"),
+                    })
+                })
+                .collect(),
+            dns_server: strings(array(status, "dns-server")),
+            dns_search: strings(array(status, "dns-search")),
+        })
+    }
+}
+
+fn addresses(status: &Table, name: &str) -> Vec<Address> {
+    tables(array(status, name))
+        .filter_map(|address| {
+            Some(Address {
+                address: string(address, "address")?,
+                mask: int(address, "mask")? as u8,
+            })
+        })
+        .collect()
+}
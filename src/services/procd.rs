@@ -0,0 +1,180 @@
+//! procd's `service` object, which supervises the init scripts' processes
+
+use super::{array, boolean, int, strings, table, Table};
+use crate::*;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// A service, from `service list`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub instances: Vec<Instance>,
+}
+
+/// A running (or respawning) instance of a service's process
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub name: String,
+    pub running: bool,
+    pub pid: Option<u32>,
+    pub command: Vec<String>,
+    /// Seconds procd waits after SIGTERM before killing the process
+    pub term_timeout: Option<u32>,
+    /// Exit code of the last run, once it has exited
+    pub exit_code: Option<i32>,
+    pub respawn: Option<Respawn>,
+}
+
+/// How procd restarts an instance which exits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Respawn {
+    /// Seconds an instance must run for to count as having started successfully
+    pub threshold: u32,
+    /// Seconds to wait before restarting
+    pub timeout: u32,
+    /// Restarts of failing instances before giving up (0 for no limit)
+    pub retry: u32,
+}
+
+/// An instance to start with `Procd::set`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstanceConfig {
+    pub name: String,
+    pub command: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub respawn: Option<Respawn>,
+}
+
+/// Client for procd's `service` object
+pub struct Procd<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    id: u32,
+}
+
+impl<'c, T: IO> Procd<'c, T> {
+    pub fn new(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id("service")?;
+        Ok(Self { connection, id })
+    }
+
+    /// Every service (or just the service `name`) with its instances
+    pub fn list(&mut self, name: Option<&str>) -> Result<Vec<ServiceInfo>, Error<T::Error>> {
+        let mut buffer = std::vec![0u8; 256];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        if let Some(name) = name {
+            args.push_string("name", name)?;
+        }
+        let reply = self
+            .connection
+            .invoke_collect(self.id, "list", args.finish())?;
+        Ok(reply
+            .iter()
+            .filter_map(|(name, service)| match service {
+                BlobMsgValue::Table(service) => Some(ServiceInfo::from_table(name, service)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Raise a procd event (such as "config.change"), with `data` as its blobmsg table
+    pub fn event(&mut self, ty: &str, data: &[u8]) -> Result<(), Error<T::Error>> {
+        let mut buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("type", ty)?;
+        args.push_table("data", |b| b.push_raw(data))?;
+        self.connection
+            .invoke(self.id, "event", args.finish(), |_| {})?;
+        Ok(())
+    }
+
+    /// Create or replace the service `name`, running `instances`
+    pub fn set(&mut self, name: &str, instances: &[InstanceConfig]) -> Result<(), Error<T::Error>> {
+        let mut buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("name", name)?;
+        args.push_table("instances", |b| {
+            instances
+                .iter()
+                .try_for_each(|instance| b.push_table(&instance.name, |b| instance.push_to(b)))
+        })?;
+        self.connection
+            .invoke(self.id, "set", args.finish(), |_| {})?;
+        Ok(())
+    }
+
+    /// Stop and remove the service `name`, or only its instance `instance`
+    pub fn delete(&mut self, name: &str, instance: Option<&str>) -> Result<(), Error<T::Error>> {
+        let mut buffer = std::vec![0u8; 256];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("name", name)?;
+        if let Some(instance) = instance {
+            args.push_string("instance", instance)?;
+        }
+        self.connection
+            .invoke(self.id, "delete", args.finish(), |_| {})?;
+        Ok(())
+    }
+}
+
+impl ServiceInfo {
+    fn from_table(name: &str, service: &Table) -> Self {
+        let instances = table(service, "instances").into_iter().flatten();
+        Self {
+            name: name.to_string(),
+            instances: instances
+                .filter_map(|(name, instance)| match instance {
+                    BlobMsgValue::Table(instance) => Some(Instance::from_table(name, instance)),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Instance {
+    fn from_table(name: &str, instance: &Table) -> Self {
+        let respawn = table(instance, "respawn").and_then(|respawn| {
+            Some(Respawn {
+                threshold: int(respawn, "threshold")? as u32,
+                timeout: int(respawn, "timeout")? as u32,
+                retry: int(respawn, "retry")? as u32,
+            })
+        });
+        Self {
+            name: name.to_string(),
+            running: boolean(instance, "running").unwrap_or(false),
+            pid: int(instance, "pid").map(|pid| pid as u32),
+            command: strings(array(instance, "command")),
+            term_timeout: int(instance, "term_timeout").map(|v| v as u32),
+            exit_code: int(instance, "exit_code").map(|v| v as i32),
+            respawn,
+        }
+    }
+}
+
+impl InstanceConfig {
+    fn push_to(&self, builder: &mut BlobMsgBuilder) -> Result<(), Error> {
+        builder.push_array("command", |b| {
+            self.command
+                .iter()
+                .try_for_each(|arg| b.push_string("", arg))
+        })?;
+        if !self.env.is_empty() {
+            builder.push_table("env", |b| {
+                self.env
+                    .iter()
+                    .try_for_each(|(name, value)| b.push_string(name, value))
+            })?;
+        }
+        if let Some(respawn) = &self.respawn {
+            // As procd.sh sends them
+            builder.push_array("respawn", |b| {
+                [respawn.threshold, respawn.timeout, respawn.retry]
+                    .iter()
+                    .try_for_each(|value| b.push_string("", &value.to_string()))
+            })?;
+        }
+        Ok(())
+    }
+}
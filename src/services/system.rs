@@ -0,0 +1,170 @@
+//! procd's `system` object
+
+use super::{array, int, missing, string, table, Table};
+use crate::*;
+use std::string::String;
+
+/// Result of `system board`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Board {
+    pub kernel: String,
+    pub hostname: String,
+    /// CPU description, e.g. "ARMv7 Processor rev 5 (v7l)"
+    pub system: String,
+    pub model: String,
+    pub board_name: String,
+    pub rootfs_type: Option<String>,
+    pub release: Release,
+}
+
+/// The OpenWrt release, from `system board`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Release {
+    pub distribution: String,
+    pub version: String,
+    pub revision: String,
+    pub target: String,
+    pub description: String,
+}
+
+/// Result of `system info`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Info {
+    /// Seconds since the epoch
+    pub localtime: i64,
+    /// Seconds since boot
+    pub uptime: i64,
+    /// 1, 5 and 15 minute load averages
+    pub load: [f64; 3],
+    pub memory: Memory,
+    pub root: Option<Storage>,
+    pub tmp: Option<Storage>,
+    pub swap: Option<Swap>,
+}
+
+/// Memory usage in bytes, from `system info`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Memory {
+    pub total: u64,
+    pub free: u64,
+    pub shared: u64,
+    pub buffered: u64,
+    pub available: Option<u64>,
+    pub cached: Option<u64>,
+}
+
+/// Filesystem usage in KiB, from `system info`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Storage {
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+    pub avail: u64,
+}
+
+/// Swap usage in bytes, from `system info`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Swap {
+    pub total: u64,
+    pub free: u64,
+}
+
+/// Client for procd's `system` object
+pub struct System<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    id: u32,
+}
+
+impl<'c, T: IO> System<'c, T> {
+    pub fn new(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id("system")?;
+        Ok(Self { connection, id })
+    }
+
+    /// Describe the hardware and the OpenWrt release running on it
+    pub fn board(&mut self) -> Result<Board, Error<T::Error>> {
+        let reply = self.connection.invoke_collect(self.id, "board", &[])?;
+        Ok(Board::from_table(&reply)?)
+    }
+
+    /// Uptime, load, and memory and storage usage
+    pub fn info(&mut self) -> Result<Info, Error<T::Error>> {
+        let reply = self.connection.invoke_collect(self.id, "info", &[])?;
+        Ok(Info::from_table(&reply)?)
+    }
+
+    /// Reboot the system
+    pub fn reboot(&mut self) -> Result<(), Error<T::Error>> {
+        self.connection.invoke(self.id, "reboot", &[], |_| {})?;
+        Ok(())
+    }
+}
+
+impl Board {
+    fn from_table(reply: &Table) -> Result<Self, Error> {
+        let release = table(reply, "release").ok_or_else(missing)?;
+        let release_field = |name| string(release, name).ok_or_else(missing);
+        let field = |name| string(reply, name).ok_or_else(missing);
+        Ok(Self {
+            kernel: field("kernel")?,
+            hostname: field("hostname")?,
+            system: field("system")?,
+            model: field("model")?,
+            board_name: field("board_name")?,
+            rootfs_type: string(reply, "rootfs_type"),
+            release: Release {
+                distribution: release_field("distribution")?,
+                version: release_field("version")?,
+                revision: release_field("revision")?,
+                target: release_field("target")?,
+                description: release_field("description")?,
+            },
+        })
+    }
+}
+
+impl Info {
+    fn from_table(reply: &Table) -> Result<Self, Error> {
+        let mut load = [0.0; 3];
+        // Fixed point, as the kernel reports them
+        for (load, value) in load.iter_mut().zip(array(reply, "load")) {
+            if let BlobMsgValue::Int64(value) = value {
+                *load = *value as f64 / 65536.0;
+            }
+        }
+        let memory = table(reply, "memory").ok_or_else(missing)?;
+        let size = |table: &Table, name| int(table, name).map(|v| v as u64);
+        let memory_field = |name| size(memory, name).ok_or_else(missing);
+        let storage = |name| {
+            let table = table(reply, name)?;
+            Some(Storage {
+                total: size(table, "total")?,
+                free: size(table, "free")?,
+                used: size(table, "used")?,
+                avail: size(table, "avail")?,
+            })
+        };
+        let swap = table(reply, "swap").and_then(|swap| {
+            Some(Swap {
+                total: size(swap, "total")?,
+                free: size(swap, "free")?,
+            })
+        });
+        Ok(Self {
+            localtime: int(reply, "localtime").ok_or_else(missing)?,
+            uptime: int(reply, "uptime").ok_or_else(missing)?,
+            load,
+            memory: Memory {
+                total: memory_field("total")?,
+                free: memory_field("free")?,
+                shared: memory_field("shared")?,
+                buffered: memory_field("buffered")?,
+                available: size(memory, "available"),
+                cached: size(memory, "cached"),
+            },
+            root: storage("root"),
+            tmp: storage("tmp"),
+            swap,
+        })
+    }
+}
@@ -0,0 +1,273 @@
+//! rpcd's `uci` object, for reading and changing configuration
+
+use super::{boolean, int, missing, string, strings, table, Table};
+use crate::*;
+use std::collections::BTreeMap;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// The value of an option: a plain option or a list
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciValue {
+    String(String),
+    List(Vec<String>),
+}
+
+/// A section and its options
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub name: String,
+    /// The section type, e.g. "interface"
+    pub ty: String,
+    /// Unnamed sections get generated names, like "cfg01411c"
+    pub anonymous: bool,
+    /// Position in the config file
+    pub index: Option<i64>,
+    pub options: BTreeMap<String, UciValue>,
+}
+
+/// A change not yet committed, from `changes`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub config: String,
+    /// What was done, e.g. "set", "add", "remove" or "list-add"
+    pub operation: String,
+    pub section: String,
+    pub option: Option<String>,
+    pub value: Option<String>,
+}
+
+/// Client for rpcd's `uci` object
+///
+/// Changes are staged until `commit`. With a session (as for calls made on behalf of a web
+/// interface user) they are staged in the session's own change set, and access is checked
+/// against its ACLs.
+pub struct Uci<'c, T: IO> {
+    connection: &'c mut Connection<T>,
+    id: u32,
+    session: Option<Session>,
+}
+
+impl<'c, T: IO> Uci<'c, T> {
+    pub fn new(connection: &'c mut Connection<T>) -> Result<Self, Error<T::Error>> {
+        let id = connection.object_id("uci")?;
+        Ok(Self {
+            connection,
+            id,
+            session: None,
+        })
+    }
+
+    /// Make every call as `session`
+    pub fn with_session(
+        connection: &'c mut Connection<T>,
+        session: Session,
+    ) -> Result<Self, Error<T::Error>> {
+        Ok(Self {
+            session: Some(session),
+            ..Self::new(connection)?
+        })
+    }
+
+    /// Every section of `config`, in file order
+    pub fn get(&mut self, config: &str) -> Result<Vec<Section>, Error<T::Error>> {
+        let reply = self.call("get", |b| b.push_string("config", config))?;
+        let values = table(&reply, "values").into_iter().flatten();
+        let mut sections: Vec<Section> = values
+            .filter_map(|(name, section)| match section {
+                BlobMsgValue::Table(section) => Some(Section::from_table(name, section)),
+                _ => None,
+            })
+            .collect();
+        sections.sort_by_key(|section| section.index);
+        Ok(sections)
+    }
+
+    /// The section `section` of `config`
+    pub fn get_section(&mut self, config: &str, section: &str) -> Result<Section, Error<T::Error>> {
+        let reply = self.call("get", |b| {
+            b.push_string("config", config)?;
+            b.push_string("section", section)
+        })?;
+        let values = table(&reply, "values").ok_or_else(missing)?;
+        Ok(Section::from_table(section, values))
+    }
+
+    /// The option `option` of `section` in `config`
+    pub fn get_option(
+        &mut self,
+        config: &str,
+        section: &str,
+        option: &str,
+    ) -> Result<UciValue, Error<T::Error>> {
+        let reply = self.call("get", |b| {
+            b.push_string("config", config)?;
+            b.push_string("section", section)?;
+            b.push_string("option", option)
+        })?;
+        let value = reply
+            .get("value")
+            .and_then(UciValue::from_value)
+            .ok_or_else(missing)?;
+        Ok(value)
+    }
+
+    /// Set options of `section` in `config`
+    pub fn set(
+        &mut self,
+        config: &str,
+        section: &str,
+        values: &[(&str, UciValue)],
+    ) -> Result<(), Error<T::Error>> {
+        self.call("set", |b| {
+            b.push_string("config", config)?;
+            b.push_string("section", section)?;
+            push_values(b, values)
+        })?;
+        Ok(())
+    }
+
+    /// Add a section of type `ty` to `config`, returning its name (generated if `name` is `None`)
+    pub fn add(
+        &mut self,
+        config: &str,
+        ty: &str,
+        name: Option<&str>,
+        values: &[(&str, UciValue)],
+    ) -> Result<String, Error<T::Error>> {
+        let reply = self.call("add", |b| {
+            b.push_string("config", config)?;
+            b.push_string("type", ty)?;
+            if let Some(name) = name {
+                b.push_string("name", name)?;
+            }
+            push_values(b, values)
+        })?;
+        let section = string(&reply, "section").ok_or_else(missing)?;
+        Ok(section)
+    }
+
+    /// Delete `section` from `config`, or only its option `option`
+    pub fn delete(
+        &mut self,
+        config: &str,
+        section: &str,
+        option: Option<&str>,
+    ) -> Result<(), Error<T::Error>> {
+        self.call("delete", |b| {
+            b.push_string("config", config)?;
+            b.push_string("section", section)?;
+            match option {
+                Some(option) => b.push_string("option", option),
+                None => Ok(()),
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Write the staged changes to `config`
+    pub fn commit(&mut self, config: &str) -> Result<(), Error<T::Error>> {
+        self.call("commit", |b| b.push_string("config", config))?;
+        Ok(())
+    }
+
+    /// Throw away the staged changes to `config`
+    pub fn revert(&mut self, config: &str) -> Result<(), Error<T::Error>> {
+        self.call("revert", |b| b.push_string("config", config))?;
+        Ok(())
+    }
+
+    /// The changes staged for `config`, or for every config
+    pub fn changes(&mut self, config: Option<&str>) -> Result<Vec<Change>, Error<T::Error>> {
+        let reply = self.call("changes", |b| match config {
+            Some(config) => b.push_string("config", config),
+            None => Ok(()),
+        })?;
+        // A single config's changes are an array, all configs' a table of arrays
+        Ok(match (config, reply.get("changes")) {
+            (Some(config), Some(BlobMsgValue::Array(changes))) => Change::parse(config, changes),
+            (None, Some(BlobMsgValue::Table(configs))) => configs
+                .iter()
+                .flat_map(|(config, changes)| match changes {
+                    BlobMsgValue::Array(changes) => Change::parse(config, changes),
+                    _ => Vec::new(),
+                })
+                .collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    fn call(
+        &mut self,
+        method: &str,
+        args: impl FnOnce(&mut BlobMsgBuilder) -> Result<(), Error>,
+    ) -> Result<Table, Error<T::Error>> {
+        let mut buffer = std::vec![0u8; DEFAULT_BUFFER_SIZE];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        args(&mut builder)?;
+        if let Some(session) = &self.session {
+            builder.push_string("ubus_rpc_session", session.id())?;
+        }
+        self.connection
+            .invoke_collect(self.id, method, builder.finish())
+    }
+}
+
+fn push_values(builder: &mut BlobMsgBuilder, values: &[(&str, UciValue)]) -> Result<(), Error> {
+    builder.push_table("values", |b| {
+        values.iter().try_for_each(|(name, value)| match value {
+            UciValue::String(v) => b.push_string(name, v),
+            UciValue::List(items) => b.push_array(name, |b| {
+                items.iter().try_for_each(|v| b.push_string("", v))
+            }),
+        })
+    })
+}
+
+impl UciValue {
+    fn from_value(value: &BlobMsgValue) -> Option<Self> {
+        match value {
+            BlobMsgValue::String(v) => Some(UciValue::String(v.to_string())),
+            BlobMsgValue::Array(items) => Some(UciValue::List(strings(items))),
+            _ => None,
+        }
+    }
+}
+
+impl Section {
+    fn from_table(name: &str, section: &Table) -> Self {
+        Self {
+            name: string(section, ".name").unwrap_or_else(|| name.to_string()),
+            ty: string(section, ".type").unwrap_or_default(),
+            anonymous: boolean(section, ".anonymous").unwrap_or(false),
+            index: int(section, ".index"),
+            options: section
+                .iter()
+                .filter(|(name, _)| !name.starts_with('.'))
+                .filter_map(|(name, value)| Some((name.to_string(), UciValue::from_value(value)?)))
+                .collect(),
+        }
+    }
+}
+
+impl Change {
+    /// Each change is an array of [operation, section, option, value]
+    fn parse(config: &str, changes: &[BlobMsgValue]) -> Vec<Self> {
+        changes
+            .iter()
+            .filter_map(|change| match change {
+                BlobMsgValue::Array(fields) => {
+                    let mut fields = strings(fields).into_iter();
+                    Some(Change {
+                        config: config.to_string(),
+                        operation: fields.next()?,
+                        section: fields.next()?,
+                        option: fields.next(),
+                        value: fields.next(),
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
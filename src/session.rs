@@ -0,0 +1,136 @@
+use crate::*;
+use core::convert::TryInto;
+use core::str;
+
+/// Length of an rpcd session id (32 hex characters)
+pub const SESSION_ID_LEN: usize = 32;
+
+/// An rpcd session, as returned by `session.login`
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Session {
+    id: [u8; SESSION_ID_LEN],
+    /// Session timeout in seconds (as reported at login)
+    pub timeout: u32,
+}
+
+impl Session {
+    /// The session rpcd uses for unauthenticated access
+    pub const UNAUTHENTICATED: Self = Self {
+        id: [b'0'; SESSION_ID_LEN],
+        timeout: 0,
+    };
+
+    /// Use an existing session id (e.g. one handed over by a web frontend)
+    pub fn from_id(id: &str) -> Result<Self, Error> {
+        let id: [u8; SESSION_ID_LEN] = id
+            .as_bytes()
+            .try_into()
+            .map_err(|_| Error::InvalidData("Session id has wrong length"))?;
+        if !id.iter().all(u8::is_ascii_hexdigit) {
+            return Err(Error::InvalidData("Session id is not hexadecimal"));
+        }
+        Ok(Self { id, timeout: 0 })
+    }
+
+    /// The `ubus_rpc_session` id
+    pub fn id(&self) -> &str {
+        str::from_utf8(&self.id).unwrap()
+    }
+}
+
+impl core::fmt::Debug for Session {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "Session({}, timeout={})", self.id(), self.timeout)
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Log in to rpcd via `session login`, returning the new session
+    pub fn login(&mut self, username: &str, password: &str) -> Result<Session, Error<T::Error>> {
+        let mut args = [0u8; 512];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut args);
+        builder.push_string("username", username)?;
+        builder.push_string("password", password)?;
+        let args = builder.finish();
+
+        let obj = self.object_id("session")?;
+        let mut session: Result<Session, Error> = Err(Error::InvalidData("No session in reply"));
+        let mut timeout = 0;
+        self.invoke(obj, "login", args, |results| {
+            for result in results {
+                match (result.name, result.data) {
+                    (Some("ubus_rpc_session"), BlobMsgData::String(id)) => {
+                        session = Session::from_id(id)
+                    }
+                    (Some("timeout"), BlobMsgData::Int32(val)) => timeout = val as u32,
+                    _ => continue,
+                }
+            }
+        })?;
+        Ok(Session {
+            timeout,
+            ..session?
+        })
+    }
+
+    /// Add `ubus_rpc_session` for `session` to the arguments of every call, or stop with `None`
+    ///
+    /// Saves passing the session to each call of ACL-protected methods, as luci does. Calls
+    /// whose arguments already have a `ubus_rpc_session` are left alone.
+    pub fn set_session(&mut self, session: Option<Session>) {
+        self.session = session;
+    }
+
+    /// The session set by `set_session`
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    /// Invoke a method with `ubus_rpc_session` added to the arguments
+    ///
+    /// `args` must be a blobmsg table payload, as produced by `BlobMsgBuilder`.
+    pub fn invoke_with_session(
+        &mut self,
+        session: &Session,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<InvokeSummary, Error<T::Error>> {
+        with_session(Some(*session), args, |args| {
+            self.invoke(obj, method, args, on_result)
+        })
+    }
+}
+
+/// Call `f` with `args`, plus `ubus_rpc_session` for `session` if there is one and `args` doesn't
+/// have its own
+pub(crate) fn with_session<R, E: From<Error>>(
+    session: Option<Session>,
+    args: &[u8],
+    f: impl FnOnce(&[u8]) -> Result<R, E>,
+) -> Result<R, E> {
+    let session = match session {
+        Some(session)
+            if !BlobIter::<BlobMsg>::new(args).any(|a| a.name == Some("ubus_rpc_session")) =>
+        {
+            session
+        }
+        _ => return f(args),
+    };
+    // Room for the arguments and the session attribute
+    let len = args.len() + 64;
+    #[cfg(not(feature = "no_std"))]
+    let mut buffer = std::vec![0u8; len];
+    #[cfg(feature = "no_std")]
+    let mut buffer = [0u8; 1024];
+    if len > buffer.len() {
+        return Err(Error::InvalidData("Arguments too large").into());
+    }
+    let (head, tail) = buffer.split_at_mut(args.len());
+    head.copy_from_slice(args);
+    let mut builder = BlobMsgBuilder::from_bytes(tail);
+    builder.push_string("ubus_rpc_session", session.id())?;
+    let len = args.len() + builder.len();
+    f(&buffer[..len])
+}
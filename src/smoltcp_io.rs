@@ -0,0 +1,109 @@
+use crate::*;
+use ::smoltcp::iface::{Interface, PollResult, SocketHandle, SocketSet};
+use ::smoltcp::phy::Device;
+use ::smoltcp::socket::tcp;
+use ::smoltcp::time::Instant;
+
+/// A smoltcp TCP socket used while it wasn't connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmoltcpError {
+    /// The socket's state at the time
+    pub state: tcp::State,
+}
+
+impl IOError for SmoltcpError {}
+
+impl core::fmt::Display for SmoltcpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "TCP socket is {}", self.state)
+    }
+}
+
+impl core::error::Error for SmoltcpError {}
+
+/// A smoltcp TCP socket as an `NbIO`, for talking to ubus through a TCP bridge without std
+///
+/// Holds the `SocketSet` the socket is in, as smoltcp needs all of its sockets to poll the
+/// interface (with `poll`) between `NbConnection::poll`s. Other sockets can live in the same set.
+///
+/// Reads and writes return `WouldBlock` while the socket is still connecting, so requests can be
+/// made as soon as the bus says hello. Once the bridge closes the connection, reads fail with
+/// "Connection closed".
+pub struct SmoltcpIo<'a> {
+    sockets: SocketSet<'a>,
+    handle: SocketHandle,
+}
+
+impl<'a> SmoltcpIo<'a> {
+    /// Talk over the TCP socket `handle` in `sockets`, which should be connected (or connecting)
+    /// to the bridge
+    pub fn new(sockets: SocketSet<'a>, handle: SocketHandle) -> Self {
+        Self { sockets, handle }
+    }
+
+    /// Send and receive packets for all of the sockets, as `Interface::poll` does
+    pub fn poll(
+        &mut self,
+        iface: &mut Interface,
+        device: &mut (impl Device + ?Sized),
+        now: Instant,
+    ) -> PollResult {
+        iface.poll(now, device, &mut self.sockets)
+    }
+
+    /// The TCP socket
+    pub fn socket(&mut self) -> &mut tcp::Socket<'a> {
+        self.sockets.get_mut(self.handle)
+    }
+
+    pub fn sockets(&self) -> &SocketSet<'a> {
+        &self.sockets
+    }
+
+    pub fn sockets_mut(&mut self) -> &mut SocketSet<'a> {
+        &mut self.sockets
+    }
+
+    pub fn into_inner(self) -> SocketSet<'a> {
+        self.sockets
+    }
+}
+
+/// Is `socket` still waiting for the connection to be set up
+fn connecting(socket: &tcp::Socket) -> bool {
+    matches!(
+        socket.state(),
+        tcp::State::SynSent | tcp::State::SynReceived
+    )
+}
+
+impl NbIO for SmoltcpIo<'_> {
+    type Error = SmoltcpError;
+    fn write(&mut self, data: &[u8]) -> nb::Result<usize, Error<SmoltcpError>> {
+        let socket = self.socket();
+        if connecting(socket) {
+            return Err(nb::Error::WouldBlock);
+        }
+        match socket.send_slice(data) {
+            Ok(0) => Err(nb::Error::WouldBlock),
+            Ok(len) => Ok(len),
+            Err(tcp::SendError::InvalidState) => Err(nb::Error::Other(Error::IO(SmoltcpError {
+                state: socket.state(),
+            }))),
+        }
+    }
+    fn read(&mut self, data: &mut [u8]) -> nb::Result<usize, Error<SmoltcpError>> {
+        let socket = self.socket();
+        if connecting(socket) {
+            return Err(nb::Error::WouldBlock);
+        }
+        match socket.recv_slice(data) {
+            Ok(0) => Err(nb::Error::WouldBlock),
+            Ok(len) => Ok(len),
+            Err(tcp::RecvError::Finished) => Ok(0),
+            Err(tcp::RecvError::InvalidState) => Err(nb::Error::Other(Error::IO(SmoltcpError {
+                state: socket.state(),
+            }))),
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use crate::hooks::receive_filtered;
+use crate::maybe_async::close_fd;
+use crate::*;
+use std::collections::BTreeMap;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::vec::Vec;
+
+/// `UBUS_STATUS_CONNECTION_FAILED`, for calls the connection failed before replying to
+const STATUS_CONNECTION_FAILED: i32 = 10;
+
+/// Reply to a request, passed from the `EventReader` to the waiting `Requester`
+pub(crate) enum Reply {
+    Data(Vec<u8>),
+    Status(i32),
+}
+
+/// Where the `EventReader` passes the replies to an outstanding request
+pub(crate) enum Waiter {
+    /// A `Requester` blocked waiting for each reply
+    Blocking(Sender<Reply>),
+    /// Collect the DATA tables, sending them all once the status arrives
+    Collect(Sender<CallReply>, Vec<Vec<u8>>),
+}
+
+#[derive(Default)]
+struct Pending {
+    /// Requests nobody is waiting for, carried over from the `Connection` or sent unwatched
+    sequences: Sequences<MAX_ABANDONED>,
+    waiting: BTreeMap<u16, Waiter>,
+}
+
+impl Pending {
+    /// Allocate a sequence number not used by any outstanding request
+    fn next_sequence(&mut self) -> u16 {
+        loop {
+            let sequence = self.sequences.allocate();
+            if !self.waiting.contains_key(&sequence) {
+                return sequence;
+            }
+        }
+    }
+
+    /// Wake up everyone still waiting, once nothing more will arrive
+    fn close(&mut self) {
+        for (sequence, waiter) in core::mem::take(&mut self.waiting) {
+            if let Waiter::Collect(tx, data) = waiter {
+                let _ = tx.send(CallReply {
+                    sequence,
+                    status: STATUS_CONNECTION_FAILED,
+                    data,
+                });
+            }
+        }
+    }
+}
+
+/// The socket, and the connection's hooks (and counters) which every message passes through
+struct Writer {
+    io: UnixStream,
+    hooks: Hooks,
+}
+
+struct Shared {
+    writer: Mutex<Writer>,
+    pending: Mutex<Pending>,
+    session: Mutex<Option<Session>>,
+}
+
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Writes go through the shared (locked) socket, so handlers can send replies
+struct SharedWriter<'a>(&'a Mutex<Writer>);
+impl IO for SharedWriter<'_> {
+    type Error = std::io::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        let Writer { io, hooks } = &mut *lock(self.0);
+        hooks.wrap(io).put(data)
+    }
+    fn get(&mut self, _data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        Err(Error::InvalidData("Cannot read from the writer half"))
+    }
+}
+
+/// Cloneable handle for making calls on a split connection from any thread
+///
+/// Replies are only received while the matching `EventReader` is being run.
+#[derive(Clone)]
+pub struct Requester {
+    shared: Arc<Shared>,
+}
+
+/// Receiving half of a split connection
+///
+/// Passes replies to the waiting `Requester`s, and dispatches everything else to the
+/// connection's objects and subscribers. Dropping it shuts the connection down, failing any
+/// requests still waiting.
+pub struct EventReader {
+    reader: UnixStream,
+    buffer: Vec<u8>,
+    max_message_size: usize,
+    handlers: Handlers,
+    strict: bool,
+    shared: Arc<Shared>,
+}
+
+impl Connection<UnixStream> {
+    /// Split into a `Requester` for making calls and an `EventReader` for receiving
+    ///
+    /// The `Requester` keeps the session set with `set_session`. Hooks set with `on_send` and
+    /// `on_receive` carry on seeing every message, and are counted in `Requester::stats`.
+    pub fn split(self) -> Result<(Requester, EventReader), Error<std::io::Error>> {
+        let io = self.io.try_clone().map_err(Error::IO)?;
+        let mut handlers = self.handlers;
+        let hooks = core::mem::take(&mut handlers.hooks);
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(Writer { io, hooks }),
+            pending: Mutex::new(Pending {
+                sequences: self.sequences,
+                waiting: BTreeMap::new(),
+            }),
+            session: Mutex::new(self.session),
+        });
+        let reader = EventReader {
+            reader: self.io,
+            buffer: self.buffer,
+            max_message_size: self.max_message_size,
+            handlers,
+            strict: self.strict,
+            shared: shared.clone(),
+        };
+        Ok((Requester { shared }, reader))
+    }
+}
+
+impl Requester {
+    /// Send a request, passing its replies to `waiter`, and return its sequence number
+    pub(crate) fn send_request<'b>(
+        &self,
+        message: MessageType,
+        peer: u32,
+        attrs: impl IntoIterator<Item = MessageAttr<'b>>,
+        waiter: Waiter,
+    ) -> Result<u16, Error<std::io::Error>> {
+        let sequence = {
+            let mut pending = lock(&self.shared.pending);
+            let sequence = pending.next_sequence();
+            pending.waiting.insert(sequence, waiter);
+            sequence
+        };
+
+        let mut writer = SharedWriter(&self.shared.writer);
+        if let Err(e) = send_message(&mut writer, message, sequence, peer, attrs) {
+            lock(&self.shared.pending).waiting.remove(&sequence);
+            return Err(e);
+        }
+        Ok(sequence)
+    }
+
+    /// Send a request, then wait for the `EventReader` to pass back the final status
+    pub(crate) fn request<'b>(
+        &self,
+        message: MessageType,
+        peer: u32,
+        attrs: impl IntoIterator<Item = MessageAttr<'b>>,
+        mut on_data: impl FnMut(BlobIter<MessageAttr>),
+    ) -> Result<(), Error<std::io::Error>> {
+        let (tx, rx) = channel();
+        let result = self
+            .send_request(message, peer, attrs, Waiter::Blocking(tx))
+            .and_then(|_| loop {
+                match rx.recv() {
+                    Ok(Reply::Data(data)) => on_data(BlobIter::new(&data)),
+                    Ok(Reply::Status(0)) => return Ok(()),
+                    Ok(Reply::Status(status)) => return Err(Error::Status(status)),
+                    Err(_) => return Err(Error::InvalidData("Connection closed")),
+                }
+            });
+        if result.is_err() {
+            lock(&self.shared.writer).hooks.stats.errors += 1;
+        }
+        result
+    }
+
+    /// Shut down the connection in both directions, which also stops the `EventReader`
+    pub(crate) fn shutdown(&self) {
+        let _ = lock(&self.shared.writer).io.shutdown(Shutdown::Both);
+    }
+
+    /// Like `Connection::stats`, for the connection this was split from
+    pub fn stats(&self) -> Stats {
+        let mut stats = lock(&self.shared.writer).hooks.stats.clone();
+        stats.outstanding = lock(&self.shared.pending).waiting.len() as u64;
+        stats
+    }
+
+    /// Like `Connection::set_session`, for this requester and all its clones
+    pub fn set_session(&self, session: Option<Session>) {
+        *lock(&self.shared.session) = session;
+    }
+
+    /// The session set by `set_session`, or the connection's when it was split
+    pub fn session(&self) -> Option<Session> {
+        *lock(&self.shared.session)
+    }
+
+    pub fn invoke(
+        &self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+        mut on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<InvokeSummary, Error<std::io::Error>> {
+        let mut summary = InvokeSummary::default();
+        with_session(self.session(), args, |args| {
+            let request = InvokeRequest::new(obj, method).args(args);
+            self.request(InvokeRequest::TYPE, obj, request, |attrs| {
+                summary.add(attrs.as_bytes());
+                for attr in attrs {
+                    if let MessageAttr::Data(data) = attr {
+                        on_result(BlobIter::new(data));
+                    }
+                }
+            })
+        })?;
+        summary.status = Some(0);
+        Ok(summary)
+    }
+
+    /// Find the id of the object at `path`
+    pub fn object_id(&self, path: &str) -> Result<u32, Error<std::io::Error>> {
+        let mut id = None;
+        let request = LookupRequest::path(path);
+        self.request(LookupRequest::TYPE, 0, request, |attrs| {
+            for attr in attrs {
+                if let MessageAttr::ObjId(val) = attr {
+                    id = Some(val);
+                }
+            }
+        })?;
+        Ok(id.ok_or(Error::<NoIO>::InvalidData("No object in lookup reply"))?)
+    }
+}
+
+impl EventReader {
+    /// Receive and handle the next message (blocking!)
+    pub fn handle_next_message(&mut self) -> Result<(), Error<std::io::Error>> {
+        let result = self.receive();
+        if result.is_err() {
+            // Nothing more will arrive, so wake up anyone still waiting
+            lock(&self.shared.pending).close();
+            lock(&self.shared.writer).hooks.stats.errors += 1;
+        }
+        result
+    }
+
+    /// Handle messages until the connection fails
+    pub fn run(&mut self) -> Result<(), Error<std::io::Error>> {
+        loop {
+            self.handle_next_message()?;
+        }
+    }
+
+    fn receive(&mut self) -> Result<(), Error<std::io::Error>> {
+        let mut writer = SharedWriter(&self.shared.writer);
+
+        // Remove objects whose handles were dropped (without waiting for the status)
+        while let Some(id) = self.handlers.objects.next_dropped() {
+            self.handlers.objects.entries.remove(&id);
+            let sequence = {
+                let mut pending = lock(&self.shared.pending);
+                let sequence = pending.next_sequence();
+                pending.sequences.insert(sequence);
+                sequence
+            };
+            let attrs = [MessageAttr::ObjId(id)];
+            send_message(&mut writer, MessageType::REMOVE_OBJECT, sequence, 0, attrs)?;
+        }
+
+        let shared = &self.shared;
+        let message = receive_filtered(
+            &mut self.reader,
+            &mut self.buffer,
+            self.max_message_size,
+            |message| lock(&shared.writer).hooks.received(message),
+        )?;
+        // Replies are copied to the waiting requester without it, and our objects don't take one
+        close_fd(message.fd);
+        let sequence = u16::from(message.header.sequence);
+        match message.header.message {
+            MessageType::DATA => {
+                match lock(&self.shared.pending).waiting.get_mut(&sequence) {
+                    Some(Waiter::Blocking(tx)) => {
+                        let _ = tx.send(Reply::Data(message.blob.data.to_vec()));
+                    }
+                    Some(Waiter::Collect(_, data)) => {
+                        for attr in BlobIter::<MessageAttr>::new(message.blob.data) {
+                            if let MessageAttr::Data(table) = attr {
+                                data.push(table.to_vec());
+                            }
+                        }
+                    }
+                    None => {}
+                }
+                Ok(())
+            }
+            MessageType::STATUS => {
+                let waiter = {
+                    let mut pending = lock(&self.shared.pending);
+                    pending.sequences.finish(sequence);
+                    pending.waiting.remove(&sequence)
+                };
+                if let Some(waiter) = waiter {
+                    let mut status = None;
+                    for attr in BlobIter::<MessageAttr>::new(message.blob.data) {
+                        if let MessageAttr::Status(val) = attr {
+                            status = Some(val);
+                        }
+                    }
+                    let status = status.ok_or(Error::<NoIO>::InvalidData("Invalid status"))?;
+                    match waiter {
+                        Waiter::Blocking(tx) => {
+                            let _ = tx.send(Reply::Status(status));
+                        }
+                        Waiter::Collect(tx, data) => {
+                            let _ = tx.send(CallReply {
+                                sequence,
+                                status,
+                                data,
+                            });
+                        }
+                    }
+                }
+                Ok(())
+            }
+            _ => self.handlers.dispatch(&mut writer, self.strict, &message),
+        }
+    }
+}
+
+impl Drop for EventReader {
+    fn drop(&mut self) {
+        // Nothing will receive replies any more, so fail requests in flight (and any sent later)
+        let _ = self.reader.shutdown(Shutdown::Both);
+        lock(&self.shared.pending).close();
+    }
+}
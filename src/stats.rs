@@ -0,0 +1,83 @@
+use crate::*;
+
+/// Number of message types counted separately (HELLO to MONITOR)
+const TYPES: usize = 0x12;
+
+/// Counters for the traffic on a connection, see `Connection::stats`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Messages written in full (those streamed with `writer` are counted as they start)
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    /// Bytes sent and received, including message headers
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Requests (and `handle_next_message` calls) which failed, with an error status or otherwise
+    pub errors: u64,
+    /// Times the connection was re-established (only counted by `Reconnecting`)
+    pub reconnects: u64,
+    /// Requests sent which are still waiting for their final reply
+    pub outstanding: u64,
+    sent_by_type: [u64; TYPES],
+    received_by_type: [u64; TYPES],
+}
+
+impl Stats {
+    /// Messages of type `ty` sent
+    pub fn sent(&self, ty: MessageType) -> u64 {
+        self.sent_by_type
+            .get(ty.value() as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Messages of type `ty` received
+    pub fn received(&self, ty: MessageType) -> u64 {
+        let counts = &self.received_by_type;
+        counts.get(ty.value() as usize).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn count_sent(&mut self, ty: MessageType, bytes: usize) {
+        self.messages_sent += 1;
+        self.bytes_sent += bytes as u64;
+        if let Some(count) = self.sent_by_type.get_mut(ty.value() as usize) {
+            *count += 1;
+        }
+    }
+
+    pub(crate) fn count_received(&mut self, ty: MessageType, bytes: usize) {
+        self.messages_received += 1;
+        self.bytes_received += bytes as u64;
+        if let Some(count) = self.received_by_type.get_mut(ty.value() as usize) {
+            *count += 1;
+        }
+    }
+
+    /// Add the counts from `other` (e.g. from a previous connection) to these
+    pub fn add(&mut self, other: &Stats) {
+        self.messages_sent += other.messages_sent;
+        self.messages_received += other.messages_received;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.errors += other.errors;
+        self.reconnects += other.reconnects;
+        self.outstanding += other.outstanding;
+        for (count, other) in self.sent_by_type.iter_mut().zip(&other.sent_by_type) {
+            *count += other;
+        }
+        for (count, other) in self
+            .received_by_type
+            .iter_mut()
+            .zip(&other.received_by_type)
+        {
+            *count += other;
+        }
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Counters for the messages sent and received so far, for exporting to monitoring
+    pub fn stats(&self) -> &Stats {
+        &self.handlers.hooks.stats
+    }
+}
@@ -1,15 +1,200 @@
 use super::*;
+use core::mem::{size_of, size_of_val, zeroed};
 use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
 use std::os::unix::net::UnixStream;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Socket paths tried (in order) when UBUS_SOCKET is not set
+pub const DEFAULT_SOCKET_PATHS: &[&str] = &["/var/run/ubus.sock", "/var/run/ubus/ubus.sock"];
+
+/// Find the ubusd socket the same way the stock tools do
+///
+/// The `UBUS_SOCKET` environment variable takes priority, otherwise the first of
+/// `DEFAULT_SOCKET_PATHS` that exists is used.
+pub fn default_socket() -> PathBuf {
+    if let Some(path) = std::env::var_os("UBUS_SOCKET") {
+        return PathBuf::from(path);
+    }
+    DEFAULT_SOCKET_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET_PATHS[0]))
+}
+
+/// Translate an expired socket timeout into `Error::Timeout`
+pub(crate) fn io_error(e: std::io::Error) -> Error<std::io::Error> {
+    use std::io::ErrorKind;
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Error::Timeout,
+        _ => Error::IO(e),
+    }
+}
 
 impl IO for UnixStream {
     type Error = std::io::Error;
     fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
-        self.write_all(data).map_err(Error::IO)
+        self.write_all(data).map_err(io_error)
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        self.read_exact(data).map_err(io_error)
+    }
+    fn put_fd(&mut self, data: &[u8], fd: i32) -> Result<(), Error<std::io::Error>> {
+        let sent = send_with_fd(self.as_raw_fd(), data, fd).map_err(io_error)?;
+        self.put(&data[sent..])
+    }
+    fn get_fd(&mut self, data: &mut [u8]) -> Result<Option<i32>, Error<std::io::Error>> {
+        let (received, fd) = recv_with_fd(self.as_raw_fd(), data).map_err(io_error)?;
+        if received == 0 && !data.is_empty() {
+            return Err(Error::IO(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        self.get(&mut data[received..])?;
+        Ok(fd)
+    }
+    fn close(&mut self) -> Result<(), Error<std::io::Error>> {
+        self.flush().map_err(io_error)?;
+        self.shutdown(std::net::Shutdown::Both).map_err(io_error)
+    }
+}
+
+/// Adapts any std stream (a pipe, TCP socket, PTY...) to `IO`, so it can back a `Connection`
+///
+/// Reads and writes go through `Read` and `Write`, without file descriptor passing (which needs
+/// the `UnixStream` impl). Timeouts set on the stream are reported as `Error::Timeout`. With the
+/// `nb` feature, streams in non-blocking mode can back an `NbConnection` instead.
+#[derive(Debug)]
+pub struct StdIo<T: Read + Write> {
+    inner: T,
+}
+
+impl<T: Read + Write> StdIo<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Read + Write> IO for StdIo<T> {
+    type Error = std::io::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        self.inner.write_all(data).map_err(io_error)?;
+        self.inner.flush().map_err(io_error)
     }
     fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
-        self.read_exact(data).map_err(Error::IO)
+        self.inner.read_exact(data).map_err(io_error)
+    }
+    fn close(&mut self) -> Result<(), Error<std::io::Error>> {
+        self.inner.flush().map_err(io_error)
+    }
+}
+
+/// For streams in non-blocking mode, as `ConnectionBuilder::connect_nonblocking` opens
+#[cfg(feature = "nb")]
+impl<T: Read + Write> NbIO for StdIo<T> {
+    type Error = std::io::Error;
+    fn write(&mut self, data: &[u8]) -> nb::Result<usize, Error<std::io::Error>> {
+        self.inner.write(data).map_err(nb_error)
+    }
+    fn read(&mut self, data: &mut [u8]) -> nb::Result<usize, Error<std::io::Error>> {
+        self.inner.read(data).map_err(nb_error)
+    }
+}
+
+#[cfg(feature = "nb")]
+fn nb_error(e: std::io::Error) -> nb::Error<Error<std::io::Error>> {
+    use std::io::ErrorKind;
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::Interrupted => nb::Error::WouldBlock,
+        _ => nb::Error::Other(Error::IO(e)),
+    }
+}
+
+impl<T: Read + Write + AsRawFd> AsRawFd for StdIo<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// Send `data` with `fd` attached as SCM_RIGHTS ancillary data, returning the bytes sent
+fn send_with_fd(socket: RawFd, data: &[u8], fd: RawFd) -> std::io::Result<usize> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut _,
+        iov_len: data.len(),
+    };
+    let mut control = [0u64; 8];
+    loop {
+        let sent = unsafe {
+            let mut msg: libc::msghdr = zeroed();
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut _;
+            msg.msg_controllen = libc::CMSG_SPACE(size_of::<RawFd>() as u32) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+            core::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+            libc::sendmsg(socket, &msg, 0)
+        };
+        if sent >= 0 {
+            return Ok(sent as usize);
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Receive into `data`, returning the bytes received and any SCM_RIGHTS file descriptor
+fn recv_with_fd(socket: RawFd, data: &mut [u8]) -> std::io::Result<(usize, Option<RawFd>)> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut _,
+        iov_len: data.len(),
+    };
+    let mut control = [0u64; 8];
+    loop {
+        let mut msg: libc::msghdr = unsafe { zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut _;
+        msg.msg_controllen = size_of_val(&control) as _;
+        let received = unsafe { libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        if received < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        let mut fd = None;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    fd = Some(core::ptr::read_unaligned(
+                        libc::CMSG_DATA(cmsg) as *const RawFd
+                    ));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        return Ok((received as usize, fd));
     }
 }
 
@@ -17,7 +202,116 @@ impl Connection<UnixStream> {
     pub fn connect(path: &Path) -> Result<Self, Error<std::io::Error>> {
         Self::new(UnixStream::connect(path).map_err(Error::IO)?)
     }
+
+    /// Connect to the socket found by `default_socket()`
+    pub fn connect_default() -> Result<Self, Error<std::io::Error>> {
+        Self::connect(&default_socket())
+    }
+
+    /// Take over an already connected (and configured) ubus socket, such as one opened before a
+    /// sandbox (seccomp or landlock) took away the ability to connect
+    ///
+    /// The server's hello is read from it, so it must be freshly connected. It must also be in
+    /// blocking mode, as messages are read and written whole.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open unix stream socket, which the connection then owns.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> Result<Self, Error<std::io::Error>> {
+        Self::new(UnixStream::from_raw_fd(fd))
+    }
+
+    /// Limit how long a receive may block, after which calls fail with `Error::Timeout`
+    ///
+    /// A message interrupted part way through leaves the stream out of sync, so the
+    /// connection should be re-established after a timeout.
+    pub fn set_read_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(), Error<std::io::Error>> {
+        self.io.set_read_timeout(timeout).map_err(Error::IO)
+    }
+
+    /// Limit how long a send may block, after which calls fail with `Error::Timeout`
+    pub fn set_write_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(), Error<std::io::Error>> {
+        self.io.set_write_timeout(timeout).map_err(Error::IO)
+    }
+}
+
+impl Connection<StdIo<TcpStream>> {
+    /// Connect to a ubus TCP bridge, such as socat forwarding a port on the router to ubusd's socket
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Self, Error<std::io::Error>> {
+        let stream = TcpStream::connect(addr).map_err(Error::IO)?;
+        stream.set_nodelay(true).map_err(Error::IO)?;
+        Self::new(StdIo::new(stream))
+    }
+
+    /// Like `Connection<UnixStream>::set_read_timeout`
+    pub fn set_read_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(), Error<std::io::Error>> {
+        self.io
+            .get_ref()
+            .set_read_timeout(timeout)
+            .map_err(Error::IO)
+    }
+
+    /// Like `Connection<UnixStream>::set_write_timeout`
+    pub fn set_write_timeout(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<(), Error<std::io::Error>> {
+        self.io
+            .get_ref()
+            .set_write_timeout(timeout)
+            .map_err(Error::IO)
+    }
+}
+
+/// The transport's descriptor, for adding the connection to poll/epoll sets or setting socket options
+///
+/// Only wait for it to become readable when no request is in progress, then handle the message with
+/// `handle_next_message`. Reading from the descriptor directly will desynchronize the connection.
+impl<T: IO + AsRawFd> AsRawFd for Connection<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.io.as_raw_fd()
+    }
+}
+
+impl<T: IO + AsFd> AsFd for Connection<T> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.io.as_fd()
+    }
 }
 
 impl IOError for std::io::Error {}
-impl std::error::Error for Error {}
+
+/// Lets functions returning `io::Result` use `?` on connection calls
+///
+/// IO errors are returned as they are. Others keep the ubus error as their inner error, with a
+/// kind matching it where there is one (e.g. NOT_FOUND is `NotFound`, a timeout `TimedOut`).
+impl From<Error<std::io::Error>> for std::io::Error {
+    fn from(e: Error<std::io::Error>) -> Self {
+        use std::io::ErrorKind;
+        let kind = match e {
+            Error::IO(e) => return e,
+            Error::InvalidData(_) => ErrorKind::InvalidData,
+            Error::Timeout => ErrorKind::TimedOut,
+            // UBUS_STATUS_* codes
+            Error::Status(1 | 2) => ErrorKind::InvalidInput,
+            Error::Status(3 | 4) => ErrorKind::NotFound,
+            Error::Status(6) => ErrorKind::PermissionDenied,
+            Error::Status(7) => ErrorKind::TimedOut,
+            Error::Status(8) => ErrorKind::Unsupported,
+            Error::Status(10) => ErrorKind::NotConnected,
+            Error::Status(11) => ErrorKind::OutOfMemory,
+            Error::Status(12) => ErrorKind::InvalidData,
+            Error::Status(_) => ErrorKind::Other,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
@@ -0,0 +1,345 @@
+use crate::async_connection::Parts;
+use crate::maybe_async::{close_fd, Link};
+use crate::*;
+use core::ops::ControlFlow;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use std::collections::{BTreeMap, VecDeque};
+use std::string::{String, ToString};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::vec::Vec;
+
+/// Number of unconsumed events an `EventStream` holds
+///
+/// `AsyncConnection::handle_next_message` waits for space before receiving the next message.
+/// Events which arrive while a stream is full anyway (such as during a request, or on a blocking
+/// `Connection`) are dropped, and counted by `EventStream::dropped`.
+pub const EVENT_STREAM_CAPACITY: usize = 64;
+
+/// An owned event delivered through an `EventStream`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Notification received by a subscriber
+    Notification {
+        ty: String,
+        /// blobmsg table payload
+        data: Vec<u8>,
+    },
+    /// Event sent on the bus, matching a registered pattern
+    Broadcast {
+        id: String,
+        /// blobmsg table payload
+        data: Vec<u8>,
+    },
+}
+
+impl Event {
+    /// Iterate over the payload of the event
+    pub fn data(&self) -> BlobIter<'_, BlobMsg<'_>> {
+        match self {
+            Event::Notification { data, .. } | Event::Broadcast { data, .. } => BlobIter::new(data),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<Event>,
+    /// The consumer, waiting for an event
+    waker: Option<Waker>,
+    /// The connection, waiting for space
+    space: Option<Waker>,
+    dropped: u64,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<State>,
+}
+
+impl Queue {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Producing end of an `EventStream`, owned by the connection's dispatcher
+pub(crate) struct EventSink {
+    queue: Arc<Queue>,
+}
+
+impl EventSink {
+    /// Queue an event, dropping it if the stream is full (the dispatcher never waits here)
+    pub(crate) fn push(&self, event: Event) {
+        let mut state = self.queue.lock();
+        if state.closed {
+            return;
+        }
+        if state.events.len() >= EVENT_STREAM_CAPACITY {
+            state.dropped += 1;
+            return;
+        }
+        state.events.push_back(event);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Ready once the stream has space for another event, or has been dropped
+    fn poll_space(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.queue.lock();
+        if state.closed || state.events.len() < EVENT_STREAM_CAPACITY {
+            Poll::Ready(())
+        } else {
+            state.space = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.queue.lock().closed
+    }
+}
+
+impl Drop for EventSink {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// `Stream` of events, ending when the connection side is dropped
+///
+/// Events are produced while the connection is being driven (e.g. by an `EventReader` thread, or
+/// `AsyncConnection::handle_next_message`). A stream holds up to `EVENT_STREAM_CAPACITY`
+/// unconsumed events.
+pub struct EventStream {
+    queue: Arc<Queue>,
+    id: u32,
+}
+
+pub(crate) fn queue() -> (EventSink, EventStream) {
+    let queue = Arc::new(Queue::default());
+    let sink = EventSink {
+        queue: queue.clone(),
+    };
+    (sink, EventStream { queue, id: 0 })
+}
+
+impl EventStream {
+    /// Object id of the (hidden) object the events are received by
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Number of events dropped because the stream was full
+    pub fn dropped(&self) -> u64 {
+        self.queue.lock().dropped
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        let mut state = self.queue.lock();
+        if let Some(event) = state.events.pop_front() {
+            if let Some(waker) = state.space.take() {
+                waker.wake();
+            }
+            Poll::Ready(Some(event))
+        } else if state.closed {
+            Poll::Ready(None)
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        // Further events are discarded, and the connection removes the object
+        let mut state = self.queue.lock();
+        state.closed = true;
+        if let Some(waker) = state.space.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Register a subscriber whose notifications are delivered as a `Stream`
+    pub fn subscriber_stream(&mut self) -> Result<(Subscriber, EventStream), Error<T::Error>> {
+        let (sink, mut stream) = queue();
+        let subscriber = self.subscriber(move |ty, data| {
+            sink.push(Event::Notification {
+                ty: ty.to_string(),
+                data: data.as_bytes().to_vec(),
+            })
+        })?;
+        stream.id = subscriber.id();
+        Ok((subscriber, stream))
+    }
+}
+
+/// The hidden objects of an `AsyncConnection` whose messages are delivered to `EventStream`s
+#[derive(Default)]
+pub(crate) struct Streams {
+    entries: BTreeMap<u32, StreamEntry>,
+}
+
+struct StreamEntry {
+    sink: EventSink,
+    /// The pattern registered for events, or None for a subscriber
+    pattern: Option<String>,
+}
+
+impl Streams {
+    fn insert(&mut self, id: u32, pattern: Option<String>) -> EventStream {
+        let (sink, mut stream) = queue();
+        stream.id = id;
+        self.entries.insert(id, StreamEntry { sink, pattern });
+        stream
+    }
+
+    /// Ready once every stream has space for another event
+    fn poll_space(&self, cx: &mut Context<'_>) -> Poll<()> {
+        for entry in self.entries.values() {
+            if entry.sink.poll_space(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(())
+    }
+
+    /// Next object whose stream was dropped, forgetting it
+    fn next_closed(&mut self) -> Option<u32> {
+        let id = *self.entries.iter().find(|(_, e)| e.sink.is_closed())?.0;
+        self.entries.remove(&id);
+        Some(id)
+    }
+
+    /// Handle a message if it is addressed to one of the streams' objects
+    ///
+    /// Returns the object, and whether the sender wants a STATUS reply.
+    pub(crate) fn dispatch(&mut self, message: &Message) -> Option<(u32, bool)> {
+        let request = Request::from_message(message);
+        let id = request.attrs.obj_id?;
+        let entry = self.entries.get(&id)?;
+        match request.message {
+            // Notifications and events both arrive as invokes, with the type or id as the method
+            MessageType::INVOKE => {
+                // Checked by `Message::validate` as it was received
+                let method = request.attrs.method.unwrap_or_default();
+                let data = request.attrs.data.unwrap_or_default().to_vec();
+                match &entry.pattern {
+                    None => entry.sink.push(Event::Notification {
+                        ty: method.to_string(),
+                        data,
+                    }),
+                    // Only pass on events we asked for
+                    Some(pattern) if event_matches(pattern, method) => {
+                        entry.sink.push(Event::Broadcast {
+                            id: method.to_string(),
+                            data,
+                        })
+                    }
+                    Some(_) => {}
+                }
+                Some((id, !request.attrs.no_reply))
+            }
+            // ubusd tells subscribers when a target object went away
+            MessageType::UNSUBSCRIBE => Some((id, false)),
+            _ => None,
+        }
+    }
+}
+
+impl<T: AsyncIO> AsyncConnection<T> {
+    /// Register a subscriber whose notifications are delivered as a `Stream`
+    ///
+    /// Subscribe it to objects with `subscribe`, passing `EventStream::id`. Notifications are
+    /// received by `handle_next_message`. Dropping the stream removes the subscriber (on the next
+    /// `handle_next_message`).
+    pub async fn subscriber_stream(&mut self) -> Result<EventStream, Error<T::Error>> {
+        let id = self.register_object().await?;
+        Ok(self.streams.insert(id, None))
+    }
+
+    /// Subscribe the object `subscriber` to notifications from the object `target`
+    pub async fn subscribe(&mut self, subscriber: u32, target: u32) -> Result<(), Error<T::Error>> {
+        let request = SubscribeRequest::new(subscriber, target);
+        self.request(request, |_| Ok(ControlFlow::Continue(())))
+            .await
+    }
+
+    /// Receive events matching `pattern` (which may end with a `*` wildcard) as a `Stream`
+    ///
+    /// Like `subscriber_stream`, events are received by `handle_next_message`, and dropping the
+    /// stream removes its registration.
+    pub async fn event_stream(&mut self, pattern: &str) -> Result<EventStream, Error<T::Error>> {
+        let id = self.register_object().await?;
+        let stream = self.streams.insert(id, Some(pattern.to_string()));
+        let mut buffer = [0u8; 512];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_int32("object", id as i32)?;
+        args.push_string("pattern", pattern)?;
+        self.invoke(EVENT_OBJECT, "register", args.finish(), |_| {})
+            .await?;
+        Ok(stream)
+    }
+
+    /// Receive and handle the next message, delivering notifications and events to streams
+    ///
+    /// Waits first until every stream has space for another event, so a slow consumer holds
+    /// back the connection rather than losing events. Objects of dropped streams are removed, and
+    /// late replies to requests given up on are dropped.
+    pub async fn handle_next_message(&mut self) -> Result<(), Error<T::Error>> {
+        while let Some(id) = self.streams.next_closed() {
+            let request = RemoveObjectRequest::new(id);
+            self.request(request, |_| Ok(ControlFlow::Continue(())))
+                .await?;
+        }
+        core::future::poll_fn(|cx| self.streams.poll_space(cx)).await;
+        let mut link = Parts {
+            io: &mut self.io,
+            max_message_size: self.max_message_size,
+            streams: &mut self.streams,
+        };
+        let message = link.recv(&mut self.buffer).await?;
+        let dispatched = match message.header.message {
+            // Late replies to requests given up on, dropped as `exchange` does
+            MessageType::STATUS | MessageType::DATA => {
+                trace!("Dropping unrelated {:?}", message);
+                let response = Response::from_message(&message);
+                if response.is_final() {
+                    self.sequences.finish(response.sequence);
+                }
+                Ok(())
+            }
+            _ => link.dispatch(&message).await,
+        };
+        close_fd(message.fd);
+        dispatched
+    }
+
+    /// Register a hidden object, returning its id
+    async fn register_object(&mut self) -> Result<u32, Error<T::Error>> {
+        let mut id = None;
+        self.request(AddObjectRequest::new(None), |message| {
+            for attr in BlobIter::<MessageAttr>::new(message.blob.data) {
+                if let MessageAttr::ObjId(val) = attr {
+                    id = Some(val);
+                }
+            }
+            Ok(ControlFlow::Continue(()))
+        })
+        .await?;
+        Ok(id.ok_or(Error::<NoIO>::InvalidData("No object id in reply"))?)
+    }
+}
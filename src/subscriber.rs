@@ -0,0 +1,71 @@
+use crate::*;
+use std::boxed::Box;
+
+/// Handle to a subscriber object registered with `Connection::subscriber`
+///
+/// Dropping the handle removes the subscriber object (and so all its subscriptions).
+#[derive(Debug)]
+pub struct Subscriber {
+    object: Object,
+}
+
+impl Subscriber {
+    /// Object id of the (hidden) subscriber object
+    pub fn id(&self) -> u32 {
+        self.object.id()
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Register a hidden subscriber object, passing notifications it receives to `callback`
+    ///
+    /// Notifications which request a reply are acknowledged with a zero status.
+    pub fn subscriber(
+        &mut self,
+        mut callback: impl FnMut(&str, BlobIter<BlobMsg>) + Send + 'static,
+    ) -> Result<Subscriber, Error<T::Error>> {
+        self.subscriber_with_status(move |ty, data| {
+            callback(ty, data);
+            0
+        })
+    }
+
+    /// Like `subscriber`, but `callback` returns the status sent back to the publisher
+    pub fn subscriber_with_status(
+        &mut self,
+        callback: impl FnMut(&str, BlobIter<BlobMsg>) -> i32 + Send + 'static,
+    ) -> Result<Subscriber, Error<T::Error>> {
+        let id = self.register_object(None)?;
+        let handler = ObjectHandler::Notify(Box::new(callback));
+        let object = self.handlers.objects.insert(id, handler);
+        Ok(Subscriber { object })
+    }
+
+    /// Subscribe to notifications from the object `target`
+    pub fn subscribe(
+        &mut self,
+        subscriber: &Subscriber,
+        target: u32,
+    ) -> Result<(), Error<T::Error>> {
+        let request = SubscribeRequest::new(subscriber.id(), target);
+        self.request(request, |_| Ok(()))?;
+        if let Some(entry) = self.handlers.objects.entries.get_mut(&subscriber.id()) {
+            entry.targets.push(target);
+        }
+        Ok(())
+    }
+
+    /// Stop receiving notifications from the object `target`
+    pub fn unsubscribe(
+        &mut self,
+        subscriber: &Subscriber,
+        target: u32,
+    ) -> Result<(), Error<T::Error>> {
+        let request = UnsubscribeRequest::new(subscriber.id(), target);
+        self.request(request, |_| Ok(()))?;
+        if let Some(entry) = self.handlers.objects.entries.get_mut(&subscriber.id()) {
+            entry.targets.retain(|t| *t != target);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::split::{lock, Waiter};
+use crate::*;
+use std::os::unix::net::UnixStream;
+use std::string::{String, ToString};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::vec::Vec;
+
+/// Owned copy of the replies to a call made with `ReaderThread::call`
+#[derive(Clone, Debug)]
+pub struct CallReply {
+    /// Sequence number returned by `call`
+    pub sequence: u16,
+    /// Final status of the call, or 10 (`UBUS_STATUS_CONNECTION_FAILED`) if the connection
+    /// failed first
+    pub status: i32,
+    /// Each DATA table replied, in order
+    pub data: Vec<Vec<u8>>,
+}
+
+impl CallReply {
+    /// Iterate over the DATA tables replied
+    pub fn tables(&self) -> impl Iterator<Item = BlobIter<'_, BlobMsg<'_>>> {
+        self.data.iter().map(|table| BlobIter::new(table))
+    }
+}
+
+/// Owned copy of an event or notification received by a `ReaderThread`
+#[derive(Clone, Debug)]
+pub struct Received {
+    /// Event id, or notification type
+    pub name: String,
+    /// Blobmsg table sent with it
+    pub data: Vec<u8>,
+}
+
+impl Received {
+    fn new(name: &str, data: BlobIter<BlobMsg>) -> Self {
+        Self {
+            name: name.to_string(),
+            data: data.as_bytes().to_vec(),
+        }
+    }
+
+    /// Iterate over the data sent with it
+    pub fn data(&self) -> BlobIter<'_, BlobMsg<'_>> {
+        BlobIter::new(&self.data)
+    }
+}
+
+/// A connection owned by a background thread, delivering what it receives over channels
+///
+/// Calls made with `call` return immediately, their replies arriving on `replies`. Events
+/// (registered with `register_event`) arrive on `events`, and notifications (from targets added
+/// with `subscribe`) arrive on `notifications`. Objects added to the connection before spawning
+/// keep being served by the thread.
+///
+/// Dropping this shuts the connection down, stopping the thread.
+pub struct ReaderThread {
+    requester: Requester,
+    event_handler: EventHandler,
+    subscriber: Subscriber,
+    replies_tx: Sender<CallReply>,
+    pub replies: Receiver<CallReply>,
+    pub events: Receiver<Received>,
+    pub notifications: Receiver<Received>,
+    thread: Option<JoinHandle<Result<(), Error<std::io::Error>>>>,
+}
+
+impl Connection<UnixStream> {
+    /// Move the connection to a background thread, see `ReaderThread`
+    pub fn spawn_reader(mut self) -> Result<ReaderThread, Error<std::io::Error>> {
+        let (events_tx, events) = channel();
+        let event_handler = self.event_handler(move |id, data| {
+            let _ = events_tx.send(Received::new(id, data));
+        })?;
+        let (notifications_tx, notifications) = channel();
+        let subscriber = self.subscriber(move |ty, data| {
+            let _ = notifications_tx.send(Received::new(ty, data));
+        })?;
+        let (requester, mut reader) = self.split()?;
+        let (replies_tx, replies) = channel();
+        let thread = thread::spawn(move || reader.run());
+        Ok(ReaderThread {
+            requester,
+            event_handler,
+            subscriber,
+            replies_tx,
+            replies,
+            events,
+            notifications,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl ReaderThread {
+    /// Handle for making blocking calls from other threads
+    pub fn requester(&self) -> &Requester {
+        &self.requester
+    }
+
+    /// Call `method` on `obj` without waiting, returning the sequence number of its `CallReply`
+    ///
+    /// The requester's session (see `Requester::set_session`) is added to the arguments.
+    pub fn call(&self, obj: u32, method: &str, args: &[u8]) -> Result<u16, Error<std::io::Error>> {
+        with_session(self.requester.session(), args, |args| {
+            let request = InvokeRequest::new(obj, method).args(args);
+            let waiter = Waiter::Collect(self.replies_tx.clone(), Vec::new());
+            self.requester
+                .send_request(InvokeRequest::TYPE, obj, request, waiter)
+        })
+    }
+
+    /// Deliver events matching `pattern` (which may end with a `*` wildcard) to `events`
+    pub fn register_event(&self, pattern: &str) -> Result<(), Error<std::io::Error>> {
+        let mut buffer = [0u8; 512];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_int32("object", self.event_handler.id() as i32)?;
+        args.push_string("pattern", pattern)?;
+        // Record the pattern first, the first events may arrive before the reply
+        lock(&self.event_handler.patterns).push(pattern.to_string());
+        let result = self
+            .requester
+            .invoke(EVENT_OBJECT, "register", args.finish(), |_| {});
+        if result.is_err() {
+            lock(&self.event_handler.patterns).retain(|p| p != pattern);
+        }
+        result?;
+        Ok(())
+    }
+
+    /// Deliver notifications from the object `target` to `notifications`
+    pub fn subscribe(&self, target: u32) -> Result<(), Error<std::io::Error>> {
+        let attrs = [
+            MessageAttr::ObjId(self.subscriber.id()),
+            MessageAttr::Target(target),
+        ];
+        self.requester
+            .request(MessageType::SUBSCRIBE, 0, attrs, |_| {})
+    }
+}
+
+impl Drop for ReaderThread {
+    fn drop(&mut self) {
+        self.requester.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::stdio::io_error;
+use crate::*;
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs, UnixStream};
+
+impl AsyncIO for UnixStream {
+    type Error = std::io::Error;
+    async fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        self.write_all(data).await.map_err(io_error)
+    }
+    async fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        self.read_exact(data).await.map(|_| ()).map_err(io_error)
+    }
+}
+
+/// For ubusd's socket forwarded over TCP (e.g. with socat), as there is no TCP listener built in
+impl AsyncIO for TcpStream {
+    type Error = std::io::Error;
+    async fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        self.write_all(data).await.map_err(io_error)
+    }
+    async fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        self.read_exact(data).await.map(|_| ()).map_err(io_error)
+    }
+}
+
+impl AsyncConnection<UnixStream> {
+    pub async fn connect(path: impl AsRef<Path>) -> Result<Self, Error<std::io::Error>> {
+        let stream = UnixStream::connect(path).await.map_err(Error::IO)?;
+        Self::new(stream).await
+    }
+
+    /// Connect to the socket found by `default_socket()`
+    pub async fn connect_default() -> Result<Self, Error<std::io::Error>> {
+        Self::connect(default_socket()).await
+    }
+}
+
+impl AsyncConnection<TcpStream> {
+    /// Connect to a TCP bridge to ubusd's socket
+    pub async fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Self, Error<std::io::Error>> {
+        let stream = TcpStream::connect(addr).await.map_err(Error::IO)?;
+        Self::new(stream).await
+    }
+}
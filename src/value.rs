@@ -0,0 +1,329 @@
+use crate::*;
+use core::convert::TryFrom;
+use std::collections::BTreeMap;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// An owned copy of a blobmsg value, which can outlive the buffer it was parsed from
+///
+/// Values are simplified as libubox's JSON conversion does: INT8 is a boolean, the other integer
+/// types widen to `i64`, and UNSPEC (or anything unknown) is `Null`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BlobMsgValue {
+    Null,
+    Bool(bool),
+    Int64(i64),
+    Double(f64),
+    String(String),
+    Array(Vec<BlobMsgValue>),
+    Table(BTreeMap<String, BlobMsgValue>),
+}
+
+impl From<&str> for BlobMsgValue {
+    fn from(value: &str) -> Self {
+        BlobMsgValue::String(value.to_string())
+    }
+}
+
+impl From<String> for BlobMsgValue {
+    fn from(value: String) -> Self {
+        BlobMsgValue::String(value)
+    }
+}
+
+impl From<bool> for BlobMsgValue {
+    fn from(value: bool) -> Self {
+        BlobMsgValue::Bool(value)
+    }
+}
+
+impl From<f64> for BlobMsgValue {
+    fn from(value: f64) -> Self {
+        BlobMsgValue::Double(value)
+    }
+}
+
+macro_rules! value_from_int {
+    ( $( $ty:ty ),* ) => { $(
+        impl From<$ty> for BlobMsgValue {
+            fn from(value: $ty) -> Self {
+                BlobMsgValue::Int64(value.into())
+            }
+        }
+    )* };
+}
+value_from_int!(i8, i16, i32, i64, u8, u16, u32);
+
+impl From<Vec<BlobMsgValue>> for BlobMsgValue {
+    fn from(value: Vec<BlobMsgValue>) -> Self {
+        BlobMsgValue::Array(value)
+    }
+}
+
+impl From<BTreeMap<String, BlobMsgValue>> for BlobMsgValue {
+    fn from(value: BTreeMap<String, BlobMsgValue>) -> Self {
+        BlobMsgValue::Table(value)
+    }
+}
+
+/// Values are pushed back as the types `BlobMsgData::to_owned` reads them from, with integers
+/// as INT32 if they fit (as libubox converts JSON)
+///
+/// Fails if tables and arrays are nested deeper than `DEFAULT_MAX_DEPTH`.
+impl PushBlobMsg for BlobMsgValue {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+        self.push_nested(builder, name, Depth::new(DEFAULT_MAX_DEPTH))
+    }
+}
+
+impl BlobMsgValue {
+    /// `push_blobmsg` for a value at `depth`
+    fn push_nested(
+        &self,
+        builder: &mut BlobMsgBuilder,
+        name: &str,
+        depth: Depth,
+    ) -> Result<(), Error> {
+        match self {
+            BlobMsgValue::Null => builder.push_null(name),
+            BlobMsgValue::Bool(v) => builder.push_bool(name, *v),
+            BlobMsgValue::Int64(v) => match i32::try_from(*v) {
+                Ok(v) => builder.push_int32(name, v),
+                Err(_) => builder.push_int64(name, *v),
+            },
+            BlobMsgValue::Double(v) => builder.push_double(name, *v),
+            BlobMsgValue::String(v) => builder.push_string(name, v),
+            BlobMsgValue::Array(items) => {
+                let depth = depth.nested()?;
+                builder.push_array(name, |b| {
+                    items
+                        .iter()
+                        .try_for_each(|item| item.push_nested(b, "", depth))
+                })
+            }
+            BlobMsgValue::Table(items) => {
+                let depth = depth.nested()?;
+                builder.push_table(name, |b| {
+                    items
+                        .iter()
+                        .try_for_each(|(name, item)| item.push_nested(b, name, depth))
+                })
+            }
+        }
+    }
+}
+
+impl PushBlobMsg for String {
+    fn push_blobmsg(&self, builder: &mut BlobMsgBuilder, name: &str) -> Result<(), Error> {
+        builder.push_string(name, self)
+    }
+}
+
+impl BlobMsgData<'_> {
+    /// Copy the value (and everything nested in it) out of the message buffer
+    ///
+    /// Fails if tables and arrays are nested deeper than `DEFAULT_MAX_DEPTH`.
+    pub fn to_owned(&self) -> Result<BlobMsgValue, Error> {
+        self.to_owned_limit(DEFAULT_MAX_DEPTH)
+    }
+
+    /// Like `to_owned`, but failing if tables and arrays are nested deeper than `max_depth`
+    pub fn to_owned_limit(&self, max_depth: usize) -> Result<BlobMsgValue, Error> {
+        self.owned(Depth::new(max_depth))
+    }
+
+    /// `to_owned` for a value at `depth`
+    fn owned(&self, depth: Depth) -> Result<BlobMsgValue, Error> {
+        Ok(match self {
+            BlobMsgData::Array(items) => {
+                let depth = depth.nested()?;
+                BlobMsgValue::Array(
+                    BlobIter::<BlobMsg>::new(items.as_bytes())
+                        .map(|item| item.data.owned(depth))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            BlobMsgData::Table(items) => BlobMsgValue::Table(items.map(depth.nested()?)?),
+            BlobMsgData::String(v) => BlobMsgValue::String(v.to_string()),
+            BlobMsgData::Int64(v) => BlobMsgValue::Int64(*v),
+            BlobMsgData::Int32(v) => BlobMsgValue::Int64(*v as i64),
+            BlobMsgData::Int16(v) => BlobMsgValue::Int64(*v as i64),
+            BlobMsgData::Int8(v) => BlobMsgValue::Bool(*v != 0),
+            BlobMsgData::Double(v) => BlobMsgValue::Double(*v),
+            BlobMsgData::Unknown(..) => BlobMsgValue::Null,
+        })
+    }
+}
+
+impl BlobMsg<'_> {
+    /// Copy the attribute's value out of the message buffer (see `BlobMsgValue`)
+    pub fn to_owned(&self) -> Result<BlobMsgValue, Error> {
+        self.data.to_owned()
+    }
+
+    /// Copy a table attribute out of the message buffer as a map, failing if it isn't a table
+    pub fn to_map(&self) -> Result<BTreeMap<String, BlobMsgValue>, Error> {
+        match &self.data {
+            BlobMsgData::Table(items) => items.map(Depth::new(DEFAULT_MAX_DEPTH).nested()?),
+            _ => Err(Error::InvalidData("Blobmsg is not a table")),
+        }
+    }
+}
+
+impl BlobIter<'_, BlobMsg<'_>> {
+    /// Copy the remaining attributes (such as a reply's DATA table) out as a map
+    ///
+    /// Nested tables and arrays are converted too, failing if they are nested deeper than
+    /// `DEFAULT_MAX_DEPTH`. Attributes without a name are keyed by "", and later attributes
+    /// replace earlier ones with the same name.
+    pub fn to_map(&self) -> Result<BTreeMap<String, BlobMsgValue>, Error> {
+        self.to_map_limit(DEFAULT_MAX_DEPTH)
+    }
+
+    /// Like `to_map`, but failing if tables and arrays are nested deeper than `max_depth`
+    pub fn to_map_limit(&self, max_depth: usize) -> Result<BTreeMap<String, BlobMsgValue>, Error> {
+        self.map(Depth::new(max_depth))
+    }
+
+    /// `to_map` for a table at `depth`
+    fn map(&self, depth: Depth) -> Result<BTreeMap<String, BlobMsgValue>, Error> {
+        BlobIter::<BlobMsg>::new(self.as_bytes())
+            .map(|item| Ok((item.name.unwrap_or("").to_string(), item.data.owned(depth)?)))
+            .collect()
+    }
+}
+
+/// Collects the DATA tables of several replies into one table
+///
+/// Some methods (e.g. `network.interface dump`) answer with more than one DATA message, each
+/// holding part of the result. Like the C `ubus` tool, the attributes of every table are appended
+/// to one buffer, in the order received, which can then be read as a single table. Where a name is
+/// repeated, `to_map` (and JSON conversion) keeps the last value.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseAccumulator {
+    data: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl ResponseAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append the attributes of a reply's DATA table
+    pub fn push(&mut self, data: BlobIter<BlobMsg>) {
+        self.data.extend_from_slice(data.as_bytes());
+        self.ends.push(self.data.len());
+    }
+
+    /// Number of tables pushed
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// All attributes of every table, in the order pushed
+    pub fn table(&self) -> BlobIter<'_, BlobMsg<'_>> {
+        BlobIter::new(&self.data)
+    }
+
+    /// Each table pushed, separately
+    pub fn replies(&self) -> impl Iterator<Item = BlobIter<'_, BlobMsg<'_>>> {
+        let starts = core::iter::once(0).chain(self.ends.iter().copied());
+        starts
+            .zip(self.ends.iter().copied())
+            .map(move |(start, end)| BlobIter::new(&self.data[start..end]))
+    }
+
+    /// Copy the merged table out as a map, later attributes replacing earlier ones
+    pub fn to_map(&self) -> Result<BTreeMap<String, BlobMsgValue>, Error> {
+        self.table().to_map()
+    }
+
+    /// Copy each table out as an element of an array
+    pub fn to_array(&self) -> Result<Vec<BlobMsgValue>, Error> {
+        self.replies()
+            .map(|table| Ok(BlobMsgValue::Table(table.to_map()?)))
+            .collect()
+    }
+}
+
+impl<'a> Extend<BlobIter<'a, BlobMsg<'a>>> for ResponseAccumulator {
+    fn extend<I: IntoIterator<Item = BlobIter<'a, BlobMsg<'a>>>>(&mut self, iter: I) {
+        for data in iter {
+            self.push(data);
+        }
+    }
+}
+
+impl<T: IO> Connection<T> {
+    /// Like `invoke`, collecting the DATA tables of every reply into a `ResponseAccumulator`
+    pub fn invoke_accumulate(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+    ) -> Result<ResponseAccumulator, Error<T::Error>> {
+        let mut accumulator = ResponseAccumulator::new();
+        self.invoke(obj, method, args, |data| accumulator.push(data))?;
+        Ok(accumulator)
+    }
+
+    /// Like `invoke`, returning the DATA tables of every reply merged into one owned table
+    ///
+    /// Replies are merged in the order received, so later values replace earlier ones with the
+    /// same name (as the `ubus` command line tool shows them).
+    pub fn invoke_collect(
+        &mut self,
+        obj: u32,
+        method: &str,
+        args: &[u8],
+    ) -> Result<BTreeMap<String, BlobMsgValue>, Error<T::Error>> {
+        let accumulator = self.invoke_accumulate(obj, method, args)?;
+        Ok(accumulator.to_map()?)
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __blobmsg_value {
+    ({ $($inner:tt)* }) => {{
+        let mut table = ::std::collections::BTreeMap::new();
+        $crate::__blobmsg_value!(@table table ($($inner)*));
+        $crate::BlobMsgValue::Table(table)
+    }};
+    ([ $($inner:tt)* ]) => {{
+        let mut array = ::std::vec::Vec::new();
+        $crate::__blobmsg_value!(@array array ($($inner)*));
+        $crate::BlobMsgValue::Array(array)
+    }};
+    (@table $t:ident ()) => {};
+    (@table $t:ident ($key:literal : { $($inner:tt)* } $(, $($rest:tt)*)?)) => {
+        $t.insert(::std::string::String::from($key), $crate::__blobmsg_value!({ $($inner)* }));
+        $crate::__blobmsg_value!(@table $t ($($($rest)*)?));
+    };
+    (@table $t:ident ($key:literal : [ $($inner:tt)* ] $(, $($rest:tt)*)?)) => {
+        $t.insert(::std::string::String::from($key), $crate::__blobmsg_value!([ $($inner)* ]));
+        $crate::__blobmsg_value!(@table $t ($($($rest)*)?));
+    };
+    (@table $t:ident ($key:literal : $value:expr $(, $($rest:tt)*)?)) => {
+        $t.insert(::std::string::String::from($key), $crate::BlobMsgValue::from($value));
+        $crate::__blobmsg_value!(@table $t ($($($rest)*)?));
+    };
+    (@array $a:ident ()) => {};
+    (@array $a:ident ({ $($inner:tt)* } $(, $($rest:tt)*)?)) => {
+        $a.push($crate::__blobmsg_value!({ $($inner)* }));
+        $crate::__blobmsg_value!(@array $a ($($($rest)*)?));
+    };
+    (@array $a:ident ([ $($inner:tt)* ] $(, $($rest:tt)*)?)) => {
+        $a.push($crate::__blobmsg_value!([ $($inner)* ]));
+        $crate::__blobmsg_value!(@array $a ($($($rest)*)?));
+    };
+    (@array $a:ident ($value:expr $(, $($rest:tt)*)?)) => {
+        $a.push($crate::BlobMsgValue::from($value));
+        $crate::__blobmsg_value!(@array $a ($($($rest)*)?));
+    };
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::*;
+use ubus::*;
+
+#[test]
+fn test() {
+    let first = table(|b| {
+        b.push_string("interface", "lan")?;
+        b.push_int32("uptime", 1)
+    });
+    let second = table(|b| {
+        b.push_string("interface", "wan")?;
+        b.push_bool("up", true)
+    });
+
+    let mut accumulator = ResponseAccumulator::new();
+    assert!(accumulator.is_empty());
+    accumulator.push(BlobIter::new(&first));
+    accumulator.push(BlobIter::new(&second));
+    assert_eq!(accumulator.len(), 2);
+
+    // Every attribute is kept, in order, as in the C tool's buffer
+    let names: Vec<_> = accumulator.table().map(|item| item.name).collect();
+    assert_eq!(
+        names,
+        [
+            Some("interface"),
+            Some("uptime"),
+            Some("interface"),
+            Some("up")
+        ]
+    );
+
+    // Merged, the later of a repeated name wins
+    let map = accumulator.to_map().unwrap();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map["interface"], BlobMsgValue::String("wan".to_string()));
+    assert_eq!(map["uptime"], BlobMsgValue::Int64(1));
+    assert_eq!(map["up"], BlobMsgValue::Bool(true));
+
+    // Or each reply kept apart
+    let replies: Vec<_> = accumulator.replies().map(|t| t.as_bytes()).collect();
+    assert_eq!(replies, [&first[..], &second[..]]);
+    let array = accumulator.to_array().unwrap();
+    assert_eq!(array.len(), 2);
+    match &array[0] {
+        BlobMsgValue::Table(t) => assert_eq!(t["interface"], BlobMsgValue::String("lan".into())),
+        other => panic!("Expected table, got {:?}", other),
+    }
+}
+
+#[test]
+fn empty() {
+    let accumulator = ResponseAccumulator::default();
+    assert_eq!(accumulator.table().count(), 0);
+    assert_eq!(accumulator.replies().count(), 0);
+    assert!(accumulator.to_map().unwrap().is_empty());
+}
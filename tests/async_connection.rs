@@ -0,0 +1,203 @@
+#![cfg(feature = "async")]
+mod common;
+
+use common::*;
+use futures_core::Stream;
+use std::future::Future;
+use std::os::unix::net::UnixStream;
+use std::pin::{pin, Pin};
+use std::sync::mpsc::channel;
+use std::task::{Context, Poll, Waker};
+use ubus::*;
+
+/// An async transport over a blocking socket, whose futures are always ready
+struct Ready(UnixStream);
+
+impl AsyncIO for Ready {
+    type Error = std::io::Error;
+    fn put(&mut self, data: &[u8]) -> impl Future<Output = Result<(), Error<Self::Error>>> {
+        std::future::ready(IO::put(&mut self.0, data))
+    }
+    fn get(&mut self, data: &mut [u8]) -> impl Future<Output = Result<(), Error<Self::Error>>> {
+        std::future::ready(IO::get(&mut self.0, data))
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("test", |method, _args, reply| {
+            assert_eq!(method, "ping");
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    let object_id = object.id();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+
+    let (client, server) = UnixStream::pair().unwrap();
+    let broker_thread = broker.clone();
+    std::thread::spawn(move || broker_thread.serve(server));
+
+    block_on(async {
+        let mut connection = AsyncConnection::new(Ready(client)).await.unwrap();
+        assert_ne!(connection.peer(), 0);
+
+        // The same results as the blocking connection
+        assert_eq!(connection.object_id("test").await.unwrap(), object_id);
+        assert!(matches!(
+            connection.object_id("missing").await,
+            Err(Error::Status(4))
+        ));
+
+        let mut objects = Vec::new();
+        connection
+            .lookup(|object| objects.push(object.path.to_string()), |_| {})
+            .await
+            .unwrap();
+        assert!(objects.contains(&"test".to_string()));
+
+        let mut replies = Vec::new();
+        connection
+            .invoke(object_id, "ping", &[], |data| {
+                for item in data {
+                    replies.push(format!("{:?}", item.data));
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].contains("pong"));
+    });
+}
+
+/// The next event in `stream`, if there is one already
+fn next_event(stream: &mut EventStream) -> Option<Event> {
+    let mut cx = Context::from_waker(Waker::noop());
+    match Pin::new(stream).poll_next(&mut cx) {
+        Poll::Ready(event) => event,
+        Poll::Pending => None,
+    }
+}
+
+#[test]
+fn streams() {
+    let broker = Broker::new();
+    let (client, server) = UnixStream::pair().unwrap();
+    let broker_thread = broker.clone();
+    std::thread::spawn(move || broker_thread.serve(server));
+
+    let mut sender = broker.connect().unwrap();
+    let (to_sender, from_test) = channel();
+    let (to_test, from_sender) = channel();
+    let sender = std::thread::spawn(move || {
+        let subscriber = from_test.recv().unwrap();
+        let mut buffer = [0u8; 64];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_int32("n", 1).unwrap();
+        let args = args.finish();
+        sender.send_event("other", args).unwrap();
+        sender.send_event("test.one", args).unwrap();
+        // A notification, as ubusd forwards them to subscribers, acknowledged by the stream
+        sender.invoke(subscriber, "update", args, |_| {}).unwrap();
+        for _ in 0..=EVENT_STREAM_CAPACITY {
+            sender.send_event("test.many", &[]).unwrap();
+        }
+        to_test.send(()).unwrap();
+
+        from_test.recv().unwrap();
+        sender.send_event("test.last", &[]).unwrap();
+    });
+
+    let mut connection = block_on(AsyncConnection::new(Ready(client))).unwrap();
+    let mut events = block_on(connection.event_stream("test.*")).unwrap();
+    let mut notifications = block_on(connection.subscriber_stream()).unwrap();
+    let subscriber = notifications.id();
+    to_sender.send(subscriber).unwrap();
+
+    block_on(connection.handle_next_message()).unwrap();
+    block_on(connection.handle_next_message()).unwrap();
+    let event = next_event(&mut events).unwrap();
+    assert!(matches!(&event, Event::Broadcast { id, .. } if id == "test.one"));
+    assert_eq!(event.data().count(), 1);
+    let event = next_event(&mut notifications).unwrap();
+    assert!(matches!(&event, Event::Notification { ty, .. } if ty == "update"));
+    assert!(next_event(&mut notifications).is_none());
+
+    from_sender.recv().unwrap();
+    for _ in 0..EVENT_STREAM_CAPACITY {
+        block_on(connection.handle_next_message()).unwrap();
+    }
+    // The last one arrives during a request, while the stream is full
+    assert!(matches!(
+        block_on(connection.object_id("missing")),
+        Err(Error::Status(4))
+    ));
+    assert_eq!(events.dropped(), 1);
+
+    // Removes the dropped stream's object, then waits for space rather than receiving
+    drop(notifications);
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(pin!(connection.handle_next_message())
+        .poll(&mut cx)
+        .is_pending());
+    assert!(matches!(
+        block_on(connection.invoke(subscriber, "update", &[], |_| {})),
+        Err(Error::Status(4))
+    ));
+
+    next_event(&mut events).unwrap();
+    to_sender.send(0).unwrap();
+    block_on(connection.handle_next_message()).unwrap();
+    sender.join().unwrap();
+
+    let mut received = Vec::new();
+    while let Some(Event::Broadcast { id, .. }) = next_event(&mut events) {
+        received.push(id);
+    }
+    assert_eq!(received.len(), EVENT_STREAM_CAPACITY);
+    assert_eq!(received[EVENT_STREAM_CAPACITY - 1], "test.last");
+}
+
+#[test]
+fn late_replies() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let server = std::thread::spawn(move || {
+        send(&mut server, MessageType::HELLO, 0, []);
+        // The replies to a request the connection has given up on
+        send(
+            &mut server,
+            MessageType::DATA,
+            1,
+            [MessageAttr::ObjId(0x100)],
+        );
+        send(
+            &mut server,
+            MessageType::STATUS,
+            1,
+            [MessageAttr::Status(0)],
+        );
+        server
+    });
+
+    let mut connection = block_on(AsyncConnection::new(Ready(client))).unwrap();
+    block_on(connection.handle_next_message()).unwrap();
+    block_on(connection.handle_next_message()).unwrap();
+    server.join().unwrap();
+}
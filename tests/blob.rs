@@ -0,0 +1,169 @@
+use ubus::*;
+
+/// An extended blob (id 3) whose header declares the given name length
+fn extended(name_len: u16, rest: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&name_len.to_be_bytes());
+    data.extend_from_slice(rest);
+    let tag = BlobTag::new_extended(3, BlobTag::SIZE + data.len()).unwrap();
+    let mut blob = tag.to_bytes().to_vec();
+    blob.extend_from_slice(&data);
+    blob
+}
+
+#[test]
+fn test() {
+    // Well formed: "ab", nul, padding, then the payload
+    let blob = extended(2, b"ab\0\0\0\0xyz\0");
+    let blob = Blob::from_bytes(&blob).unwrap();
+    assert_eq!(blob.name, Some("ab"));
+    assert_eq!(blob.data, b"xyz\0");
+
+    // Truncated or inconsistent extended headers are errors, not panics
+    let bad = [
+        extended(100, b"ab\0\0"),
+        extended(2, b"ab"),
+        extended(2, b"abX\0"),
+        extended(3, b"abc\0"),
+    ];
+    for blob in bad.iter() {
+        assert!(matches!(Blob::from_bytes(blob), Err(Error::InvalidData(_))));
+    }
+
+    // Missing name length
+    let mut blob = BlobTag::new_extended(3, BlobTag::SIZE + 1)
+        .unwrap()
+        .to_bytes()
+        .to_vec();
+    blob.push(0);
+    assert!(matches!(
+        Blob::from_bytes(&blob),
+        Err(Error::InvalidData(_))
+    ));
+}
+
+#[test]
+fn named() {
+    let mut buffer = [0u8; 128];
+    let mut builder = BlobBuilder::from_bytes(&mut buffer);
+    builder
+        .push_named_str(BlobMsgType::STRING.value(), "name", "value")
+        .unwrap();
+    builder
+        .push_named_u32(BlobMsgType::INT32.value(), "number", 42)
+        .unwrap();
+    builder
+        .push_named_bool(BlobMsgType::INT8.value(), "flag", true)
+        .unwrap();
+    builder.push_u32(BlobMsgType::INT32.value(), 7).unwrap();
+    let len = builder.len();
+
+    let values: Vec<_> = BlobIter::<BlobMsg>::new(&buffer[..len])
+        .map(|value| (value.name, format!("{:?}", value.data)))
+        .collect();
+    assert_eq!(
+        values,
+        [
+            (Some("name"), "String(\"value\")".to_string()),
+            (Some("number"), "Int32(42)".to_string()),
+            (Some("flag"), "Int8(1)".to_string()),
+            (None, "Int32(7)".to_string()),
+        ]
+    );
+
+    // Names which don't fit are rejected
+    let mut buffer = [0u8; 8];
+    let mut builder = BlobBuilder::from_bytes(&mut buffer);
+    assert!(builder.push_named_u32(5, "too long", 0).is_err());
+}
+
+#[test]
+fn no_partial_writes() {
+    let mut buffer = [0xaau8; 24];
+    {
+        let mut builder = BlobBuilder::from_bytes(&mut buffer);
+        builder.push_u32(1, 7).unwrap();
+        assert_eq!(builder.len(), 8);
+
+        // Pushes which don't fit fail without writing anything
+        assert!(builder.push_str(2, "too long to fit").is_err());
+        assert!(builder.push_named_bytes(3, "name", &[0u8; 8]).is_err());
+        assert!(builder.push_bytes(4, &[1u8; 13]).is_err());
+        assert_eq!(builder.len(), 8);
+    }
+    assert_eq!(&buffer[4..8], &[0, 0, 0, 7]);
+    assert_eq!(&buffer[8..], &[0xaa; 16]);
+
+    let mut buffer = [0xaau8; 16];
+    {
+        let mut builder = BlobBuilder::from_bytes(&mut buffer);
+        assert!(builder.push_str(1, "sixteen bytes!").is_err());
+        assert!(builder.is_empty());
+    }
+    assert_eq!(buffer, [0xaa; 16]);
+}
+
+#[test]
+fn write_to() {
+    let mut buffer = [0u8; 128];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_string("odd", "abc").unwrap();
+    builder.push_int32("", 7).unwrap();
+    builder
+        .push_table("table", |b| b.push_bool("flag", true))
+        .unwrap();
+    let data = builder.finish();
+
+    // Parsed blobs (named or not) write back out byte for byte
+    let mut copy = [0u8; 128];
+    let mut builder = BlobBuilder::from_bytes(&mut copy);
+    let mut rest = data;
+    while !rest.is_empty() {
+        let blob = Blob::from_bytes(rest).unwrap();
+        assert_eq!(blob.to_bytes().unwrap(), rest[..blob.encoded_len()]);
+        blob.write_to(&mut builder).unwrap();
+        rest = &rest[blob.encoded_len()..];
+    }
+    let len = builder.len();
+    assert_eq!(&copy[..len], data);
+
+    let blob = Blob::from_bytes(&[0, 0, 0, 5, 1, 0, 0, 0]).unwrap();
+    assert_eq!(blob.to_bytes().unwrap(), [0, 0, 0, 5, 1, 0, 0, 0]);
+
+    // Too big for the builder
+    let mut small = [0u8; 8];
+    let blob = Blob::from_bytes(data).unwrap();
+    assert!(blob
+        .write_to(&mut BlobBuilder::from_bytes(&mut small))
+        .is_err());
+}
+
+#[test]
+fn invalid_name() {
+    let blob = extended(1, b"\xff\0xyz\0");
+    let blob = Blob::from_bytes(&blob).unwrap();
+    assert_eq!(blob.name, None);
+    assert_eq!(blob.raw_name, Some(&b"\xff"[..]));
+    assert_eq!(blob.name_lossy().unwrap(), "\u{fffd}");
+    assert_eq!(blob.data, b"xyz\0");
+
+    // A badly encoded name doesn't stop the attributes after it being read
+    let mut buffer = [0u8; 64];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_string("bad!", "first").unwrap();
+    builder.push_int32("good", 7).unwrap();
+    let len = builder.finish().len();
+    buffer[6] = 0xc3;
+    let data = &buffer[..len];
+    let values: Vec<_> = BlobIter::<BlobMsg>::new(data)
+        .map(|value| (value.name, value.name_lossy().unwrap()))
+        .collect();
+    assert_eq!(
+        values,
+        [(None, "\u{fffd}ad!".into()), (Some("good"), "good".into())]
+    );
+
+    // And is written back out unchanged
+    let blob = Blob::from_bytes(data).unwrap();
+    assert_eq!(blob.to_bytes().unwrap(), data[..blob.encoded_len()]);
+}
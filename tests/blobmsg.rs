@@ -0,0 +1,117 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_string("ssid", "foo").unwrap();
+    builder.push_int32("channel", 11).unwrap();
+    builder
+        .push_array("keys", |b| {
+            b.push_string("", "a")?;
+            b.push_string("", "b")
+        })
+        .unwrap();
+    let data = builder.finish();
+
+    let mut iter = BlobIter::<BlobMsg>::new(data);
+
+    let ssid = iter.next().unwrap();
+    assert_eq!(ssid.name, Some("ssid"));
+    assert!(matches!(ssid.data, BlobMsgData::String("foo")));
+
+    let channel = iter.next().unwrap();
+    assert_eq!(channel.name, Some("channel"));
+    assert!(matches!(channel.data, BlobMsgData::Int32(11)));
+
+    let keys = iter.next().unwrap();
+    assert_eq!(keys.name, Some("keys"));
+    if let BlobMsgData::Array(keys) = keys.data {
+        let keys: Vec<_> = keys
+            .map(|key| match key.data {
+                BlobMsgData::String(s) => s,
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(keys, ["a", "b"]);
+    } else {
+        panic!();
+    }
+
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn path() {
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder
+        .push_array("interface", |b| {
+            b.push_table("", |b| {
+                b.push_string("interface", "lan")?;
+                b.push_array("ipv4-address", |b| {
+                    b.push_table("", |b| {
+                        b.push_string("address", "192.168.1.1")?;
+                        b.push_int32("mask", 24)
+                    })
+                })
+            })
+        })
+        .unwrap();
+    let data = builder.finish();
+    let reply = BlobIter::<BlobMsg>::new(data);
+
+    let address = reply.path("interface.0.ipv4-address.0.address").unwrap();
+    assert_eq!(address.name, Some("address"));
+    assert!(matches!(address.data, BlobMsgData::String("192.168.1.1")));
+
+    let interface = reply.path("interface.0").unwrap();
+    assert!(matches!(
+        interface.path("ipv4-address.0.mask").unwrap().data,
+        BlobMsgData::Int32(24)
+    ));
+    assert!(matches!(
+        interface.path("interface").unwrap().data,
+        BlobMsgData::String("lan")
+    ));
+
+    for missing in [
+        "",
+        "missing",
+        "interface.1",
+        "interface.x",
+        "interface.0.interface.0",
+    ] {
+        assert!(reply.path(missing).is_none(), "{}", missing);
+    }
+}
+
+#[test]
+fn display() {
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_string("name", "say \"hi\"\n").unwrap();
+    builder.push_bool("up", true).unwrap();
+    builder.push_double("nan", f64::NAN).unwrap();
+    builder
+        .push_array("list", |b| {
+            b.push_int32("", 1)?;
+            b.push_table("", |b| b.push_int64("big", -5))
+        })
+        .unwrap();
+    let data = builder.finish();
+    let reply = BlobIter::<BlobMsg>::new(data);
+
+    assert_eq!(
+        reply.to_string(),
+        r#"{"name":"say \"hi\"\n","up":true,"nan":null,"list":[1,{"big":-5}]}"#
+    );
+    assert_eq!(reply.path("list").unwrap().to_string(), r#"[1,{"big":-5}]"#);
+    assert_eq!(reply.path("up").unwrap().data.to_string(), "true");
+
+    // Badly encoded names are still valid JSON
+    let mut data = data.to_vec();
+    data[6] = 0xff;
+    let reply = BlobIter::<BlobMsg>::new(&data);
+    assert!(reply.to_string().starts_with("{\"\u{fffd}ame\":"));
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use ubus::*;
+
+#[test]
+fn test() {
+    let mut buffer = [0u8; 256];
+    let channel = 11;
+    let name = String::from("wlan0");
+    let data = blobmsg!(&mut buffer => {
+        "ssid": "foo",
+        "channel": channel,
+        "name": name,
+        "power": -3i8,
+        "ratio": 0.5,
+        "up": true,
+        "keys": ["a", "b", [1, 2], {}],
+        "radio": { "band": "2g", "htmode": { "width": 40u16 } },
+    })
+    .unwrap();
+
+    // The same as building it by hand
+    let mut expected = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut expected);
+    builder.push_string("ssid", "foo").unwrap();
+    builder.push_int32("channel", 11).unwrap();
+    builder.push_string("name", "wlan0").unwrap();
+    builder.push_int8("power", -3).unwrap();
+    builder.push_double("ratio", 0.5).unwrap();
+    builder.push_bool("up", true).unwrap();
+    builder
+        .push_array("keys", |b| {
+            b.push_string("", "a")?;
+            b.push_string("", "b")?;
+            b.push_array("", |b| {
+                b.push_int32("", 1)?;
+                b.push_int32("", 2)
+            })?;
+            b.push_table("", |_| Ok(()))
+        })
+        .unwrap();
+    builder
+        .push_table("radio", |b| {
+            b.push_string("band", "2g")?;
+            b.push_table("htmode", |b| b.push_int32("width", 40))
+        })
+        .unwrap();
+    assert_eq!(data, builder.finish());
+
+    let mut small = [0u8; 16];
+    assert!(blobmsg!(&mut small => { "ssid": "a long network name" }).is_err());
+    assert!(blobmsg!(&mut small => {}).unwrap().is_empty());
+
+    // Owned values, which convert back to the same table
+    let value = blobmsg!({
+        "channel": channel,
+        "keys": ["a", ["b"], { "c": 1 }],
+        "radio": { "up": true },
+    });
+    let mut c = BTreeMap::new();
+    c.insert("c".to_string(), BlobMsgValue::Int64(1));
+    let mut radio = BTreeMap::new();
+    radio.insert("up".to_string(), BlobMsgValue::Bool(true));
+    let mut table = BTreeMap::new();
+    table.insert("channel".to_string(), BlobMsgValue::Int64(11));
+    table.insert(
+        "keys".to_string(),
+        BlobMsgValue::Array(vec![
+            "a".into(),
+            BlobMsgValue::Array(vec!["b".into()]),
+            BlobMsgValue::Table(c),
+        ]),
+    );
+    table.insert("radio".to_string(), BlobMsgValue::Table(radio));
+    assert_eq!(value, BlobMsgValue::Table(table.clone()));
+
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    for (name, value) in &table {
+        builder.push_value(name, value).unwrap();
+    }
+    assert_eq!(
+        BlobIter::<BlobMsg>::new(builder.finish()).to_map().unwrap(),
+        table
+    );
+}
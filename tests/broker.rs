@@ -0,0 +1,64 @@
+use std::sync::mpsc::channel;
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+
+    // A service with a single object, handling calls on its own thread
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("test", |method, _args, reply| {
+            assert_eq!(method, "ping");
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    let object_id = object.id();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+
+    // A client listening for events
+    let mut listener = broker.connect().unwrap();
+    let (tx, events) = channel();
+    let subscriber = listener
+        .subscriber(move |id, _data| tx.send(id.to_string()).unwrap())
+        .unwrap();
+    let mut buffer = [0u8; 256];
+    let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+    args.push_int32("object", subscriber.id() as i32).unwrap();
+    args.push_string("pattern", "test.*").unwrap();
+    listener
+        .invoke(1, "register", args.finish(), |_| {})
+        .unwrap();
+
+    // Another client looks up and calls the service's object
+    let mut client = broker.connect().unwrap();
+    assert_eq!(client.object_id("test").unwrap(), object_id);
+    assert!(matches!(client.object_id("missing"), Err(Error::Status(4))));
+    let mut results = Vec::new();
+    client
+        .invoke(object_id, "ping", &[], |reply| {
+            for value in reply {
+                if let BlobMsgData::String(s) = value.data {
+                    results.push(s.to_string());
+                }
+            }
+        })
+        .unwrap();
+    assert_eq!(results, ["pong"]);
+
+    // Events are delivered to matching listeners only
+    for id in ["other.event", "test.event"] {
+        let mut buffer = [0u8; 256];
+        let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+        args.push_string("id", id).unwrap();
+        args.push_table("data", |_| Ok(())).unwrap();
+        client.invoke(1, "send", args.finish(), |_| {}).unwrap();
+    }
+    listener.handle_next_message().unwrap();
+    assert_eq!(events.try_recv().unwrap(), "test.event");
+    assert!(events.try_recv().is_err());
+}
@@ -0,0 +1,39 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let pool = BufferPool::new(2);
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+
+    // Each connection gives its own buffer up, and only the pool's two are kept
+    let mut connections: Vec<_> = (0..4)
+        .map(|_| {
+            let mut connection = broker.connect().unwrap();
+            connection.set_buffer_pool(Some(pool.clone()));
+            connection
+        })
+        .collect();
+    assert_eq!(pool.idle(), 2);
+
+    // Buffers are borrowed for each request and given back afterwards
+    for connection in &mut connections {
+        assert_eq!(connection.object_id("test").unwrap(), object.id());
+        assert_eq!(pool.idle(), 2);
+    }
+
+    // Going back to a buffer of its own
+    let mut connection = connections.pop().unwrap();
+    connection.set_buffer_pool(None);
+    assert!(connection.buffer_pool().is_none());
+    connection.lookup(|_| {}, |_| {}).unwrap();
+    assert_eq!(pool.idle(), 2);
+
+    // Taken buffers are new when none are idle, and the pool stays capped
+    let taken: Vec<_> = (0..3).map(|_| pool.take()).collect();
+    assert_eq!(pool.idle(), 0);
+    assert_eq!(taken[2].capacity(), 0);
+    taken.into_iter().for_each(|buffer| pool.give(buffer));
+    assert_eq!(pool.idle(), 2);
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "calloop")]
+use calloop::EventLoop;
+use std::sync::mpsc::channel;
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let (tx, calls) = channel();
+    let object = service
+        .add_object("test", move |method, _args, reply| {
+            tx.send(method.to_string()).unwrap();
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    let object_id = object.id();
+
+    // The service's calls are handled by the event loop
+    let mut event_loop: EventLoop<usize> = EventLoop::try_new().unwrap();
+    let source = ConnectionSource::new(service).unwrap();
+    event_loop
+        .handle()
+        .insert_source(source, |result, _connection, handled| {
+            result.unwrap();
+            *handled += 1;
+        })
+        .unwrap();
+
+    let client = std::thread::spawn(move || {
+        let mut client = broker.connect().unwrap();
+        let mut results = Vec::new();
+        client
+            .invoke(object_id, "ping", &[], |reply| {
+                for value in reply {
+                    if let BlobMsgData::String(s) = value.data {
+                        results.push(s.to_string());
+                    }
+                }
+            })
+            .unwrap();
+        results
+    });
+
+    let mut handled = 0;
+    while handled == 0 {
+        event_loop.dispatch(None, &mut handled).unwrap();
+    }
+    assert_eq!(client.join().unwrap(), ["pong"]);
+    assert_eq!(calls.try_recv().unwrap(), "ping");
+    drop(object);
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "cbor")]
+use ubus::*;
+
+#[test]
+fn test() {
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_string("ssid", "foo").unwrap();
+    builder.push_int32("channel", 11).unwrap();
+    builder.push_int64("bytes", 1 << 40).unwrap();
+    builder.push_bool("up", true).unwrap();
+    builder.push_double("load", 0.5).unwrap();
+    builder.push_null("none").unwrap();
+    builder
+        .push_array("keys", |b| {
+            b.push_string("", "a")?;
+            b.push_table("", |b| b.push_int32("n", -1))
+        })
+        .unwrap();
+    let data = builder.finish();
+
+    let mut cbor = [0u8; 256];
+    let len = blobmsg_to_cbor(BlobIter::new(data), &mut cbor).unwrap();
+    assert!(blobmsg_to_cbor(BlobIter::new(data), &mut cbor[..len - 1]).is_err());
+
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    cbor_to_blobmsg(&cbor[..len], &mut builder).unwrap();
+    assert_eq!(builder.finish(), data);
+
+    // Definite length map: {"a": 1, "b": [true]}
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    let cbor = [0xa2, 0x61, b'a', 0x01, 0x61, b'b', 0x81, 0xf5];
+    cbor_to_blobmsg(&cbor, &mut builder).unwrap();
+    let mut iter = BlobIter::<BlobMsg>::new(builder.finish());
+    let a = iter.next().unwrap();
+    assert_eq!(a.name, Some("a"));
+    assert!(matches!(a.data, BlobMsgData::Int32(1)));
+    let b = iter.next().unwrap();
+    assert!(matches!(b.data, BlobMsgData::Array(_)));
+    assert!(iter.next().is_none());
+
+    // Not a map, truncated, and trailing data
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    assert!(cbor_to_blobmsg(&[0x81, 0x01], &mut builder).is_err());
+    assert!(cbor_to_blobmsg(&[0xa1, 0x61, b'a'], &mut builder).is_err());
+    assert!(cbor_to_blobmsg(&[0xa0, 0x00], &mut builder).is_err());
+}
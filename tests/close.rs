@@ -0,0 +1,28 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+    let handler = service.event_handler(|_, _| {}).unwrap();
+    service.register_event(&handler, "*").unwrap();
+
+    let mut client = broker.connect().unwrap();
+    assert_eq!(client.object_id("test").unwrap(), object.id());
+
+    // Objects are gone as soon as close returns
+    service.close().unwrap();
+    assert!(matches!(client.object_id("test"), Err(Error::Status(4))));
+    client.send_event("test", &[]).unwrap();
+
+    // Also for other transports
+    let mut service = broker.connect_loopback().unwrap();
+    let _object = service.add_object("loopback", |_, _, _| 0).unwrap();
+    assert!(client.object_id("loopback").is_ok());
+    service.close().unwrap();
+    assert!(matches!(
+        client.object_id("loopback"),
+        Err(Error::Status(4))
+    ));
+}
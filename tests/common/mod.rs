@@ -0,0 +1,67 @@
+//! Building and exchanging messages for the tests which play the part of ubusd (or an object)
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+/// The header of a message from the peer 0x100
+pub fn header(message: MessageType, sequence: u16) -> MessageHeader {
+    MessageHeader {
+        version: MessageVersion::CURRENT,
+        message,
+        sequence: sequence.into(),
+        peer: 0x100.into(),
+    }
+}
+
+/// The message with `header` and `attrs`
+pub fn build<'a>(
+    header: MessageHeader,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) -> Vec<u8> {
+    // Large enough for the tests of messages bigger than the default buffer
+    let mut buffer = vec![0u8; 4 * DEFAULT_BUFFER_SIZE];
+    let mut builder = MessageBuilder::new(&mut buffer, header).unwrap();
+    for attr in attrs {
+        builder.put(attr).unwrap();
+    }
+    builder.finish().to_vec()
+}
+
+/// The message `ty` with `attrs`, from the peer 0x100
+pub fn message<'a>(
+    ty: MessageType,
+    sequence: u16,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) -> Vec<u8> {
+    build(header(ty, sequence), attrs)
+}
+
+/// Send the message `ty` with `attrs` to the client
+pub fn send<'a>(
+    server: &mut UnixStream,
+    ty: MessageType,
+    sequence: u16,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) {
+    server.write_all(&message(ty, sequence, attrs)).unwrap();
+}
+
+/// The blobmsg table built by `f`
+pub fn table(f: impl FnOnce(&mut BlobMsgBuilder) -> Result<(), Error>) -> Vec<u8> {
+    let mut buffer = vec![0u8; DEFAULT_BUFFER_SIZE];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    f(&mut builder).unwrap();
+    builder.finish().to_vec()
+}
+
+/// Read a request, returning its sequence number (or `None` once the client has gone)
+pub fn read_request(server: &mut UnixStream) -> Option<u16> {
+    let mut request = [0u8; 12];
+    server.read_exact(&mut request).ok()?;
+    let len = u32::from_be_bytes([request[8], request[9], request[10], request[11]]) & 0xff_ffff;
+    let mut rest = vec![0u8; len as usize - 4];
+    server.read_exact(&mut rest).unwrap();
+    Some(u16::from_be_bytes([request[2], request[3]]))
+}
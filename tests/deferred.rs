@@ -0,0 +1,53 @@
+use std::sync::mpsc::channel;
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let (tx, deferred) = channel();
+    let object = service
+        .add_object_deferrable("slow", move |method, _args, reply, request| {
+            if method == "check" {
+                // Answered later, once the work is done
+                tx.send(request).unwrap();
+                None
+            } else {
+                reply.push_str(BlobMsgType::STRING.value(), "now").unwrap();
+                Some(0)
+            }
+        })
+        .unwrap();
+    let object_id = object.id();
+
+    let client = std::thread::spawn(move || {
+        let mut client = broker.connect().unwrap();
+        let mut results = Vec::new();
+        for method in ["fast", "check"] {
+            client
+                .invoke(object_id, method, &[], |reply| {
+                    for value in reply {
+                        if let BlobMsgData::String(s) = value.data {
+                            results.push(s.to_string());
+                        }
+                    }
+                })
+                .unwrap();
+        }
+        results
+    });
+
+    // Handle both calls, completing the deferred one afterwards
+    service.handle_next_message().unwrap();
+    service.handle_next_message().unwrap();
+    let request = deferred.try_recv().unwrap();
+    let mut buffer = [0u8; 64];
+    let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+    data.push_string("result", "later").unwrap();
+    service
+        .complete_deferred(request, 0, data.finish())
+        .unwrap();
+
+    assert_eq!(client.join().unwrap(), ["now", "later"]);
+    drop(object);
+}
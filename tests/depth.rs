@@ -0,0 +1,124 @@
+use ubus::*;
+
+/// Push `depth` tables, each nested in the last
+fn nest(builder: &mut BlobMsgBuilder, depth: usize) -> Result<(), Error> {
+    match depth {
+        0 => builder.push_int32("n", 1),
+        _ => builder.push_table("a", |b| nest(b, depth - 1)),
+    }
+}
+
+fn too_deep<T>(result: Result<T, Error>) -> bool {
+    matches!(result, Err(Error::InvalidData("Blobmsg nested too deeply")))
+}
+
+#[test]
+fn test() {
+    let mut deep = vec![0u8; 4096];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut deep);
+    nest(&mut builder, DEFAULT_MAX_DEPTH + 1).unwrap();
+    let deep = builder.finish();
+    let mut limit = vec![0u8; 4096];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut limit);
+    nest(&mut builder, DEFAULT_MAX_DEPTH).unwrap();
+    let limit = builder.finish();
+
+    assert!(BlobIter::<BlobMsg>::new(limit).to_map().is_ok());
+    assert!(too_deep(BlobIter::<BlobMsg>::new(deep).to_map()));
+    let value = BlobIter::<BlobMsg>::new(deep).next().unwrap();
+    assert!(too_deep(value.to_owned()));
+
+    // Values built in memory can't be pushed nested any deeper either
+    let mut value = BlobMsgValue::Int64(1);
+    for _ in 0..DEFAULT_MAX_DEPTH + 1 {
+        value = BlobMsgValue::Array(vec![value]);
+    }
+    let mut buffer = vec![0u8; 4096];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    assert!(too_deep(builder.push_value("a", &value)));
+
+    #[cfg(feature = "cbor")]
+    {
+        let mut cbor = [0u8; 256];
+        assert!(too_deep(blobmsg_to_cbor(BlobIter::new(deep), &mut cbor)));
+        let len = blobmsg_to_cbor(BlobIter::new(limit), &mut cbor).unwrap();
+        let mut buffer = vec![0u8; 4096];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        cbor_to_blobmsg(&cbor[..len], &mut builder).unwrap();
+        // One more map around the outermost one
+        let mut nested = vec![0xa1, 0x61, b'b'];
+        nested.extend_from_slice(&cbor[..len]);
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        assert!(too_deep(cbor_to_blobmsg(&nested, &mut builder)));
+    }
+
+    #[cfg(feature = "json")]
+    {
+        let json = |depth| {
+            let mut json = "{".to_string();
+            json.push_str(&r#""a":{"#.repeat(depth));
+            json.push_str(&"}".repeat(depth + 1));
+            json
+        };
+        let mut buffer = vec![0u8; 4096];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        json_to_blobmsg(json(DEFAULT_MAX_DEPTH).as_bytes(), &mut [], &mut builder).unwrap();
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        let json = json(DEFAULT_MAX_DEPTH + 1);
+        assert!(too_deep(json_to_blobmsg(
+            json.as_bytes(),
+            &mut [],
+            &mut builder
+        )));
+    }
+
+    #[cfg(feature = "serde_json")]
+    {
+        let object = BlobIter::<BlobMsg>::new(limit).to_json().unwrap();
+        assert!(too_deep(BlobIter::<BlobMsg>::new(deep).to_json()));
+        let nested = serde_json::json!({ "b": object });
+        let mut buffer = vec![0u8; 4096];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        assert!(too_deep(builder.push_json(&nested)));
+    }
+
+    // The limit can be raised (or lowered) for a single conversion
+    assert!(BlobIter::<BlobMsg>::new(deep)
+        .to_map_limit(DEFAULT_MAX_DEPTH + 1)
+        .is_ok());
+    assert!(too_deep(BlobIter::<BlobMsg>::new(limit).to_map_limit(0)));
+    assert!(BlobIter::<BlobMsg>::new(limit).to_map().is_ok());
+    let value = BlobIter::<BlobMsg>::new(limit).next().unwrap();
+    assert!(too_deep(value.data.to_owned_limit(1)));
+
+    #[cfg(feature = "cbor")]
+    {
+        let mut cbor = [0u8; 256];
+        let len = blobmsg_to_cbor_limit(BlobIter::new(deep), &mut cbor, DEFAULT_MAX_DEPTH + 1);
+        let mut buffer = vec![0u8; 4096];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        assert!(too_deep(cbor_to_blobmsg_limit(
+            &cbor[..len.unwrap()],
+            &mut builder,
+            DEFAULT_MAX_DEPTH
+        )));
+    }
+
+    #[cfg(feature = "json")]
+    {
+        let mut buffer = vec![0u8; 4096];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+        let json = r#"{"a":{"b":{}}}"#.as_bytes();
+        assert!(too_deep(json_to_blobmsg_limit(
+            json,
+            &mut [],
+            &mut builder,
+            1
+        )));
+    }
+
+    #[cfg(feature = "serde_json")]
+    {
+        assert!(too_deep(BlobIter::<BlobMsg>::new(limit).to_json_limit(1)));
+    }
+}
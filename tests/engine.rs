@@ -0,0 +1,199 @@
+#![cfg(feature = "nb")]
+mod common;
+
+use common::*;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+/// A minimal blocking transport: receive until `on_event` returns something
+fn until<R>(
+    engine: &mut Engine<Vec<u8>>,
+    socket: &mut UnixStream,
+    mut on_event: impl FnMut(EngineEvent) -> Option<R>,
+) -> R {
+    loop {
+        match engine.poll().unwrap() {
+            Some(event) => {
+                if let Some(result) = on_event(event) {
+                    return result;
+                }
+            }
+            None => {
+                let len = socket.read(engine.receive_buffer()).unwrap();
+                assert!(len > 0, "Connection closed");
+                engine.received(len);
+            }
+        }
+    }
+}
+
+#[test]
+fn broker() {
+    let (mut client, server) = UnixStream::pair().unwrap();
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let _object = service
+        .add_object("test", |_, _, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    std::thread::spawn(move || broker.serve(server));
+    std::thread::spawn(move || while service.handle_next_message().is_ok() {});
+
+    let mut engine = Engine::new(vec![0u8; 4096]);
+    let peer = until(&mut engine, &mut client, |event| match event {
+        EngineEvent::Hello { peer } => Some(peer),
+        _ => panic!("Expected hello"),
+    });
+    assert_eq!(engine.peer(), Some(peer));
+
+    let mut out = [0u8; 256];
+    let (sequence, bytes) = engine
+        .request(
+            &mut out,
+            MessageType::LOOKUP,
+            0,
+            [MessageAttr::ObjPath("test")],
+        )
+        .unwrap();
+    client.write_all(bytes).unwrap();
+    let mut id = None;
+    until(&mut engine, &mut client, |event| match event {
+        EngineEvent::Data { attrs, .. } => {
+            for attr in attrs {
+                if let MessageAttr::ObjId(obj) = attr {
+                    id = Some(obj);
+                }
+            }
+            None
+        }
+        EngineEvent::Done {
+            sequence: done,
+            status,
+        } => {
+            assert_eq!((done, status), (sequence, 0));
+            Some(())
+        }
+        _ => panic!("Unexpected {:?}", event),
+    });
+
+    let (sequence, bytes) = engine.invoke(&mut out, id.unwrap(), "ping", &[]).unwrap();
+    client.write_all(bytes).unwrap();
+    assert!(engine.is_pending(sequence));
+    let mut replies = Vec::new();
+    let status = until(&mut engine, &mut client, |event| match event {
+        EngineEvent::Data { attrs, .. } => {
+            for attr in attrs {
+                if let MessageAttr::Data(data) = attr {
+                    replies.push(BlobIter::<Blob>::new(data).count());
+                }
+            }
+            None
+        }
+        EngineEvent::Done { status, .. } => Some(status),
+        _ => panic!("Unexpected {:?}", event),
+    });
+    assert_eq!((replies, status), (vec![1], 0));
+    assert!(!engine.is_pending(sequence));
+}
+
+#[test]
+fn bytes() {
+    let mut engine = Engine::new([0u8; 128]);
+    let mut out = [0u8; 128];
+    assert!(engine.invoke(&mut out, 0x100, "ping", &[]).is_err());
+
+    // A byte at a time, as a slow transport might deliver them
+    for byte in message(MessageType::HELLO, 0, []) {
+        assert!(engine.poll().unwrap().is_none());
+        assert_eq!(engine.feed(&[byte]), 1);
+    }
+    assert!(matches!(
+        engine.poll().unwrap(),
+        Some(EngineEvent::Hello { peer: 0x100 })
+    ));
+    let (sequence, _) = engine.invoke(&mut out, 0x100, "ping", &[]).unwrap();
+
+    // Replies to other requests are dropped, as are messages too large for the buffer
+    let mut buffer = [0u8; 64];
+    let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+    data.push_int32("count", 1).unwrap();
+    let data = data.finish();
+    let large = vec![0u8; 200];
+    let status = |status| [MessageAttr::Status(status), MessageAttr::ObjId(0x100)];
+    engine.feed(&message(
+        MessageType::DATA,
+        sequence + 1,
+        [MessageAttr::Data(data)],
+    ));
+    engine.feed(&message(
+        MessageType::DATA,
+        sequence,
+        [MessageAttr::Data(data)],
+    ));
+    assert!(matches!(
+        engine.poll().unwrap(),
+        Some(EngineEvent::Data { .. })
+    ));
+    let large = message(MessageType::DATA, sequence, [MessageAttr::Data(&large)]);
+    engine.feed(&large[..100]);
+    engine.poll().unwrap_err();
+    engine.feed(&large[100..]);
+    // A STATUS without its status attribute
+    engine.feed(&message(
+        MessageType::STATUS,
+        sequence,
+        [MessageAttr::ObjId(0x100)],
+    ));
+    engine.poll().unwrap_err();
+    engine.feed(&message(MessageType::STATUS, sequence, status(4)));
+    match engine.poll().unwrap() {
+        Some(EngineEvent::Done {
+            sequence: done,
+            status,
+        }) => assert_eq!((done, status), (sequence, 4)),
+        _ => panic!("Expected status"),
+    }
+    assert!(engine.poll().unwrap().is_none());
+
+    // Calls to our objects are passed on, for the user to answer
+    let call = [
+        MessageAttr::ObjId(0x200),
+        MessageAttr::Method("ping"),
+        MessageAttr::Data(&[]),
+    ];
+    engine.feed(&message(MessageType::INVOKE, 7, call));
+    let (sequence, peer) = match engine.poll().unwrap() {
+        Some(EngineEvent::Message(call)) => (call.header.sequence.into(), call.header.peer.into()),
+        _ => panic!("Expected call"),
+    };
+    let reply = engine
+        .reply(&mut out, MessageType::STATUS, sequence, peer, status(0))
+        .unwrap();
+    assert_eq!(reply, &message(MessageType::STATUS, 7, status(0))[..]);
+}
+
+// Invalid data panics in debug builds, unless fuzzing
+#[cfg(feature = "fuzzing")]
+#[test]
+fn bad_header() {
+    let mut engine = Engine::new([0u8; 128]);
+    let mut hello = message(MessageType::HELLO, 0, []);
+    engine.feed(&hello);
+    assert!(engine.poll().unwrap().is_some());
+
+    // The wrong version, followed by a message which arrived with it
+    hello[0] = 1;
+    engine.feed(&hello);
+    engine.feed(&message(MessageType::INVOKE, 1, []));
+    engine.poll().unwrap_err();
+    assert!(engine.poll().unwrap().is_none());
+
+    engine.feed(&message(MessageType::PING, 2, []));
+    assert!(matches!(
+        engine.poll().unwrap(),
+        Some(EngineEvent::Message(message)) if message.header.message == MessageType::PING
+    ));
+}
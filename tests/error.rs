@@ -0,0 +1,54 @@
+use std::error::Error as _;
+use ubus::*;
+
+fn boxed<E: std::error::Error + Send + Sync + 'static>(e: E) -> Box<dyn std::error::Error> {
+    Box::new(e)
+}
+
+#[test]
+fn test() {
+    let e: Error = Error::InvalidData("bad");
+    assert!(e.source().is_none());
+    assert_eq!(boxed(e).to_string(), "Invalid Data: bad");
+
+    // The transport's error is the source
+    let io = std::io::Error::new(std::io::ErrorKind::BrokenPipe, "gone");
+    let e: Error<std::io::Error> = Error::IO(io);
+    let source = e.source().unwrap();
+    assert_eq!(source.to_string(), "gone");
+    assert!(source.downcast_ref::<std::io::Error>().is_some());
+    assert!(boxed(Error::<std::io::Error>::Timeout).source().is_none());
+}
+
+#[test]
+fn io_error() {
+    use std::io::ErrorKind;
+
+    fn call(e: Error<std::io::Error>) -> std::io::Result<()> {
+        Err(e)?;
+        Ok(())
+    }
+
+    let io = std::io::Error::new(ErrorKind::BrokenPipe, "gone");
+    let e = call(Error::IO(io)).unwrap_err();
+    assert_eq!(
+        (e.kind(), e.to_string()),
+        (ErrorKind::BrokenPipe, "gone".into())
+    );
+
+    let cases = [
+        (Error::InvalidData("bad"), ErrorKind::InvalidData),
+        (Error::Timeout, ErrorKind::TimedOut),
+        (Error::Status(2), ErrorKind::InvalidInput),
+        (Error::Status(4), ErrorKind::NotFound),
+        (Error::Status(6), ErrorKind::PermissionDenied),
+        (Error::Status(9), ErrorKind::Other),
+    ];
+    for (error, kind) in cases {
+        let e = call(error).unwrap_err();
+        assert_eq!(e.kind(), kind);
+        // The ubus error is kept
+        let inner = e.get_ref().unwrap();
+        assert!(inner.downcast_ref::<Error<std::io::Error>>().is_some());
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::mpsc::channel;
+use ubus::*;
+
+#[test]
+fn test() {
+    assert!(event_matches("network.*", "network.interface"));
+    assert!(event_matches("*", "anything"));
+    assert!(!event_matches("network.*", "system.boot"));
+    assert!(!event_matches("system", "system.boot"));
+
+    let broker = Broker::new();
+    let mut listener = broker.connect().unwrap();
+    let (tx_a, events_a) = channel();
+    let a = listener
+        .event_handler(move |id, _data| tx_a.send(id.to_string()).unwrap())
+        .unwrap();
+    listener.register_event(&a, "network.*").unwrap();
+    listener.register_event(&a, "network.interface").unwrap();
+    listener.register_event(&a, "system.boot").unwrap();
+    let (tx_b, events_b) = channel();
+    let b = listener
+        .event_handler(move |id, _data| tx_b.send(id.to_string()).unwrap())
+        .unwrap();
+    listener.register_event(&b, "*").unwrap();
+
+    let mut sender = broker.connect().unwrap();
+    let mut buffer = [0u8; 256];
+    let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+    data.push_string("state", "up").unwrap();
+    let data = data.finish();
+    for id in ["network.interface", "system.boot", "other"] {
+        sender.send_event(id, data).unwrap();
+    }
+
+    // One delivery per handler per event, despite overlapping patterns
+    for _ in 0..5 {
+        listener.handle_next_message().unwrap();
+    }
+    let a: Vec<String> = events_a.try_iter().collect();
+    assert_eq!(a, ["network.interface", "system.boot"]);
+    let b: Vec<String> = events_b.try_iter().collect();
+    assert_eq!(b, ["network.interface", "system.boot", "other"]);
+}
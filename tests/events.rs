@@ -0,0 +1,85 @@
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::channel;
+use ubus::events::{Event, HotplugAction};
+use ubus::*;
+
+fn send(connection: &mut Connection<UnixStream>, id: &str, fields: &[(&str, &str)]) {
+    let mut buffer = [0u8; 256];
+    let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+    for (name, value) in fields {
+        data.push_string(name, value).unwrap();
+    }
+    connection.send_event(id, data.finish()).unwrap();
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut listener = broker.connect().unwrap();
+    let (tx, events) = channel();
+    let handler = listener
+        .event_handler(move |id, data| {
+            let parsed = match Event::parse(id, data) {
+                Ok(Event::InterfaceUp { interface }) => format!("up {}", interface),
+                Ok(Event::InterfaceDown { interface }) => format!("down {}", interface),
+                Ok(Event::ObjectAdded { path, .. }) => format!("added {}", path),
+                Ok(Event::ObjectRemoved { path, .. }) => format!("removed {}", path),
+                Ok(Event::Hotplug(hotplug)) => {
+                    assert_eq!(hotplug.var("DEVPATH"), Some("/devices/virtual/net/wg0"));
+                    assert_eq!(hotplug.action, HotplugAction::Add);
+                    format!("{} {:?}", hotplug.subsystem, hotplug.device)
+                }
+                Ok(Event::Other { id, .. }) => format!("other {}", id),
+                Err(e) => format!("{}", e),
+            };
+            tx.send(parsed).unwrap();
+        })
+        .unwrap();
+    listener.register_event(&handler, "*").unwrap();
+
+    let mut sender = broker.connect().unwrap();
+    let interface = "network.interface";
+    send(
+        &mut sender,
+        interface,
+        &[("action", "ifup"), ("interface", "wan")],
+    );
+    send(
+        &mut sender,
+        interface,
+        &[("action", "ifdown"), ("interface", "lan")],
+    );
+    send(
+        &mut sender,
+        interface,
+        &[("action", "ifupdate"), ("interface", "lan")],
+    );
+    send(&mut sender, interface, &[("action", "ifup")]);
+    let hotplug = [
+        ("ACTION", "add"),
+        ("DEVPATH", "/devices/virtual/net/wg0"),
+        ("INTERFACE", "wg0"),
+    ];
+    send(&mut sender, "hotplug.net", &hotplug);
+    send(&mut sender, "system.boot", &[]);
+    let object = sender.add_object("test", |_, _, _| 0).unwrap();
+    sender.remove_object(object.id()).unwrap();
+
+    for _ in 0..8 {
+        listener.handle_next_message().unwrap();
+    }
+    let events: Vec<String> = events.try_iter().collect();
+    assert_eq!(
+        events,
+        [
+            "up wan",
+            "down lan",
+            "other network.interface",
+            "Invalid Data: Event is missing a field",
+            "net Some(\"wg0\")",
+            "other system.boot",
+            "added test",
+            "removed test",
+        ]
+    );
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use common::*;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use ubus::*;
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+
+        // The request should arrive with a file descriptor attached
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        assert_eq!(message.header.message, MessageType::INVOKE);
+        let mut file = unsafe { File::from_raw_fd(message.fd.unwrap()) };
+        let mut text = String::new();
+        file.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "request");
+
+        // Reply with a descriptor of our own
+        let (reader, mut writer) = UnixStream::pair().unwrap();
+        writer.write_all(b"reply").unwrap();
+        drop(writer);
+        let sequence = message.header.sequence.into();
+        let status = common::message(MessageType::STATUS, sequence, [MessageAttr::Status(0)]);
+        server.put_fd(&status, reader.as_raw_fd()).unwrap();
+        drop(reader);
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+
+    let (reader, mut writer) = UnixStream::pair().unwrap();
+    writer.write_all(b"request").unwrap();
+    drop(writer);
+    let fd = connection
+        .invoke_fd(0x100, "exec", &[], Some(reader.as_raw_fd()), |_| {})
+        .unwrap();
+    drop(reader);
+
+    let mut file = unsafe { File::from_raw_fd(fd.unwrap()) };
+    let mut text = String::new();
+    file.read_to_string(&mut text).unwrap();
+    assert_eq!(text, "reply");
+    server.join().unwrap();
+}
+
+/// Send a message with a socket attached, returning the other end of it
+fn send_fd<'a>(
+    io: &mut UnixStream,
+    ty: MessageType,
+    sequence: u16,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) -> UnixStream {
+    let (passed, kept) = UnixStream::pair().unwrap();
+    io.put_fd(&message(ty, sequence, attrs), passed.as_raw_fd())
+        .unwrap();
+    kept
+}
+
+#[test]
+fn stray() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        let sequence = message.header.sequence.into();
+        let status = || [MessageAttr::Status(0)];
+        let invoke = [
+            MessageAttr::ObjId(0x999),
+            MessageAttr::Method("call"),
+            MessageAttr::NoReply(true),
+        ];
+        let data = [MessageAttr::ObjId(0x100), MessageAttr::Data(&[])];
+        let closed = vec![
+            // An unrelated reply, a call to an object we don't have, and an earlier reply
+            send_fd(&mut server, MessageType::STATUS, sequence + 1, status()),
+            send_fd(&mut server, MessageType::INVOKE, 7, invoke),
+            send_fd(&mut server, MessageType::DATA, sequence, data),
+        ];
+        let kept = send_fd(&mut server, MessageType::STATUS, sequence, status());
+        (closed, kept)
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+    let fd = connection
+        .invoke_fd(0x100, "exec", &[], None, |_| {})
+        .unwrap();
+    let (closed, kept) = server.join().unwrap();
+
+    // The other ends see the descriptors passed with them closed
+    for mut other in closed {
+        other
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(other.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+    // Other than the last reply's, which the caller owns
+    kept.set_nonblocking(true).unwrap();
+    let error = (&kept).read(&mut [0u8; 1]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::WouldBlock);
+    drop(unsafe { UnixStream::from_raw_fd(fd.unwrap()) });
+}
+
+#[test]
+fn split() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        let sequence = message.header.sequence.into();
+        let data = [MessageAttr::ObjId(0x100), MessageAttr::Data(&[])];
+        let invoke = [
+            MessageAttr::ObjId(0x999),
+            MessageAttr::Method("call"),
+            MessageAttr::NoReply(true),
+        ];
+        vec![
+            send_fd(&mut server, MessageType::INVOKE, 7, invoke),
+            send_fd(&mut server, MessageType::DATA, sequence, data),
+            send_fd(
+                &mut server,
+                MessageType::STATUS,
+                sequence,
+                [MessageAttr::Status(0)],
+            ),
+        ]
+    });
+
+    let (requester, mut reader) = Connection::new(client).unwrap().split().unwrap();
+    std::thread::spawn(move || reader.run());
+    requester.invoke(0x100, "exec", &[], |_| {}).unwrap();
+
+    // Replies are copied to the requester, so none of the descriptors are kept
+    for mut other in server.join().unwrap() {
+        other
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(other.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+}
+
+#[test]
+fn rejected() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    let big = vec![0u8; 2000];
+    let server = std::thread::spawn(move || {
+        send(&mut server, MessageType::HELLO, 0, []);
+        let data = [MessageAttr::ObjId(0x100), MessageAttr::Data(&big)];
+        vec![
+            // Too large, and missing the status a STATUS must have
+            send_fd(&mut server, MessageType::DATA, 1, data),
+            send_fd(&mut server, MessageType::STATUS, 1, []),
+        ]
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+    connection.set_max_message_size(Some(1024));
+    assert!(matches!(
+        connection.next_message(),
+        Err(Error::InvalidData("Message larger than the maximum size"))
+    ));
+    assert!(connection.next_message().is_err());
+
+    // The descriptors passed with messages which couldn't be received are closed
+    for mut other in server.join().unwrap() {
+        other
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(other.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+}
+
+#[test]
+fn hooks() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        let sequence = message.header.sequence.into();
+        let data = [MessageAttr::ObjId(0x100), MessageAttr::Data(&[])];
+        vec![
+            send_fd(&mut server, MessageType::DATA, sequence, data),
+            send_fd(
+                &mut server,
+                MessageType::STATUS,
+                sequence,
+                [MessageAttr::Status(0)],
+            ),
+        ]
+    });
+
+    // The DATA reply is dropped, and the STATUS fails
+    let mut connection = Connection::new(client).unwrap();
+    connection.on_receive(|message| match message.header.message {
+        MessageType::DATA => Ok(Intercept::Drop),
+        _ => Err(Error::InvalidData("injected")),
+    });
+    assert!(matches!(
+        connection.invoke_fd(0x100, "exec", &[], None, |_| {}),
+        Err(Error::InvalidData("injected"))
+    ));
+
+    for mut other in server.join().unwrap() {
+        other
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(other.read(&mut [0u8; 1]).unwrap(), 0);
+    }
+}
@@ -0,0 +1,35 @@
+use std::os::unix::io::{AsFd, AsRawFd};
+use std::sync::mpsc::channel;
+use ubus::*;
+
+fn readable(fd: i32, timeout_ms: i32) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+    assert!(ready >= 0);
+    ready == 1 && pollfd.revents & libc::POLLIN != 0
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut listener = broker.connect().unwrap();
+    let (tx, events) = channel();
+    let handler = listener
+        .event_handler(move |id, _data| tx.send(id.to_string()).unwrap())
+        .unwrap();
+    listener.register_event(&handler, "test.*").unwrap();
+    assert_eq!(listener.as_fd().as_raw_fd(), listener.as_raw_fd());
+
+    // Nothing to read until an event is sent
+    assert!(!readable(listener.as_raw_fd(), 0));
+
+    let mut sender = broker.connect().unwrap();
+    sender.send_event("test.event", &[]).unwrap();
+    assert!(readable(listener.as_raw_fd(), 1000));
+    listener.handle_next_message().unwrap();
+    assert_eq!(events.try_recv().unwrap(), "test.event");
+}
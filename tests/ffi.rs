@@ -0,0 +1,109 @@
+#![cfg(feature = "ffi")]
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::os::unix::net::UnixListener;
+use std::ptr;
+use ubus::ffi::*;
+use ubus::*;
+
+extern "C" fn on_data(context: *mut c_void, data: *const u8, len: usize) {
+    let replies = unsafe { &mut *(context as *mut Vec<Vec<u8>>) };
+    replies.push(unsafe { std::slice::from_raw_parts(data, len) }.to_vec());
+}
+
+extern "C" fn on_event(context: *mut c_void, id: *const c_char, _data: *const u8, _len: usize) {
+    let events = unsafe { &mut *(context as *mut Vec<String>) };
+    events.push(unsafe { CStr::from_ptr(id) }.to_str().unwrap().into());
+}
+
+#[test]
+fn test() {
+    let socket = std::env::temp_dir().join(format!("ubus-ffi-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("test", |method, _, reply| match method {
+            "ping" => {
+                reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+                0
+            }
+            _ => 3,
+        })
+        .unwrap();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+    std::thread::spawn(move || broker.run(listener));
+
+    let path = CString::new(socket.to_str().unwrap()).unwrap();
+    let missing = CString::new("/nonexistent/ubus.sock").unwrap();
+    unsafe {
+        assert!(ubus_rs_connect(missing.as_ptr()).is_null());
+        let connection = ubus_rs_connect(path.as_ptr());
+        assert!(!connection.is_null());
+        assert!(ubus_rs_fd(connection) >= 0);
+
+        let mut id = 0;
+        let name = CString::new("test").unwrap();
+        assert_eq!(ubus_rs_lookup_id(connection, name.as_ptr(), &mut id), 0);
+        let name = CString::new("missing").unwrap();
+        assert_eq!(ubus_rs_lookup_id(connection, name.as_ptr(), &mut id), 4);
+
+        let mut replies: Vec<Vec<u8>> = Vec::new();
+        let context = &mut replies as *mut _ as *mut c_void;
+        let method = CString::new("ping").unwrap();
+        let status = ubus_rs_invoke(
+            connection,
+            id,
+            method.as_ptr(),
+            ptr::null(),
+            0,
+            Some(on_data),
+            context,
+        );
+        assert_eq!(status, 0);
+        assert_eq!(replies.len(), 1);
+        let method = CString::new("other").unwrap();
+        let status = ubus_rs_invoke(
+            connection,
+            id,
+            method.as_ptr(),
+            ptr::null(),
+            0,
+            None,
+            context,
+        );
+        assert_eq!(status, 3);
+        assert_eq!(
+            ubus_rs_invoke(connection, id, ptr::null(), ptr::null(), 0, None, context),
+            2
+        );
+
+        let mut events: Vec<String> = Vec::new();
+        let pattern = CString::new("test.*").unwrap();
+        let context = &mut events as *mut _ as *mut c_void;
+        assert_eq!(
+            ubus_rs_register_event(connection, pattern.as_ptr(), on_event, context),
+            0
+        );
+        // Sent from a second connection, as a connection's own events arrive while it waits
+        // for the send to be acknowledged
+        let sender = ubus_rs_connect(path.as_ptr());
+        let event = CString::new("test.started").unwrap();
+        let mut buffer = [0u8; 64];
+        let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+        data.push_string("a", "b").unwrap();
+        let data = data.finish();
+        let status = ubus_rs_send_event(sender, event.as_ptr(), data.as_ptr(), data.len());
+        assert_eq!(status, 0);
+        ubus_rs_free(sender);
+        assert_eq!(ubus_rs_handle_next(connection), 0);
+        assert_eq!(events, ["test.started"]);
+
+        ubus_rs_free(connection);
+    }
+    std::fs::remove_file(&socket).unwrap();
+}
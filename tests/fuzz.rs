@@ -0,0 +1,57 @@
+#![cfg(feature = "fuzzing")]
+mod common;
+
+use arbitrary::{Arbitrary, Unstructured};
+use common::*;
+use ubus::*;
+
+#[test]
+fn test() {
+    // A well formed message, then every truncation and a few corruptions of it
+    let mut args = [0u8; 64];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut args);
+    builder
+        .push_table("table", |b| b.push_string("name", "value"))
+        .unwrap();
+    let args = builder.finish();
+    let message = message(
+        MessageType::INVOKE,
+        1,
+        [
+            MessageAttr::ObjId(0x100),
+            MessageAttr::Method("ping"),
+            MessageAttr::Data(args),
+        ],
+    );
+
+    for len in 0..=message.len() {
+        fuzz_parse_message(&message[..len]);
+    }
+    for i in 0..message.len() {
+        for byte in [0x00, 0x01, 0x7f, 0x80, 0xff] {
+            let mut corrupt = message.clone();
+            corrupt[i] = byte;
+            fuzz_parse_message(&corrupt);
+        }
+    }
+    for len in 0..=args.len() {
+        fuzz_parse_blobmsg(&args[..len]);
+    }
+    fuzz_parse_blobmsg(&[0xff; 32]);
+
+    // Structures generated from arbitrary bytes build into messages the parser accepts or rejects
+    let seed: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+    let mut u = Unstructured::new(&seed);
+    while !u.is_empty() {
+        let header = MessageHeader::arbitrary(&mut u).unwrap();
+        let attrs: Vec<MessageAttr> = u.arbitrary().unwrap();
+        let mut buffer = [0u8; 1024];
+        let mut builder = MessageBuilder::new(&mut buffer, header).unwrap();
+        for attr in attrs {
+            if builder.put(attr).is_err() {
+                break;
+            }
+        }
+        fuzz_parse_message(builder.finish());
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::*;
+use ubus::*;
+
+#[test]
+fn test() {
+    assert_eq!(format!("{:?}", HexDump(&[])), "");
+    assert_eq!(
+        format!("{:?}", HexDump(b"\x00\x01abcdefghijklmnop~\x7f")),
+        "0000  00 01 61 62 63 64 65 66  67 68 69 6a 6b 6c 6d 6e  |..abcdefghijklmn|\n\
+         0010  6f 70 7e 7f                                       |op~.|"
+    );
+
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_string("key", "value").unwrap();
+    let blob = Blob::from_bytes(builder.finish()).unwrap();
+    // Plain `{:?}` is unchanged
+    assert_eq!(
+        format!("{:?}", blob),
+        "Blob { tag: BlobTag(id=3, len=18, extended), data: [118, 97, 108, 117, 101, 0], \
+         name: Some(\"key\"), raw_name: Some([107, 101, 121]) }"
+    );
+    assert_eq!(
+        format!("{:#?}", blob),
+        "Blob {
+    tag: BlobTag(id=3, len=18, extended),
+    name: Some(
+        \"key\",
+    ),
+}
+0000  76 61 6c 75 65 00                                 |value.|"
+    );
+}
+
+#[test]
+fn message() {
+    let header = header(MessageType::INVOKE, 7);
+    let bytes = build(
+        header,
+        [MessageAttr::ObjId(5), MessageAttr::Method("status")],
+    );
+    let message = Message {
+        header,
+        blob: Blob::from_bytes(&bytes[MessageHeader::SIZE..]).unwrap(),
+        fd: None,
+    };
+    assert_eq!(
+        format!("{:?}", message),
+        "Message(INVOKE seq=7 peer=00000100, size=20)"
+    );
+    assert_eq!(
+        format!("{:#?}", message),
+        "Message {
+    message: INVOKE,
+    sequence: 7,
+    peer: 00000100,
+    size: 20,
+    fd: None,
+    attrs: [
+        ObjId(5),
+        Method(\"status\"),
+    ],
+}
+0000  03 00 00 08 00 00 00 05  04 00 00 0b 73 74 61 74  |............stat|
+0010  75 73 00 00                                       |us..|"
+    );
+}
@@ -0,0 +1,63 @@
+use std::sync::{Arc, Mutex};
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("echo", |_, _, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "hi").unwrap();
+            0
+        })
+        .unwrap();
+    let id = object.id();
+    std::thread::spawn(move || while service.handle_next_message().is_ok() {});
+
+    let mut client = broker.connect().unwrap();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let sent = log.clone();
+    client.on_send(move |header, data| {
+        let len = data.len();
+        sent.lock()
+            .unwrap()
+            .push(format!("send {:?} {}", header.message, len > 0));
+        Ok(Intercept::Pass)
+    });
+    let received = log.clone();
+    let drop_data = Arc::new(Mutex::new(false));
+    let dropping = drop_data.clone();
+    client.on_receive(move |message| {
+        received
+            .lock()
+            .unwrap()
+            .push(format!("recv {:?}", message.header.message));
+        if *dropping.lock().unwrap() && message.header.message == MessageType::DATA {
+            return Ok(Intercept::Drop);
+        }
+        Ok(Intercept::Pass)
+    });
+
+    let mut replies = 0;
+    client.invoke(id, "call", &[], |_| replies += 1).unwrap();
+    assert_eq!(replies, 1);
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["send INVOKE true", "recv DATA", "recv STATUS"]
+    );
+
+    // Dropped replies are never seen by the call
+    *drop_data.lock().unwrap() = true;
+    client.invoke(id, "call", &[], |_| replies += 1).unwrap();
+    assert_eq!(replies, 1);
+
+    // Failing sends never reach the bus
+    client.on_send(|header, _| match header.message {
+        MessageType::INVOKE => Err(Error::InvalidData("injected")),
+        _ => Ok(Intercept::Pass),
+    });
+    assert!(matches!(
+        client.invoke(id, "call", &[], |_| {}),
+        Err(Error::InvalidData("injected"))
+    ));
+}
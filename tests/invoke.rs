@@ -1,6 +1,5 @@
 use std::io::{Read, Write};
 use std::os::unix::net::UnixStream;
-use std::{print, println};
 use ubus::*;
 
 #[test]
@@ -11,7 +10,7 @@ fn test() {
         server.write_all(TEST_HELLO).unwrap();
         let mut command = [0u8; TEST_TX.len()];
         server.read_exact(&mut command).unwrap();
-        assert_eq!(&command[..], &TEST_TX[..]);
+        assert_eq!(&command[..], TEST_TX);
         for i in TEST_RX {
             server.write_all(i).unwrap();
         }
@@ -0,0 +1,68 @@
+use std::convert::TryFrom;
+use ubus::*;
+
+#[derive(Debug, PartialEq)]
+struct Info {
+    uptime: i32,
+    hostname: String,
+}
+
+impl TryFrom<BlobMsg<'_>> for Info {
+    type Error = ();
+    fn try_from(msg: BlobMsg) -> Result<Self, ()> {
+        let table = match msg.data {
+            BlobMsgData::Table(table) => table,
+            _ => return Err(()),
+        };
+        let (mut uptime, mut hostname) = (None, None);
+        for value in table {
+            match (value.name, value.data) {
+                (Some("uptime"), BlobMsgData::Int32(v)) => uptime = Some(v),
+                (Some("hostname"), BlobMsgData::String(v)) => hostname = Some(v.to_string()),
+                _ => {}
+            }
+        }
+        Ok(Info {
+            uptime: uptime.ok_or(())?,
+            hostname: hostname.ok_or(())?,
+        })
+    }
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("system", |method, _args, reply| {
+            if method == "info" {
+                reply
+                    .push_named_u32(BlobMsgType::INT32.value(), "uptime", 42)
+                    .unwrap();
+                reply
+                    .push_named_str(BlobMsgType::STRING.value(), "hostname", "OpenWrt")
+                    .unwrap();
+            }
+            0
+        })
+        .unwrap();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+
+    let mut client = broker.connect().unwrap();
+    let system = client.object_id("system").unwrap();
+    let info: Info = client.invoke_as(system, "info", &[]).unwrap();
+    assert_eq!(
+        info,
+        Info {
+            uptime: 42,
+            hostname: "OpenWrt".into()
+        }
+    );
+
+    // No reply data, so nothing to convert
+    let result: Result<Info, _> = client.invoke_as(system, "other", &[]);
+    assert!(matches!(result, Err(Error::InvalidData(_))));
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::*;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    // Answers with two DATA replies, as objects using ubus_send_reply repeatedly do
+    std::thread::spawn(move || {
+        server
+            .write_all(&message(MessageType::HELLO, 0, []))
+            .unwrap();
+        let sequence = read_request(&mut server).unwrap();
+
+        let first = table(|b| {
+            b.push_string("hostname", "OpenWrt")?;
+            b.push_int32("uptime", 1)
+        });
+        let second = table(|b| {
+            b.push_int32("uptime", 2)?;
+            b.push_table("memory", |b| b.push_int64("free", 1 << 33))
+        });
+        for data in [first, second] {
+            let attrs = [MessageAttr::ObjId(0x100), MessageAttr::Data(&data)];
+            server
+                .write_all(&message(MessageType::DATA, sequence, attrs))
+                .unwrap();
+        }
+        let attrs = [MessageAttr::Status(0), MessageAttr::ObjId(0x100)];
+        server
+            .write_all(&message(MessageType::STATUS, sequence, attrs))
+            .unwrap();
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+    let result = connection.invoke_collect(0x100, "info", &[]).unwrap();
+
+    let keys: Vec<_> = result.keys().map(String::as_str).collect();
+    assert_eq!(keys, ["hostname", "memory", "uptime"]);
+    assert_eq!(result["uptime"], BlobMsgValue::Int64(2));
+    assert_eq!(
+        result["hostname"],
+        BlobMsgValue::String("OpenWrt".to_string())
+    );
+    match &result["memory"] {
+        BlobMsgValue::Table(memory) => assert_eq!(memory["free"], BlobMsgValue::Int64(1 << 33)),
+        other => panic!("Expected table, got {:?}", other),
+    }
+}
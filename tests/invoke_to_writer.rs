@@ -0,0 +1,91 @@
+mod common;
+
+use common::*;
+use std::io::{self, Write};
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+/// Answer each request with two DATA replies
+fn serve(mut server: UnixStream) {
+    server
+        .write_all(&message(MessageType::HELLO, 0, []))
+        .unwrap();
+    while let Some(sequence) = read_request(&mut server) {
+        let first = table(|b| {
+            b.push_string("hostname", "Open\"Wrt\"\n")?;
+            b.push_bool("up", true)?;
+            b.push_double("load", 0.5)?;
+            b.push_null("none")?;
+            b.push_array("dns", |b| {
+                b.push_string("", "192.168.1.1")?;
+                b.push_int16("", 53)
+            })
+        });
+        let second = table(|b| b.push_table("memory", |b| b.push_int64("free", 1 << 33)));
+        for data in [first, second] {
+            let attrs = [MessageAttr::ObjId(0x100), MessageAttr::Data(&data)];
+            server
+                .write_all(&message(MessageType::DATA, sequence, attrs))
+                .unwrap();
+        }
+        let attrs = [MessageAttr::Status(0), MessageAttr::ObjId(0x100)];
+        server
+            .write_all(&message(MessageType::STATUS, sequence, attrs))
+            .unwrap();
+    }
+}
+
+/// A sink which fails after `limit` bytes
+struct Limited {
+    written: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for Limited {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.written.len() + data.len() > self.limit {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+        }
+        self.written.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test() {
+    let (client, server) = UnixStream::pair().unwrap();
+    std::thread::spawn(move || serve(server));
+    let mut connection = Connection::new(client).unwrap();
+
+    let mut out = Vec::new();
+    connection
+        .invoke_to_writer(0x100, "info", &[], &mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            r#"{"hostname":"Open\"Wrt\"\n","up":true,"load":0.5,"none":null,"#,
+            r#""dns":["192.168.1.1",53]}"#,
+            "\n",
+            r#"{"memory":{"free":8589934592}}"#,
+            "\n",
+        )
+    );
+
+    // Write errors are returned once the call is finished, leaving the connection usable
+    let mut out = Limited {
+        written: Vec::new(),
+        limit: 16,
+    };
+    let result = connection.invoke_to_writer(0x100, "info", &[], &mut out);
+    assert!(matches!(result, Err(Error::IO(_))));
+    let mut out = Vec::new();
+    connection
+        .invoke_to_writer(0x100, "info", &[], &mut out)
+        .unwrap();
+    assert_eq!(out.iter().filter(|&&b| b == b'\n').count(), 2);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::*;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+fn reply(server: &mut UnixStream, sequence: u16, count: i32) {
+    let mut buffer = [0u8; 64];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_int32("count", count).unwrap();
+    let attrs = [
+        MessageAttr::ObjId(0x100),
+        MessageAttr::Data(builder.finish()),
+    ];
+    server
+        .write_all(&message(MessageType::DATA, sequence, attrs))
+        .unwrap();
+}
+
+fn count(data: BlobIter<BlobMsg>) -> Option<i32> {
+    data.into_iter().find_map(|item| match item.data {
+        BlobMsgData::Int32(count) => Some(count),
+        _ => None,
+    })
+}
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    std::thread::spawn(move || {
+        server
+            .write_all(&message(MessageType::HELLO, 0, []))
+            .unwrap();
+
+        // A chatty object, which sends several replies before finishing
+        let sequence = read_request(&mut server).unwrap();
+        for count in 0..5 {
+            reply(&mut server, sequence, count);
+        }
+        let status = || [MessageAttr::Status(0), MessageAttr::ObjId(0x100)];
+        server
+            .write_all(&message(MessageType::STATUS, sequence, status()))
+            .unwrap();
+
+        let second = read_request(&mut server).unwrap();
+        reply(&mut server, second, 100);
+        server
+            .write_all(&message(MessageType::STATUS, second, status()))
+            .unwrap();
+
+        // Success without data
+        let third = read_request(&mut server).unwrap();
+        server
+            .write_all(&message(MessageType::STATUS, third, status()))
+            .unwrap();
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+    let mut counts = Vec::new();
+    let summary = connection
+        .invoke_until(0x100, "watch", &[], |data| {
+            counts.extend(count(data));
+            if counts.len() == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+    assert_eq!(counts, [0, 1]);
+    // Stopped before the status
+    assert_eq!((summary.messages, summary.status), (2, None));
+
+    // The rest of the cancelled call's replies are dropped, leaving the connection usable
+    let mut counts = Vec::new();
+    let summary = connection
+        .invoke(0x100, "info", &[], |data| counts.extend(count(data)))
+        .unwrap();
+    assert_eq!(counts, [100]);
+    // The table holding "count" (16 bytes)
+    assert_eq!(
+        summary,
+        InvokeSummary {
+            messages: 1,
+            bytes: 16,
+            status: Some(0),
+        }
+    );
+    assert!(summary.has_data());
+
+    let summary = connection.invoke(0x100, "ping", &[], |_| {}).unwrap();
+    assert_eq!(summary.status, Some(0));
+    assert!(!summary.has_data());
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "json")]
+use ubus::*;
+
+#[test]
+fn test() {
+    let json = br#" {
+        "ssid": "foo",
+        "quoted": "a \"b\" \u00e9",
+        "channel": 11,
+        "bytes": -1099511627776,
+        "load": 0.5e1,
+        "up": true,
+        "none": null,
+        "keys": ["a", {"n": false}, []]
+    } "#;
+    let mut scratch = [0u8; 16];
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    json_to_blobmsg(json, &mut scratch, &mut builder).unwrap();
+
+    let mut expected = [0u8; 256];
+    let mut eb = BlobMsgBuilder::from_bytes(&mut expected);
+    eb.push_string("ssid", "foo").unwrap();
+    eb.push_string("quoted", "a \"b\" \u{e9}").unwrap();
+    eb.push_int32("channel", 11).unwrap();
+    eb.push_int64("bytes", -(1 << 40)).unwrap();
+    eb.push_double("load", 5.0).unwrap();
+    eb.push_bool("up", true).unwrap();
+    eb.push_null("none").unwrap();
+    eb.push_array("keys", |b| {
+        b.push_string("", "a")?;
+        b.push_table("", |b| b.push_bool("n", false))?;
+        b.push_array("", |_| Ok(()))
+    })
+    .unwrap();
+    assert_eq!(builder.finish(), eb.finish());
+
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    // Not an object, unterminated, trailing data, and escapes too long for the scratch buffer
+    assert!(json_to_blobmsg(b"[1]", &mut scratch, &mut builder).is_err());
+    assert!(json_to_blobmsg(br#"{"a": 1"#, &mut scratch, &mut builder).is_err());
+    assert!(json_to_blobmsg(br#"{"a": tru}"#, &mut scratch, &mut builder).is_err());
+    assert!(json_to_blobmsg(b"{} {}", &mut scratch, &mut builder).is_err());
+    let long = br#"{"a": "\"0123456789abcdef"}"#;
+    assert!(json_to_blobmsg(long, &mut scratch, &mut builder).is_err());
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "jsonrpc")]
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use ubus::*;
+
+/// Answer JSON-RPC requests over HTTP like uhttpd would, returning the requests received
+fn serve(listener: TcpListener, count: usize) -> Vec<Value> {
+    let mut requests = Vec::new();
+    for _ in 0..count {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            let line = line.to_ascii_lowercase();
+            if let Some(value) = line.strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).unwrap();
+        let request: Value = serde_json::from_slice(&body).unwrap();
+
+        let result = match (&request["method"], &request["params"][1]) {
+            (Value::String(m), _) if m == "list" => json!({
+                "system": { "board": {}, "reboot": { "delay": "number" } }
+            }),
+            (_, Value::String(path)) if path == "session" => {
+                json!([0, { "ubus_rpc_session": "0123456789abcdef0123456789abcdef" }])
+            }
+            (_, Value::String(path)) if path == "system" => {
+                json!([0, { "echo": request["params"][3], "big": 1u64 << 40 }])
+            }
+            _ => json!([4]),
+        };
+        let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+        let response = response.to_string();
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+        requests.push(request);
+    }
+    requests
+}
+
+#[test]
+fn test() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/ubus", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || serve(listener, 4));
+
+    let mut rpc = JsonRpc::new(&url);
+    assert_eq!(rpc.session(), ANONYMOUS_SESSION);
+    rpc.login("root", "secret").unwrap();
+    assert_eq!(rpc.session(), "0123456789abcdef0123456789abcdef");
+
+    let mut buffer = [0u8; 64];
+    let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+    args.push_string("name", "foo").unwrap();
+    args.push_bool("flag", true).unwrap();
+    let mut replies = Vec::new();
+    rpc.call("system", "board", args.finish(), |data| {
+        for value in data {
+            replies.push(format!("{}={:?}", value.name.unwrap(), value.data));
+        }
+    })
+    .unwrap();
+    assert_eq!(replies.len(), 2);
+    assert!(replies[1].starts_with("big=Int64"), "{:?}", replies);
+
+    let mut signatures = Vec::new();
+    rpc.list(
+        None,
+        |object| assert_eq!(object.path, "system"),
+        |signature| {
+            let args: Vec<_> = signature.args.map(|(name, _)| name.to_string()).collect();
+            signatures.push((signature.name.to_string(), args));
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        signatures,
+        [
+            ("board".to_string(), vec![]),
+            ("reboot".to_string(), vec!["delay".to_string()])
+        ]
+    );
+
+    assert!(matches!(
+        rpc.call("missing", "method", &[], |_| panic!()),
+        Err(Error::Status(4))
+    ));
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests[1]["params"][0], "0123456789abcdef0123456789abcdef");
+    assert_eq!(
+        requests[1]["params"][3],
+        json!({ "name": "foo", "flag": true })
+    );
+    assert_eq!(requests[2]["params"], json!(["*"]));
+}
@@ -0,0 +1,38 @@
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let (requester, mut reader) = broker.connect().unwrap().split().unwrap();
+    std::thread::spawn(move || reader.run());
+
+    let keepalive = requester.keepalive(Duration::from_millis(10), Duration::from_secs(1));
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!keepalive.is_broken());
+    assert!(matches!(
+        requester.object_id("missing"),
+        Err(Error::Status(4))
+    ));
+}
+
+#[test]
+fn unanswered() {
+    // A bus which says hello, then never answers anything
+    let (client, mut server) = UnixStream::pair().unwrap();
+    server
+        .write_all(&[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 4])
+        .unwrap();
+
+    let (requester, mut reader) = Connection::new(client).unwrap().split().unwrap();
+    let started = Instant::now();
+    let keepalive = requester.keepalive(Duration::from_millis(10), Duration::from_millis(50));
+
+    // The reader finds the connection shut down
+    assert!(matches!(reader.run(), Err(Error::IO(_))));
+    assert!(keepalive.is_broken());
+    assert!(started.elapsed() >= Duration::from_millis(60));
+    drop(server);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::*;
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let large = "x".repeat(3 * DEFAULT_BUFFER_SIZE);
+
+    let expected = large.clone();
+    std::thread::spawn(move || {
+        let mut buffer = vec![0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, None);
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        let seq = message.header.sequence.into();
+
+        let mut table = vec![0u8; 4 * DEFAULT_BUFFER_SIZE];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut table);
+        builder.push_string("large", &expected).unwrap();
+        let table = builder.finish();
+        send(
+            &mut server,
+            MessageType::DATA,
+            seq,
+            [MessageAttr::Data(table)],
+        );
+        send(
+            &mut server,
+            MessageType::STATUS,
+            seq,
+            [MessageAttr::Status(0)],
+        );
+    });
+
+    // Messages larger than the default buffer are received in full
+    let mut connection = Connection::new(client).unwrap();
+    let mut received = None;
+    connection
+        .invoke(0x1234, "large", &[], |reply| {
+            for value in reply {
+                if let BlobMsgData::String(s) = value.data {
+                    received = Some(s.len());
+                }
+            }
+        })
+        .unwrap();
+    assert_eq!(received, Some(large.len()));
+}
+
+#[test]
+fn args() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let large = "x".repeat(2000);
+    let args = table(|b| b.push_string("large", &large));
+
+    let expected = args.clone();
+    let server = std::thread::spawn(move || {
+        let mut buffer = vec![0u8; DEFAULT_BUFFER_SIZE];
+        send(&mut server, MessageType::HELLO, 0, None);
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        let seq = message.header.sequence.into();
+        let data = BlobIter::<MessageAttr>::new(message.blob.data).find_map(|attr| match attr {
+            MessageAttr::Data(data) => Some(data.to_vec()),
+            _ => None,
+        });
+        assert_eq!(data, Some(expected));
+        send(
+            &mut server,
+            MessageType::STATUS,
+            seq,
+            [MessageAttr::Status(0)],
+        );
+    });
+
+    // Requests aren't limited to a fixed size buffer either
+    let mut connection = Connection::new(client).unwrap();
+    connection.invoke(0x1234, "large", &args, |_| {}).unwrap();
+    server.join().unwrap();
+}
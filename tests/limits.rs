@@ -0,0 +1,42 @@
+mod common;
+
+use common::*;
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let _object = service.add_object("test", |_, _, _| 0).unwrap();
+    let mut connection = broker.connect().unwrap();
+
+    let long = "x".repeat(MAX_PATH_LEN + 1);
+    assert!(matches!(
+        connection.object_id(&long),
+        Err(Error::InvalidData("Object path too long"))
+    ));
+    assert!(matches!(
+        connection.object_id("te\0st"),
+        Err(Error::InvalidData("String attribute contains NUL"))
+    ));
+    let id = connection.object_id("test").unwrap();
+
+    let long = "x".repeat(MAX_METHOD_LEN + 1);
+    assert!(matches!(
+        connection.invoke(id, &long, &[], |_| {}),
+        Err(Error::InvalidData("Method name too long"))
+    ));
+    assert!(matches!(
+        connection.invoke(id, "a\0b", &[], |_| {}),
+        Err(Error::InvalidData("String attribute contains NUL"))
+    ));
+
+    // Nothing was sent, so the connection is still in step with the bus
+    assert_eq!(connection.object_id("test").unwrap(), id);
+
+    let mut buffer = [0u8; 64];
+    let header = header(MessageType::INVOKE, 1);
+    let mut builder = MessageBuilder::new(&mut buffer, header).unwrap();
+    assert!(builder.put(MessageAttr::User("root\0")).is_err());
+    builder.put(MessageAttr::Method(&"x".repeat(16))).unwrap();
+}
@@ -1,10 +1,10 @@
+use std::cell::RefCell;
 use std::io::{Read, Write};
 use std::os::unix::net::UnixStream;
 use std::{print, println};
 use ubus::*;
 
-#[test]
-fn test() {
+fn connect() -> Connection<UnixStream> {
     let (client, mut server) = UnixStream::pair().unwrap();
 
     std::thread::spawn(move || {
@@ -17,7 +17,12 @@ fn test() {
         }
     });
 
-    let mut connection = Connection::new(client).unwrap();
+    Connection::new(client).unwrap()
+}
+
+#[test]
+fn test() {
+    let mut connection = connect();
 
     connection
         .lookup(
@@ -35,6 +40,39 @@ fn test() {
         .unwrap();
 }
 
+#[test]
+fn lookup_iter() {
+    // The same objects and methods as through the callbacks
+    let expected = RefCell::new(Vec::new());
+    let mut connection = connect();
+    connection
+        .lookup(
+            |obj| expected.borrow_mut().push(format!("{:?}", obj)),
+            |sig| {
+                let args: Vec<_> = sig
+                    .args
+                    .map(|(name, ty)| format!("{}: {:?}", name, ty))
+                    .collect();
+                expected
+                    .borrow_mut()
+                    .push(format!("{}({})", sig.name, args.join(", ")));
+            },
+        )
+        .unwrap();
+
+    let mut found = Vec::new();
+    let mut connection = connect();
+    let mut lookup = connection.lookup_iter(None).unwrap();
+    while let Some(entry) = lookup.next().unwrap() {
+        found.push(format!("{:?}", entry.object));
+        found.extend(entry.methods().map(|method| format!("{:?}", method)));
+    }
+    assert!(lookup.next().unwrap().is_none());
+    let expected = expected.into_inner();
+    assert!(expected.len() > 10);
+    assert_eq!(found, expected);
+}
+
 // Data dumped from `ubus list`
 const TEST_HELLO: &[u8] = &[
     0x00, 0x00, 0x00, 0x00, 0x2e, 0xb8, 0x63, 0xdb, 0x00, 0x00, 0x00, 0x04,
@@ -0,0 +1,23 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let _first = service.add_object("first", |_, _, _| 0).unwrap();
+    let second = service.add_object("second", |_, _, _| 0).unwrap();
+
+    let mut client = broker.connect().unwrap();
+    let mut found = Vec::new();
+    client
+        .lookup_id(second.id(), |object| {
+            found.push((object.path.to_string(), object.id))
+        })
+        .unwrap();
+    assert_eq!(found, [("second".to_string(), second.id())]);
+
+    assert!(matches!(
+        client.lookup_id(0x7fff_0000, |_| panic!()),
+        Err(Error::Status(4))
+    ));
+}
@@ -0,0 +1,30 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let objects: Vec<_> = ["a.one", "a.two", "a.three", "b"]
+        .iter()
+        .map(|path| service.add_object(path, |_, _, _| 0).unwrap())
+        .collect();
+
+    let mut connection = broker.connect().unwrap();
+    let mut paths = Vec::new();
+    let mut lookup = connection.lookup_iter(Some("a.*")).unwrap();
+    while let Some(entry) = lookup.next().unwrap() {
+        paths.push(entry.object.path.to_string());
+    }
+    paths.sort();
+    assert_eq!(paths, ["a.one", "a.three", "a.two"]);
+
+    // Stopping early leaves the rest of the replies to be dropped
+    let mut lookup = connection.lookup_iter(None).unwrap();
+    assert!(lookup.next().unwrap().is_some());
+    assert_eq!(connection.object_id("b").unwrap(), objects[3].id());
+
+    // Errors come back from `next`
+    let mut lookup = connection.lookup_iter(Some("missing")).unwrap();
+    assert!(matches!(lookup.next(), Err(Error::Status(4))));
+    assert!(lookup.next().unwrap().is_none());
+}
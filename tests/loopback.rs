@@ -0,0 +1,43 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    // Raw pair: bytes written at one end are read at the other, in order
+    let (mut a, mut b) = LoopbackIo::pair_with_capacity(4);
+    let writer = std::thread::spawn(move || a.put(b"hello world").unwrap());
+    let mut data = [0u8; 11];
+    b.get(&mut data).unwrap();
+    assert_eq!(&data, b"hello world");
+    writer.join().unwrap();
+    // The other end was dropped
+    assert!(matches!(b.get(&mut data), Err(Error::IO(_))));
+    assert!(matches!(b.put(b"x"), Err(Error::IO(_))));
+
+    // Through the broker
+    let broker = Broker::new();
+    let mut service = broker.connect_loopback().unwrap();
+    let object = service
+        .add_object("test", |_method, _args, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+
+    let mut client = broker.connect_loopback().unwrap();
+    let obj = client.object_id("test").unwrap();
+    let mut results = Vec::new();
+    client
+        .invoke(obj, "ping", &[], |reply| {
+            for value in reply {
+                if let BlobMsgData::String(s) = value.data {
+                    results.push(s.to_string());
+                }
+            }
+        })
+        .unwrap();
+    assert_eq!(results, ["pong"]);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::*;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+/// The message `ty` with `data`
+fn with_data(ty: MessageType, data: &[u8]) -> Vec<u8> {
+    message(ty, 1, [MessageAttr::ObjId(0x100), MessageAttr::Data(data)])
+}
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let big = vec![0u8; 100_000];
+    let small = [0u8; 8];
+    std::thread::spawn(move || {
+        server
+            .write_all(&with_data(MessageType::HELLO, &[]))
+            .unwrap();
+        for data in [&big[..], &small, &big, &small] {
+            server
+                .write_all(&with_data(MessageType::DATA, data))
+                .unwrap();
+        }
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+    assert_eq!(connection.max_message_size(), None);
+    connection.set_max_message_size(Some(1024));
+    assert_eq!(connection.max_message_size(), Some(1024));
+
+    // The oversized message is skipped over, leaving the next one to be received
+    assert!(matches!(
+        connection.next_message(),
+        Err(Error::InvalidData("Message larger than the maximum size"))
+    ));
+    let message = connection.next_message().unwrap();
+    assert_eq!(message.header.message, MessageType::DATA);
+
+    connection.set_max_message_size(None);
+    let message = connection.next_message().unwrap();
+    assert!(message.blob.data.len() > 100_000);
+    connection.next_message().unwrap();
+}
+
+#[test]
+fn buffer() {
+    let (mut client, mut server) = UnixStream::pair().unwrap();
+    std::thread::spawn(move || {
+        server
+            .write_all(&with_data(MessageType::DATA, &[0; 1000]))
+            .unwrap();
+        for _ in 0..2 {
+            server
+                .write_all(&with_data(MessageType::DATA, &[0; 8]))
+                .unwrap();
+        }
+    });
+
+    // Messages too large for a fixed buffer are skipped over too
+    let mut buffer = [0u8; 256];
+    assert!(matches!(
+        Message::from_io(&mut client, &mut buffer),
+        Err(Error::InvalidData("Message too large for receive buffer"))
+    ));
+    assert!(matches!(
+        Message::from_io_limit(&mut client, &mut buffer, 16),
+        Err(Error::InvalidData("Message larger than the maximum size"))
+    ));
+    let message = Message::from_io(&mut client, &mut buffer).unwrap();
+    assert_eq!(message.header.message, MessageType::DATA);
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::*;
+use ubus::*;
+
+/// The attributes of a MONITOR message from ubusd, describing a message with `attrs`
+fn monitor(ty: MessageType, send: bool, attrs: &[u8]) -> Vec<u8> {
+    let mut buffer = [0u8; 512];
+    let mut builder = BlobBuilder::from_bytes(&mut buffer);
+    builder
+        .push_u32(MonitorAttrId::CLIENT.value(), 0x1b0d2f9e)
+        .unwrap();
+    builder.push_u32(MonitorAttrId::PEER.value(), 0).unwrap();
+    builder
+        .push_bool(MonitorAttrId::SEND.value(), send)
+        .unwrap();
+    builder.push_u32(MonitorAttrId::SEQ.value(), 42).unwrap();
+    builder
+        .push_u32(MonitorAttrId::TYPE.value(), ty.value().into())
+        .unwrap();
+    builder
+        .push_bytes(MonitorAttrId::DATA.value(), attrs)
+        .unwrap();
+    let len = builder.len();
+    buffer[..len].to_vec()
+}
+
+fn invoke_attrs() -> Vec<u8> {
+    let mut args = [0u8; 128];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut args);
+    builder.push_string("name", "a\"b").unwrap();
+    builder.push_int32("n", 5).unwrap();
+    builder.push_bool("on", true).unwrap();
+    let args = builder.finish();
+
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobBuilder::from_bytes(&mut buffer);
+    // Out of order, with an OBJID too short for its type
+    builder
+        .push_str(MessageAttrId::METHOD.value(), "status")
+        .unwrap();
+    builder.push_u32(MessageAttrId::OBJID.value(), 1).unwrap();
+    builder
+        .push_u32(MessageAttrId::OBJID.value(), -123i32 as u32)
+        .unwrap();
+    builder
+        .push_bytes(MessageAttrId::OBJID.value(), &[1, 2])
+        .unwrap();
+    builder
+        .push_bytes(MessageAttrId::DATA.value(), args)
+        .unwrap();
+    builder.push_u32(MessageAttrId::TARGET.value(), 7).unwrap();
+    builder
+        .push_bool(MessageAttrId::NO_REPLY.value(), true)
+        .unwrap();
+    let len = builder.len();
+    buffer[..len].to_vec()
+}
+
+#[test]
+fn test() {
+    let data = monitor(MessageType::INVOKE, true, &invoke_attrs());
+    let message = MonitorMessage::parse(&data).unwrap();
+    assert_eq!(message.client, 0x1b0d2f9e);
+    assert_eq!(message.sequence, 42);
+    assert_eq!(message.message, MessageType::INVOKE);
+    assert_eq!(
+        message.to_string(),
+        r#"-> 1b0d2f9e #00000000         invoke: {"objid":-123,"method":"status","data":{"name":"a\"b","n":5,"on":true},"no_reply":true}"#
+    );
+
+    let data = monitor(MessageType::MONITOR, false, &[]);
+    assert_eq!(
+        MonitorMessage::parse(&data).unwrap().to_string(),
+        "<- 1b0d2f9e #00000000             17: {}"
+    );
+}
+
+#[test]
+fn types() {
+    assert_eq!(
+        MonitorType(MessageType::ADD_OBJECT).to_string(),
+        "add_object"
+    );
+    assert_eq!(format!("{:>8}", MonitorType(MessageType::PING)), "    ping");
+    assert_eq!(
+        format!("{:>4}", MonitorType(MessageType::from(0x20))),
+        "  32"
+    );
+}
+
+#[test]
+fn invalid() {
+    let data = monitor(MessageType::INVOKE, true, &[]);
+    // Without DATA
+    let len = data.len() - BlobTag::SIZE;
+    assert!(matches!(
+        MonitorMessage::parse(&data[..len]),
+        Err(Error::InvalidData(_))
+    ));
+}
+
+#[test]
+fn message() {
+    let header = header(MessageType::STATUS, 9);
+    let bytes = build(header, [MessageAttr::Status(-2), MessageAttr::ObjId(0x100)]);
+    let message = Message {
+        header,
+        blob: Blob::from_bytes(&bytes[MessageHeader::SIZE..]).unwrap(),
+        fd: None,
+    };
+    let monitored = MonitorMessage::new(0x200, true, &message);
+    assert_eq!(monitored.sequence, 9);
+    assert_eq!(
+        monitored.to_string(),
+        r#"-> 00000200 #00000100         status: {"status":-2,"objid":256}"#
+    );
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "nb")]
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+/// A non-blocking socket which moves only a few bytes at a time, and would block every other
+/// call, so messages arrive (and leave) in pieces
+struct Trickle {
+    stream: UnixStream,
+    reads: usize,
+    writes: usize,
+}
+
+fn nb_error(e: std::io::Error) -> nb::Error<Error<std::io::Error>> {
+    match e.kind() {
+        ErrorKind::WouldBlock => nb::Error::WouldBlock,
+        _ => nb::Error::Other(Error::IO(e)),
+    }
+}
+
+impl NbIO for Trickle {
+    type Error = std::io::Error;
+    fn write(&mut self, data: &[u8]) -> nb::Result<usize, Error<std::io::Error>> {
+        self.writes += 1;
+        if self.writes.is_multiple_of(2) {
+            return Err(nb::Error::WouldBlock);
+        }
+        let len = data.len().min(5);
+        self.stream.write(&data[..len]).map_err(nb_error)
+    }
+    fn read(&mut self, data: &mut [u8]) -> nb::Result<usize, Error<std::io::Error>> {
+        self.reads += 1;
+        if self.reads.is_multiple_of(2) {
+            return Err(nb::Error::WouldBlock);
+        }
+        let len = data.len().min(3);
+        self.stream.read(&mut data[..len]).map_err(nb_error)
+    }
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+
+    let (client, server) = UnixStream::pair().unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || serving.serve(server));
+    client.set_nonblocking(true).unwrap();
+    let io = Trickle {
+        stream: client,
+        reads: 0,
+        writes: 0,
+    };
+    let mut connection = NbConnection::new(io, [0u8; 1024], [0u8; 256]);
+
+    // A superloop: the lookup is sent once the hello arrives, then its replies collected
+    let mut lookup = None;
+    let mut found = None;
+    let mut would_block = 0;
+    let status = loop {
+        if connection.peer().is_some() && lookup.is_none() {
+            match connection.request(LookupRequest::path("test")) {
+                Ok(sequence) => lookup = Some(sequence),
+                Err(nb::Error::WouldBlock) => would_block += 1,
+                Err(nb::Error::Other(e)) => panic!("{:?}", e),
+            }
+        }
+        match connection.poll() {
+            Ok(EngineEvent::Hello { .. }) => {}
+            Ok(EngineEvent::Data { sequence, attrs }) => {
+                assert_eq!(Some(sequence), lookup);
+                for attr in attrs {
+                    if let MessageAttr::ObjId(id) = attr {
+                        found = Some(id);
+                    }
+                }
+            }
+            Ok(EngineEvent::Done { sequence, status }) => {
+                assert_eq!(Some(sequence), lookup);
+                break status;
+            }
+            Ok(event) => panic!("Unexpected {:?}", event),
+            Err(nb::Error::WouldBlock) => would_block += 1,
+            Err(nb::Error::Other(e)) => panic!("{:?}", e),
+        }
+    };
+    assert_eq!(status, 0);
+    assert_eq!(found, Some(object.id()));
+    assert!(would_block > 0);
+}
@@ -0,0 +1,25 @@
+use std::sync::mpsc::channel;
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let (tx, calls) = channel();
+    let object = service
+        .add_object("log", move |method, _args, _reply| {
+            tx.send(method.to_string()).unwrap();
+            0
+        })
+        .unwrap();
+    let log = object.id();
+
+    let mut client = broker.connect().unwrap();
+    client.invoke_noreply(log, "rotate", &[]).unwrap();
+
+    // The call arrives, and no reply is sent back for it
+    service.handle_next_message().unwrap();
+    assert_eq!(calls.try_recv().unwrap(), "rotate");
+    assert_eq!(client.object_id("log").unwrap(), log);
+    drop(object);
+}
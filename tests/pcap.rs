@@ -0,0 +1,64 @@
+use std::convert::TryInto;
+use ubus::*;
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Split a pcapng capture into its blocks (type and body)
+fn blocks(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut blocks = Vec::new();
+    while !data.is_empty() {
+        let len = u32_at(data, 4) as usize;
+        assert_eq!(u32_at(data, len - 4) as usize, len);
+        blocks.push((u32_at(data, 0), &data[8..len - 4]));
+        data = &data[len..];
+    }
+    blocks
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let _object = service.add_object("test", |_, _, _| 0).unwrap();
+
+    let (client, server) = LoopbackIo::pair();
+    let serving = broker.clone();
+    std::thread::spawn(move || serving.serve_io(server.clone(), server));
+    let mut capture = Vec::new();
+    let pcap = PcapWriter::new(&mut capture).unwrap();
+    let mut connection = Connection::new(PcapIo::new(client, pcap)).unwrap();
+    connection.object_id("test").unwrap();
+    drop(connection);
+
+    let blocks = blocks(&capture);
+    assert_eq!(blocks[0].0, 0x0a0d_0d0a);
+    assert_eq!(u32_at(blocks[0].1, 0), 0x1a2b_3c4d);
+    assert_eq!(blocks[1].0, 1);
+    assert_eq!(&blocks[1].1[..2], &147u16.to_le_bytes());
+
+    // Hello, lookup, its reply and status: each a whole message, with its direction
+    let packets: Vec<_> = blocks[2..]
+        .iter()
+        .map(|(ty, body)| {
+            assert_eq!(*ty, 6);
+            let len = u32_at(body, 12) as usize;
+            let message = &body[20..20 + len];
+            let padded = len.div_ceil(4) * 4;
+            let flags = u32_at(body, 20 + padded + 4);
+            let tag = BlobTag::from_bytes(message[8..12].try_into().unwrap());
+            assert_eq!(tag.size() + 8, len);
+            (MessageType::from(message[1]), flags)
+        })
+        .collect();
+    assert_eq!(
+        packets,
+        [
+            (MessageType::HELLO, 1),
+            (MessageType::LOOKUP, 2),
+            (MessageType::DATA, 1),
+            (MessageType::STATUS, 1),
+        ]
+    );
+}
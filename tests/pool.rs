@@ -0,0 +1,146 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use ubus::*;
+
+/// Loopback transport which can be broken from outside, like a socket ubusd has closed
+struct FlakyIo {
+    io: LoopbackIo,
+    broken: Arc<AtomicBool>,
+    /// Break on the next receive instead, once a request has been sent
+    deaf: Arc<AtomicBool>,
+    /// Time out on the next receive, once a request has been sent
+    slow: Arc<AtomicBool>,
+}
+
+impl IO for FlakyIo {
+    type Error = std::io::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        if self.broken.swap(false, Ordering::SeqCst) {
+            return Err(Error::IO(std::io::ErrorKind::BrokenPipe.into()));
+        }
+        self.io.put(data)
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        if self.deaf.swap(false, Ordering::SeqCst) {
+            return Err(Error::IO(std::io::ErrorKind::ConnectionReset.into()));
+        }
+        if self.slow.swap(false, Ordering::SeqCst) {
+            return Err(Error::Timeout);
+        }
+        self.io.get(data)
+    }
+}
+
+/// Wait for `calls` to reach `expected`, returning the count
+///
+/// Calls sent on different connections can reach the service in either order, so the one which
+/// failed may be handled after the one which followed it.
+fn settled(calls: &AtomicUsize, expected: usize) -> usize {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while calls.load(Ordering::SeqCst) < expected && Instant::now() < deadline {
+        thread::yield_now();
+    }
+    calls.load(Ordering::SeqCst)
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let object = service
+        .add_object("echo", move |_method, _args, reply| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    let echo = object.id();
+    thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+
+    let broken = Arc::new(AtomicBool::new(false));
+    let deaf = Arc::new(AtomicBool::new(false));
+    let slow = Arc::new(AtomicBool::new(false));
+    let pool = {
+        let (broker, broken, deaf, slow) =
+            (broker.clone(), broken.clone(), deaf.clone(), slow.clone());
+        Pool::new(2, move || {
+            let (client, server) = LoopbackIo::pair();
+            let broker = broker.clone();
+            thread::spawn(move || broker.serve_io(server.clone(), server));
+            let (broken, deaf, slow) = (broken.clone(), deaf.clone(), slow.clone());
+            Connection::new(FlakyIo {
+                io: client,
+                broken,
+                deaf,
+                slow,
+            })
+        })
+    };
+    assert_eq!(pool.object_id("echo").unwrap(), echo);
+
+    let args: &[u8] = &[];
+
+    // Concurrent callers share at most two connections
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for _ in 0..10 {
+                    let mut results = Vec::new();
+                    pool.invoke(echo, "ping", args, |reply| {
+                        for value in reply {
+                            if let BlobMsgData::String(s) = value.data {
+                                results.push(s.to_string());
+                            }
+                        }
+                    })
+                    .unwrap();
+                    assert_eq!(results, ["pong"]);
+                }
+            });
+        }
+    });
+    assert_eq!(pool.open(), 2);
+
+    // A broken connection is replaced, and the call retried
+    broken.store(true, Ordering::SeqCst);
+    let mut replies = 0;
+    pool.invoke(echo, "ping", args, |_| replies += 1).unwrap();
+    assert_eq!(replies, 1);
+    assert_eq!(pool.open(), 1);
+
+    // But not once the call has been sent, as it may have been made
+    let before = calls.load(Ordering::SeqCst);
+    deaf.store(true, Ordering::SeqCst);
+    let result = pool.invoke(echo, "ping", args, |_| {});
+    assert!(matches!(result, Err(Error::IO(_))));
+    assert_eq!(pool.open(), 0);
+    pool.invoke(echo, "ping", args, |_| {}).unwrap();
+    assert_eq!(settled(&calls, before + 2), before + 2);
+
+    // Nor once it has timed out, and the connection its late reply would arrive on is closed
+    let before = calls.load(Ordering::SeqCst);
+    slow.store(true, Ordering::SeqCst);
+    let result = pool.invoke(echo, "ping", args, |_| {});
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert_eq!(pool.open(), 0);
+    pool.invoke(echo, "ping", args, |_| {}).unwrap();
+    assert_eq!(settled(&calls, before + 2), before + 2);
+
+    // Lookups are safe to repeat, so are retried after a timeout
+    slow.store(true, Ordering::SeqCst);
+    assert_eq!(pool.object_id("echo").unwrap(), echo);
+    assert_eq!(pool.open(), 1);
+
+    // Connections can also be checked out directly
+    let mut connection = pool.get().unwrap();
+    assert_eq!(connection.object_id("echo").unwrap(), echo);
+    connection.discard();
+    assert_eq!(pool.open(), 0);
+}
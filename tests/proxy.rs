@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::Duration;
+use ubus::*;
+
+#[test]
+fn test() {
+    let (host, container) = (Broker::new(), Broker::new());
+
+    // A service on the host bus, with a filler object so ids differ between the buses
+    let mut service = host.connect().unwrap();
+    let _filler = service.add_object("filler", |_, _, _| 0).unwrap();
+    let gets = Arc::new(AtomicUsize::new(0));
+    let counted = gets.clone();
+    let _object = service
+        .add_object("network", move |method, _, reply| match method {
+            "status" => {
+                reply.push_str(BlobMsgType::STRING.value(), "up").unwrap();
+                0
+            }
+            // Like `uci get` of a missing section
+            "get" => {
+                counted.fetch_add(1, Ordering::SeqCst);
+                reply.push_str(BlobMsgType::STRING.value(), "none").unwrap();
+                4
+            }
+            // UBUS_STATUS_METHOD_NOT_FOUND
+            _ => 3,
+        })
+        .unwrap();
+    std::thread::spawn(move || while service.handle_next_message().is_ok() {});
+
+    let mut listener = host.connect().unwrap();
+    let (tx, events) = channel();
+    let handler = listener
+        .event_handler(move |id, _| tx.send(id.to_string()).unwrap())
+        .unwrap();
+    listener.register_event(&handler, "container.*").unwrap();
+    std::thread::spawn(move || while listener.handle_next_message().is_ok() {});
+
+    let mut proxy = Proxy::new(host.connect().unwrap(), container.connect().unwrap());
+    proxy.forward_object(Side::A, "network").unwrap();
+    proxy.forward_events(Side::B, "container.*").unwrap();
+    std::thread::spawn(move || proxy.run());
+
+    // Calls made on the container's bus are answered by the host's service
+    let mut client = container.connect().unwrap();
+    let id = client.object_id("network").unwrap();
+    let mut replies = Vec::new();
+    client
+        .invoke(id, "status", &[], |data| {
+            for value in data {
+                if let BlobMsgData::String(s) = value.data {
+                    replies.push(s.to_string());
+                }
+            }
+        })
+        .unwrap();
+    assert_eq!(replies, ["up"]);
+    assert!(matches!(
+        client.invoke(id, "missing", &[], |_| {}),
+        Err(Error::Status(3))
+    ));
+
+    // A method returning NOT_FOUND isn't called again, as if the object had gone away
+    let mut replies = 0;
+    let result = client.invoke(id, "get", &[], |data| replies += data.count());
+    assert!(matches!(result, Err(Error::Status(4))));
+    assert_eq!(gets.load(Ordering::SeqCst), 1);
+    assert_eq!(replies, 1);
+
+    client.send_event("container.started", &[]).unwrap();
+    client.send_event("other", &[]).unwrap();
+    let timeout = Duration::from_secs(5);
+    assert_eq!(events.recv_timeout(timeout).unwrap(), "container.started");
+    assert!(events.recv_timeout(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn reregistered() {
+    let (host, container) = (Broker::new(), Broker::new());
+    let mut first = host.connect().unwrap();
+    let object = first.add_object("network", |_, _, _| 0).unwrap();
+
+    let mut proxy = Proxy::new(host.connect().unwrap(), container.connect().unwrap());
+    proxy.forward_object(Side::A, "network").unwrap();
+    std::thread::spawn(move || proxy.run());
+
+    // The service restarts, registering the object again with a new id
+    drop(object);
+    drop(first);
+    let mut second = host.connect().unwrap();
+    let _filler = second.add_object("filler", |_, _, _| 0).unwrap();
+    let _object = second
+        .add_object("network", |_, _, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "up").unwrap();
+            0
+        })
+        .unwrap();
+    std::thread::spawn(move || while second.handle_next_message().is_ok() {});
+
+    let mut client = container.connect().unwrap();
+    let id = client.object_id("network").unwrap();
+    let mut replies = 0;
+    client
+        .invoke(id, "status", &[], |data| replies += data.count())
+        .unwrap();
+    assert_eq!(replies, 1);
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "serde_json")]
+use serde_json::json;
+use ubus::*;
+
+#[test]
+fn test() {
+    let value = json!({
+        "ssid": "foo",
+        "channel": 11,
+        "big": 1u64 << 40,
+        "ratio": 0.5,
+        "up": true,
+        "none": null,
+        "keys": ["a", { "b": -1 }],
+    });
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_json(&value).unwrap();
+    let data = builder.finish();
+
+    let values: Vec<_> = BlobIter::<BlobMsg>::new(data)
+        .map(|value| format!("{}={:?}", value.name.unwrap(), value.data))
+        .collect();
+    assert_eq!(
+        values,
+        [
+            "ssid=String(\"foo\")",
+            "channel=Int32(11)",
+            "big=Int64(1099511627776)",
+            "ratio=Double(0.5)",
+            "up=Int8(1)",
+            "none=Unknown(UNSPEC, [])",
+            "keys=Array(BlobIter)",
+        ]
+    );
+    let b = BlobIter::<BlobMsg>::new(data).path("keys.1.b").unwrap();
+    assert!(matches!(b.data, BlobMsgData::Int32(-1)));
+
+    // Only objects have names to push their values under
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    assert!(builder.push_json(&json!([1, 2])).is_err());
+    builder.push_value("list", &json!([1, 2])).unwrap();
+    assert!(!builder.is_empty());
+}
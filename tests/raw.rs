@@ -0,0 +1,66 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let objects: Vec<Object> = ["test", "other"]
+        .iter()
+        .map(|path| {
+            service
+                .add_object(path, |_, _, reply| {
+                    reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+                    0
+                })
+                .unwrap()
+        })
+        .collect();
+    let id = objects[0].id();
+    std::thread::spawn(move || {
+        let _objects = objects;
+        while service.handle_next_message().is_ok() {}
+    });
+    let mut connection = broker.connect().unwrap();
+
+    let mut replies = Vec::new();
+    connection
+        .invoke_raw(id, "ping", &[], |message| {
+            let attrs: Vec<_> = BlobIter::<MessageAttr>::new(message.blob.data)
+                .map(|attr| attr.id())
+                .collect();
+            replies.push((message.header.message, attrs));
+        })
+        .unwrap();
+    assert_eq!(
+        replies,
+        [(
+            MessageType::DATA,
+            vec![MessageAttrId::OBJID, MessageAttrId::DATA]
+        )]
+    );
+
+    let mut paths = Vec::new();
+    connection
+        .lookup_raw(None, |message| {
+            for attr in BlobIter::<MessageAttr>::new(message.blob.data) {
+                if let MessageAttr::ObjPath(path) = attr {
+                    paths.push(path.to_string());
+                }
+            }
+        })
+        .unwrap();
+    paths.sort();
+    assert_eq!(paths, ["other", "test"]);
+
+    let mut found = Vec::new();
+    connection
+        .lookup_raw(Some("test"), |message| {
+            for attr in BlobIter::<MessageAttr>::new(message.blob.data) {
+                if let MessageAttr::ObjId(obj) = attr {
+                    found.push(obj);
+                }
+            }
+        })
+        .unwrap();
+    assert_eq!(found, [id]);
+}
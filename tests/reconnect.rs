@@ -0,0 +1,215 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use ubus::*;
+
+/// Loopback transport which fails once `lost` is set, like a connection to a ubusd which exited
+struct LosableIo {
+    io: LoopbackIo,
+    lost: Arc<AtomicBool>,
+    /// Fail the next receive, once a request has been sent
+    deaf: Arc<AtomicBool>,
+    /// Time out the next receive, once a request has been sent
+    slow: Arc<AtomicBool>,
+}
+
+impl IO for LosableIo {
+    type Error = std::io::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        if self.lost.load(Ordering::SeqCst) {
+            return Err(Error::IO(std::io::ErrorKind::BrokenPipe.into()));
+        }
+        self.io.put(data)
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        if self.deaf.swap(false, Ordering::SeqCst) {
+            return Err(Error::IO(std::io::ErrorKind::ConnectionReset.into()));
+        }
+        if self.slow.swap(false, Ordering::SeqCst) {
+            return Err(Error::Timeout);
+        }
+        self.io.get(data)
+    }
+}
+
+/// Start a broker serving `test`, with `padding` other objects added first to shift its id
+fn start(padding: usize) -> (Broker, Vec<Object>, u32) {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let mut objects = Vec::new();
+    for i in 0..padding {
+        let path = format!("padding{}", i);
+        objects.push(service.add_object(&path, |_, _, _| 0).unwrap());
+    }
+    let object = service
+        .add_object("test", |_, _, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    let id = object.id();
+    objects.push(object);
+    std::thread::spawn(move || while service.handle_next_message().is_ok() {});
+    (broker, objects, id)
+}
+
+#[test]
+fn test() {
+    let (broker, _first, first_id) = start(0);
+    let current = Arc::new(Mutex::new(broker));
+    let lost = Arc::new(AtomicBool::new(false));
+    let deaf = Arc::new(AtomicBool::new(false));
+    let slow = Arc::new(AtomicBool::new(false));
+
+    let mut connection = {
+        let (current, lost, deaf, slow) =
+            (current.clone(), lost.clone(), deaf.clone(), slow.clone());
+        Reconnecting::new(move || {
+            lost.store(false, Ordering::SeqCst);
+            let (client, server) = LoopbackIo::pair();
+            let broker = current.lock().unwrap().clone();
+            std::thread::spawn(move || broker.serve_io(server.clone(), server));
+            let (lost, deaf, slow) = (lost.clone(), deaf.clone(), slow.clone());
+            Connection::new(LosableIo {
+                io: client,
+                lost,
+                deaf,
+                slow,
+            })
+        })
+        .unwrap()
+    };
+    let (tx, refreshes) = channel();
+    connection.on_refresh(move |refreshed| tx.send(refreshed.clone()).unwrap());
+
+    let test = connection.resolve("test").unwrap();
+    assert_eq!(test.id(), Some(first_id));
+    let mut replies = 0;
+    connection
+        .invoke(&test, "ping", &[], |_| replies += 1)
+        .unwrap();
+    assert_eq!(replies, 1);
+
+    // "Restart" ubusd: the object comes back with a new id
+    let (broker, _second, second_id) = start(3);
+    assert_ne!(first_id, second_id);
+    *current.lock().unwrap() = broker;
+    lost.store(true, Ordering::SeqCst);
+
+    // The call is retried on a new connection, using the refreshed id
+    connection
+        .invoke(&test, "ping", &[], |_| replies += 1)
+        .unwrap();
+    assert_eq!(replies, 2);
+    assert_eq!(test.id(), Some(second_id));
+    let refreshed = refreshes.try_recv().unwrap();
+    assert_eq!(
+        refreshed,
+        Refreshed {
+            path: "test".to_string(),
+            old: Some(first_id),
+            new: Some(second_id),
+        }
+    );
+    assert!(refreshes.try_recv().is_err());
+
+    // A call which was sent before the connection was lost isn't retried, as it may have been made
+    deaf.store(true, Ordering::SeqCst);
+    assert!(matches!(
+        connection.invoke(&test, "ping", &[], |_| replies += 1),
+        Err(Error::IO(_))
+    ));
+    assert!(!connection.is_connected());
+    assert_eq!(replies, 2);
+    connection
+        .invoke(&test, "ping", &[], |_| replies += 1)
+        .unwrap();
+    assert_eq!(replies, 3);
+    assert_eq!(refreshes.try_recv().unwrap().new, Some(second_id));
+
+    // Nor is one which timed out, and its late reply can't turn up on the next connection
+    slow.store(true, Ordering::SeqCst);
+    assert!(matches!(
+        connection.invoke(&test, "ping", &[], |_| replies += 1),
+        Err(Error::Timeout)
+    ));
+    assert!(!connection.is_connected());
+    connection
+        .invoke(&test, "ping", &[], |_| replies += 1)
+        .unwrap();
+    assert_eq!(replies, 4);
+    assert_eq!(refreshes.try_recv().unwrap().new, Some(second_id));
+
+    // Objects missing after a restart are reported, and calls fail with NOT_FOUND
+    *current.lock().unwrap() = Broker::new();
+    connection.reconnect().unwrap();
+    assert_eq!(refreshes.try_recv().unwrap().new, None);
+    assert!(matches!(
+        connection.invoke(&test, "ping", &[], |_| {}),
+        Err(Error::Status(4))
+    ));
+}
+
+/// Loopback transport which fails each send once `sends` runs out
+struct LimitedIo {
+    io: LoopbackIo,
+    sends: Arc<AtomicUsize>,
+}
+
+impl IO for LimitedIo {
+    type Error = std::io::Error;
+    fn put(&mut self, data: &[u8]) -> Result<(), Error<std::io::Error>> {
+        let take = self
+            .sends
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if take.is_err() {
+            return Err(Error::IO(std::io::ErrorKind::BrokenPipe.into()));
+        }
+        self.io.put(data)
+    }
+    fn get(&mut self, data: &mut [u8]) -> Result<(), Error<std::io::Error>> {
+        self.io.get(data)
+    }
+}
+
+#[test]
+fn partial() {
+    let (broker, _first, first_id) = start(1);
+    let current = Arc::new(Mutex::new(broker));
+    let sends = Arc::new(AtomicUsize::new(usize::MAX));
+
+    let mut connection = {
+        let (current, sends) = (current.clone(), sends.clone());
+        Reconnecting::new(move || {
+            let (client, server) = LoopbackIo::pair();
+            let broker = current.lock().unwrap().clone();
+            std::thread::spawn(move || broker.serve_io(server.clone(), server));
+            let sends = sends.clone();
+            Connection::new(LimitedIo { io: client, sends })
+        })
+        .unwrap()
+    };
+    let (tx, refreshes) = channel();
+    connection.on_refresh(move |refreshed| tx.send(refreshed.clone()).unwrap());
+    let test = connection.resolve("test").unwrap();
+    let padding = connection.resolve("padding0").unwrap();
+    let padding_id = padding.id().unwrap();
+    assert_eq!(test.id(), Some(first_id));
+
+    // The connection fails after the first of the lookups, so neither handle is updated
+    let (broker, _second, second_id) = start(3);
+    *current.lock().unwrap() = broker;
+    sends.store(1, Ordering::SeqCst);
+    assert!(matches!(connection.reconnect(), Err(Error::IO(_))));
+    assert!(!connection.is_connected());
+    assert_eq!(padding.id(), Some(padding_id));
+    assert_eq!(test.id(), Some(first_id));
+    assert!(refreshes.try_recv().is_err());
+
+    // Until a reconnect gets through all of them
+    sends.store(usize::MAX, Ordering::SeqCst);
+    connection.reconnect().unwrap();
+    assert_eq!(test.id(), Some(second_id));
+    let refreshed: Vec<_> = refreshes.try_iter().map(|r| r.path).collect();
+    assert_eq!(refreshed, ["test", "padding0"]);
+}
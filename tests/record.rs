@@ -0,0 +1,41 @@
+use ubus::*;
+
+fn lookups<T: IO<Error = std::io::Error>>(connection: &mut Connection<T>) -> (u32, bool) {
+    let id = connection.object_id("test").unwrap();
+    let missing = matches!(connection.object_id("missing"), Err(Error::Status(4)));
+    (id, missing)
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let _object = service.add_object("test", |_, _, _| 0).unwrap();
+
+    // Record a session with the broker
+    let mut log = Vec::new();
+    let (client, server) = LoopbackIo::pair();
+    let serving = broker.clone();
+    std::thread::spawn(move || serving.serve_io(server.clone(), server));
+    let mut connection = Connection::new(RecordingIo::new(client, &mut log)).unwrap();
+    let recorded = lookups(&mut connection);
+    assert!(recorded.1);
+    drop(connection);
+
+    let text = String::from_utf8(log.clone()).unwrap();
+    assert!(text.lines().any(|line| line.contains(" > ")));
+    assert!(text.lines().any(|line| line.contains(" < ")));
+
+    // Replaying it gives the same results, without the broker
+    let replay = ReplayIo::new(&log[..]).unwrap();
+    let mut connection = Connection::new(replay).unwrap();
+    assert_eq!(lookups(&mut connection), recorded);
+
+    // Diverging from the recording is detected
+    let replay = ReplayIo::new(&log[..]).unwrap();
+    let mut connection = Connection::new(replay).unwrap();
+    assert!(matches!(
+        connection.object_id("other"),
+        Err(Error::InvalidData(_))
+    ));
+}
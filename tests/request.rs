@@ -0,0 +1,121 @@
+mod common;
+
+use common::*;
+use ubus::*;
+
+/// Build a message the long way, with `put`
+fn manual<'a>(
+    ty: MessageType,
+    peer: u32,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) -> Vec<u8> {
+    let header = MessageHeader {
+        peer: peer.into(),
+        ..header(ty, 7)
+    };
+    build(header, attrs)
+}
+
+fn typed<'a>(request: impl RequestMessage<'a>) -> Vec<u8> {
+    let mut buffer = [0u8; 256];
+    MessageBuilder::from_request(&mut buffer, 7, request)
+        .unwrap()
+        .finish()
+        .to_vec()
+}
+
+#[test]
+fn test() {
+    assert_eq!(
+        typed(LookupRequest::all()),
+        manual(MessageType::LOOKUP, 0, [])
+    );
+    assert_eq!(
+        typed(LookupRequest::path("network.*")),
+        manual(MessageType::LOOKUP, 0, [MessageAttr::ObjPath("network.*")])
+    );
+
+    let args = [0u8, 0, 0, 4];
+    let invoke = InvokeRequest::new(0x100, "status").args(&args);
+    assert_eq!(invoke.peer(), 0x100);
+    assert_eq!(
+        typed(invoke),
+        manual(
+            MessageType::INVOKE,
+            0x100,
+            [
+                MessageAttr::ObjId(0x100),
+                MessageAttr::Method("status"),
+                MessageAttr::Data(&args),
+            ]
+        )
+    );
+    assert_eq!(
+        typed(invoke.no_reply()),
+        manual(
+            MessageType::INVOKE,
+            0x100,
+            [
+                MessageAttr::ObjId(0x100),
+                MessageAttr::Method("status"),
+                MessageAttr::Data(&args),
+                MessageAttr::NoReply(true),
+            ]
+        )
+    );
+
+    let attrs = || [MessageAttr::ObjId(0x200), MessageAttr::Target(0x100)];
+    assert_eq!(
+        typed(SubscribeRequest::new(0x200, 0x100)),
+        manual(MessageType::SUBSCRIBE, 0, attrs())
+    );
+    assert_eq!(
+        typed(UnsubscribeRequest::new(0x200, 0x100)),
+        manual(MessageType::UNSUBSCRIBE, 0, attrs())
+    );
+
+    // The builders' checks still apply
+    let mut buffer = [0u8; 64];
+    let long = "x".repeat(MAX_METHOD_LEN + 1);
+    assert!(MessageBuilder::from_request(&mut buffer, 1, InvokeRequest::new(1, &long)).is_err());
+}
+
+#[test]
+fn request_response() {
+    // A request built from its parsed attributes is the same as one built directly
+    let args = [0u8, 0, 0, 4];
+    let invoke = InvokeRequest::new(0x100, "status").args(&args).no_reply();
+    let request = Request::new(7, invoke);
+    assert_eq!(request.message, MessageType::INVOKE);
+    assert_eq!(request.peer, 0x100);
+    assert_eq!(request.attrs.method, Some("status"));
+    assert!(request.attrs.no_reply);
+    let mut buffer = [0u8; 256];
+    assert_eq!(
+        request.build(&mut buffer).unwrap().finish(),
+        &typed(invoke)[..]
+    );
+
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+    let mut connection = broker.connect().unwrap();
+
+    let mut responses = Vec::new();
+    connection
+        .call(LookupRequest::path("test"), |response| {
+            assert!(!response.is_final());
+            assert!(response.status().is_err());
+            responses.push((
+                response.attrs.obj_path.unwrap().to_string(),
+                response.attrs.obj_id,
+            ));
+        })
+        .unwrap();
+    assert_eq!(responses, [("test".to_string(), Some(object.id()))]);
+
+    assert!(matches!(
+        connection.call(LookupRequest::path("missing"), |_| {}),
+        Err(Error::Status(4))
+    ));
+}
@@ -0,0 +1,77 @@
+use std::os::unix::net::UnixListener;
+use std::time::{Duration, Instant};
+use ubus::*;
+
+#[test]
+fn test() {
+    let policy = RetryPolicy::new()
+        .initial_delay(Duration::from_millis(10))
+        .max_delay(Duration::from_millis(40));
+    assert_eq!(policy.delay(0), Duration::from_millis(10));
+    assert_eq!(policy.delay(2), Duration::from_millis(40));
+    assert_eq!(policy.delay(100), Duration::from_millis(40));
+
+    // Gives up after the given number of attempts
+    let socket = std::env::temp_dir().join(format!("ubus-retry-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let started = Instant::now();
+    let result = Connection::connect_with_retry(&socket, &policy.clone().jitter(0.0).attempts(3));
+    assert!(matches!(result, Err(Error::IO(_))));
+    assert!(started.elapsed() >= Duration::from_millis(30));
+
+    // Connects once ubusd turns up
+    let starting = socket.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        let listener = UnixListener::bind(&starting).unwrap();
+        Broker::new().run(listener)
+    });
+    let mut connection = Connection::connect_with_retry(&socket, &policy).unwrap();
+    assert!(matches!(
+        connection.object_id("missing"),
+        Err(Error::Status(4))
+    ));
+    std::fs::remove_file(&socket).unwrap();
+
+    // Other errors aren't retried
+    let mut attempts = 0;
+    let result: Result<(), Error<std::io::Error>> = policy.retry(|| {
+        attempts += 1;
+        Err(Error::InvalidData("bad"))
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+
+    // Nor are IO errors which waiting won't fix
+    let mut attempts = 0;
+    let result: Result<(), Error<std::io::Error>> = policy.retry(|| {
+        attempts += 1;
+        Err(Error::IO(std::io::ErrorKind::PermissionDenied.into()))
+    });
+    assert!(matches!(result, Err(Error::IO(_))));
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn builder() {
+    let socket = std::env::temp_dir().join(format!("ubus-builder-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let builder = Connection::builder().socket(&socket).retry(
+        RetryPolicy::new()
+            .initial_delay(Duration::from_millis(10))
+            .max_delay(Duration::from_millis(40)),
+    );
+
+    let starting = socket.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        let listener = UnixListener::bind(&starting).unwrap();
+        Broker::new().run(listener)
+    });
+    let mut connection = builder.connect().unwrap();
+    assert!(matches!(
+        connection.object_id("missing"),
+        Err(Error::Status(4))
+    ));
+    std::fs::remove_file(&socket).unwrap();
+}
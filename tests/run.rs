@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+
+    let calls = Arc::new(AtomicU32::new(0));
+    let counted = calls.clone();
+    let _object = service
+        .add_object("counter", move |_, _, _| {
+            counted.fetch_add(1, Ordering::Relaxed);
+            0
+        })
+        .unwrap();
+    let stopped = Arc::new(AtomicBool::new(false));
+    let stop = stopped.clone();
+    let handler = service
+        .event_handler(move |_, _| stop.store(true, Ordering::Relaxed))
+        .unwrap();
+    service.register_event(&handler, "counter.stop").unwrap();
+
+    let client = std::thread::spawn({
+        let broker = broker.clone();
+        move || {
+            let mut connection = broker.connect().unwrap();
+            let id = connection.object_id("counter").unwrap();
+            for _ in 0..3 {
+                connection.invoke(id, "count", &[], |_| {}).unwrap();
+            }
+            connection.send_event("counter.stop", &[]).unwrap();
+        }
+    });
+
+    // Serves the calls, then returns after the event
+    service
+        .run_until(|| stopped.load(Ordering::Relaxed))
+        .unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 3);
+    client.join().unwrap();
+}
+
+#[test]
+fn disconnected() {
+    let (client, mut server) = std::os::unix::net::UnixStream::pair().unwrap();
+    // HELLO, then the bus goes away
+    std::io::Write::write_all(&mut server, &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 4]).unwrap();
+    drop(server);
+
+    let mut connection = Connection::new(client).unwrap();
+    assert!(matches!(connection.run(), Err(Error::IO(_))));
+}
@@ -0,0 +1,101 @@
+use ubus::*;
+
+/// A bus with one object, listing a fixed signature
+struct Signatures;
+
+impl Bus for Signatures {
+    type Error = std::io::Error;
+
+    fn call(
+        &mut self,
+        _path: &str,
+        _method: &str,
+        _args: &[u8],
+        _on_result: impl FnMut(BlobIter<BlobMsg>),
+    ) -> Result<(), Error<Self::Error>> {
+        unimplemented!()
+    }
+
+    fn list(
+        &mut self,
+        path: Option<&str>,
+        mut on_object: impl FnMut(ObjectResult),
+        mut on_signature: impl FnMut(SignatureResult),
+    ) -> Result<(), Error<Self::Error>> {
+        if path != Some("network.interface") {
+            return Ok(());
+        }
+        let object = ObjectResult {
+            path: "network.interface",
+            id: 0x100,
+            ty: 0x200,
+        };
+        on_object(object);
+        let methods: [(&str, &[(&str, BlobMsgType)]); 2] = [
+            ("up", &[]),
+            (
+                "status",
+                &[
+                    ("interface", BlobMsgType::STRING),
+                    ("verbose", BlobMsgType::INT8),
+                    ("ipv4-address", BlobMsgType::ARRAY),
+                    ("\"odd\"", BlobMsgType::UNSPEC),
+                ],
+            ),
+        ];
+        for (name, args) in methods {
+            on_signature(SignatureResult {
+                object,
+                name,
+                args: &mut args.iter().copied(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test() {
+    let schema = object_schema(&mut Signatures, "network.interface").unwrap();
+    assert_eq!(
+        schema,
+        concat!(
+            r#"{"$schema":"https://json-schema.org/draft/2020-12/schema","#,
+            r#""title":"network.interface","$defs":{"#,
+            r#""up":{"type":"object","properties":{}},"#,
+            r#""status":{"type":"object","properties":{"#,
+            r#""interface":{"type":"string"},"verbose":{"type":"boolean"},"#,
+            r#""ipv4-address":{"type":"array"},"\"odd\"":{}}}}}"#,
+        )
+    );
+
+    assert!(matches!(
+        object_schema(&mut Signatures, "missing"),
+        Err(Error::Status(4))
+    ));
+}
+
+#[test]
+fn codegen() {
+    let module = generate_bindings(&mut Signatures, &["network.interface"]).unwrap();
+    let expected = [
+        "pub struct NetworkInterfaceUpArgs {}",
+        "pub struct NetworkInterfaceStatusArgs {\n    pub interface: Option<String>,\n    \
+         pub verbose: Option<bool>,\n    pub ipv4_address: Option<Vec<u8>>,\n}",
+        "builder.push_bool(\"verbose\", *value)?;",
+        "builder.push_array(\"ipv4-address\", |b| b.push_raw(value))?;",
+        "pub struct NetworkInterface<'c, T: IO> {",
+        "connection.object_id(\"network.interface\")?;",
+        "    pub fn status(\n        &mut self,\n        args: &NetworkInterfaceStatusArgs,",
+        "self.connection.invoke_collect(self.id, \"status\", args)",
+    ];
+    for expected in expected {
+        assert!(module.contains(expected), "{}\n{}", expected, module);
+    }
+    assert!(!module.contains("odd"));
+
+    assert!(matches!(
+        generate_bindings(&mut Signatures, &["network.interface", "missing"]),
+        Err(Error::Status(4))
+    ));
+}
@@ -0,0 +1,46 @@
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+use ubus::*;
+
+#[test]
+fn test() {
+    let (host, container) = (Broker::new(), Broker::new());
+    let (tx, events) = channel();
+    let mut listeners = Vec::new();
+    let mut handlers = Vec::new();
+    for broker in [&host, &container] {
+        let mut listener = broker.connect().unwrap();
+        let tx = tx.clone();
+        let handler = listener
+            .event_handler(move |id, _| tx.send(id.to_string()).unwrap())
+            .unwrap();
+        listener.register_event(&handler, "*").unwrap();
+        listeners.push(listener);
+        handlers.push(handler);
+    }
+
+    // Nothing to receive yet
+    let timeout = Duration::from_millis(50);
+    let started = Instant::now();
+    assert!(select(&[&listeners[0], &listeners[1]], Some(timeout))
+        .unwrap()
+        .is_empty());
+    assert!(started.elapsed() >= timeout);
+
+    container
+        .connect()
+        .unwrap()
+        .send_event("container.started", &[])
+        .unwrap();
+    let ready = select(&[&listeners[0], &listeners[1]], None).unwrap();
+    assert_eq!(ready, [1]);
+    listeners[1].handle_next_message().unwrap();
+    assert_eq!(events.try_recv().unwrap(), "container.started");
+
+    let mut sender = host.connect().unwrap();
+    sender.send_event("host.started", &[]).unwrap();
+    let ready = select(&[&listeners[0], &listeners[1]], None).unwrap();
+    assert_eq!(ready, [0]);
+    listeners[0].handle_next_message().unwrap();
+    assert_eq!(events.try_recv().unwrap(), "host.started");
+}
@@ -0,0 +1,45 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let mut sequences = Sequences::<4>::new();
+    assert_eq!(sequences.start().unwrap(), 1);
+    assert_eq!(sequences.start().unwrap(), 2);
+    assert!(sequences.is_pending(1));
+    assert!(sequences.finish(1));
+    assert!(!sequences.finish(1));
+    assert!(!sequences.is_pending(1));
+    assert_eq!(sequences.len(), 1);
+
+    // Wrapping around skips those still pending
+    for _ in 3..=u16::MAX {
+        sequences.allocate();
+    }
+    assert_eq!(sequences.allocate(), 0);
+    assert_eq!(sequences.allocate(), 1);
+    assert_eq!(sequences.allocate(), 3);
+
+    // Full: `start` fails, `insert` forgets the oldest
+    sequences.insert(10);
+    sequences.insert(11);
+    sequences.insert(12);
+    assert!(matches!(sequences.start(), Err(Error::InvalidData(_))));
+    sequences.insert(13);
+    assert!(!sequences.is_pending(2));
+    assert!([10, 11, 12, 13].iter().all(|&s| sequences.is_pending(s)));
+    sequences.clear();
+    assert!(sequences.is_empty());
+}
+
+#[test]
+fn wraparound() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+
+    // More requests than there are sequence numbers
+    let mut connection = broker.connect().unwrap();
+    for _ in 0..=u16::MAX as u32 + 10 {
+        assert_eq!(connection.object_id("test").unwrap(), object.id());
+    }
+}
@@ -0,0 +1,879 @@
+#![cfg(feature = "services")]
+mod common;
+
+use common::*;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use ubus::services::dhcp::*;
+use ubus::services::hostapd::*;
+use ubus::services::iwinfo::*;
+use ubus::services::log::*;
+use ubus::services::network::*;
+use ubus::services::procd::*;
+use ubus::services::system::*;
+use ubus::services::uci::*;
+use ubus::*;
+
+/// Serve `objects` on a new broker, replying to each call with the table `reply` builds from the
+/// object's path, the method and its arguments
+fn serve(
+    objects: &[&'static str],
+    reply: impl Fn(&str, &str, BlobIter<BlobMsg>, &mut BlobMsgBuilder) -> Result<(), Error>
+        + Send
+        + Sync
+        + 'static,
+) -> Connection<UnixStream> {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let reply = Arc::new(reply);
+    let objects: Vec<Object> = objects
+        .iter()
+        .map(|&path| {
+            let reply = reply.clone();
+            service
+                .add_object(path, move |method, args, builder| {
+                    let mut buffer = vec![0u8; 4096];
+                    let mut table = BlobMsgBuilder::from_bytes(&mut buffer);
+                    reply(path, method, args, &mut table).unwrap();
+                    // Copy the table's attributes into the reply
+                    for attr in BlobIter::<BlobMsg>::new(table.finish()) {
+                        let (ty, data) = raw(&attr);
+                        builder
+                            .push_named_bytes(ty.value(), attr.name.unwrap_or(""), &data)
+                            .unwrap();
+                    }
+                    0
+                })
+                .unwrap()
+        })
+        .collect();
+    std::thread::spawn(move || {
+        let _objects = objects;
+        while service.handle_next_message().is_ok() {}
+    });
+    broker.connect().unwrap()
+}
+
+/// The type and encoded data of a blobmsg attribute
+fn raw(attr: &BlobMsg) -> (BlobMsgType, Vec<u8>) {
+    match &attr.data {
+        BlobMsgData::Table(items) => (BlobMsgType::TABLE, items.as_bytes().to_vec()),
+        BlobMsgData::Array(items) => (BlobMsgType::ARRAY, items.as_bytes().to_vec()),
+        BlobMsgData::String(v) => {
+            let mut bytes = v.as_bytes().to_vec();
+            bytes.push(0);
+            (BlobMsgType::STRING, bytes)
+        }
+        BlobMsgData::Int64(v) => (BlobMsgType::INT64, v.to_be_bytes().to_vec()),
+        BlobMsgData::Int32(v) => (BlobMsgType::INT32, v.to_be_bytes().to_vec()),
+        BlobMsgData::Int16(v) => (BlobMsgType::INT16, v.to_be_bytes().to_vec()),
+        BlobMsgData::Int8(v) => (BlobMsgType::INT8, v.to_be_bytes().to_vec()),
+        BlobMsgData::Double(v) => (BlobMsgType::DOUBLE, v.to_be_bytes().to_vec()),
+        BlobMsgData::Unknown(ty, data) => (*ty, data.to_vec()),
+    }
+}
+
+/// Compact JSON for a table or array, to compare the arguments of calls against
+fn json(items: BlobIter<BlobMsg>) -> String {
+    let mut out = String::new();
+    let mut table = None;
+    for item in items {
+        out.push(if out.is_empty() { '{' } else { ',' });
+        if let Some(name) = item.name.filter(|name| !name.is_empty()) {
+            out.push_str(&format!("{:?}:", name));
+            table = Some(true);
+        } else {
+            table = Some(false);
+        }
+        match item.data {
+            BlobMsgData::Table(items) | BlobMsgData::Array(items) => out.push_str(&json(items)),
+            BlobMsgData::String(v) => out.push_str(&format!("{:?}", v)),
+            BlobMsgData::Int32(v) => out.push_str(&v.to_string()),
+            BlobMsgData::Int8(v) => out.push_str(&(v != 0).to_string()),
+            other => out.push_str(&format!("{:?}", other)),
+        }
+    }
+    match table {
+        None => "{}".into(),
+        Some(true) => out + "}",
+        Some(false) => format!("[{}]", &out[1..]),
+    }
+}
+
+#[test]
+fn system() {
+    let mut connection = serve(&["system"], |_, method, _, b| match method {
+        "board" => {
+            b.push_string("kernel", "5.15.150")?;
+            b.push_string("hostname", "OpenWrt")?;
+            b.push_string("system", "ARMv7 Processor rev 5 (v7l)")?;
+            b.push_string("model", "Linksys WRT3200ACM")?;
+            b.push_string("board_name", "linksys,rango")?;
+            b.push_string("rootfs_type", "squashfs")?;
+            b.push_table("release", |b| {
+                b.push_string("distribution", "OpenWrt")?;
+                b.push_string("version", "23.05.3")?;
+                b.push_string("revision", "r23809-234f1a2efa")?;
+                b.push_string("target", "mvebu/cortexa9")?;
+                b.push_string("description", "OpenWrt 23.05.3 r23809-234f1a2efa")
+            })
+        }
+        "info" => {
+            b.push_int32("localtime", 1_700_000_000)?;
+            b.push_int32("uptime", 3600)?;
+            b.push_array("load", |b| {
+                b.push_int32("", 65536)?;
+                b.push_int32("", 32768)?;
+                b.push_int32("", 0)
+            })?;
+            b.push_table("memory", |b| {
+                b.push_int64("total", 512 << 20)?;
+                b.push_int64("free", 256 << 20)?;
+                b.push_int64("shared", 1 << 20)?;
+                b.push_int64("buffered", 2 << 20)?;
+                b.push_int64("available", 300 << 20)
+            })?;
+            b.push_table("swap", |b| {
+                b.push_int64("total", 0)?;
+                b.push_int64("free", 0)
+            })
+        }
+        _ => Ok(()),
+    });
+    let mut system = System::new(&mut connection).unwrap();
+
+    let board = system.board().unwrap();
+    assert_eq!(board.model, "Linksys WRT3200ACM");
+    assert_eq!(board.rootfs_type.as_deref(), Some("squashfs"));
+    assert_eq!(board.release.version, "23.05.3");
+
+    let info = system.info().unwrap();
+    assert_eq!(info.uptime, 3600);
+    assert_eq!(info.load, [1.0, 0.5, 0.0]);
+    assert_eq!(info.memory.total, 512 << 20);
+    assert_eq!(info.memory.available, Some(300 << 20));
+    assert_eq!(info.memory.cached, None);
+    assert_eq!(info.root, None);
+    assert_eq!(info.swap, Some(Swap { total: 0, free: 0 }));
+
+    system.reboot().unwrap();
+}
+
+/// Push an interface's status, as netifd does
+fn interface(b: &mut BlobMsgBuilder, up: bool) -> Result<(), Error> {
+    b.push_bool("up", up)?;
+    b.push_bool("pending", false)?;
+    b.push_bool("available", true)?;
+    b.push_int32("uptime", 120)?;
+    b.push_string("l3_device", "br-lan")?;
+    b.push_string("proto", "static")?;
+    b.push_string("device", "br-lan")?;
+    b.push_array("ipv4-address", |b| {
+        b.push_table("", |b| {
+            b.push_string("address", "192.168.1.1")?;
+            b.push_int32("mask", 24)
+        })
+    })?;
+    b.push_array("route", |b| {
+        b.push_table("", |b| {
+            b.push_string("target", "0.0.0.0")?;
+            b.push_int32("mask", 0)?;
+            b.push_string("nexthop", "192.168.1.254")
+        })
+    })?;
+    b.push_array("dns-server", |b| b.push_string("", "192.168.1.254"))
+}
+
+#[test]
+fn network() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let log = calls.clone();
+    let objects = ["network.interface", "network.interface.lan"];
+    let mut connection = serve(&objects, move |path, method, _, b| {
+        log.lock().unwrap().push(format!("{} {}", path, method));
+        match (path, method) {
+            ("network.interface", "dump") => b.push_array("interface", |b| {
+                b.push_table("", |b| {
+                    b.push_string("interface", "lan")?;
+                    interface(b, true)
+                })?;
+                b.push_table("", |b| {
+                    b.push_string("interface", "wan")?;
+                    interface(b, false)
+                })
+            }),
+            (_, "status") => interface(b, true),
+            _ => Ok(()),
+        }
+    });
+    let mut network = Network::new(&mut connection);
+
+    let interfaces = network.dump().unwrap();
+    let names: Vec<_> = interfaces.iter().map(|i| i.interface.as_str()).collect();
+    assert_eq!(names, ["lan", "wan"]);
+    assert!(interfaces[0].up && !interfaces[1].up);
+
+    let lan = network.status("lan").unwrap();
+    assert_eq!(lan, interfaces[0]);
+    assert_eq!(lan.l3_device.as_deref(), Some("br-lan"));
+    assert_eq!(
+        lan.ipv4_address,
+        [Address {
+            address: "192.168.1.1".into(),
+            mask: 24
+        }]
+    );
+    assert_eq!(lan.route[0].nexthop, "192.168.1.254");
+    assert_eq!(lan.dns_server, ["192.168.1.254"]);
+    assert!(lan.ipv6_address.is_empty());
+
+    network.renew("lan").unwrap();
+    network.down("lan").unwrap();
+    network.up("lan").unwrap();
+    assert!(matches!(network.status("missing"), Err(Error::Status(4))));
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "network.interface dump",
+            "network.interface.lan status",
+            "network.interface.lan renew",
+            "network.interface.lan down",
+            "network.interface.lan up",
+        ]
+    );
+}
+
+#[test]
+fn procd() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let log = calls.clone();
+    let mut connection = serve(&["service"], move |_, method, args, b| {
+        log.lock()
+            .unwrap()
+            .push(format!("{} {}", method, json(args)));
+        match method {
+            "list" => b.push_table("dnsmasq", |b| {
+                b.push_table("instances", |b| {
+                    b.push_table("cfg01411c", |b| {
+                        b.push_bool("running", true)?;
+                        b.push_int32("pid", 1234)?;
+                        b.push_array("command", |b| {
+                            b.push_string("", "/usr/sbin/dnsmasq")?;
+                            b.push_string("", "-k")
+                        })?;
+                        b.push_int32("term_timeout", 5)?;
+                        b.push_table("respawn", |b| {
+                            b.push_int32("threshold", 3600)?;
+                            b.push_int32("timeout", 5)?;
+                            b.push_int32("retry", 5)
+                        })
+                    })
+                })
+            }),
+            _ => Ok(()),
+        }
+    });
+    let mut procd = Procd::new(&mut connection).unwrap();
+
+    let services = procd.list(Some("dnsmasq")).unwrap();
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].name, "dnsmasq");
+    let instance = &services[0].instances[0];
+    assert_eq!(instance.name, "cfg01411c");
+    assert!(instance.running);
+    assert_eq!(instance.pid, Some(1234));
+    assert_eq!(instance.command, ["/usr/sbin/dnsmasq", "-k"]);
+    assert_eq!(instance.exit_code, None);
+    let respawn = Respawn {
+        threshold: 3600,
+        timeout: 5,
+        retry: 5,
+    };
+    assert_eq!(instance.respawn, Some(respawn.clone()));
+
+    let instance = InstanceConfig {
+        name: "main".into(),
+        command: vec!["/bin/true".into()],
+        env: vec![("A".into(), "1".into())],
+        respawn: Some(respawn),
+    };
+    procd.set("test", &[instance]).unwrap();
+    procd.delete("test", Some("main")).unwrap();
+    let mut buffer = [0u8; 64];
+    let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+    data.push_string("package", "network").unwrap();
+    procd.event("config.change", data.finish()).unwrap();
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            r#"list {"name":"dnsmasq"}"#,
+            concat!(
+                r#"set {"name":"test","instances":{"main":{"command":["/bin/true"],"#,
+                r#""env":{"A":"1"},"respawn":["3600","5","5"]}}}"#
+            ),
+            r#"delete {"name":"test","instance":"main"}"#,
+            r#"event {"type":"config.change","data":{"package":"network"}}"#,
+        ]
+    );
+}
+
+#[test]
+fn uci() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let log = calls.clone();
+    let mut connection = serve(&["uci"], move |_, method, args, b| {
+        log.lock()
+            .unwrap()
+            .push(format!("{} {}", method, json(args)));
+        match method {
+            "get" => b.push_table("values", |b| {
+                b.push_table("cfg01e48a", |b| {
+                    b.push_bool(".anonymous", true)?;
+                    b.push_string(".type", "dnsmasq")?;
+                    b.push_string(".name", "cfg01e48a")?;
+                    b.push_int32(".index", 0)?;
+                    b.push_string("domain", "lan")
+                })?;
+                b.push_table("lan", |b| {
+                    b.push_bool(".anonymous", false)?;
+                    b.push_string(".type", "dhcp")?;
+                    b.push_string(".name", "lan")?;
+                    b.push_int32(".index", 1)?;
+                    b.push_string("interface", "lan")?;
+                    b.push_array("dhcp_option", |b| {
+                        b.push_string("", "3,192.168.1.1")?;
+                        b.push_string("", "6,192.168.1.1")
+                    })
+                })
+            }),
+            "add" => b.push_string("section", "cfg03e48a"),
+            "changes" => b.push_array("changes", |b| {
+                b.push_array("", |b| {
+                    b.push_string("", "set")?;
+                    b.push_string("", "lan")?;
+                    b.push_string("", "leasetime")?;
+                    b.push_string("", "12h")
+                })?;
+                b.push_array("", |b| {
+                    b.push_string("", "remove")?;
+                    b.push_string("", "cfg02e48a")
+                })
+            }),
+            _ => Ok(()),
+        }
+    });
+    let session = Session::from_id("0123456789abcdef0123456789abcdef").unwrap();
+    let mut uci = Uci::with_session(&mut connection, session).unwrap();
+
+    let sections = uci.get("dhcp").unwrap();
+    assert_eq!(sections.len(), 2);
+    assert!(sections[0].anonymous);
+    assert_eq!(sections[0].ty, "dnsmasq");
+    assert_eq!(sections[1].name, "lan");
+    assert_eq!(sections[1].index, Some(1));
+    assert_eq!(
+        sections[1].options["dhcp_option"],
+        UciValue::List(vec!["3,192.168.1.1".into(), "6,192.168.1.1".into()])
+    );
+
+    let leasetime = ("leasetime", UciValue::String("12h".into()));
+    uci.set("dhcp", "lan", std::slice::from_ref(&leasetime))
+        .unwrap();
+    let section = uci.add("dhcp", "host", None, &[leasetime]).unwrap();
+    assert_eq!(section, "cfg03e48a");
+    uci.delete("dhcp", "cfg02e48a", None).unwrap();
+    let changes = uci.changes(Some("dhcp")).unwrap();
+    assert_eq!(
+        changes,
+        [
+            Change {
+                config: "dhcp".into(),
+                operation: "set".into(),
+                section: "lan".into(),
+                option: Some("leasetime".into()),
+                value: Some("12h".into()),
+            },
+            Change {
+                config: "dhcp".into(),
+                operation: "remove".into(),
+                section: "cfg02e48a".into(),
+                option: None,
+                value: None,
+            },
+        ]
+    );
+    uci.commit("dhcp").unwrap();
+
+    let session = r#""ubus_rpc_session":"0123456789abcdef0123456789abcdef""#;
+    let expected: Vec<String> = [
+        r#"get {"config":"dhcp","#,
+        r#"set {"config":"dhcp","section":"lan","values":{"leasetime":"12h"},"#,
+        r#"add {"config":"dhcp","type":"host","values":{"leasetime":"12h"},"#,
+        r#"delete {"config":"dhcp","section":"cfg02e48a","#,
+        r#"changes {"config":"dhcp","#,
+        r#"commit {"config":"dhcp","#,
+    ]
+    .iter()
+    .map(|call| format!("{}{}}}", call, session))
+    .collect();
+    assert_eq!(*calls.lock().unwrap(), expected);
+}
+
+#[test]
+fn dhcp() {
+    let mut connection = serve(&["dhcp", "luci-rpc"], |path, method, args, b| {
+        match (path, method) {
+            ("dhcp", "ipv4leases") => b.push_table("device", |b| {
+                b.push_table("br-lan", |b| {
+                    b.push_array("leases", |b| {
+                        b.push_table("", |b| {
+                            b.push_string("mac", "00113344AABB")?;
+                            b.push_string("hostname", "laptop")?;
+                            b.push_string("address", "192.168.1.100")?;
+                            b.push_int32("valid", 43000)
+                        })?;
+                        b.push_table("", |b| {
+                            b.push_string("mac", "00113344aacc")?;
+                            b.push_string("hostname", "")?;
+                            b.push_string("address", "192.168.1.10")?;
+                            b.push_int32("valid", i32::MAX)
+                        })
+                    })
+                })
+            }),
+            ("dhcp", "ipv6leases") => b.push_table("device", |b| {
+                b.push_table("br-lan", |b| {
+                    b.push_array("leases", |b| {
+                        b.push_table("", |b| {
+                            b.push_string("duid", "00010001aabbccdd")?;
+                            b.push_string("hostname", "laptop")?;
+                            b.push_array("ipv6-addr", |b| {
+                                b.push_table("", |b| b.push_string("address", "fd00::100"))?;
+                                b.push_table("", |b| b.push_string("address", "fd00::101"))
+                            })?;
+                            b.push_int32("valid", 3600)
+                        })
+                    })
+                })
+            }),
+            ("luci-rpc", "getDHCPLeases") => {
+                assert_eq!(json(args), r#"{"family":4}"#);
+                b.push_array("dhcp_leases", |b| {
+                    b.push_table("", |b| {
+                        b.push_int32("expires", 43000)?;
+                        b.push_string("hostname", "laptop")?;
+                        b.push_string("macaddr", "00:11:33:44:AA:BB")?;
+                        b.push_string("ipaddr", "192.168.1.100")
+                    })?;
+                    b.push_table("", |b| {
+                        b.push_bool("expires", false)?;
+                        b.push_string("macaddr", "00:11:33:44:aa:cc")?;
+                        b.push_string("ipaddr", "192.168.1.10")
+                    })
+                })
+            }
+            _ => Ok(()),
+        }
+    });
+
+    let mut dhcp = Dhcp::new(&mut connection).unwrap();
+    let leases = dhcp.ipv4_leases().unwrap();
+    assert_eq!(
+        leases[0],
+        Lease {
+            mac: Some("00:11:33:44:aa:bb".into()),
+            duid: None,
+            hostname: Some("laptop".into()),
+            addresses: vec!["192.168.1.100".into()],
+            device: Some("br-lan".into()),
+            expires: Some(43000),
+        }
+    );
+    assert_eq!(leases[1].hostname, None);
+    assert_eq!(leases[1].expires, None);
+    let leases = dhcp.ipv6_leases().unwrap();
+    assert_eq!(leases[0].duid.as_deref(), Some("00010001aabbccdd"));
+    assert_eq!(leases[0].addresses, ["fd00::100", "fd00::101"]);
+
+    let mut luci = Dhcp::luci(&mut connection).unwrap();
+    let leases = luci.ipv4_leases().unwrap();
+    assert_eq!(leases.len(), 2);
+    assert_eq!(leases[0].mac.as_deref(), Some("00:11:33:44:aa:bb"));
+    assert_eq!(leases[0].addresses, ["192.168.1.100"]);
+    assert_eq!(leases[0].expires, Some(43000));
+    assert_eq!(leases[1].expires, None);
+    assert_eq!(leases[1].device, None);
+}
+
+/// Push a network's security, as iwinfo does
+fn encryption(b: &mut BlobMsgBuilder) -> Result<(), Error> {
+    b.push_table("encryption", |b| {
+        b.push_bool("enabled", true)?;
+        b.push_array("wpa", |b| {
+            b.push_int32("", 2)?;
+            b.push_int32("", 3)
+        })?;
+        b.push_array("authentication", |b| {
+            b.push_string("", "psk")?;
+            b.push_string("", "sae")
+        })?;
+        b.push_array("ciphers", |b| b.push_string("", "ccmp"))
+    })
+}
+
+#[test]
+fn iwinfo() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let log = calls.clone();
+    let mut connection = serve(&["iwinfo"], move |_, method, args, b| {
+        log.lock()
+            .unwrap()
+            .push(format!("{} {}", method, json(args)));
+        match method {
+            "devices" => b.push_array("devices", |b| {
+                b.push_string("", "phy0-ap0")?;
+                b.push_string("", "phy1-ap0")
+            }),
+            "info" => {
+                b.push_string("phy", "phy0")?;
+                b.push_string("ssid", "OpenWrt")?;
+                b.push_string("bssid", "00:11:22:33:44:55")?;
+                b.push_string("mode", "Master")?;
+                b.push_int32("channel", 36)?;
+                b.push_int32("frequency", 5180)?;
+                b.push_int32("signal", -40)?;
+                b.push_int32("bitrate", 866700)?;
+                encryption(b)
+            }
+            "assoclist" => b.push_array("results", |b| {
+                b.push_table("", |b| {
+                    b.push_string("mac", "66:77:88:99:AA:BB")?;
+                    b.push_int32("signal", -52)?;
+                    b.push_int32("inactive", 10)?;
+                    b.push_bool("authorized", true)?;
+                    b.push_table("rx", |b| {
+                        b.push_int32("rate", 585000)?;
+                        b.push_int32("mhz", 80)?;
+                        b.push_int32("packets", 1000)
+                    })?;
+                    b.push_table("tx", |b| b.push_int32("rate", 780000))
+                })
+            }),
+            "scan" => b.push_array("results", |b| {
+                b.push_table("", |b| {
+                    b.push_string("bssid", "AA:BB:CC:DD:EE:FF")?;
+                    b.push_string("mode", "Master")?;
+                    b.push_int32("channel", 6)?;
+                    b.push_int32("signal", -70)?;
+                    encryption(b)
+                })
+            }),
+            _ => Ok(()),
+        }
+    });
+    let mut iwinfo = Iwinfo::new(&mut connection).unwrap();
+
+    assert_eq!(iwinfo.devices().unwrap(), ["phy0-ap0", "phy1-ap0"]);
+
+    let info = iwinfo.info("phy0-ap0").unwrap();
+    assert_eq!(info.ssid.as_deref(), Some("OpenWrt"));
+    assert_eq!(info.frequency, Some(5180));
+    assert_eq!(info.signal, Some(-40));
+    assert_eq!(info.noise, None);
+    let encryption = Encryption {
+        enabled: true,
+        wpa: vec![2, 3],
+        authentication: vec!["psk".into(), "sae".into()],
+        ciphers: vec!["ccmp".into()],
+    };
+    assert_eq!(info.encryption.as_ref(), Some(&encryption));
+
+    let stations = iwinfo.assoclist("phy0-ap0").unwrap();
+    assert_eq!(stations[0].mac, "66:77:88:99:AA:BB");
+    assert_eq!(stations[0].authorized, Some(true));
+    assert_eq!(stations[0].rx.mhz, Some(80));
+    assert_eq!(stations[0].tx.rate, Some(780000));
+    assert_eq!(stations[0].tx.packets, None);
+
+    let networks = iwinfo.scan("phy0-ap0").unwrap();
+    assert_eq!(networks[0].ssid, None);
+    assert_eq!(networks[0].channel, Some(6));
+    assert_eq!(networks[0].encryption, Some(encryption));
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "devices {}",
+            r#"info {"device":"phy0-ap0"}"#,
+            r#"assoclist {"device":"phy0-ap0"}"#,
+            r#"scan {"device":"phy0-ap0"}"#,
+        ]
+    );
+}
+
+/// Push a log entry's fields, as logd does
+fn entry(b: &mut BlobMsgBuilder, id: i32, message: &str) -> Result<(), Error> {
+    b.push_string("msg", message)?;
+    b.push_int32("id", id)?;
+    b.push_int32("priority", 3 << 3 | 6)?;
+    b.push_int32("source", 1)?;
+    b.push_int64("time", 1_700_000_000_000 + id as i64)
+}
+
+#[test]
+fn log() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let log = calls.clone();
+    let mut connection = serve(&["log"], move |_, method, args, b| {
+        log.lock()
+            .unwrap()
+            .push(format!("{} {}", method, json(args)));
+        match method {
+            "read" => b.push_array("log", |b| {
+                b.push_table("", |b| entry(b, 1, "dnsmasq[1234]: started"))?;
+                b.push_table("", |b| entry(b, 2, "dnsmasq[1234]: read /etc/hosts"))
+            }),
+            _ => Ok(()),
+        }
+    });
+    let mut log = Log::new(&mut connection).unwrap();
+
+    let entries = log.read(Some(2)).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        entries[0],
+        Entry {
+            message: "dnsmasq[1234]: started".into(),
+            id: 1,
+            priority: 30,
+            source: 1,
+            time: 1_700_000_000_001,
+        }
+    );
+    assert_eq!((entries[0].facility(), entries[0].severity()), (3, 6));
+    log.write("hello").unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            r#"read {"lines":2,"stream":false}"#,
+            r#"write {"event":"hello"}"#
+        ]
+    );
+}
+
+/// Answer a lookup of the object `path`, giving it the id 0x200
+fn answer_lookup(server: &mut UnixStream, buffer: &mut [u8], path: &str) {
+    let lookup = Message::from_io(server, buffer).unwrap();
+    assert_eq!(lookup.header.message, MessageType::LOOKUP);
+    let sequence = lookup.header.sequence.into();
+    let object = [
+        MessageAttr::ObjPath(path),
+        MessageAttr::ObjId(0x200),
+        MessageAttr::ObjType(0x300),
+        // An empty signature, which ends the object
+        MessageAttr::Signature(BlobIter::new(&[])),
+    ];
+    send(server, MessageType::DATA, sequence, object);
+    send(
+        server,
+        MessageType::STATUS,
+        sequence,
+        [MessageAttr::Status(0)],
+    );
+}
+
+#[test]
+fn log_follow() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    std::thread::spawn(move || {
+        send(&mut server, MessageType::HELLO, 0, []);
+        let mut buffer = [0u8; 1024];
+        answer_lookup(&mut server, &mut buffer, "log");
+
+        let read = Message::from_io(&mut server, &mut buffer).unwrap();
+        let sequence: u16 = read.header.sequence.into();
+        let args = BlobIter::<MessageAttr>::new(read.blob.data).find_map(|attr| match attr {
+            MessageAttr::Data(data) => Some(json(BlobIter::new(data))),
+            _ => None,
+        });
+        assert_eq!(args.as_deref(), Some(r#"{"lines":0,"stream":true}"#));
+
+        // Pass back a pipe, then stream entries through it
+        let (reader, mut writer) = UnixStream::pair().unwrap();
+        server
+            .put_fd(
+                &message(MessageType::STATUS, sequence, [MessageAttr::Status(0)]),
+                reader.as_raw_fd(),
+            )
+            .unwrap();
+        drop(reader);
+        for id in 1..=3 {
+            let mut buffer = [0u8; 256];
+            let mut b = BlobMsgBuilder::from_bytes(&mut buffer);
+            entry(&mut b, id, &format!("line {}", id)).unwrap();
+            let table = b.finish();
+            let tag = BlobTag::new(0, BlobTag::SIZE + table.len()).unwrap();
+            writer.write_all(&tag.to_bytes()).unwrap();
+            writer.write_all(table).unwrap();
+        }
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+    let mut log = Log::new(&mut connection).unwrap();
+    let messages: Vec<String> = log
+        .follow(None)
+        .unwrap()
+        .map(|entry| entry.unwrap().message)
+        .collect();
+    assert_eq!(messages, ["line 1", "line 2", "line 3"]);
+}
+
+#[test]
+fn hostapd() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let log = calls.clone();
+    let mut connection = serve(&["hostapd.phy0-ap0"], move |_, method, args, b| {
+        log.lock()
+            .unwrap()
+            .push(format!("{} {}", method, json(args)));
+        match method {
+            "get_clients" => {
+                b.push_int32("freq", 5180)?;
+                b.push_table("clients", |b| {
+                    b.push_table("66:77:88:99:aa:bb", |b| {
+                        b.push_bool("auth", true)?;
+                        b.push_bool("assoc", true)?;
+                        b.push_bool("authorized", true)?;
+                        b.push_bool("wmm", true)?;
+                        b.push_bool("vht", true)?;
+                        b.push_int32("aid", 1)?;
+                        b.push_int32("signal", -48)?;
+                        b.push_table("bytes", |b| {
+                            b.push_int64("rx", 123456)?;
+                            b.push_int64("tx", 654321)
+                        })?;
+                        b.push_table("rate", |b| {
+                            b.push_int32("rx", 585000)?;
+                            b.push_int32("tx", 780000)
+                        })
+                    })
+                })
+            }
+            _ => Ok(()),
+        }
+    });
+    let mut hostapd = Hostapd::new(&mut connection, "phy0-ap0").unwrap();
+
+    let clients = hostapd.get_clients().unwrap();
+    assert_eq!(
+        clients,
+        [Client {
+            mac: "66:77:88:99:aa:bb".into(),
+            auth: true,
+            assoc: true,
+            authorized: true,
+            wmm: true,
+            vht: true,
+            aid: Some(1),
+            signal: Some(-48),
+            rx_bytes: Some(123456),
+            tx_bytes: Some(654321),
+            rx_rate: Some(585000),
+            tx_rate: Some(780000),
+            ..Default::default()
+        }]
+    );
+    hostapd
+        .del_client("66:77:88:99:aa:bb", 5, true, Some(60_000))
+        .unwrap();
+    hostapd.wps_start().unwrap();
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [
+            "get_clients {}",
+            r#"del_client {"addr":"66:77:88:99:aa:bb","reason":5,"deauth":true,"ban_time":60000}"#,
+            "wps_start {}",
+        ]
+    );
+}
+
+#[test]
+fn hostapd_events() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let server = std::thread::spawn(move || {
+        send(&mut server, MessageType::HELLO, 0, []);
+        let mut buffer = [0u8; 1024];
+        answer_lookup(&mut server, &mut buffer, "hostapd.phy0-ap0");
+
+        // The subscriber object, then its subscription
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        assert_eq!(message.header.message, MessageType::ADD_OBJECT);
+        let sequence = message.header.sequence.into();
+        send(
+            &mut server,
+            MessageType::DATA,
+            sequence,
+            [MessageAttr::ObjId(0x100)],
+        );
+        send(
+            &mut server,
+            MessageType::STATUS,
+            sequence,
+            [MessageAttr::Status(0)],
+        );
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        assert_eq!(message.header.message, MessageType::SUBSCRIBE);
+        let sequence = message.header.sequence.into();
+        send(
+            &mut server,
+            MessageType::STATUS,
+            sequence,
+            [MessageAttr::Status(0)],
+        );
+
+        for (sequence, ty) in [(10, "probe"), (11, "assoc"), (12, "disassoc")] {
+            let mut data = [0u8; 128];
+            let mut b = BlobMsgBuilder::from_bytes(&mut data);
+            b.push_string("address", "66:77:88:99:aa:bb").unwrap();
+            if ty != "disassoc" {
+                b.push_int32("signal", -60).unwrap();
+                b.push_int32("freq", 2412).unwrap();
+            }
+            let notification = [
+                MessageAttr::ObjId(0x100),
+                MessageAttr::Method(ty),
+                MessageAttr::Data(b.finish()),
+            ];
+            send(&mut server, MessageType::INVOKE, sequence, notification);
+        }
+        // Kept open for the acknowledgements
+        server
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+    let mut hostapd = Hostapd::new(&mut connection, "phy0-ap0").unwrap();
+    let (tx, events) = std::sync::mpsc::channel();
+    let _subscriber = hostapd
+        .subscribe(move |event| tx.send(event).unwrap())
+        .unwrap();
+    let _server = server.join().unwrap();
+    for _ in 0..3 {
+        connection.handle_next_message().unwrap();
+    }
+    let events: Vec<_> = events.try_iter().collect();
+    let address = String::from("66:77:88:99:aa:bb");
+    assert_eq!(
+        events,
+        [
+            ClientEvent::Assoc {
+                address: address.clone(),
+                signal: Some(-60),
+                freq: Some(2412),
+            },
+            ClientEvent::Disassoc { address },
+        ]
+    );
+}
@@ -0,0 +1,125 @@
+use std::sync::mpsc::{channel, Receiver};
+use ubus::*;
+
+/// Serve the object "acl", passing the method and sessions of each call to it to `calls`
+fn serve(broker: &Broker) -> (u32, Receiver<(String, Vec<String>)>) {
+    let mut service = broker.connect().unwrap();
+    let (tx, calls) = channel();
+    let object = service
+        .add_object("acl", move |method, args, _| {
+            // The sessions each call was made with
+            let sessions: Vec<String> = args
+                .filter(|arg| arg.name == Some("ubus_rpc_session"))
+                .filter_map(|arg| match arg.data {
+                    BlobMsgData::String(id) => Some(id.to_string()),
+                    _ => None,
+                })
+                .collect();
+            tx.send((method.to_string(), sessions)).unwrap();
+            0
+        })
+        .unwrap();
+    let id = object.id();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+    (id, calls)
+}
+
+/// Check the calls made, and the session (if any) each was made with
+fn expect(calls: &Receiver<(String, Vec<String>)>, expected: &[(&str, Option<Session>)]) {
+    for (method, session) in expected {
+        let sessions: Vec<String> = session.iter().map(|s| s.id().to_string()).collect();
+        assert_eq!(calls.recv().unwrap(), (method.to_string(), sessions));
+    }
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let (id, calls) = serve(&broker);
+
+    let session = Session::from_id("0123456789abcdef0123456789abcdef").unwrap();
+    let other = Session::from_id("fedcba9876543210fedcba9876543210").unwrap();
+    let mut connection = broker.connect().unwrap();
+    connection.invoke(id, "none", &[], |_| {}).unwrap();
+    connection.set_session(Some(session));
+    assert_eq!(connection.session(), Some(&session));
+
+    let mut buffer = [0u8; 128];
+    let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+    args.push_string("config", "network").unwrap();
+    connection.invoke(id, "get", args.finish(), |_| {}).unwrap();
+    connection.invoke_collect(id, "collect", &[]).unwrap();
+    connection.invoke_noreply(id, "noreply", &[]).unwrap();
+    // An explicit session wins
+    connection
+        .invoke_with_session(&other, id, "other", &[], |_| {})
+        .unwrap();
+    connection.set_session(None);
+    connection.invoke(id, "cleared", &[], |_| {}).unwrap();
+
+    let expected = [
+        ("none", None),
+        ("get", Some(session)),
+        ("collect", Some(session)),
+        ("noreply", Some(session)),
+        ("other", Some(other)),
+        ("cleared", None),
+    ];
+    expect(&calls, &expected);
+}
+
+#[test]
+fn shared() {
+    let broker = Broker::new();
+    let (id, calls) = serve(&broker);
+    let session = Session::from_id("0123456789abcdef0123456789abcdef").unwrap();
+
+    // Split connections keep the session they had
+    let mut connection = broker.connect().unwrap();
+    connection.set_session(Some(session));
+    let reader = connection.spawn_reader().unwrap();
+    reader
+        .requester()
+        .invoke(id, "requester", &[], |_| {})
+        .unwrap();
+    reader.call(id, "call", &[]).unwrap();
+    reader.replies.recv().unwrap();
+    reader.requester().set_session(None);
+    reader
+        .requester()
+        .invoke(id, "cleared", &[], |_| {})
+        .unwrap();
+
+    let connect = broker.clone();
+    let pool = Pool::new(2, move || connect.connect());
+    pool.invoke(id, "none", &[], |_| {}).unwrap();
+    pool.set_session(Some(session));
+    pool.invoke(id, "pool", &[], |_| {}).unwrap();
+    pool.invoke_noreply(id, "noreply", &[]).unwrap();
+
+    let expected = [
+        ("requester", Some(session)),
+        ("call", Some(session)),
+        ("cleared", None),
+        ("none", None),
+        ("pool", Some(session)),
+        ("noreply", Some(session)),
+    ];
+    expect(&calls, &expected);
+}
+
+#[test]
+fn invalid_id() {
+    // Ids come from outside (e.g. a web frontend), so bad ones are errors rather than panics
+    assert!(matches!(
+        Session::from_id("0123456789abcdef0123456789abcdeg"),
+        Err(Error::InvalidData("Session id is not hexadecimal"))
+    ));
+    assert!(matches!(
+        Session::from_id("0123456789abcdef"),
+        Err(Error::InvalidData("Session id has wrong length"))
+    ));
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "smol")]
+use async_io::Async;
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use ubus::*;
+
+fn service(broker: &Broker) -> u32 {
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+    let id = object.id();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+    id
+}
+
+#[test]
+fn unix() {
+    let broker = Broker::new();
+    let id = service(&broker);
+    let path = std::env::temp_dir().join(format!("ubus-rs-smol-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || serving.run(listener));
+
+    async_io::block_on(async {
+        let mut connection = AsyncConnection::<Async<UnixStream>>::connect(&path)
+            .await
+            .unwrap();
+        assert_eq!(connection.object_id("test").await.unwrap(), id);
+        let mut replies = 0;
+        connection
+            .invoke(id, "anything", &[], |_| replies += 1)
+            .await
+            .unwrap();
+        assert_eq!(replies, 0);
+    });
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn tcp() {
+    let broker = Broker::new();
+    let id = service(&broker);
+    // A TCP bridge to the broker, as socat would give
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let writer = StdIo::new(stream.try_clone().unwrap());
+        serving.serve_io(StdIo::new(stream), writer)
+    });
+
+    async_io::block_on(async {
+        let mut connection = AsyncConnection::<Async<TcpStream>>::connect_tcp(addr)
+            .await
+            .unwrap();
+        assert_eq!(connection.object_id("test").await.unwrap(), id);
+    });
+}
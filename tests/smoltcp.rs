@@ -0,0 +1,108 @@
+#![cfg(feature = "smoltcp")]
+use smoltcp::iface::{Config, Interface, SocketSet};
+use smoltcp::phy::{Loopback, Medium};
+use smoltcp::socket::tcp;
+use smoltcp::time::Instant;
+use smoltcp::wire::{HardwareAddress, IpAddress, IpCidr};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use ubus::*;
+
+fn socket() -> tcp::Socket<'static> {
+    tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 1024]),
+        tcp::SocketBuffer::new(vec![0; 1024]),
+    )
+}
+
+/// Pass what's arrived at the bridge's end of the TCP connection on to the broker, and back
+fn relay(bridge: &mut tcp::Socket, broker: &mut UnixStream) {
+    let mut buffer = [0u8; 256];
+    let len = bridge.recv_slice(&mut buffer).unwrap_or(0);
+    broker.write_all(&buffer[..len]).unwrap();
+    let space = bridge.send_capacity() - bridge.send_queue();
+    match broker.read(&mut buffer[..space.min(256)]) {
+        Ok(len) => assert_eq!(bridge.send_slice(&buffer[..len]), Ok(len)),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+    let (mut client, server) = UnixStream::pair().unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || serving.serve(server));
+    client.set_nonblocking(true).unwrap();
+
+    let mut device = Loopback::new(Medium::Ip);
+    let mut iface = Interface::new(Config::new(HardwareAddress::Ip), &mut device, Instant::ZERO);
+    iface.update_ip_addrs(|addrs| {
+        addrs
+            .push(IpCidr::new(IpAddress::v4(127, 0, 0, 1), 8))
+            .unwrap();
+    });
+
+    // Both ends of the TCP connection go through the loopback interface
+    let mut bridge = socket();
+    bridge.listen(8000).unwrap();
+    let mut ours = socket();
+    ours.connect(iface.context(), (IpAddress::v4(127, 0, 0, 1), 8000), 49152)
+        .unwrap();
+    let mut sockets = SocketSet::new(vec![]);
+    let bridge = sockets.add(bridge);
+    let ours = sockets.add(ours);
+    let mut connection = NbConnection::new(SmoltcpIo::new(sockets, ours), [0u8; 1024], [0u8; 256]);
+
+    let mut lookup = None;
+    let mut found = None;
+    for millis in 0..10000 {
+        let io = connection.io_mut();
+        io.poll(&mut iface, &mut device, Instant::from_millis(millis));
+        relay(io.sockets_mut().get_mut(bridge), &mut client);
+
+        if connection.peer().is_some() && lookup.is_none() {
+            lookup = Some(connection.request(LookupRequest::path("test")).unwrap());
+        }
+        match connection.poll() {
+            Ok(EngineEvent::Hello { .. }) => {}
+            Ok(EngineEvent::Data { sequence, attrs }) => {
+                assert_eq!(Some(sequence), lookup);
+                for attr in attrs {
+                    if let MessageAttr::ObjId(id) = attr {
+                        found = Some(id);
+                    }
+                }
+            }
+            Ok(EngineEvent::Done { sequence, status }) => {
+                assert_eq!(Some(sequence), lookup);
+                assert_eq!(status, 0);
+                assert_eq!(found, Some(object.id()));
+                return;
+            }
+            Ok(event) => panic!("Unexpected {:?}", event),
+            Err(nb::Error::WouldBlock) => {}
+            Err(nb::Error::Other(e)) => panic!("{:?}", e),
+        }
+    }
+    panic!("No reply to lookup");
+}
+
+#[test]
+fn closed() {
+    let mut device = Loopback::new(Medium::Ip);
+    let mut iface = Interface::new(Config::new(HardwareAddress::Ip), &mut device, Instant::ZERO);
+    let mut sockets = SocketSet::new(vec![]);
+    let ours = sockets.add(socket());
+    let mut connection = NbConnection::new(SmoltcpIo::new(sockets, ours), [0u8; 1024], [0u8; 256]);
+    connection
+        .io_mut()
+        .poll(&mut iface, &mut device, Instant::ZERO);
+    match connection.poll() {
+        Err(nb::Error::Other(Error::IO(e))) => assert_eq!(e.state, tcp::State::Closed),
+        other => panic!("Unexpected {:?}", other.map(|_| ())),
+    }
+}
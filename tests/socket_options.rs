@@ -0,0 +1,109 @@
+use std::os::unix::io::{AsRawFd, IntoRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use ubus::*;
+
+fn buffer_size(fd: i32, option: libc::c_int) -> usize {
+    let mut size: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            &mut size as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    assert_eq!(result, 0);
+    size as usize
+}
+
+fn flags(fd: i32) -> (bool, bool) {
+    let fd_flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    let fl_flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    (
+        fd_flags & libc::FD_CLOEXEC != 0,
+        fl_flags & libc::O_NONBLOCK != 0,
+    )
+}
+
+#[test]
+fn test() {
+    let socket = std::env::temp_dir().join(format!("ubus-options-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+    let broker = Broker::new();
+    std::thread::spawn(move || broker.run(listener));
+
+    let connection = Connection::builder()
+        .socket(&socket)
+        .socket_send_buffer(64 * 1024)
+        .socket_recv_buffer(128 * 1024)
+        .cloexec(false)
+        .connect()
+        .unwrap();
+    let fd = connection.as_raw_fd();
+    // Linux doubles the requested sizes, for its own bookkeeping
+    assert!(buffer_size(fd, libc::SO_SNDBUF) >= 64 * 1024);
+    assert!(buffer_size(fd, libc::SO_RCVBUF) >= 128 * 1024);
+    assert_eq!(flags(fd), (false, false));
+
+    // The message buffer starts small, growing for the lookup's reply
+    let mut connection = Connection::builder()
+        .socket(&socket)
+        .recv_buffer(16)
+        .connect()
+        .unwrap();
+    assert_eq!(flags(connection.as_raw_fd()), (true, false));
+    connection.object_id("missing").unwrap_err();
+    std::fs::remove_file(&socket).unwrap();
+}
+
+#[cfg(feature = "nb")]
+#[test]
+fn nonblocking() {
+    let socket = std::env::temp_dir().join(format!("ubus-nb-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+    let broker = Broker::new();
+    std::thread::spawn(move || broker.run(listener));
+
+    let mut connection = Connection::builder()
+        .socket(&socket)
+        .max_message_size(1024)
+        .connect_nonblocking()
+        .unwrap();
+    assert_eq!(flags(connection.io_mut().as_raw_fd()), (true, true));
+
+    // Nothing has arrived yet, or the rest of the hello is still on its way
+    let mut lookup = None;
+    let status = loop {
+        if connection.peer().is_some() && lookup.is_none() {
+            lookup = Some(connection.request(LookupRequest::path("missing")).unwrap());
+        }
+        match connection.poll() {
+            Ok(EngineEvent::Done { sequence, status }) if Some(sequence) == lookup => break status,
+            Ok(_) | Err(nb::Error::WouldBlock) => std::thread::yield_now(),
+            Err(nb::Error::Other(e)) => panic!("{:?}", e),
+        }
+    };
+    assert_eq!(status, 4);
+    std::fs::remove_file(&socket).unwrap();
+}
+
+#[test]
+fn from_raw_fd() {
+    let (client, server) = UnixStream::pair().unwrap();
+    let broker = Broker::new();
+    std::thread::spawn(move || broker.serve(server));
+
+    let mut connection = unsafe { Connection::from_raw_fd(client.into_raw_fd()) }.unwrap();
+    let mut buffer = [0u8; 64];
+    let mut args = BlobMsgBuilder::from_bytes(&mut buffer);
+    args.push_string("a", "b").unwrap();
+    connection.send_event("test", args.finish()).unwrap();
+    assert!(matches!(
+        connection.object_id("missing"),
+        Err(Error::Status(4))
+    ));
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use common::*;
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Mutex};
+use ubus::*;
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        assert_eq!(message.header.message, MessageType::INVOKE);
+        let seq = message.header.sequence.into();
+
+        let mut table = [0u8; 64];
+        let mut builder = BlobMsgBuilder::from_bytes(&mut table);
+        builder.push_string("hello", "world").unwrap();
+        let table = builder.finish();
+        send(
+            &mut server,
+            MessageType::DATA,
+            seq,
+            [MessageAttr::Data(table)],
+        );
+        send(
+            &mut server,
+            MessageType::STATUS,
+            seq,
+            [MessageAttr::Status(0)],
+        );
+    });
+
+    let connection = Connection::new(client).unwrap();
+    let (requester, mut reader) = connection.split().unwrap();
+    std::thread::spawn(move || reader.run());
+
+    let mut results = Vec::new();
+    requester
+        .clone()
+        .invoke(0x100, "test", &[], |data| {
+            for msg in data {
+                if let BlobMsgData::String(s) = msg.data {
+                    results.push((msg.name.unwrap().to_string(), s.to_string()));
+                }
+            }
+        })
+        .unwrap();
+    assert_eq!(results, [("hello".to_string(), "world".to_string())]);
+}
+
+#[test]
+fn hooks() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("echo", |_, _, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "hi").unwrap();
+            0
+        })
+        .unwrap();
+    let id = object.id();
+    std::thread::spawn(move || while service.handle_next_message().is_ok() {});
+
+    let mut client = broker.connect().unwrap();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let sent = log.clone();
+    client.on_send(move |header, _| {
+        sent.lock()
+            .unwrap()
+            .push(format!("send {:?}", header.message));
+        Ok(Intercept::Pass)
+    });
+    let received = log.clone();
+    client.on_receive(move |message| {
+        received
+            .lock()
+            .unwrap()
+            .push(format!("recv {:?}", message.header.message));
+        match message.header.message {
+            MessageType::DATA => Ok(Intercept::Drop),
+            _ => Ok(Intercept::Pass),
+        }
+    });
+
+    // Hooks and counters carry on after the split
+    let (requester, mut reader) = client.split().unwrap();
+    std::thread::spawn(move || reader.run());
+    let mut replies = 0;
+    requester.invoke(id, "call", &[], |_| replies += 1).unwrap();
+    assert_eq!(replies, 0);
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["send INVOKE", "recv DATA", "recv STATUS"]
+    );
+
+    let stats = requester.stats();
+    assert_eq!(stats.sent(MessageType::INVOKE), 1);
+    assert_eq!(stats.received(MessageType::HELLO), 1);
+    assert_eq!(stats.received(MessageType::DATA), 1);
+    assert_eq!(stats.received(MessageType::STATUS), 1);
+    assert_eq!(stats.outstanding, 0);
+    assert_eq!(stats.errors, 0);
+}
+
+#[test]
+fn summary() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("echo", |_, _, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "hi").unwrap();
+            0
+        })
+        .unwrap();
+    let id = object.id();
+    std::thread::spawn(move || while service.handle_next_message().is_ok() {});
+
+    let mut connection = broker.connect().unwrap();
+    let expected = connection.invoke(id, "call", &[], |_| {}).unwrap();
+    assert!(expected.has_data());
+
+    // The same reply is summarised the same way through a requester
+    let (requester, mut reader) = broker.connect().unwrap().split().unwrap();
+    std::thread::spawn(move || reader.run());
+    let summary = requester.invoke(id, "call", &[], |_| {}).unwrap();
+    assert_eq!(summary, expected);
+}
+
+#[test]
+fn dropped() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let (received, invoked) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+        // Never reply, but keep the socket open
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        received.send(message.header.message).unwrap();
+        let _ = Message::from_io(&mut server, &mut buffer);
+    });
+
+    let (requester, reader) = Connection::new(client).unwrap().split().unwrap();
+    let waiting = requester.clone();
+    let call = std::thread::spawn(move || waiting.invoke(0x100, "test", &[], |_| {}));
+    assert_eq!(invoked.recv().unwrap(), MessageType::INVOKE);
+
+    // The call in flight fails rather than waiting forever, as do any made later
+    drop(reader);
+    assert!(call.join().unwrap().is_err());
+    assert!(requester.invoke(0x100, "test", &[], |_| {}).is_err());
+}
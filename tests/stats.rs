@@ -0,0 +1,44 @@
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("echo", |method, _, reply| match method {
+            "ok" => {
+                reply.push_str(BlobMsgType::STRING.value(), "hi").unwrap();
+                0
+            }
+            _ => 3,
+        })
+        .unwrap();
+    let id = object.id();
+    std::thread::spawn(move || while service.handle_next_message().is_ok() {});
+
+    let mut client = broker.connect().unwrap();
+    // Just the HELLO so far
+    let stats = client.stats().clone();
+    assert_eq!(stats.messages_received, 1);
+    assert_eq!(stats.received(MessageType::HELLO), 1);
+    assert_eq!(stats.messages_sent, 0);
+
+    client.invoke(id, "ok", &[], |_| {}).unwrap();
+    assert!(client.invoke(id, "missing", &[], |_| {}).is_err());
+
+    let stats = client.stats();
+    assert_eq!(stats.sent(MessageType::INVOKE), 2);
+    assert_eq!(stats.messages_sent, 2);
+    assert_eq!(stats.received(MessageType::DATA), 1);
+    assert_eq!(stats.received(MessageType::STATUS), 2);
+    assert_eq!(stats.messages_received, 4);
+    assert!(stats.bytes_sent > 0 && stats.bytes_received > stats.bytes_sent);
+    assert_eq!(stats.errors, 1);
+    assert_eq!(stats.outstanding, 0);
+
+    let mut reconnecting = Reconnecting::new(move || broker.connect()).unwrap();
+    reconnecting.reconnect().unwrap();
+    let stats = reconnecting.stats();
+    assert_eq!(stats.reconnects, 1);
+    assert_eq!(stats.received(MessageType::HELLO), 2);
+}
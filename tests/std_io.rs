@@ -0,0 +1,72 @@
+use std::io::{BufReader, Read, Write};
+use std::net::TcpListener;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use ubus::*;
+
+/// A stream which is only `Read + Write`, as a pipe pair or socat bridge would be
+struct Pipe {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+
+    let (client, server) = UnixStream::pair().unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || serving.serve(server));
+
+    let pipe = Pipe {
+        reader: BufReader::new(client.try_clone().unwrap()),
+        writer: client,
+    };
+    let mut connection = Connection::new(StdIo::new(pipe)).unwrap();
+    assert_eq!(connection.object_id("test").unwrap(), object.id());
+    assert!(matches!(
+        connection.object_id("missing"),
+        Err(Error::Status(4))
+    ));
+}
+
+#[test]
+fn tcp() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+
+    // The broker stands in for a TCP bridge to ubusd
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let writer = StdIo::new(stream.try_clone().unwrap());
+        serving.serve_io(StdIo::new(stream), writer)
+    });
+
+    let mut connection = Connection::connect_tcp(addr).unwrap();
+    connection
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    assert_eq!(connection.object_id("test").unwrap(), object.id());
+    assert!(matches!(connection.next_message(), Err(Error::Timeout)));
+}
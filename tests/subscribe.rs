@@ -0,0 +1,81 @@
+mod common;
+
+use common::*;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::channel;
+use ubus::*;
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        assert_eq!(message.header.message, MessageType::ADD_OBJECT);
+        let seq = message.header.sequence.into();
+        send(
+            &mut server,
+            MessageType::DATA,
+            seq,
+            [MessageAttr::ObjId(0x100)],
+        );
+        send(
+            &mut server,
+            MessageType::STATUS,
+            seq,
+            [MessageAttr::Status(0)],
+        );
+
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        assert_eq!(message.header.message, MessageType::SUBSCRIBE);
+        let seq = message.header.sequence.into();
+        send(
+            &mut server,
+            MessageType::STATUS,
+            seq,
+            [MessageAttr::Status(0)],
+        );
+
+        let notification = [
+            MessageAttr::ObjId(0x100),
+            MessageAttr::Method("update"),
+            MessageAttr::Data(&[]),
+        ];
+        send(&mut server, MessageType::INVOKE, 7, notification);
+
+        // The notification should be acknowledged
+        let message = Message::from_io(&mut server, &mut buffer).unwrap();
+        assert_eq!(message.header.message, MessageType::STATUS);
+        assert_eq!(u16::from(message.header.sequence), 7);
+
+        // Dropping the subscriber removes its object before the next request
+        for expected in [MessageType::REMOVE_OBJECT, MessageType::LOOKUP] {
+            let message = Message::from_io(&mut server, &mut buffer).unwrap();
+            assert_eq!(message.header.message, expected);
+            let seq = message.header.sequence.into();
+            send(
+                &mut server,
+                MessageType::STATUS,
+                seq,
+                [MessageAttr::Status(0)],
+            );
+        }
+    });
+
+    let mut connection = Connection::new(client).unwrap();
+
+    let (tx, rx) = channel();
+    let subscriber = connection
+        .subscriber(move |ty, _data| tx.send(ty.to_string()).unwrap())
+        .unwrap();
+    assert_eq!(subscriber.id(), 0x100);
+    connection.subscribe(&subscriber, 0x200).unwrap();
+    connection.handle_next_message().unwrap();
+    assert_eq!(rx.try_recv().unwrap(), "update");
+    drop(subscriber);
+    connection.lookup(|_| {}, |_| {}).unwrap();
+    server.join().unwrap();
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let object = service
+        .add_object("test", |_method, _args, reply| {
+            reply.push_str(BlobMsgType::STRING.value(), "pong").unwrap();
+            0
+        })
+        .unwrap();
+    let object_id = object.id();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+
+    let reader = broker.connect().unwrap().spawn_reader().unwrap();
+    let timeout = Duration::from_secs(5);
+
+    // Calls return straight away, with the replies arriving on their channel
+    let first = reader.call(object_id, "ping", &[]).unwrap();
+    let second = reader.call(object_id, "ping", &[]).unwrap();
+    for sequence in [first, second] {
+        let reply = reader.replies.recv_timeout(timeout).unwrap();
+        assert_eq!(reply.sequence, sequence);
+        assert_eq!(reply.status, 0);
+        let mut results = Vec::new();
+        for table in reply.tables() {
+            for value in table {
+                if let BlobMsgData::String(s) = value.data {
+                    results.push(s.to_string());
+                }
+            }
+        }
+        assert_eq!(results, ["pong"]);
+    }
+    assert_eq!(reader.requester().object_id("test").unwrap(), object_id);
+
+    // Events arrive on their own channel
+    reader.register_event("test.*").unwrap();
+    let mut sender = broker.connect().unwrap();
+    let mut buffer = [0u8; 256];
+    let mut data = BlobMsgBuilder::from_bytes(&mut buffer);
+    data.push_string("state", "up").unwrap();
+    let data = data.finish();
+    sender.send_event("other", data).unwrap();
+    sender.send_event("test.event", data).unwrap();
+    let event = reader.events.recv_timeout(timeout).unwrap();
+    assert_eq!(event.name, "test.event");
+    let value = event.data().next().unwrap();
+    assert_eq!(value.name, Some("state"));
+    assert!(reader.events.try_recv().is_err());
+    assert!(reader.notifications.try_recv().is_err());
+
+    // Dropping it stops the thread
+    drop(reader);
+}
+
+#[test]
+fn closed() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let server = std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        send(&mut server, MessageType::HELLO, 0, []);
+        // Close the connection once the call has arrived (after the setup requests), unanswered
+        loop {
+            let message = Message::from_io(&mut server, &mut buffer).unwrap();
+            let sequence = message.header.sequence.into();
+            match message.header.message {
+                MessageType::INVOKE => break,
+                MessageType::ADD_OBJECT => {
+                    let attrs = [MessageAttr::ObjId(0x100), MessageAttr::ObjType(0x100)];
+                    send(&mut server, MessageType::DATA, sequence, attrs);
+                    send(
+                        &mut server,
+                        MessageType::STATUS,
+                        sequence,
+                        [MessageAttr::Status(0)],
+                    );
+                }
+                _ => send(
+                    &mut server,
+                    MessageType::STATUS,
+                    sequence,
+                    [MessageAttr::Status(0)],
+                ),
+            }
+        }
+    });
+
+    let reader = Connection::new(client).unwrap().spawn_reader().unwrap();
+    let sequence = reader.call(0x100, "ping", &[]).unwrap();
+    server.join().unwrap();
+
+    // The call is still answered, with the status for a failed connection
+    let reply = reader.replies.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reply.sequence, sequence);
+    assert_eq!(reply.status, 10);
+}
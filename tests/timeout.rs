@@ -0,0 +1,22 @@
+mod common;
+
+use common::*;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use ubus::*;
+
+#[test]
+fn test() {
+    let (client, mut server) = UnixStream::pair().unwrap();
+
+    send(&mut server, MessageType::HELLO, 0, []);
+
+    let mut connection = Connection::new(client).unwrap();
+    connection
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+
+    // The server never answers
+    let result = connection.object_id("test");
+    assert!(matches!(result, Err(Error::Timeout)), "{:?}", result);
+}
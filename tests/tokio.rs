@@ -0,0 +1,58 @@
+#![cfg(feature = "tokio")]
+use std::net::TcpListener;
+use std::os::unix::net::UnixListener;
+use ubus::*;
+
+fn service(broker: &Broker) -> u32 {
+    let mut service = broker.connect().unwrap();
+    let object = service.add_object("test", |_, _, _| 0).unwrap();
+    let id = object.id();
+    std::thread::spawn(move || {
+        let _object = object;
+        while service.handle_next_message().is_ok() {}
+    });
+    id
+}
+
+#[tokio::test]
+async fn unix() {
+    let broker = Broker::new();
+    let id = service(&broker);
+    let path = std::env::temp_dir().join(format!("ubus-rs-tokio-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || serving.run(listener));
+
+    let mut connection = AsyncConnection::<tokio::net::UnixStream>::connect(&path)
+        .await
+        .unwrap();
+    assert_eq!(connection.object_id("test").await.unwrap(), id);
+    let mut replies = 0;
+    connection
+        .invoke(id, "anything", &[], |_| replies += 1)
+        .await
+        .unwrap();
+    assert_eq!(replies, 0);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn tcp() {
+    let broker = Broker::new();
+    let id = service(&broker);
+    // A TCP bridge to the broker, as socat would give
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let serving = broker.clone();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let writer = StdIo::new(stream.try_clone().unwrap());
+        serving.serve_io(StdIo::new(stream), writer)
+    });
+
+    let mut connection = AsyncConnection::<tokio::net::TcpStream>::connect_tcp(addr)
+        .await
+        .unwrap();
+    assert_eq!(connection.object_id("test").await.unwrap(), id);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::*;
+use ubus::*;
+
+/// A message's attributes, as received from a newer ubusd: a known one, an unknown one, an
+/// unknown one with an extended (named) header, and unknown ones with unaligned payloads
+fn attrs() -> Vec<u8> {
+    let mut buffer = [0u8; 128];
+    let mut builder = BlobBuilder::from_bytes(&mut buffer);
+    builder
+        .push_u32(MessageAttrId::OBJID.value(), 0x1234)
+        .unwrap();
+    builder.push_bytes(0x40, &[1, 2, 3, 4]).unwrap();
+    builder.push_named_u32(0x41, "future", 7).unwrap();
+    builder.push_bytes(0x42, &[5]).unwrap();
+    builder.push_named_u32(0x43, "xy", 8).unwrap();
+    let len = builder.len();
+    buffer[..len].to_vec()
+}
+
+#[test]
+fn test() {
+    let original = attrs();
+    let parsed: Vec<_> = BlobIter::<MessageAttr>::new(&original).collect();
+    assert_eq!(parsed.len(), 5);
+    match &parsed[2] {
+        MessageAttr::Raw(blob) => {
+            assert_eq!(blob.name, Some("future"));
+            assert_eq!(blob.tag.id(), 0x41);
+        }
+        other => panic!("Expected raw attribute, got {:?}", other),
+    }
+    assert_eq!(parsed[2].id(), MessageAttrId::from(0x41));
+
+    round_trip(&original);
+}
+
+/// Check `original` comes out the same after parsing and rebuilding, in a buffer and streamed
+fn round_trip(original: &[u8]) {
+    let parsed: Vec<_> = BlobIter::<MessageAttr>::new(original).collect();
+
+    // Rebuilt in a buffer
+    let built = build(
+        header(MessageType::DATA, 3),
+        BlobIter::<MessageAttr>::new(original),
+    );
+    let prefix = MessageHeader::SIZE + BlobTag::SIZE;
+    assert_eq!(built[prefix..], original[..]);
+
+    // And streamed
+    let (mut a, mut b) = LoopbackIo::pair();
+    let len = parsed.iter().map(MessageAttr::size).sum();
+    assert_eq!(len, original.len());
+    let mut writer = MessageWriter::new(&mut a, header(MessageType::DATA, 3), len).unwrap();
+    for attr in parsed.iter() {
+        writer.put(attr).unwrap();
+    }
+    writer.finish().unwrap();
+    let mut streamed = vec![0u8; built.len()];
+    b.get(&mut streamed).unwrap();
+    assert_eq!(streamed, built);
+}
+
+/// An object found by a lookup, with its signature, and the subscribers of an object
+#[test]
+fn lookup_reply() {
+    let mut signature = [0u8; 64];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut signature);
+    builder
+        .push_table("status", |args| {
+            args.push_int32("name", BlobMsgType::STRING.value() as i32)
+        })
+        .unwrap();
+    let signature = builder.finish();
+
+    let mut subscribers = [0u8; 16];
+    let mut builder = BlobBuilder::from_bytes(&mut subscribers);
+    builder
+        .push_u32(MessageAttrId::OBJID.value(), 0x5678)
+        .unwrap();
+    let len = builder.len();
+    let subscribers = &subscribers[..len];
+
+    let mut buffer = [0u8; 128];
+    let mut builder = BlobBuilder::from_bytes(&mut buffer);
+    builder
+        .push_str(MessageAttrId::OBJPATH.value(), "network")
+        .unwrap();
+    builder
+        .push_u32(MessageAttrId::OBJID.value(), 0x1234)
+        .unwrap();
+    builder
+        .push_u32(MessageAttrId::OBJTYPE.value(), 0x4321)
+        .unwrap();
+    builder
+        .push_bytes(MessageAttrId::SIGNATURE.value(), signature)
+        .unwrap();
+    builder
+        .push_bytes(MessageAttrId::SUBSCRIBERS.value(), subscribers)
+        .unwrap();
+    let len = builder.len();
+    let original = &buffer[..len];
+    assert!(BlobIter::<MessageAttr>::new(original).any(|a| matches!(a, MessageAttr::Signature(_))));
+    round_trip(original);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use ubus::*;
+
+fn receive<'a>(
+    message: MessageType,
+    attrs: impl IntoIterator<Item = MessageAttr<'a>>,
+) -> Result<(), Error<std::io::Error>> {
+    let (mut tx, mut rx) = LoopbackIo::pair();
+    tx.put(&common::message(message, 1, attrs)).unwrap();
+    let mut buffer = Vec::new();
+    Message::from_io_vec(&mut rx, &mut buffer).map(|_| ())
+}
+
+fn invalid(result: Result<(), Error<std::io::Error>>) -> &'static str {
+    match result {
+        Err(Error::InvalidData(msg)) => msg,
+        other => panic!("Expected invalid data, got {:?}", other),
+    }
+}
+
+#[test]
+fn test() {
+    let (obj, method) = (|| MessageAttr::ObjId(0x100), || MessageAttr::Method("ping"));
+    receive(MessageType::INVOKE, [obj(), method()]).unwrap();
+    receive(MessageType::STATUS, [MessageAttr::Status(0)]).unwrap();
+    receive(MessageType::DATA, []).unwrap();
+
+    // Required attributes are missing
+    assert_eq!(
+        invalid(receive(MessageType::INVOKE, [obj()])),
+        "INVOKE without METHOD attribute"
+    );
+    assert_eq!(
+        invalid(receive(MessageType::INVOKE, [method()])),
+        "INVOKE without OBJID attribute"
+    );
+    assert_eq!(
+        invalid(receive(MessageType::STATUS, [])),
+        "STATUS without STATUS attribute"
+    );
+
+    // Attributes which can't hold their type
+    let short = MessageAttr::Unknown(MessageAttrId::OBJID, &[0, 1]);
+    assert_eq!(
+        invalid(receive(MessageType::DATA, [short])),
+        "Attribute too short for its type"
+    );
+    let not_utf8 = MessageAttr::Unknown(MessageAttrId::METHOD, &[0xff, 0]);
+    assert_eq!(
+        invalid(receive(MessageType::DATA, [not_utf8])),
+        "Blob not valid UTF-8"
+    );
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+use ubus::*;
+
+/// Parse a reply and keep its values, after the buffer is gone
+fn parse() -> Vec<(Option<String>, BlobMsgValue)> {
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder.push_string("ssid", "foo").unwrap();
+    builder.push_int32("channel", 11).unwrap();
+    builder.push_bool("up", true).unwrap();
+    builder.push_double("load", 0.5).unwrap();
+    builder.push_null("none").unwrap();
+    builder
+        .push_array("keys", |b| {
+            b.push_string("", "a")?;
+            b.push_int16("", -2)
+        })
+        .unwrap();
+    builder
+        .push_table("stats", |b| b.push_int64("rx", 1 << 40))
+        .unwrap();
+    let data = builder.finish();
+    BlobIter::<BlobMsg>::new(data)
+        .map(|value| (value.name.map(str::to_string), value.to_owned().unwrap()))
+        .collect()
+}
+
+#[test]
+fn map() {
+    let mut buffer = [0u8; 256];
+    let mut builder = BlobMsgBuilder::from_bytes(&mut buffer);
+    builder
+        .push_table("interface", |b| {
+            b.push_string("device", "eth0")?;
+            b.push_array("ipv4-address", |b| {
+                b.push_table("", |b| b.push_string("address", "192.168.1.1"))
+            })
+        })
+        .unwrap();
+    builder.push_int32("count", 1).unwrap();
+    let data = builder.finish();
+
+    let map = BlobIter::<BlobMsg>::new(data).to_map().unwrap();
+    assert_eq!(map["count"], BlobMsgValue::Int64(1));
+    let interface = BlobIter::<BlobMsg>::new(data).next().unwrap().to_map();
+    let interface = interface.unwrap();
+    assert_eq!(
+        Some(&interface),
+        match &map["interface"] {
+            BlobMsgValue::Table(table) => Some(table),
+            _ => None,
+        }
+    );
+    let mut address = BTreeMap::new();
+    address.insert(
+        "address".to_string(),
+        BlobMsgValue::String("192.168.1.1".to_string()),
+    );
+    assert_eq!(
+        interface["ipv4-address"],
+        BlobMsgValue::Array(vec![BlobMsgValue::Table(address)])
+    );
+    assert!(matches!(
+        BlobIter::<BlobMsg>::new(data).nth(1).unwrap().to_map(),
+        Err(Error::InvalidData(_))
+    ));
+}
+
+#[test]
+fn test() {
+    let values = std::thread::spawn(parse).join().unwrap();
+    let mut stats = BTreeMap::new();
+    stats.insert("rx".to_string(), BlobMsgValue::Int64(1 << 40));
+    let name = |name: &str| Some(name.to_string());
+    assert_eq!(
+        values,
+        [
+            (name("ssid"), BlobMsgValue::String("foo".to_string())),
+            (name("channel"), BlobMsgValue::Int64(11)),
+            (name("up"), BlobMsgValue::Bool(true)),
+            (name("load"), BlobMsgValue::Double(0.5)),
+            (name("none"), BlobMsgValue::Null),
+            (
+                name("keys"),
+                BlobMsgValue::Array(vec![
+                    BlobMsgValue::String("a".to_string()),
+                    BlobMsgValue::Int64(-2)
+                ])
+            ),
+            (name("stats"), BlobMsgValue::Table(stats)),
+        ]
+    );
+}
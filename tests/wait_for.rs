@@ -0,0 +1,59 @@
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::{Duration, Instant};
+use ubus::*;
+
+#[test]
+fn test() {
+    let broker = Broker::new();
+    let mut service = broker.connect().unwrap();
+    let _early = service.add_object("early", |_, _, _| 0).unwrap();
+
+    // Objects already there are found by the initial lookup
+    let mut connection = broker.connect().unwrap();
+    connection
+        .wait_for_objects(&["early"], Duration::from_secs(1))
+        .unwrap();
+
+    // Later ones by their "ubus.object.add" events
+    let (done, finished) = channel();
+    let late = broker.connect().unwrap();
+    let adder = thread::spawn(move || {
+        let mut late = late;
+        thread::sleep(Duration::from_millis(100));
+        let _first = late.add_object("late.first", |_, _, _| 0).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        let _second = late.add_object("late.second", |_, _, _| 0).unwrap();
+        finished.recv().unwrap();
+    });
+    let started = Instant::now();
+    connection
+        .wait_for_objects(
+            &["early", "late.first", "late.second"],
+            Duration::from_secs(5),
+        )
+        .unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    assert!(connection.object_id("late.second").is_ok());
+    done.send(()).unwrap();
+    adder.join().unwrap();
+}
+
+#[test]
+fn timeout() {
+    let broker = Broker::new();
+    let mut connection = broker.connect().unwrap();
+    let timeout = Duration::from_millis(100);
+    let started = Instant::now();
+    assert!(matches!(
+        connection.wait_for_objects(&["missing"], timeout),
+        Err(Error::Timeout)
+    ));
+    assert!(started.elapsed() >= timeout);
+
+    // Still usable afterwards
+    let _object = connection.add_object("found", |_, _, _| 0).unwrap();
+    connection
+        .wait_for_objects(&["found"], Duration::from_secs(1))
+        .unwrap();
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::*;
+use ubus::*;
+
+fn attrs() -> [MessageAttr<'static>; 4] {
+    [
+        MessageAttr::ObjId(0x1234),
+        MessageAttr::Method("method"),
+        MessageAttr::Data(&[1, 2, 3]),
+        MessageAttr::Status(-1),
+    ]
+}
+
+#[test]
+fn test() {
+    let header = header(MessageType::INVOKE, 3);
+
+    // Built in a buffer
+    let expected = build(header, attrs());
+
+    // Streamed
+    let (mut a, mut b) = LoopbackIo::pair();
+    let len = attrs().iter().map(MessageAttr::size).sum();
+    let mut writer = MessageWriter::new(&mut a, header, len).unwrap();
+    for attr in attrs().iter() {
+        writer.put(attr).unwrap();
+    }
+    writer.finish().unwrap();
+    let mut streamed = vec![0u8; expected.len()];
+    b.get(&mut streamed).unwrap();
+    assert_eq!(streamed, expected);
+
+    // Attributes must add up to the declared size
+    let mut writer = MessageWriter::new(&mut a, header, 4).unwrap();
+    assert!(writer.put(&MessageAttr::Method("too long")).is_err());
+    assert!(writer.finish().is_err());
+}